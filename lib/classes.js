@@ -1,13 +1,17 @@
 "use strict"
 
 var fs = require('fs'),
+    os = require('os'),
     {inspect} = require('util'),
-    {extname} = require('path'),
+    {EventEmitter} = require('events'),
+    {spawn} = require('child_process'),
+    {extname, join} = require('path'),
     glob = require('glob').sync,
     get = require('simple-get'),
     native = require('../native'),
-    {DOMMatrix} = require('./geometry'),
-    {parseFont, parseVariant, parseFilter} = require('./parse'),
+    {DOMPoint, DOMMatrix, DOMRect} = require('./geometry'),
+    {addOutline} = require('./pdf'),
+    {parseFont, parseVariant, parseSize, parseFilter, parseDecoration} = require('./parse'),
     REPR = inspect.custom
 
 //
@@ -33,6 +37,23 @@ const readOnly = (obj, attr, value) => {
   Object.defineProperty(obj, attr, {value, writable:false, enumerable:true})
 }
 
+// decode the contents of a data: url
+const fromDataURL = url => {
+  let split = url.indexOf(','),
+      enc = url.lastIndexOf('base64', split) !== -1 ? 'base64' : 'utf8',
+      content = url.slice(split + 1);
+  return Buffer.from(content, enc);
+}
+
+// flatten a list of [x, y] pairs, {x, y} objects, or bare numbers into a list of coordinates
+const toCoords = pts => [...pts].flatMap(pt => Array.isArray(pt) ? pt.slice(0, 2) : typeof pt=='object' ? [pt.x, pt.y] : [pt])
+
+// hand a list of numbers (or a Float32Array) to rust as a buffer without copying it element-by-element
+const toFloatBuffer = nums => {
+  let floats = nums instanceof Float32Array ? nums : Float32Array.from([...nums].flat(2))
+  return Buffer.from(floats.buffer, floats.byteOffset, floats.byteLength)
+}
+
 // convert arguments list to a string of type abbreviations
 function signature(args){
   return args.map(v => (Array.isArray(v) ? 'a' : {string:'s', number:'n', object:'o'}[typeof v] || 'x')).join('')
@@ -73,16 +94,28 @@ function RustClass(cls){
 function toSkMatrix(jsMatrix){
   if (Array.isArray(jsMatrix)){
     var [a, b, c, d, e, f] = jsMatrix
+  }else if (jsMatrix.is2D === false){
+    // a 3D DOMMatrix's perspective terms are carried over (though its z-axis components are ignored)
+    let {m11, m12, m14, m21, m22, m24, m41, m42, m44} = jsMatrix
+    return [m11, m21, m41, m12, m22, m42, m14, m24, m44]
   }else{
     var {a, b, c, d, e, f} = jsMatrix
   }
   return [a, c, e, b, d, f]
 }
 
+function rotation3d(x, y, angle, distance){
+  // a rotation about the x or y axis, optionally seen in perspective from a given distance along the z axis
+  let matrix = new DOMMatrix()
+  if (distance > 0) matrix.m34 = -1 / distance
+  return matrix.rotateAxisAngle(x, y, 0, angle * 180 / Math.PI)
+}
+
 function fromSkMatrix(skMatrix){
-  // TBD: how/if to map the perspective terms
+  // perspective terms can only be represented by a 3D matrix
   let [a, c, e, b, d, f, p0, p1, p2] = skMatrix
-  return new DOMMatrix([a, b, c, d, e, f])
+  return (p0 || p1 || p2 != 1) ? new DOMMatrix([a, b, 0, p0, c, d, 0, p1, 0, 0, 1, 0, e, f, 0, p2])
+                               : new DOMMatrix([a, b, c, d, e, f])
 }
 
 //
@@ -96,23 +129,98 @@ let png = "image/png",
     svg = "image/svg+xml",
     gif = "image/gif",
     heic = "image/heic",
-    webp = "image/webp";
+    webp = "image/webp",
+    tif = "image/tiff",
+    tiff = "image/tiff",
+    skp = "application/x-skp";
 
 function toMime(ext){
   return {
-    png, jpg, jpeg, gif, pdf, svg, heic, webp
+    png, jpg, jpeg, gif, pdf, svg, heic, webp, tif, tiff, skp
   }[(ext||'').replace(/^\./, '').toLowerCase()]
 }
 
 function fromMime(mime){
   return {
     [png]: "png", [jpg]: "jpg", [pdf]: "pdf", [svg]: "svg",
-    [gif]: "gif", [heic]: "heic", [webp]: "webp",
+    [gif]: "gif", [heic]: "heic", [webp]: "webp", [tiff]: "tiff", [skp]: "skp",
   }[mime]
 }
 
+// pdf text is always embedded (as subsetted fonts) so it remains selectable, only svg can outline it
+const checkOutlines = (fmt, outlineText) => {
+  if (outlineText && fmt == 'pdf'){
+    throw new TypeError("The outlineText option is only supported for SVG output (PDFs always embed their fonts)")
+  }
+}
+
+// document info fields are passed to rust as a fixed-order list of strings
+const pdfMetadata = ({title='', author='', subject='', keywords='', creator=''}={}) =>
+  [title, author, subject, [keywords].flat().join(', '), creator].map(toString)
+
+// creation & modification dates are passed as utc components (defaulting to now for pdf/a documents)
+const pdfDates = ({creation, modified}={}, pdfa) => {
+  if (pdfa){
+    creation = creation || new Date()
+    modified = modified || creation
+  }
+  return [creation, modified].map(date => {
+    if (date === undefined) return null
+    if (!(date instanceof Date) || isNaN(date)) throw new TypeError("Expected metadata dates to be valid Date objects")
+    return [date.getUTCFullYear(), date.getUTCMonth() + 1, date.getUTCDay(), date.getUTCDate(),
+            date.getUTCHours(), date.getUTCMinutes(), date.getUTCSeconds()]
+  })
+}
+
 const toFormat = str => fromMime(toMime(str) || str),
-      toString = val => typeof val=='string' ? val : new String(val).toString();
+      toString = val => typeof val=='string' ? val : new String(val).toString(),
+      toDataURL = (fmt, buffer) => `data:${toMime(fmt)};base64,${buffer.toString('base64')}`,
+      isBitmap = fmt => ['png', 'jpg', 'webp', 'gif', 'heic'].includes(fmt),
+      isRaster = fmt => isBitmap(fmt) || fmt == 'tiff';
+
+const noBreakBeforeRE = /[\u3001\u3002\uFF0C\uFF0E\uFF1A\uFF1B\uFF01\uFF1F\u30FB\u3005\u303B\u309D\u309E\u30FD\u30FE\u30FC\u2010\u2013\u301C\u30A0\u300D\u300F\u3011\u3015\u3009\u300B\u3017\u3019\u301B\uFF09\uFF3D\uFF5D\u2019\u201D\u3041\u3043\u3045\u3047\u3049\u3063\u3083\u3085\u3087\u308E\u3095\u3096\u30A1\u30A3\u30A5\u30A7\u30A9\u30C3\u30E3\u30E5\u30E7\u30EE\u30F5\u30F6\u31F0-\u31FF]/u
+
+function segmentGraphemes(text){
+  // split a string into user-perceived characters (falling back to an approximation if Intl.Segmenter is unavailable)
+  if (typeof Intl.Segmenter == 'function'){
+    return Array.from(new Intl.Segmenter(undefined, {granularity:'grapheme'}).segment(text), ({segment, index}) => ({text:segment, index}))
+  }
+  let clusterRE = /\r\n|(?:\p{Regional_Indicator}{2}|\P{M})[\p{M}\u{1F3FB}-\u{1F3FF}]*(?:\u200D\P{M}[\p{M}\u{1F3FB}-\u{1F3FF}]*)*/gu
+  return Array.from(text.matchAll(clusterRE), m => ({text:m[0], index:m.index}))
+}
+
+function breakText(text, mode, hyphenate){
+  // insert invisible line-break controls into the string, noting their positions (as both code-point
+  // and UTF-16 offsets) so that indices reported by measureText() can be mapped back onto the original text
+  let chars = [],
+      added = [],
+      units = 0,
+      push = (...cs) => cs.forEach(c => { chars.push(c); units += c.length }),
+      add = c => { added.push([chars.length, units]); push(c) };
+
+  if (mode == 'strict'){
+    // glue CJK closing punctuation, small kana, and iteration marks to the preceding character
+    for (const c of text){
+      if (chars.length && noBreakBeforeRE.test(c)) add('\u2060')
+      push(c)
+    }
+  }else if (mode == 'hyphenate' && hyphenate){
+    // let the dictionary hook split longer words into syllables and join them with soft-hyphens
+    for (const [word] of text.matchAll(/\p{L}+|\P{L}+/gu)){
+      let parts = /^\p{L}{5,}$/u.test(word) ? hyphenate(word) : [word]
+      parts = typeof parts == 'string' ? parts.split('\u00AD') : [parts || word].flat().map(toString)
+      if (parts.join('') != word) parts = [word]
+      parts.forEach((part, i) => {
+        if (i > 0) add('\u00AD')
+        push(...part)
+      })
+    }
+  }else{
+    push(...text)
+  }
+
+  return {text: chars.join(''), added}
+}
 
 //
 // Extensions to the classes defined in rust (primarily for argument boxing/unboxing)
@@ -121,20 +229,145 @@ const toFormat = str => fromMime(toMime(str) || str),
 class Canvas extends RustClass(native.Canvas){
   static parent = new WeakMap()
   static context = new WeakMap()
+  static layers = new WeakMap()
+  static engine = new WeakMap()
+  static fonts = new WeakMap()
+  static bookmarks = new WeakMap()
+  static destinations = new WeakMap()
+  static destinationCount = 0
+  static pool = new Map()
+
+  static withPooled(width, height, callback){
+    // lend out a recycled canvas of the given size, resetting it and returning it to the pool when done
+    let key = `${width}x${height}`
+    if (!Canvas.pool.has(key)) Canvas.pool.set(key, [])
+
+    let idle = Canvas.pool.get(key),
+        canvas = idle.pop() || new Canvas(width, height);
+
+    const release = () => {
+      Canvas.context.set(canvas, Canvas.context.get(canvas).slice(-1))
+      canvas._clearBookmarks()
+      canvas.getContext('2d', {colorSpace:'srgb'})
+      canvas.width = width
+      if (canvas.height != height) canvas.height = height
+      if (idle.length < 4) idle.push(canvas)
+    }
+
+    let result
+    try{
+      result = callback(canvas, canvas.getContext('2d'))
+    }catch(err){
+      release()
+      throw err
+    }
 
-  constructor(width, height, density){
+    if (result && typeof result.then == 'function'){
+      return result.then(val => { release(); return val }, err => { release(); throw err })
+    }
+    release()
+    return result
+  }
+
+  constructor(width, height, options){
+    let {density, gpu='cpu', device=0, fallback=true, colorSpace='srgb', pixelFormat='rgba8', fontLibrary=FontLibrary.shared} = typeof options == 'object' ? options || {} : {density:options}
+    if (!(fontLibrary instanceof FontLibrary)) throw new TypeError("Expected a FontLibrary (e.g., one returned by FontLibrary.create())")
     super(width, height, density)
-    let ctx = new CanvasRenderingContext2D(width * this.density, height * this.density, FontLibrary.shared)
+    Canvas.fonts.set(this, fontLibrary)
+    let ctx = new CanvasRenderingContext2D(width * this.density, height * this.density, fontLibrary)
+    $(ctx, 'setColorSpace', toString(colorSpace))
+    $(ctx, 'setPixelFormat', toString(pixelFormat))
     Canvas.parent.set(ctx, this)
     Canvas.context.set(this, [ctx])
+    Canvas.engine.set(this, {...Canvas.selectEngine(gpu, device, fallback), pictureBudgetMB:null, flatten:'auto'})
+  }
+
+  static selectEngine(gpu, device, fallback){
+    // the native module is currently built without any of skia’s gpu backends, so requests for
+    // one fall back to the software rasterizer (or fail if fallback has been disabled)
+    let api = toString(gpu).toLowerCase(),
+        error = !['vulkan', 'metal', 'gl', 'cpu'].includes(api) ? `Unknown gpu api "${gpu}" (expected "vulkan", "metal", "gl", or "cpu")`
+              : api != 'cpu' ? `skia-canvas was built without ${api} support`
+              : device != 0 ? `No ${api} device at index ${device}`
+              : null;
+
+    if (!error) return {api, device:0}
+    if (!fallback) throw new Error(error)
+    return {api:'cpu', device:0, error}
+  }
+
+  get engine(){ return Object.assign({}, Canvas.engine.get(this)) }
+  set engine({pictureBudgetMB, flatten}={}){
+    // the gpu settings are fixed at creation, but the recording's memory policy can be changed at any time
+    let engine = Canvas.engine.get(this),
+        policy = {flatten:engine.flatten, pictureBudgetMB:engine.pictureBudgetMB};
+    if (flatten !== undefined) policy.flatten = toString(flatten)
+    if (pictureBudgetMB !== undefined){
+      if (pictureBudgetMB !== null && !(pictureBudgetMB > 0)) throw new RangeError("pictureBudgetMB must be a positive number (or null)")
+      policy.pictureBudgetMB = pictureBudgetMB
+    }
+    for (const ctx of this._contexts()) Canvas.applyBudget(ctx, policy)
+    Object.assign(engine, policy)
+  }
+
+  static applyBudget(ctx, {flatten, pictureBudgetMB}){
+    $(ctx, 'setFlattening', flatten, pictureBudgetMB)
   }
 
-  getContext(kind){
-    return (kind=="2d") ? Canvas.context.get(this)[0] : null
+  _contexts(){
+    // every page along with any layers that have been attached to them
+    return Canvas.context.get(this).flatMap(page => [page, ...(Canvas.layers.get(page) || []).map(l => l.context)])
   }
 
-  newPage(width, height){
-    let ctx = new CanvasRenderingContext2D(width * this.density, height * this.density, FontLibrary.shared)
+  static fromPicture(buffer, options){
+    // recreate a canvas (sized to the picture's bounds) from a serialized .skp scene
+    if (typeof buffer == 'string') buffer = fs.readFileSync(buffer)
+    if (!Buffer.isBuffer(buffer)) throw new TypeError("Expected a Buffer or path to a .skp file")
+    let canvas = new Canvas(1, 1, options)
+    $(canvas, 'loadPicture', buffer)
+    return canvas
+  }
+
+  static compare(a, b, {threshold=0.1, antialiasTolerance=1}={}){
+    // diff two renderings (encoded image Buffers/paths, Images, or Canvases) pixel by pixel
+    let load = src => typeof src == 'string' ? fs.readFileSync(src) : src,
+        diff = new Image(),
+        stats = native.compare(load(a), load(b), +threshold, +antialiasTolerance, diff);
+    return {diff, ...stats}
+  }
+
+  getContext(kind, {colorSpace, antialias}={}){
+    if (kind != "2d") return null
+    let ctx = Canvas.context.get(this)[0]
+    if (colorSpace !== undefined) $(ctx, 'setColorSpace', toString(colorSpace))
+    if (antialias !== undefined) ctx.antialias = antialias === false ? 'none' : antialias === true ? 'grayscale' : toString(antialias)
+    return ctx
+  }
+
+  get colorSpace(){ return this.getContext('2d').getContextAttributes().colorSpace }
+
+  get pixelFormat(){ return this.getContext('2d').getContextAttributes().pixelFormat }
+
+  // resizing clears the current page (along with any bookmarks pointing to it)
+  get width(){ return super.width }
+  set width(width){
+    super.width = width
+    this._clearBookmarks(this.pages.length - 1)
+  }
+
+  get height(){ return super.height }
+  set height(height){
+    super.height = height
+    this._clearBookmarks(this.pages.length - 1)
+  }
+
+  get fontLibrary(){ return Canvas.fonts.get(this) }
+
+  newPage(width=this.width, height=this.height){
+    let ctx = new CanvasRenderingContext2D(width * this.density, height * this.density, this.fontLibrary)
+    $(ctx, 'setColorSpace', this.colorSpace)
+    $(ctx, 'setPixelFormat', this.pixelFormat)
+    Canvas.applyBudget(ctx, Canvas.engine.get(this))
     Canvas.parent.set(ctx, this)
     Canvas.context.get(this).unshift(ctx)
     Object.assign(this, {width, height})
@@ -145,12 +378,107 @@ class Canvas extends RustClass(native.Canvas){
     return Canvas.context.get(this).slice().reverse()
   }
 
+  createLayer(name, {zIndex=1}={}){
+    // add a separate context to the current page whose contents get composited with it at export time
+    name = toString(name)
+    zIndex = +zIndex
+    if (!isFinite(zIndex)) throw new TypeError("Expected a numeric zIndex")
+
+    let page = this.getContext('2d'),
+        layers = Canvas.layers.get(page) || [];
+    if (layers.some(layer => layer.name == name)) throw new Error(`A layer named "${name}" already exists`)
+
+    let ctx = new CanvasRenderingContext2D(this.width * this.density, this.height * this.density, this.fontLibrary)
+    $(ctx, 'setColorSpace', this.colorSpace)
+    $(ctx, 'setPixelFormat', this.pixelFormat)
+    Canvas.applyBudget(ctx, Canvas.engine.get(this))
+    Canvas.parent.set(ctx, this)
+
+    // layers sharing a zIndex are stacked in creation order
+    let idx = layers.findIndex(layer => layer.zIndex > zIndex)
+    layers.splice(idx < 0 ? layers.length : idx, 0, {name, zIndex, context:ctx})
+    Canvas.layers.set(page, layers)
+    return ctx
+  }
+
+  removeLayer(name){
+    let page = this.getContext('2d'),
+        layers = Canvas.layers.get(page) || [],
+        idx = layers.findIndex(layer => layer.name == toString(name));
+    if (idx >= 0) layers.splice(idx, 1)
+    return idx >= 0
+  }
+
+  get layers(){
+    let page = this.getContext('2d')
+    return (Canvas.layers.get(page) || []).map(({name, zIndex, context}) => ({name, zIndex, context}))
+  }
+
+  animate(fps, callback){
+    if (typeof fps == 'function') [fps, callback] = [60, fps]
+    if (!(fps > 0)) throw new RangeError("Frame rate must be greater than zero")
+
+    let ctx = this.getContext('2d'),
+        interval = 1000 / fps,
+        start = Date.now(),
+        anim = {frame:0, dropped:0, last:start, timer:null, running:true},
+        done = {};
+
+    let finished = new Promise((resolve, reject) => Object.assign(done, {resolve, reject})),
+        halt = err => {
+          if (!anim.running) return
+          anim.running = false
+          clearTimeout(anim.timer)
+          $(this, 'discard')
+          if (err) done.reject(err)
+          else done.resolve(anim.frame)
+        };
+
+    const tick = () => {
+      let now = Date.now(),
+          info = {frame:anim.frame, time:now - start, delta:now - anim.last, dropped:anim.dropped};
+      anim.last = now
+
+      Promise.resolve().then(() => callback(ctx, info)).then(result => {
+        if (!anim.running) return
+
+        // publish the completed frame to the front buffer so displays never show a partial one
+        $(this, 'present')
+        if (result === false) return halt()
+
+        // schedule the next frame on the pacing grid, skipping any deadlines missed by a slow callback
+        let next = Math.max(anim.frame + 1, Math.ceil((Date.now() - start) / interval))
+        anim.dropped += next - anim.frame - 1
+        anim.frame = next
+        anim.timer = setTimeout(tick, Math.max(0, start + next * interval - Date.now()))
+      }).catch(halt)
+    }
+
+    tick()
+    return {stop:() => halt(), finished, get frame(){ return anim.frame }, get dropped(){ return anim.dropped }}
+  }
+
   get png(){ return this.toBuffer("png") }
   get jpg(){ return this.toBuffer("jpg") }
   get pdf(){ return this.toBuffer("pdf") }
   get svg(){ return this.toBuffer("svg") }
 
-  saveAs(filename, {format, quality=100}={}){
+  saveAs(filename, options){
+    for (const [path, opts] of this._outputs(filename, options)){
+      fs.writeFileSync(path, this._toBuffer(opts))
+    }
+  }
+
+  async saveAsAsync(filename, options){
+    await Promise.all(this._outputs(filename, options).map(async ([path, opts]) =>
+      fs.promises.writeFile(path, await this._toBufferAsync(opts))
+    ))
+  }
+
+  _outputs(filename, {format, ...options}={}){
+    // expand the filename into a list of [path, export options] pairs: one per page if it contains a "{}"
+    // template (with optional padding width) or else a single file with either the full document (for
+    // pdfs) or the most recent page
     var seq
     filename = filename.replace(/{(\d*)}/g, (_, pad) => {
       pad = parseInt(pad, 10)
@@ -158,21 +486,65 @@ class Canvas extends RustClass(native.Canvas){
       return "{}"
     })
 
-    let ext = format || extname(filename),
-        fmt = toFormat(ext);
-    if (!fmt){
-      throw new Error(`Unsupported file format "${ext}" (expected "png", "jpg", "pdf", or "svg")`)
+    let opts = this._exportOptions(format || extname(filename), options)
+    if (seq === undefined) return [[filename, opts]]
+
+    let pp = this.pages.length,
+        digits = seq == -1 ? String(pp).length : seq;
+    return this.pages.map((_, i) => [
+      filename.replace('{}', String(i + 1).padStart(digits, '0')), {...opts, idx:pp - 1 - i}
+    ])
+  }
+
+  addBookmark(title, {page, y=0, parent}={}){
+    let pp = this.pages.length,
+        idx = page === undefined ? pp - 1
+            : page >= 0 ? page - 1
+            : pp + page,
+        marks = Canvas.bookmarks.get(this) || [];
+
+    if (!(idx >= 0 && idx < pp)){
+      throw new RangeError(
+        pp == 1 ? `Canvas only has a ‘page 1’ (${page} is out of bounds)`
+                : `Canvas has pages 1–${pp} (${page} is out of bounds)`
+      )
+    }else if (!Number.isFinite(y)){
+      throw new TypeError("Expected a finite y offset")
+    }else if (parent !== undefined && !marks.includes(parent)){
+      throw new TypeError("Expected parent to be a bookmark returned by this canvas’s addBookmark()")
     }
-    $(this, "saveAs", filename, seq, fmt, quality)
+
+    // skia records the page & position as a named destination that the outline entry can refer to
+    let mark = Object.freeze({title:toString(title), page:idx + 1, y, parent}),
+        dest = `bookmark-${++Canvas.destinationCount}`;
+    $(this.pages[idx], 'addDestination', dest, 0, y * this.density)
+    Canvas.destinations.set(mark, dest)
+    Canvas.bookmarks.set(this, [...marks, mark])
+    return mark
+  }
+
+  _clearBookmarks(pageIdx){
+    // bookmarks are discarded along with the content of the page they point to
+    let marks = (Canvas.bookmarks.get(this) || []).filter(m => pageIdx !== undefined && m.page - 1 != pageIdx)
+    Canvas.bookmarks.set(this, marks)
+  }
+
+  _outline(pageIdx){
+    // arrange the bookmarks into a tree (limited to a single page if one is specified)
+    let marks = (Canvas.bookmarks.get(this) || []).filter(m => pageIdx === undefined || m.page - 1 == pageIdx),
+        tree = parent => marks.filter(m => m.parent === parent || (parent === undefined && !marks.includes(m.parent)))
+          .map(m => ({title:m.title, dest:Canvas.destinations.get(m), children:tree(m)}));
+    return tree(undefined)
   }
 
-  toBuffer(extension, {format="png", quality=100, page}={}){
-    ({format, quality, page} = Object.assign(
-      {format, quality, page},
+  _exportOptions(extension, options={}){
+    // validate the options shared by all the export methods and convert them to the native module's units
+    let {format="png", quality=100, page, left, top, width, height, trim=false, padding=0, density=this.density, matte,
+         outlineText=false, metadata, pdfa=false, profile, intent="perceptual"} = Object.assign({}, options,
       typeof extension == 'string' ? {format:extension}
     : typeof extension == 'object' ? extension
     : {}
-    ));
+    );
 
     let fmt = toFormat(format),
         pp = this.pages.length,
@@ -181,29 +553,181 @@ class Canvas extends RustClass(native.Canvas){
             : undefined
 
     if (!fmt){
-      throw new Error(`Unsupported file format "${format}" (expected "png", "jpg", "pdf", or "svg")`)
+      throw new Error(`Unsupported file format "${format}" (expected "png", "jpg", "pdf", "svg", "tiff", or "skp")`)
     }else if (isFinite(idx) && idx < 0 || idx >= pp){
       throw new RangeError(
         pp == 1 ? `Canvas only has a ‘page 1’ (${page} is out of bounds)`
                 : `Canvas has pages 1–${pp} (${page} is out of bounds)`
       )
+    }else if (!(density > 0 && isFinite(density))){
+      throw new RangeError("Expected density to be a positive number")
     }
 
-    return $(this, "toBuffer", fmt, quality, idx)
+    // vector formats are always generated at the canvas’s own density
+    if (!isRaster(fmt)) density = this.density
+
+    // trimming crops the page to the geometric bounds of the recorded draws (plus any padding)
+    if (trim){
+      if ([left, top, width, height].some(n => n !== undefined)){
+        throw new TypeError("The `trim` option can’t be combined with `left`, `top`, `width`, or `height`")
+      }else if (!isFinite(padding)){
+        throw new TypeError("Expected padding to be a finite number")
+      }
+      let bounds = $(this, "contentBounds", idx)
+      if (!bounds) throw new RangeError("Can’t trim a page that has nothing drawn on it")
+      let [x, y, w, h] = bounds
+      left = x - padding
+      top = y - padding
+      width = w + 2 * padding
+      height = h + 2 * padding
+    }
+
+    let crop
+    if ([left, top, width, height].some(n => n !== undefined)){
+      left = left || 0
+      top = top || 0
+      crop = [left, top, width ?? this.width - left, height ?? this.height - top]
+      if (!crop.every(isFinite)){
+        throw new TypeError("Crop dimensions must be finite numbers")
+      }else if (crop[2] <= 0 || crop[3] <= 0){
+        throw new RangeError(`Crop width & height must be greater than zero (got ${crop[2]}×${crop[3]})`)
+      }
+      crop = crop.map(n => Math.round(n * density))
+    }
+
+    // mattes can be a solid color or a checkerboard (whose squares are sized in canvas units)
+    if (matte == 'checkerboard' || (matte && typeof matte == 'object')){
+      let {size=8, colors=['white', 'silver']} = typeof matte == 'object' ? matte : {}
+      matte = [size * density, ...[colors].flat().map(toString)]
+    }else if (matte !== undefined){
+      matte = toString(matte)
+    }
+
+    checkOutlines(fmt, outlineText)
+    if (typeof profile == 'string') profile = fs.readFileSync(profile)
+    if (profile !== undefined && !Buffer.isBuffer(profile)) throw new TypeError("Expected an ICC `profile` as a Buffer or file path")
+
+    return {fmt, quality, idx, crop, density, scale:density / this.density, matte,
+            outlineText:!!outlineText, metadata, pdfa:!!pdfa, profile, intent:toString(intent)}
   }
 
-  toDataURL(extension, {format="png", quality=100, page}={}){
-    ({format, quality, page} = Object.assign(
-      {format, quality, page},
-      typeof extension == 'string' ? {format:extension}
-    : typeof extension == 'object' ? extension
-    : {}
-    ));
+  toBuffer(extension, options){
+    return this._toBuffer(this._exportOptions(extension, options))
+  }
+
+  async toBufferAsync(extension, options){
+    return this._toBufferAsync(this._exportOptions(extension, options))
+  }
+
+  _toBuffer({fmt, quality, idx, crop, density, scale, matte, outlineText, metadata, pdfa, profile, intent}){
+    if (fmt == 'tiff'){
+      // tiffs are sized to match pdf output and are either converted to cmyk using the destination's icc
+      // profile or written as rgba at the page's bit depth
+      return $(this, "toTIFF", idx, crop, scale, matte, profile, intent, 72 * density)
+    }
+
+    let buffer = $(this, "toBuffer", fmt, quality, idx, crop, scale, matte, outlineText,
+      pdfMetadata(metadata), pdfa, pdfDates(metadata, pdfa))
+
+    let pp = this.pages.length,
+        outline = fmt == 'pdf' ? this._outline(idx === undefined ? undefined : pp - 1 - idx) : []
+    return outline.length ? addOutline(buffer, outline) : buffer
+  }
+
+  _toBufferAsync(opts){
+    // bitmaps are compressed on a background thread while other formats are generated immediately
+    let {fmt, quality, idx, crop, scale, matte} = opts
+    return !isBitmap(fmt) ? Promise.resolve().then(() => this._toBuffer(opts))
+      : new Promise((res, rej) => $(this, "encode", fmt, quality, idx, crop, scale, matte,
+          (err, buffer) => err ? rej(err) : res(buffer)
+        ))
+  }
+
+  renderInto(buffer, {format="rgba", premultiplied=false, rowBytes, page}={}){
+    let pp = this.pages.length,
+        idx = page >= 0 ? pp - page
+            : page < 0 ? pp + page
+            : 0,
+        view = ArrayBuffer.isView(buffer) ? new Uint8Array(buffer.buffer, buffer.byteOffset, buffer.byteLength)
+             : buffer instanceof ArrayBuffer || buffer instanceof SharedArrayBuffer ? new Uint8Array(buffer)
+             : null;
+
+    if (!view){
+      throw new TypeError("Expected an ArrayBuffer, SharedArrayBuffer, or typed array to render into")
+    }else if (idx < 0 || idx >= pp){
+      throw new RangeError(
+        pp == 1 ? `Canvas only has a ‘page 1’ (${page} is out of bounds)`
+                : `Canvas has pages 1–${pp} (${page} is out of bounds)`
+      )
+    }
 
+    $(this, "renderInto", view, format, !!premultiplied, rowBytes, idx)
+    return buffer
+  }
+
+  record(options={}){
+    return new Recorder(this, options)
+  }
+
+  toDataURL(extension, options){
+    let opts = this._exportOptions(extension, options)
+    return toDataURL(opts.fmt, this._toBuffer(opts))
+  }
+
+  async toDataURLAsync(extension, options){
+    let opts = this._exportOptions(extension, options)
+    return toDataURL(opts.fmt, await this._toBufferAsync(opts))
+  }
+
+  async exportTiles({tileSize=256, zoomLevels=0, dir, callback, format="png", quality=100, page, scheme="xyz"}={}){
     let fmt = toFormat(format),
-        mime = toMime(fmt),
-        buffer = this.toBuffer({format, quality, page});
-    return `data:${mime};base64,${buffer.toString('base64')}`
+        levels = Array.isArray(zoomLevels) ? zoomLevels : [...Array(zoomLevels + 1).keys()];
+
+    if (!['png', 'jpg', 'webp'].includes(fmt)){
+      throw new Error(`Unsupported tile format "${format}" (expected "png", "jpg", or "webp")`)
+    }else if (!['xyz', 'tms'].includes(scheme)){
+      throw new TypeError(`Expected scheme to be "xyz" or "tms" (got ${JSON.stringify(scheme)})`)
+    }else if (!(tileSize > 0) || !levels.every(z => Number.isInteger(z) && z >= 0)){
+      throw new RangeError("Expected a positive tileSize and non-negative integer zoomLevels")
+    }else if (dir === undefined && typeof callback != 'function'){
+      throw new TypeError("Expected either a `dir` to write tiles into or a `callback` to receive them")
+    }
+
+    // at each zoom level the page is scaled so its longer side spans 2^z tiles, skipping any
+    // tiles that fall entirely beyond its shorter side
+    let width = this.width * this.density,
+        height = this.height * this.density,
+        tiles = []
+    for (let z of levels){
+      let count = 2 ** z,
+          scale = tileSize * count / Math.max(width, height)
+      for (let x=0; x < Math.ceil(width * scale / tileSize); x++){
+        for (let y=0; y < Math.ceil(height * scale / tileSize); y++){
+          tiles.push({z, x, y:scheme=='tms' ? count - 1 - y : y, spec:[scale, x * tileSize, y * tileSize]})
+        }
+      }
+    }
+
+    // record the scene once then rasterize batches of tiles from it on the thread pool in parallel
+    let skp = this.toBuffer("skp", {page}),
+        batchSize = Math.ceil(tiles.length / os.cpus().length),
+        batches = []
+    for (let i=0; i < tiles.length; i += batchSize){
+      let batch = tiles.slice(i, i + batchSize),
+          specs = batch.flatMap(t => t.spec)
+      batches.push(new Promise((res, rej) => $(this, "renderTiles", skp, fmt, quality, this.colorSpace, tileSize, specs,
+        (err, buffers) => err ? rej(err) : res(batch.map(({z, x, y}, i) => ({z, x, y, buffer:buffers[i]})))
+      )))
+    }
+
+    for (let {z, x, y, buffer} of (await Promise.all(batches)).flat()){
+      if (dir !== undefined){
+        fs.mkdirSync(join(dir, `${z}`, `${x}`), {recursive:true})
+        fs.writeFileSync(join(dir, `${z}`, `${x}`, `${y}.${fmt}`), buffer)
+      }
+      if (callback) await callback(buffer, {z, x, y})
+    }
+    return tiles.length
   }
 
   [REPR](depth, options) {
@@ -212,42 +736,444 @@ class Canvas extends RustClass(native.Canvas){
   }
 }
 
+class OffscreenCanvas extends Canvas{
+  transferToImageBitmap(){
+    let bitmap = new Image()
+    $(bitmap, 'transfer', this)
+    return bitmap
+  }
+
+  [REPR](depth, options) {
+    let {width, height} = this
+    return `OffscreenCanvas ${inspect({width, height}, options)}`
+  }
+}
+
+class Recorder{
+  static codecs = {
+    '.mp4': ['-c:v', 'libx264', '-pix_fmt', 'yuv420p'],
+    '.mov': ['-c:v', 'libx264', '-pix_fmt', 'yuv420p'],
+    '.webm': ['-c:v', 'libvpx-vp9', '-pix_fmt', 'yuva420p'],
+    '.gif': [],
+  }
+
+  constructor(canvas, {fps=30, codec, output, ffmpeg='ffmpeg', args=[]}={}){
+    if (!(fps > 0)) throw new RangeError("Frame rate must be greater than zero")
+    let width = Math.floor(canvas.width * canvas.density),
+        height = Math.floor(canvas.height * canvas.density);
+    Object.assign(this, {canvas, fps, width, height, frames:0})
+
+    if (output && typeof output.write == 'function'){
+      // hand the raw rgba frames to the caller’s stream rather than an encoder
+      this.stream = output
+    }else if (typeof output == 'string'){
+      let codecArgs = codec ? ['-c:v', codec] : Recorder.codecs[extname(output).toLowerCase()]
+      if (!codecArgs){
+        throw new Error(`Unsupported video format "${extname(output)}" (expected "mp4", "mov", "webm", or "gif" unless a codec is specified)`)
+      }
+
+      this.process = spawn(ffmpeg, [
+        '-y', '-f', 'rawvideo', '-pix_fmt', 'rgba', '-s', `${width}x${height}`, '-r', `${fps}`, '-i', '-',
+        ...codecArgs, ...args, output
+      ], {stdio:['pipe', 'ignore', 'pipe']})
+      this.stream = this.process.stdin
+      this.stream.on('error', () => {}) // a broken pipe is reported via the exit status instead
+
+      let log = []
+      this.process.stderr.on('data', chunk => log.push(chunk))
+      this.exited = new Promise((resolve, reject) => {
+        this.process.on('error', reject)
+        this.process.on('close', code => code === 0 ? resolve()
+          : reject(new Error(`ffmpeg exited with code ${code}:\n${Buffer.concat(log).toString().trim()}`)))
+      })
+      this.exited.catch(() => {}) // surface errors from addFrame() & finish() rather than as unhandled rejections
+    }else{
+      throw new TypeError("Expected an output filename or a writable stream")
+    }
+  }
+
+  addFrame(){
+    if (!this.stream) throw new Error("Recording has already finished")
+    let frame = Buffer.alloc(this.width * this.height * 4)
+    this.canvas.renderInto(frame, {format:'rgba'})
+
+    // wait for the encoder to catch up whenever its input buffer fills
+    let stream = this.stream,
+        count = ++this.frames;
+    return new Promise((resolve, reject) => {
+      if (this.exited) this.exited.catch(reject)
+      if (stream.write(frame)) resolve(count)
+      else stream.once('drain', () => resolve(count))
+    })
+  }
+
+  async finish(){
+    if (!this.stream) return this.frames
+    let stream = this.stream
+    this.stream = null
+    if (this.process){
+      stream.end()
+      await this.exited
+    }
+    return this.frames
+  }
+
+  [REPR](depth, options) {
+    let {fps, width, height, frames} = this
+    return `Recorder ${inspect({fps, width, height, frames}, options)}`
+  }
+}
+
 class CanvasRenderingContext2D extends RustClass(native.CanvasRenderingContext2D){
+  static hyphenator = new WeakMap()
+
+  // the drawing-state properties captured by getState() (`font` comes first since setting it resets the variant)
+  static stateProperties = [
+    'font', 'fontVariant', 'fontFeatures', 'fontFallbacks', 'fontHinting', 'textAlign', 'textBaseline',
+    'direction', 'textDecoration', 'textBackground', 'textIndent', 'textOrientation', 'textRendering', 'textTracking',
+    'paintOrder', 'textWrap', 'textOverflow', 'maxLines', 'writingMode', 'lineBreak', 'lineHeight', 'tabSize', 'tabStops',
+    'fillStyle', 'strokeStyle', 'lineWidth', 'lineCap', 'lineJoin', 'miterLimit', 'lineDashOffset', 'lineAlignment',
+    'strokePrecision', 'strokeWidthIsAbsolute', 'dashesIgnoreTransform', 'pathEffect',
+    'globalAlpha', 'globalCompositeOperation', 'imageSmoothingEnabled', 'imageSmoothingQuality',
+    'snapToPixel', 'antialias', 'dither', 'filter', 'backdropFilter', 'shadowBlur', 'shadowColor',
+    'shadowOffsetX', 'shadowOffsetY', 'shadowSpread', 'shadowInset', 'shadowsIgnoreTransform', 'renderPass',
+  ]
+
   get canvas(){ return Canvas.parent.get(this) }
 
+  getContextAttributes(){
+    return {alpha:true, antialias:this.antialias, colorSpace:$(this, 'getColorSpace'),
+            pixelFormat:$(this, 'getPixelFormat'), desynchronized:false, willReadFrequently:false}
+  }
+
   get currentTransform(){ return fromSkMatrix( $(this, 'get_currentTransform') ) }
   set currentTransform(matrix){  $(this, 'set_currentTransform', toSkMatrix(matrix) ) }
   getTransform(){ return this.currentTransform }
   setTransform(matrix){
     this.currentTransform = arguments.length > 1 ? [...arguments] : matrix
   }
+  setTransform3x3(terms){ $(this, 'set_currentTransform', [...terms]) }
+  concat44(matrix){
+    // the context's transform is 3x3, so the product is flattened by toSkMatrix (dropping the
+    // third row & column) and any depth it introduced won't affect later 3D operations
+    let m44 = matrix instanceof DOMMatrix ? matrix : new DOMMatrix(matrix)
+    this.currentTransform = this.currentTransform.multiply(m44)
+  }
+  rotateX(angle, perspective){ this.concat44(rotation3d(1, 0, angle, perspective)) }
+  rotateY(angle, perspective){ this.concat44(rotation3d(0, 1, angle, perspective)) }
 
   get font(){ return $(this, 'get_font') }
   set font(str){ $(this, 'set_font', parseFont(str)) }
   get fontVariant(){ return $(this, 'get_fontVariant') }
   set fontVariant(str){ $(this, 'set_fontVariant', parseVariant(str)) }
+  get fontFeatures(){ return $(this, 'get_fontFeatures') }
+  set fontFeatures(features){
+    if (features === null) features = {}
+    if (typeof features == 'object'){
+      features = Object.fromEntries(Object.entries(features)
+        .map(([feat, val]) => [feat, typeof val=='boolean' ? +val : parseInt(val, 10)])
+        .filter(([feat, val]) => isFinite(val)))
+      $(this, 'set_fontFeatures', features)
+    }
+  }
+  get textDecoration(){ return $(this, 'get_textDecoration') }
+  set textDecoration(str){
+    let decoration = parseDecoration(str)
+    if (decoration) $(this, 'set_textDecoration', decoration)
+  }
+
+  get textBackground(){
+    let spec = $(this, 'getTextBackground')
+    if (!spec) return null
+    let [color, padY, padX, radius] = spec
+    return {color, padding:padX == padY ? padX : [padY, padX], radius}
+  }
+  set textBackground(spec){
+    if (!spec || spec == 'none') return $(this, 'setTextBackground', null)
+    let {color, padding=0, radius=0} = typeof spec == 'object' ? spec : {color:spec},
+        [padY, padX=padY] = [padding].flat().map(Number);
+    $(this, 'setTextBackground', toString(color), padY, padX, +radius)
+  }
+
+  get lineHeight(){ return $(this, 'get_lineHeight') }
+  set lineHeight(val){
+    let str = toString(val).trim()
+    if (str == 'normal') $(this, 'set_lineHeight', 'normal')
+    else if (isFinite(str) && str !== '') $(this, 'set_lineHeight', 'scale', parseFloat(str))
+    else if (/(r?em|%)$/.test(str)) $(this, 'set_lineHeight', 'scale', parseSize(str, 1))
+    else $(this, 'set_lineHeight', 'px', parseSize(str))
+  }
+  get tabSize(){ return $(this, 'get_tabSize') }
+  set tabSize(val){
+    let str = toString(val).trim()
+    if (isFinite(str) && str !== '') $(this, 'set_tabSize', 'spaces', parseFloat(str))
+    else $(this, 'set_tabSize', 'px', parseSize(str))
+  }
+  get tabStops(){ return $(this, 'get_tabStops') }
+  set tabStops(stops){
+    stops = stops == null ? [] : [stops].flat().map(stop => typeof stop=='string' ? parseSize(stop) : stop)
+    $(this, 'set_tabStops', stops)
+  }
+  get textIndent(){ return $(this, 'get_textIndent') }
+  set textIndent(val){ $(this, 'set_textIndent', typeof val=='string' ? parseSize(val) : val) }
+
+  get lineBreak(){ return $(this, 'get_lineBreak') }
+  set lineBreak(mode){ $(this, 'set_lineBreak', toString(mode)) }
+  get hyphenator(){ return CanvasRenderingContext2D.hyphenator.get(this) || null }
+  set hyphenator(fn){
+    if (typeof fn == 'function') CanvasRenderingContext2D.hyphenator.set(this, fn)
+    else if (fn === null) CanvasRenderingContext2D.hyphenator.delete(this)
+  }
+  _breakText(text){
+    return this.textWrap ? breakText(toString(text), this.lineBreak, this.hyphenator)
+                         : {text: toString(text), added: []}
+  }
+
+  fillTextSpans(spans, ...args){
+    spans = [spans].flat().map(({text='', font, fillStyle, underline}) => ({
+      text: this._breakText(text).text,
+      font: font ? parseFont(font) || null : null,
+      fillStyle, underline: !!underline
+    }))
+    $(this, 'fillTextSpans', spans, ...args)
+  }
 
   measureText(text, ...args){
-    let [metrics, ...lines] = $(this, 'measureText', toString(text), ...args)
-    return new TextMetrics(metrics, lines)
+    let {text:broken, added} = this._breakText(text),
+        [metrics, ...lines] = $(this, 'measureText', broken, ...args),
+        fallbacks = $(this, 'textFallbacks', toString(text)),
+        unbreak = (idx, unit=0) => idx - added.filter(pos => pos[unit] < idx).length,
+        inserted = new Set(added.map(([_, pos]) => pos));
+    lines = lines.map(([x, y, width, height, baseline, start, end, ...glyphs]) => [
+      x, y, width, height, baseline, unbreak(start), unbreak(end),
+      ...Array.from({length:glyphs.length / 3}, (_, i) => glyphs.slice(i * 3, i * 3 + 3))
+             .filter(([x, advance, index]) => !inserted.has(index))
+             .flatMap(([x, advance, index]) => [x, advance, unbreak(index, 1)])
+    ])
+    return new TextMetrics(metrics, lines, fallbacks, toString(text))
+  }
+
+  layoutText(text, width){
+    // position each grapheme relative to the point that fillText() would have been called with
+    let metrics = this.measureText(text, width),
+        offset = -metrics.alphabeticBaseline,
+        glyphs = [], positions = [];
+    for (const {baseline, graphemes} of metrics.lines){
+      for (const {text, index, x, advance} of graphemes){
+        glyphs.push({text, index, advance})
+        positions.push({x, y:baseline + offset, rotation:0})
+      }
+    }
+    return {glyphs, positions}
+  }
+
+  drawCanvas(canvas, x, y, {live=false}={}){
+    if (!(canvas instanceof Canvas)) throw new TypeError("Expected a Canvas argument")
+    if (live) $(this, 'drawCanvas', canvas, x, y)
+    else this.drawImage(canvas, x, y)
+  }
+
+  linkTo(url, rect){
+    // the region can be any DOMRect-like object or an [x, y, width, height] array (or omitted to use the current path)
+    if (rect === undefined) return $(this, 'linkTo', toString(url))
+    let {x, y, width, height} = Array.isArray(rect) ? {x:rect[0], y:rect[1], width:rect[2], height:rect[3]} : rect || {}
+    if (![x, y, width, height].every(Number.isFinite)) throw new TypeError("Expected a rect with finite x, y, width, and height values")
+    $(this, 'linkTo', toString(url), x, y, width, height)
+  }
+
+  drawGlyphs(glyphs, positions, font){
+    glyphs = [...glyphs].map(g => toString(g && typeof g=='object' ? g.text : g))
+    positions = [...positions].map(pos => Array.isArray(pos) ? [pos[0], pos[1], pos[2] || 0] : [pos.x, pos.y, pos.rotation || 0])
+    if (glyphs.length != positions.length) throw new TypeError("Expected the same number of glyphs and positions")
+    $(this, 'drawGlyphs', glyphs, toFloatBuffer(positions), font ? parseFont(toString(font)) || null : null)
+  }
+
+  drawImageNine(image, center, dest, filterMode){
+    let rect = r => Array.isArray(r) ? r : [r.x, r.y, r.width, r.height]
+    $(this, 'drawImageNine', image, ...rect(center), ...rect(dest), ...(filterMode ? [filterMode] : []))
+  }
+
+  drawImageMesh(image, points, uvs, indices){
+    $(this, 'drawImageMesh', image, toCoords(points), toCoords(uvs), ...(indices ? [[...indices]] : []))
+  }
+
+  drawMesh({positions, texCoords, colors, indices, mode='triangles', blendMode='modulate', shader}={}){
+    if (!positions) throw new TypeError("A mesh requires a list of positions")
+    let floats = pts => pts instanceof Float32Array ? pts : toCoords(pts)
+    $(this, 'drawMesh', toString(mode), toFloatBuffer(floats(positions)),
+      texCoords ? toFloatBuffer(floats(texCoords)) : null,
+      colors ? [...colors] : null,
+      indices ? [...indices] : null,
+      toString(blendMode), shader
+    )
+  }
+
+  fillRects(rects){
+    $(this, 'fillRects', toFloatBuffer(rects))
   }
 
+  drawPoints(mode, coords){
+    $(this, 'drawPoints', toString(mode), toFloatBuffer(coords))
+  }
+
+  drawAtlas(image, xforms, texRects, colors){
+    $(this, 'drawAtlas', image, toFloatBuffer(xforms), toFloatBuffer(texRects), ...(colors ? [[...colors]] : []))
+  }
+
+  history(){
+    return $(this, 'history').map(({op, bounds:[x, y, width, height], paint}) => ({
+      op, bounds:new DOMRect(x, y, width, height), paint
+    }))
+  }
+
+  replay(filter=() => true){
+    let {width, height, density, fontLibrary} = this.canvas,
+        canvas = new Canvas(width, height, {density, fontLibrary}),
+        indices = this.history().flatMap((entry, i) => filter(entry, i) ? [i] : []);
+    $(this, 'replay', canvas, indices)
+    return canvas
+  }
+
+  get pathEffect(){
+    let spec = $(this, 'getPathEffect')
+    if (!spec) return null
+    let [type, ...vals] = spec
+    return type == 'corner' ? {type, radius:vals[0]}
+                            : {type, segLength:vals[0], deviation:vals[1], seed:vals[2]}
+  }
+
+  set pathEffect(spec){
+    if (!spec) return $(this, 'setPathEffect', null)
+    let {type, radius, segLength, deviation=0, seed=0} = spec
+    $(this, 'setPathEffect', toString(type), ...(type == 'corner' ? [radius] : [segLength, deviation, seed]))
+  }
+
+  clipBounds(space='local'){
+    space = toString(space)
+    if (!['local', 'device'].includes(space)) throw new TypeError(`Expected "local" or "device" (got "${space}")`)
+    let [x, y, width, height] = $(this, 'clipBounds', space == 'local')
+    return new DOMRect(x, y, width, height)
+  }
+
+  contentBounds(space='device'){
+    space = toString(space)
+    if (!['local', 'device'].includes(space)) throw new TypeError(`Expected "local" or "device" (got "${space}")`)
+    let [x, y, width, height] = $(this, 'contentBounds', space == 'local')
+    return new DOMRect(x, y, width, height)
+  }
+
+  isRectVisible(...args){
+    let [x, y, width, height] = args.length > 1 ? args : [args[0].x, args[0].y, args[0].width, args[0].height]
+    return $(this, 'isRectVisible', x, y, width, height)
+  }
+
+  localToDevice(...args){
+    let [x, y] = args.length > 1 ? args : [args[0].x, args[0].y]
+    return new DOMPoint(...$(this, 'localToDevice', x, y))
+  }
+
+  deviceToLocal(...args){
+    let [x, y] = args.length > 1 ? args : [args[0].x, args[0].y]
+    return new DOMPoint(...$(this, 'deviceToLocal', x, y))
+  }
+
+  stats(){
+    let [opCount, bytesUsed, pathVerbs, pathPoints, stackDepth] = $(this, 'stats')
+    return {opCount, bytesUsed, pathVerbs, pathPoints, stackDepth}
+  }
+
+  getState(){
+    let state = {}
+    for (const prop of CanvasRenderingContext2D.stateProperties) state[prop] = this[prop]
+    return Object.assign(state, {
+      transform:this.getTransform(), lineDash:this.getLineDash(), lineDashMarker:this.getLineDashMarker()
+    })
+  }
+
+  setState(state){
+    if (!state || typeof state != 'object') throw new TypeError("Expected a state object returned by getState()")
+    for (const prop of CanvasRenderingContext2D.stateProperties){
+      if (state[prop] !== undefined) this[prop] = state[prop]
+    }
+
+    let {transform, lineDash, lineDashMarker} = state
+    if (transform !== undefined) this.setTransform(transform)
+    if (lineDash !== undefined) this.setLineDash(lineDash)
+    if (lineDashMarker !== undefined) this.setLineDashMarker(lineDashMarker && lineDashMarker.path, lineDashMarker || {})
+  }
+
+  getLineDashMarker(){
+    let path = new Path2D(),
+        info = $(this, 'getLineDashMarker', path);
+    if (!info) return null
+    let [spacing, offset, rotate, morph] = info
+    return {path, spacing, offset, rotate, morph}
+  }
+
+  setLineDashMarker(path, {spacing, offset=0, rotate=true, morph=false}={}){
+    $(this, 'setLineDashMarker', path || null, spacing, +offset || 0, !!rotate, !!morph)
+  }
+
+  outlineText(text, ...args){
+    let path = new Path2D()
+    $(this, 'outlineText', path, this._breakText(text).text, ...args)
+    return path
+  }
+
+  textBounds(text, start=0, end=Infinity, ...args){
+    let {text:broken, added} = this._breakText(text),
+        rebreak = idx => idx + added.filter(([_, pos], i) => pos - i < idx).length;
+    start = Math.max(0, Math.floor(start) || 0)
+    end = Math.min(toString(text).length, Math.floor(end))
+    return $(this, 'textBounds', broken, rebreak(start), rebreak(end), ...args)
+      .map(([x, y, width, height]) => new DOMRect(x, y, width, height))
+  }
+
+  get fontFallbacks(){ return $(this, 'get_fontFallbacks') }
+  set fontFallbacks(list){ $(this, 'set_fontFallbacks', [list || []].flat().map(toString)) }
+
   fillText(text, ...args){
-    $(this, 'fillText', toString(text), ...args)
+    $(this, 'fillText', this._breakText(text).text, ...args)
   }
 
   strokeText(text, ...args){
-    $(this, 'strokeText', toString(text), ...args)
+    $(this, 'strokeText', this._breakText(text).text, ...args)
+  }
+
+  fillAndStrokeText(text, ...args){
+    $(this, 'fillAndStrokeText', this._breakText(text).text, ...args)
   }
 
   get filter(){ return $(this, 'get_filter') }
-  set filter(str){ $(this, 'set_filter', parseFilter(str)) }
+  set filter(str){ $(this, 'set_filter', str instanceof CanvasShader ? str : parseFilter(str)) }
+  get backdropFilter(){ return $(this, 'get_backdropFilter') }
+  set backdropFilter(str){ $(this, 'set_backdropFilter', parseFilter(str)) }
+  colorMatrix(matrix){
+    let values = [...arguments].flat(2),
+        chain = typeof this.filter == 'string' && this.filter != 'none' ? this.filter : '',
+        prior = chain.match(/color-matrix\((.*?)\)/);
+    if (values.length != 20 || !values.every(isFinite)) throw new TypeError("colorMatrix() requires a 5×4 matrix (20 numbers)")
+
+    // the filter can only hold one color-matrix entry, so fold the new matrix into any existing one
+    // (treating each as a 5×5 matrix whose last row is [0 0 0 0 1] and applying the prior one first)
+    if (prior){
+      let old = prior[1].trim().split(/\s+/).map(parseFloat)
+      values = values.map((_, i) => {
+        let [row, col] = [Math.floor(i / 5), i % 5]
+        return [0, 1, 2, 3].reduce((sum, k) => sum + values[row * 5 + k] * old[k * 5 + col], col == 4 ? values[row * 5 + 4] : 0)
+      })
+      this.filter = chain.replace(prior[0], `color-matrix(${values.join(' ')})`)
+    }else{
+      this.filter = `${chain} color-matrix(${values.join(' ')})`.trim()
+    }
+  }
   createImageData(width, height){ return new ImageData(width, height) }
   getImageData(...args){ return new ImageData( $(this, 'getImageData', ...args) ) }
 
   createLinearGradient(...args){ return new CanvasGradient("Linear", ...args) }
   createRadialGradient(...args){ return new CanvasGradient("Radial", ...args) }
   createPattern(...args){ return new CanvasPattern(...args) }
+  createShader(sksl, uniforms){ return new CanvasShader(sksl, uniforms) }
 
   [REPR](depth, options) {
     let props = [ "canvas", "currentTransform", "fillStyle", "strokeStyle", "filter", "font", "direction", "textAlign", "textBaseline",
@@ -264,6 +1190,17 @@ class CanvasRenderingContext2D extends RustClass(native.CanvasRenderingContext2D
   }
 }
 
+// tag the entries that drawing calls add to the context's history with the method's name
+for (let op of ["fill", "stroke", "fillRect", "strokeRect", "clearRect", "fillText", "strokeText", "fillAndStrokeText",
+                "fillTextSpans", "drawGlyphs", "drawImage", "drawImageNine", "drawImageMesh", "drawMesh", "drawAtlas", "drawPoints", "fillRects"]){
+  let method = CanvasRenderingContext2D.prototype[op]
+  CanvasRenderingContext2D.prototype[op] = function(...args){
+    let result = method.apply(this, args)
+    $(this, 'labelHistory', op)
+    return result
+  }
+}
+
 class Path2D extends RustClass(native.Path2D){
   addPath(path, matrix){
     if (matrix) $(this, 'addPath', path, toSkMatrix(matrix) )
@@ -271,16 +1208,94 @@ class Path2D extends RustClass(native.Path2D){
   }
 }
 
-class CanvasGradient extends RustClass(native.CanvasGradient){}
+class CanvasGradient extends RustClass(native.CanvasGradient){
+  constructor(kind, ...args){
+    // an optional trailing object selects the space (and hue direction) that colors are blended in
+    let {interpolation, hue, premultiplied} = args.length && typeof args[args.length - 1] == 'object' ? args.pop() || {} : {}
+    super(kind, ...args)
+    if (interpolation !== undefined) this.interpolation = toString(interpolation)
+    if (hue !== undefined) this.hue = toString(hue)
+    if (premultiplied !== undefined) this.premultiplied = !!premultiplied
+  }
+}
 
 class CanvasPattern extends RustClass(native.CanvasPattern){
+  constructor(src, repetition, options){
+    if (src instanceof Path2D){
+      // paths take their paint settings from an options object (which can also specify the repetition)
+      if (repetition && typeof repetition == 'object') [options, repetition] = [repetition, repetition.repetition]
+      let {fillStyle, strokeStyle, lineWidth=1, width, height} = options || {}
+      if (fillStyle === undefined && strokeStyle === undefined) fillStyle = 'black'
+      super(src, repetition ?? 'repeat', fillStyle ?? null, strokeStyle ?? null, lineWidth, width, height)
+    }else{
+      super(...arguments)
+    }
+  }
+
   setTransform(matrix){
     if (arguments.length>1) matrix = [...arguments]
     $(this, 'setTransform', toSkMatrix(matrix) )
   }
 }
 
+class CanvasShader extends RustClass(native.CanvasShader){
+  constructor(sksl, uniforms={}){
+    super(sksl)
+    this.setUniforms(uniforms)
+  }
+
+  setUniform(name, value){
+    $(this, 'setUniform', name, [value].flat(2))
+    return this
+  }
+
+  setUniforms(uniforms){
+    for (let [name, value] of Object.entries(uniforms || {})) this.setUniform(name, value)
+    return this
+  }
+
+  [REPR](depth, options) {
+    let {uniforms} = this
+    return `CanvasShader ${inspect({uniforms}, options)}`
+  }
+}
+
+class TextureGenerator extends RustClass(native.TextureGenerator){
+  noise({type='fractal', frequency=0.05, octaves=1, seed=0, tileable=true}={}){
+    let [fx, fy=fx] = [frequency].flat()
+    return this.generate('noise', type, fx, fy, octaves, seed, !!tileable)
+  }
+
+  checkerboard({size=8, colors=['white', 'silver']}={}){
+    return this.generate('checkerboard', size, ...colors)
+  }
+
+  fill(style){
+    return this.generate('fill', style)
+  }
+
+  generate(kind, ...args){
+    // render the texture into a fresh Image that can be drawn directly or used in a pattern
+    let image = new Image()
+    $(this, kind, image, ...args)
+    return image
+  }
+
+  [REPR](depth, options) {
+    let {width, height} = this
+    return `TextureGenerator ${inspect({width, height}, options)}`
+  }
+}
+
 class Image extends RustClass(native.Image){
+  static decoding = new WeakMap()
+  static modes = new WeakMap()
+
+  get decoding(){ return Image.modes.get(this) || 'auto' }
+  set decoding(mode){
+    if (['sync', 'async', 'auto'].includes(mode)) Image.modes.set(this, mode)
+  }
+
   get src(){ return $(this, "get_src") }
   set src(src){
     var data
@@ -289,35 +1304,54 @@ class Image extends RustClass(native.Image){
     else if (typeof src != 'string') return
     else if (/^\s*data:/.test(src)) {
       // data URI
-      let split = src.indexOf(','),
-          enc = src.lastIndexOf('base64', split) !== -1 ? 'base64' : 'utf8',
-          content = src.slice(split + 1);
-      data = Buffer.from(content, enc);
+      data = fromDataURL(src)
     } else if (/^\s*https?:\/\//.test(src)) {
       // remote URL
-      get.concat(src, (err, res, data) => {
-        let code = res.statusCode,
-            onerror = this.onerror || (() => {}),
-            onload = this.onload || (() => {});
-        if (err) onerror(err)
+      data = new Promise((res, rej) => get.concat(src, (err, resp, data) => {
+        let code = resp && resp.statusCode
+        if (err) rej(err)
         else if (code < 200 || code >= 300) {
-          onerror(new Error(`Failed to load image from "${src}" (error ${code})`))
+          rej(new Error(`Failed to load image from "${src}" (error ${code})`))
         }else{
-          if ($(this, "set_data", data)) onload(this)
-          else onerror(new Error("Could not decode image data"))
+          res(data)
         }
-      })
+      }))
     } else {
       // local file path
       data = fs.readFileSync(src);
     }
 
-    $(this, "set_src", src)
-    if (data){
-      let onerror = this.onerror || (() => {}), onload = this.onload || (() => {});
-      if ($(this, "set_data", data)) onload(this)
-      else onerror(new Error("Could not decode image data"))
+    $(this, "set_src", typeof src=='string' ? src : '')
+
+    // local data is decoded immediately unless the `decoding` attribute opts into background decoding
+    if (Buffer.isBuffer(data) && this.decoding != 'async'){
+      let error = null
+      try{ $(this, "decodeSync", data) }
+      catch(err){ error = err }
+
+      let decoding = error ? Promise.reject(error) : Promise.resolve()
+      decoding.catch(() => {})
+      Image.decoding.set(this, decoding)
+      if (error) (this.onerror || (() => {}))(error)
+      else (this.onload || (() => {}))(this)
+      return
     }
+
+    // otherwise decode the pixels on the thread pool, ignoring the results if src changes in the meantime
+    let current = () => Image.decoding.get(this) === decoding,
+        decoding = Promise.resolve(data)
+          .then(data => new Promise((res, rej) => $(this, "decode", data, (err, decoded) => err ? rej(err) : res(decoded))))
+          .then(decoded => { if (current()) $(this, "adopt", decoded) });
+    Image.decoding.set(this, decoding)
+
+    decoding.then(
+      () => current() && (this.onload || (() => {}))(this),
+      err => current() && (this.onerror || (() => {}))(err)
+    )
+  }
+
+  decode(){
+    return Image.decoding.get(this) || Promise.reject(new Error("Image has no src to decode"))
   }
 
   [REPR](depth, options) {
@@ -328,40 +1362,87 @@ class Image extends RustClass(native.Image){
 }
 
 class ImageData extends RustClass(native.ImageData){
-  constructor(width, height){
+  constructor(width, height, {colorType='rgba', premultiplied=false}={}){
     if (arguments[0] instanceof native.ImageData){
-      var {width, height, data} = arguments[0]
+      var {width, height, data, colorType, premultiplied} = arguments[0]
     }
-    super(width, height)
-    let bytes = (this.width * this.height * 4)
-    readOnly(this, "data", new Uint8ClampedArray(data && data.buffer || bytes))
+    super(width, height, colorType, !!premultiplied)
+    // high-bit-depth pixels are exposed as one array element per channel
+    let bytes = (this.width * this.height * $(this, 'bytesPerPixel')),
+        Channels = {rgba16:Uint16Array, rgba32f:Float32Array}[this.colorType] || Uint8ClampedArray,
+        array = data ? new Channels(data.buffer, data.byteOffset, data.byteLength / Channels.BYTES_PER_ELEMENT)
+                     : new Channels(bytes / Channels.BYTES_PER_ELEMENT);
+    readOnly(this, "data", array)
+  }
+
+  resize(width, height, {quality='low'}={}){
+    return new ImageData($(this, 'resize', width, height, quality))
+  }
+
+  convert({colorType=this.colorType, premultiply=this.premultiplied}={}){
+    return new ImageData($(this, 'convert', colorType, !!premultiply))
   }
 
   [REPR](depth, options) {
-    let {width, height, data} = this
-    return `ImageData ${inspect({width, height, data}, options)}`
+    let {width, height, colorType, premultiplied, data} = this
+    return `ImageData ${inspect({width, height, colorType, premultiplied, data}, options)}`
   }
 }
 
-const _expand = paths => [paths].flat(2).map(filename => glob(filename)).flat()
+// font sources can be file paths, globs, data urls, Buffers, or {src, family, weight, style} descriptors
+const _isSource = obj => Buffer.isBuffer(obj) || (!!obj && typeof obj=='object' && 'src' in obj)
+const _expand = paths => [paths].flat(2).map(src =>
+  Buffer.isBuffer(src) ? {data:src, file:'<Buffer>'}
+  : typeof src=='string' && /^\s*data:/.test(src) ? {data:fromDataURL(src), file:'<data URL>'}
+  : _isSource(src) ? _describe(src)
+  : glob(src)
+).flat()
+const _describe = ({src, family, weight, style}) => _expand(src).map(font => ({
+  ...(typeof font=='string' ? {data:fs.readFileSync(font), file:font} : font),
+  ...(family ? {family} : {}),
+  ...(weight ? {weight:+weight} : {}),
+  ...(style ? {style} : {}),
+}))
 
 class FontLibrary extends RustClass(native.FontLibrary){
   static shared = new FontLibrary()
 
+  create({systemFonts=true}={}){
+    // a separate library (initially containing just the system fonts) for canvases that shouldn't share the global one
+    let library = new FontLibrary(true)
+    if (!systemFonts) library.reset({systemFonts})
+    return library
+  }
+
+  listFamilies({withStyles=false}={}){
+    return withStyles ? this.families.map(name => this.family(name)).filter(Boolean) : this.families
+  }
+
+  match(font){
+    let spec = parseFont(toString(font))
+    if (!spec) throw new TypeError(`Could not parse font specification "${font}"`)
+    return $(this, 'match', spec)
+  }
+
+  reset({systemFonts=true}={}){
+    // forget all the fonts added via use() and optionally stop consulting the platform's installed fonts
+    $(this, 'reset', !!systemFonts)
+  }
+
   use(...args){
     let sig = signature(args)
-    if (sig=='o'){
+    if (sig=='o' && !_isSource(args[0])){
       let results = {}
       for (let [alias, paths] of Object.entries(args.shift())){
         results[alias] = $(this, "addFamily", alias, _expand(paths))
       }
       return results
-    }else if (sig.match(/^s?[as]$/)){
+    }else if (sig.match(/^s?[aso]$/)){
       let fonts = _expand(args.pop())
       let alias = args.shift()
       return $(this, "addFamily", alias, fonts)
     }else{
-      throw new Error("Expected an array of file paths or an object mapping family names to font files")
+      throw new Error("Expected an array of file paths, Buffers, & data URLs or an object mapping family names to font files")
     }
   }
 
@@ -372,7 +1453,11 @@ class TextMetrics{
     width, left, right, ascent, descent,
     fontAscent, fontDescent, emAscent, emDescent,
     hanging, alphabetic, ideographic
-  ], lines){
+  ], lines, fallbacks=[], text=''){
+    let segments = segmentGraphemes(text),
+        owner = [];
+    segments.forEach(seg => { for (let j=0; j<seg.text.length; j++) owner[seg.index + j] = seg })
+
     readOnly(this, "width", width)
     readOnly(this, "actualBoundingBoxLeft", left)
     readOnly(this, "actualBoundingBoxRight", right)
@@ -385,14 +1470,96 @@ class TextMetrics{
     readOnly(this, "hangingBaseline", hanging)
     readOnly(this, "alphabeticBaseline", alphabetic)
     readOnly(this, "ideographicBaseline", ideographic)
-    readOnly(this, "lines", lines.map( ([x, y, width, height, baseline, startIndex, endIndex]) => (
-      {x, y, width, height, baseline, startIndex, endIndex}
-    )))
+    readOnly(this, "lines", lines.map( ([x, y, width, height, baseline, startIndex, endIndex, ...glyphs]) => {
+      glyphs = Array.from({length:glyphs.length / 3}, (_, i) => {
+        let [x, advance, index] = glyphs.slice(i * 3, i * 3 + 3)
+        return {x, advance, index}
+      })
+
+      // merge the glyphs belonging to each user-perceived character (emoji sequences, combining marks, etc.)
+      let clusters = new Map()
+      for (const {x, advance, index} of glyphs){
+        let seg = owner[index] || {index, text:text.slice(index, index+1)},
+            [left, right] = clusters.has(seg) ? clusters.get(seg) : [x, x + advance];
+        clusters.set(seg, [Math.min(left, x), Math.max(right, x + advance)])
+      }
+      let graphemes = [...clusters].map(([{index, text}, [left, right]]) => ({x:left, advance:right - left, index, text}))
+
+      return {x, y, width, height, baseline, startIndex, endIndex, glyphs, graphemes}
+    }))
+    readOnly(this, "fallbacks", fallbacks.filter(({family}) => family !== null))
+    readOnly(this, "missing", fallbacks.filter(({family}) => family === null).map(({index, text}) => ({index, text})))
+  }
+}
+
+//
+// Live display (only available when built with the `window` cargo feature)
+//
+
+const NativeWindow = native.Window && RustClass(native.Window)
+
+class Window extends EventEmitter{
+  static open = new Map()
+  static timer = null
+
+  static tick(){
+    // route the pending ui events to their windows then redraw every window that's still open
+    for (const event of native.pumpEvents()){
+      let win = Window.open.get(event.window)
+      if (!win) continue
+      if (event.type == 'close') win.close()
+      else win.emit(event.type, event)
+    }
+
+    for (const win of Window.open.values()){
+      win.emit('frame', {frame:win.frame++})
+      if (win.handle) $(win.handle, 'draw', win.canvas)
+    }
+  }
+
+  constructor({canvas, width, height, title='', background='white', fps=60}={}){
+    // validate the options before anything is opened on screen
+    if (canvas !== undefined && !(canvas instanceof Canvas)) throw new TypeError("Expected `canvas` to be a Canvas object")
+    for (const [dim, val] of Object.entries({width, height})){
+      if (val !== undefined && !(Number.isFinite(val) && val > 0)) throw new RangeError(`Window ${dim} must be a positive number (got ${val})`)
+    }
+    if (typeof background != 'string') throw new TypeError("Expected `background` to be a CSS color string")
+    if (!(fps > 0 && fps < Infinity)) throw new RangeError("Frame rate must be greater than zero")
+    if (!NativeWindow) throw new Error("Windows are unavailable (rebuild skia-canvas with the `window` cargo feature enabled)")
+    super()
+    this.canvas = canvas || new Canvas(width || 300, height || 150)
+    this.handle = new NativeWindow(width || this.canvas.width, height || this.canvas.height, String(title), background)
+    this.frame = 0
+
+    Window.open.set(this.handle.id, this)
+    if (!Window.timer) Window.timer = setInterval(Window.tick, 1000 / fps)
+  }
+
+  get title(){ return this.handle ? this.handle.title : '' }
+  set title(title){ if (this.handle) this.handle.title = String(title) }
+
+  close(){
+    if (!this.handle) return
+    Window.open.delete(this.handle.id)
+    $(this.handle, 'close')
+    this.handle = null
+    this.emit('close')
+
+    // let the process exit once the last window has been closed
+    if (!Window.open.size){
+      clearInterval(Window.timer)
+      Window.timer = null
+    }
+  }
+
+  [REPR](depth, options) {
+    let {title, canvas, frame} = this
+    return `Window ${inspect({title, canvas, frame}, options)}`
   }
 }
 
 module.exports = {
-  Canvas, CanvasRenderingContext2D, Path2D,
-  Image, ImageData, CanvasGradient, CanvasPattern,
+  Canvas, OffscreenCanvas, CanvasRenderingContext2D, Path2D, Window,
+  Image, ImageData, CanvasGradient, CanvasPattern, CanvasShader, TextureGenerator,
   FontLibrary:FontLibrary.shared
 }
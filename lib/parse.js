@@ -9,7 +9,7 @@
 //    https://www.w3.org/TR/css-fonts-3/#font-size-prop
 
 var splitBy = require('string-split-by'),
-    m, cache = {font:{}, variant:{}};
+    m, cache = {font:{}, variant:{}, decoration:{}};
 
 const styleRE = /^(normal|italic|oblique)$/,
       smallcapsRE = /^(normal|small-caps)$/,
@@ -158,11 +158,50 @@ function parseVariant(str){
   return cache.variant[str];
 }
 
+// -- Text Decoration ---------------------------------------------------------------------
+//    https://developer.mozilla.org/en-US/docs/Web/CSS/text-decoration
+
+const decorLineRE = /^(underline|overline|line-through)$/,
+      decorStyleRE = /^(solid|double|dotted|dashed|wavy)$/,
+      decorAutoRE = /^(auto|from-font)$/;
+
+function parseDecoration(str){
+  if (cache.decoration[str]===undefined){
+    let decor = {line:[], style:'solid', color:null, thickness:null},
+        tokens = splitBy(String(str).trim(), /\s+/),
+        valid = tokens.length > 0;
+
+    for (let token of tokens){
+      if (token=='none' && tokens.length==1) continue
+      else if (decorLineRE.test(token) && !decor.line.includes(token)) decor.line.push(token)
+      else if (decorStyleRE.test(token)) decor.style = token
+      else if (decorAutoRE.test(token)) decor.thickness = null
+      else if (numSizeRE.test(token) && isFinite(parseSize(token))) decor.thickness = parseSize(token)
+      else if (!decor.color && /^[#a-z]/i.test(token)) decor.color = token
+      else valid = false
+    }
+
+    cache.decoration[str] = !valid ? null : Object.assign(decor, {
+      canonical: decor.line.length==0 ? 'none' : [
+        decor.line.join(' '),
+        decor.style != 'solid' && decor.style,
+        decor.color,
+        decor.thickness !== null && `${decor.thickness}px`
+      ].filter(Boolean).join(' ')
+    })
+  }
+  return cache.decoration[str]
+}
+
 // -- Image Filters -----------------------------------------------------------------------
 //    https://developer.mozilla.org/en-US/docs/Web/CSS/filter
 
 var plainFilterRE = /(blur|hue-rotate|brightness|contrast|grayscale|invert|opacity|saturate|sepia)\((.*?)\)/,
     shadowFilterRE = /drop-shadow\((.*)\)/,
+    noiseFilterRE = /(turbulence|fractal-noise|displace)\((.*?)\)/,
+    matrixFilterRE = /color-matrix\((.*?)\)/,
+    lightFilterRE = /(diffuse|specular)-lighting\((.*)\)/,
+    lightArgs = {distant:2, point:3, spot:8},
     percentValueRE = /^(\+|-)?\d{1,3}%$/,
     angleValueRE = /([\d\.]+)(deg|g?rad|turn)/;
 
@@ -181,6 +220,44 @@ function parseFilter(str){
         filters[kind] = [...dims, color]
         canonical.push(`${kind}(${lengths.join(' ')} ${color.replace(/ /g,'')})`)
       }
+    }else if (m = noiseFilterRE.exec(spec)){
+      // turbulence(<freq> [<octaves> [<seed>]]), displace(<scale> <freq> [<octaves> [<seed>]])
+      let [kind, arg] = m.slice(1),
+          args = arg.trim().split(/\s+/),
+          scale = kind=='displace' ? [parseSize(args.shift())] : [],
+          [freq, octaves=1, seed=0] = args.map(parseFloat),
+          vals = [...scale, freq, freq, Math.max(1, Math.floor(octaves)), seed];
+      if (args.length <= 3 && vals.every(isFinite) && freq >= 0){
+        filters[kind] = vals
+        canonical.push(`${kind}(${arg.trim()})`)
+      }
+    }else if (m = matrixFilterRE.exec(spec)){
+      // color-matrix(<20 numbers>), a row-major 5×4 matrix as in SVG's feColorMatrix
+      let kind = 'color-matrix',
+          vals = m[1].trim().split(/[\s,]+/).map(parseFloat);
+      if (vals.length==20 && vals.every(isFinite)){
+        filters[kind] = vals
+        canonical.push(`${kind}(${vals.join(' ')})`)
+      }
+    }else if (m = lightFilterRE.exec(spec)){
+      // diffuse-lighting(distant <azimuth> <elevation> [<surface-scale>] [<color>])
+      // diffuse-lighting(point <x> <y> <z> [<surface-scale>] [<color>])
+      // diffuse-lighting(spot <x> <y> <z> <tx> <ty> <tz> <exponent> <cone-angle> [<surface-scale>] [<color>])
+      let kind = `${m[1]}-lighting`,
+          [light, ...args] = m[2].trim().split(/\s+/),
+          count = lightArgs[light],
+          angle = s => /\d$/.test(s) ? parseFloat(s) : parseAngle(s),
+          coords = light=='distant' ? args.slice(0,2).map(angle)
+                 : light=='point' ? args.slice(0,3).map(s => parseSize(s))
+                 : light=='spot' ? [...args.slice(0,6).map(s => parseSize(s)), parseFloat(args[6]), angle(args[7] || '')]
+                 : [],
+          rest = args.slice(count),
+          surface = /^[-+]?[\d\.]+$/.test(rest[0]) ? parseFloat(rest.shift()) : 1,
+          color = rest.join(' ') || 'white';
+      if (count && coords.length==count && coords.every(isFinite)){
+        filters[kind] = [light, ...coords, surface, color]
+        canonical.push(`${kind}(${m[2].trim()})`)
+      }
     }else if (m = plainFilterRE.exec(spec)){
       let [kind, arg] = m.slice(1)
       let val = kind=='blur' ? parseSize(arg)
@@ -295,4 +372,4 @@ const alternatesMap = {
   "annotation": "nalt #",
 }
 
-module.exports = {parseFont, parseVariant, parseSize, parseFilter}
\ No newline at end of file
+module.exports = {parseFont, parseVariant, parseSize, parseFilter, parseDecoration}
\ No newline at end of file
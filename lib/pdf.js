@@ -0,0 +1,186 @@
+"use strict"
+
+const zlib = require('zlib')
+
+//
+// PDF post-processing
+//
+
+// Skia's pdf backend has no api for document outlines, so bookmarks are added to its output by appending an
+// 'incremental update' containing the outline items and a revised catalog that points to them. Each item
+// refers to a named destination that skia already wrote into the page, so the only object that has to be
+// read back is the catalog (which may be stored directly or inside a compressed object stream, and be
+// indexed by either a plain-text xref table or an xref stream).
+
+const pad = (num, width) => String(num).padStart(width, '0')
+
+function dictAt(text, pos){
+  // extract the (possibly nested) dictionary starting at or after `pos`, skipping over strings
+  let start = text.indexOf('<<', pos),
+      depth = 0;
+  for (let i=start; i>=0 && i<text.length; i++){
+    let c = text[i]
+    if (c == '(') {
+      for (let nest=1; nest && ++i<text.length;){
+        if (text[i] == '\\') i++
+        else if (text[i] == '(') nest++
+        else if (text[i] == ')') nest--
+      }
+    }else if (c == '<' && text[i + 1] == '<'){
+      depth++; i++
+    }else if (c == '>' && text[i + 1] == '>'){
+      depth--; i++
+      if (depth == 0) return text.slice(start, i + 1)
+    }else if (c == '<'){
+      i = text.indexOf('>', i)
+    }
+  }
+  throw new Error("Could not parse PDF dictionary")
+}
+
+const ref = (dict, key) => {
+  let match = dict.match(new RegExp(`/${key}\\s+(\\d+)\\s+\\d+\\s+R`))
+  return match && Number(match[1])
+}
+
+const num = (dict, key) => {
+  let match = dict.match(new RegExp(`/${key}\\s+(\\d+)\\b(?!\\s+\\d+\\s+R)`))
+  return match && Number(match[1])
+}
+
+function readTrailer(text, offset){
+  // the trailer entries follow a plain xref table or are part of an xref stream's dictionary
+  let isStream = !text.startsWith('xref', offset),
+      trailer = dictAt(text, isStream ? offset : text.indexOf('trailer', offset));
+  return {trailer, isStream}
+}
+
+function directObject(text, objNum){
+  // find the most recent definition of an object written directly into the file (if any)
+  let found = null,
+      direct = new RegExp(`(^|[^\\d])${objNum}\\s+0\\s+obj\\b`, 'g');
+  for (const match of text.matchAll(direct)){
+    let start = match.index + match[0].length
+    found = {pos:match.index, body:text.slice(start, text.indexOf('endobj', start)).trim()}
+  }
+  return found
+}
+
+function findObject(pdf, text, objNum){
+  // look up an object's most recent definition, whether written directly or packed into an object stream
+  let found = directObject(text, objNum)
+
+  for (const match of text.matchAll(/\/Type\s*\/ObjStm\b/g)){
+    let header = [...text.slice(0, match.index).matchAll(/(\d+)\s+\d+\s+obj\b/g)].pop()
+    if (!header || (found && found.pos > header.index)) continue
+
+    let dict = dictAt(text, header.index + header[0].length),
+        length = ref(dict, 'Length') ? Number(directObject(text, ref(dict, 'Length')).body) : num(dict, 'Length'),
+        keyword = text.slice(header.index).match(/stream\r?\n/),
+        start = header.index + keyword.index + keyword[0].length,
+        data = pdf.subarray(start, start + length);
+    if (/\/Filter\s*\/FlateDecode/.test(dict)) data = zlib.inflateSync(data)
+
+    let contents = data.toString('latin1'),
+        first = num(dict, 'First'),
+        offsets = contents.slice(0, first).trim().split(/\s+/).map(Number);
+    for (let i=0; i<offsets.length; i+=2){
+      if (offsets[i] != objNum) continue
+      let end = i + 3 < offsets.length ? first + offsets[i + 3] : contents.length
+      found = {pos:header.index, body:contents.slice(first + offsets[i + 1], end).trim()}
+    }
+  }
+
+  if (!found) throw new Error(`Could not find object ${objNum} in PDF`)
+  return found.body
+}
+
+function textString(str){
+  // encode as utf-16be hex so titles can contain any characters
+  let hex = Buffer.from('\uFEFF' + str, 'utf16le').swap16().toString('hex').toUpperCase()
+  return `<${hex}>`
+}
+
+function addOutline(pdf, bookmarks){
+  // bookmarks are a tree of {title, dest, children} where `dest` is the name of a destination in the document
+  let text = pdf.toString('latin1'),
+      prevXref = Number([...text.matchAll(/startxref\s+(\d+)/g)].pop()[1]),
+      {trailer, isStream} = readTrailer(text, prevXref),
+      size = num(trailer, 'Size'),
+      root = ref(trailer, 'Root');
+  if (/\/Encrypt\b/.test(trailer)) throw new Error("Can’t add bookmarks to an encrypted PDF")
+  let catalog = dictAt(findObject(pdf, text, root), 0);
+
+  // number the outline root & its items sequentially after the existing objects
+  let nextNum = size,
+      outlineNum = nextNum++,
+      objects = {},
+      number = items => items.map(item => ({...item, num:nextNum++, children:number(item.children)})),
+      count = items => items.reduce((sum, item) => sum + 1 + count(item.children), 0);
+  bookmarks = number(bookmarks)
+
+  let emit = (items, parent) => items.forEach((item, i) => {
+    let {num, title, dest, children} = item,
+        dict = [`/Title ${textString(title)}`, `/Parent ${parent} 0 R`, `/Dest /${dest}`];
+    if (i > 0) dict.push(`/Prev ${items[i - 1].num} 0 R`)
+    if (i < items.length - 1) dict.push(`/Next ${items[i + 1].num} 0 R`)
+    if (children.length){
+      let [first, last] = [children[0], children[children.length - 1]]
+      dict.push(`/First ${first.num} 0 R /Last ${last.num} 0 R /Count ${count(children)}`)
+    }
+    objects[num] = `<<${dict.join(' ')}>>`
+    emit(children, num)
+  })
+  emit(bookmarks, outlineNum)
+
+  objects[outlineNum] = bookmarks.length
+    ? `<</Type /Outlines /First ${bookmarks[0].num} 0 R /Last ${bookmarks[bookmarks.length - 1].num} 0 R /Count ${count(bookmarks)}>>`
+    : `<</Type /Outlines /Count 0>>`
+  objects[root] = catalog.replace(/\s*\/(Outlines\s+\d+\s+\d+\s+R|PageMode\s*\/\w+)/g, '')
+                         .replace(/>>$/, ` /Outlines ${outlineNum} 0 R /PageMode /UseOutlines>>`)
+
+  // append the new & revised objects
+  let update = '\n',
+      entries = [],
+      start = pdf.length,
+      offset = () => start + Buffer.byteLength(update, 'latin1');
+  for (const num of Object.keys(objects).map(Number).sort((a, b) => a - b)){
+    entries.push([num, offset()])
+    update += `${num} 0 obj\n${objects[num]}\nendobj\n`
+  }
+
+  // then index them in the same style of cross-reference section the original file used
+  let info = ref(trailer, 'Info'),
+      id = trailer.match(/\/ID\s*\[[^\]]*\]/),
+      xrefOffset = offset(),
+      tail = ` /Root ${root} 0 R` + (info ? ` /Info ${info} 0 R` : '') + (id ? ` ${id[0]}` : '') + ` /Prev ${prevXref}`;
+  if (isStream) entries.push([nextNum++, xrefOffset])
+
+  let sections = entries.reduce((runs, entry) => {
+    let run = runs[runs.length - 1]
+    if (run && run[run.length - 1][0] == entry[0] - 1) run.push(entry)
+    else runs.push([entry])
+    return runs
+  }, [])
+
+  if (isStream){
+    let rows = Buffer.alloc(entries.length * 7)
+    sections.flat().forEach(([_, off], i) => {
+      rows.writeUInt8(1, i * 7)
+      rows.writeUInt32BE(off, i * 7 + 1)
+    })
+    let index = sections.map(run => `${run[0][0]} ${run.length}`).join(' ')
+    update += `${nextNum - 1} 0 obj\n<</Type /XRef /Size ${nextNum} /W [1 4 2] /Index [${index}]${tail} /Length ${rows.length}>>\nstream\n`
+            + rows.toString('latin1') + `\nendstream\nendobj\n`
+  }else{
+    update += 'xref\n' + sections.map(run =>
+      `${run[0][0]} ${run.length}\n` + run.map(([_, off]) => `${pad(off, 10)} 00000 n \n`).join('')
+    ).join('')
+    update += `trailer\n<</Size ${nextNum}${tail}>>\n`
+  }
+  update += `startxref\n${xrefOffset}\n%%EOF\n`
+
+  return Buffer.concat([pdf, Buffer.from(update, 'latin1')])
+}
+
+module.exports = {addOutline}
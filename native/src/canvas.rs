@@ -3,53 +3,49 @@
 #![allow(unused_variables)]
 #![allow(dead_code)]
 #![allow(clippy::needless_range_loop)]
+#![allow(clippy::too_many_arguments)]
 use std::fs;
 use std::path::Path;
 use neon::prelude::*;
 use neon::result::Throw;
 use neon::object::This;
-use skia_safe::{Surface, Rect, Picture, EncodedImageFormat, Data, pdf, svg};
+use skia_safe::{Image as SkImage, Surface, surfaces, images, Paint, Rect, IRect, Picture, PictureRecorder, EncodedImageFormat, Data, ImageInfo, ColorType, AlphaType, Color, ColorSpace, pdf, svg};
 
 
 use crate::utils::*;
 use crate::context::{JsContext2D, Context2D};
+use crate::pool;
+use crate::cmyk;
+use crate::tiff;
+use crate::texture::checkerboard;
 
 pub struct Canvas{
   pub width: f32,
   pub height: f32,
   pub density: f32,
+  pub front: Option<Picture>,
 }
 
 impl Canvas{
 
-  fn encode_image(&self, picture: &Picture, format:&str, width: f32, height: f32, quality: f32) -> Option<Data> {
-    let img_format = match format {
-      "jpg" | "jpeg" => Some(EncodedImageFormat::JPEG),
-      "png" => Some(EncodedImageFormat::PNG),
-      "webp" => Some(EncodedImageFormat::WEBP),
-      "gif" => Some(EncodedImageFormat::GIF),
-      "heic" => Some(EncodedImageFormat::HEIF),
-      _ => None
-    };
-
-    if let Some(format) = img_format{
-      let img_dims = (width as i32, height as i32);
-      if let Some(mut surface) = Surface::new_raster_n32_premul(img_dims){
-        surface.canvas().draw_picture(&picture, None, None);
-        let img = surface.image_snapshot();
-        img.encode_to_data_with_quality(format, quality as i32)
-      }else{
-        None
-      }
+  fn encode_image(&self, picture: &Picture, format:&str, width: f32, height: f32, quality: f32, space:WorkingSpace, depth:PixelFormat, outline_text:bool, metadata:&pdf::Metadata) -> Option<Data> {
+    if to_bitmap_format(format).is_some(){
+      encode_bitmap(picture, format, (width as i32, height as i32), quality, space, depth)
     }else if format == "pdf"{
       let img_dims = (width as i32, height as i32);
-      let mut document = pdf::new_document(None).begin_page(img_dims, None);
+      let mut bytes = vec![];
+      let mut document = pdf::new_document(&mut bytes, Some(metadata)).begin_page(img_dims, None);
       let canvas = document.canvas();
       canvas.draw_picture(&picture, None, None);
-      Some(document.end_page().close())
+      document.end_page().close();
+      Some(Data::new_copy(&bytes))
+    }else if format == "skp"{
+      Some(picture.serialize())
     }else if format == "svg"{
-      let img_dims = (self.width as i32, self.height as i32);
-      let mut canvas = svg::Canvas::new(Rect::from_size(img_dims), None);
+      let img_dims = ((width / self.density) as i32, (height / self.density) as i32);
+      // text is written as <text> elements unless it's been requested as glyph outlines
+      let flags = if outline_text{ Some(svg::canvas::Flags::CONVERT_TEXT_TO_PATHS) }else{ None };
+      let mut canvas = svg::Canvas::new(Rect::from_size(img_dims), flags);
       canvas.draw_picture(&picture, None, None);
       Some(canvas.end())
     }else{
@@ -57,25 +53,323 @@ impl Canvas{
     }
   }
 
-  fn write_page(&self, page: &mut Context2D, filename: &str, file_format:&str, quality: f32) -> Result<(), String> {
-    let path = Path::new(&filename);
-    if page.width() == 0.0 || page.height() == 0.0 {
-      return Err("Width and height must be non-zero to generate an image".to_string())
+}
+
+fn to_bitmap_format(format:&str) -> Option<EncodedImageFormat>{
+  match format {
+    "jpg" | "jpeg" => Some(EncodedImageFormat::JPEG),
+    "png" => Some(EncodedImageFormat::PNG),
+    "webp" => Some(EncodedImageFormat::WEBP),
+    "gif" => Some(EncodedImageFormat::GIF),
+    "heic" => Some(EncodedImageFormat::HEIF),
+    _ => None
+  }
+}
+
+fn encode_bitmap(picture: &Picture, format:&str, dims:(i32, i32), quality: f32, space:WorkingSpace, depth:PixelFormat) -> Option<Data> {
+  // bitmaps are rendered in the page's color space (whose profile is embedded in the file), though only
+  // PNGs can be written with 16 bits per channel
+  let format = to_bitmap_format(format)?;
+  let depth = if format == EncodedImageFormat::PNG{ depth }else{ PixelFormat::RGBA8 };
+  let mut surface = pool::take_surface_as(dims, space, depth)?;
+  surface.canvas().draw_picture(&picture, None, None);
+  let img = surface.image_snapshot();
+  let data = img.encode(None, format, quality as u32);
+  pool::recycle_surface(surface);
+  data
+}
+
+struct TileTask{
+  // a batch of tiles to be rasterized in the background from a serialized copy of the page's picture,
+  // each described by its scale factor and the offset of its top-left corner in the scaled scene
+  skp: Vec<u8>,
+  format: String,
+  quality: f32,
+  space: WorkingSpace,
+  size: f32,
+  tiles: Vec<(f32, f32, f32)>,
+}
+
+impl Task for TileTask{
+  type Output = Vec<Vec<u8>>;
+  type Error = String;
+  type JsEvent = JsArray;
+
+  fn perform(&self) -> Result<Self::Output, Self::Error>{
+    let picture = Picture::from_bytes(&self.skp).ok_or("Could not read the page's picture data")?;
+    let bounds = Rect::from_wh(self.size, self.size);
+    self.tiles.iter().map(|(scale, left, top)|{
+      let mut recorder = PictureRecorder::new();
+      let canvas = recorder.begin_recording(bounds, None);
+      canvas.clip_rect(bounds, None, None);
+      canvas.translate((-left, -top));
+      canvas.scale((*scale, *scale));
+      canvas.draw_picture(&picture, None, None);
+      recorder.finish_recording_as_picture(Some(&bounds))
+        .and_then(|tile| encode_bitmap(&tile, &self.format, (self.size as i32, self.size as i32), self.quality, self.space, PixelFormat::RGBA8))
+        .map(|data| data.as_bytes().to_vec())
+        .ok_or_else(|| format!("Could not render tile in {:?} format", self.format))
+    }).collect()
+  }
+
+  fn complete(self, mut cx:TaskContext, result:Result<Self::Output, Self::Error>) -> JsResult<JsArray>{
+    let tiles = match result{
+      Ok(tiles) => tiles,
+      Err(msg) => return cx.throw_error(msg)
+    };
+
+    let array = JsArray::new(&mut cx, tiles.len() as u32);
+    for (i, data) in tiles.iter().enumerate(){
+      let mut buffer = JsBuffer::new(&mut cx, data.len() as u32)?;
+      cx.borrow_mut(&mut buffer, |buf_data| {
+        buf_data.as_mut_slice().copy_from_slice(&data);
+      });
+      array.set(&mut cx, i as u32, buffer)?;
     }
+    Ok(array)
+  }
+}
 
-    let data = match page.get_picture(None) {
-      Some(picture) => self.encode_image(&picture, &file_format, page.width(), page.height(), quality),
-      None => None
+struct EncodeTask{
+  // a page's rendered pixels (in the layout of the surface it was drawn into) to be compressed in the background
+  pixels: Vec<u8>,
+  dims: (i32, i32),
+  space: WorkingSpace,
+  depth: PixelFormat,
+  format: String,
+  quality: f32,
+}
+
+impl Task for EncodeTask{
+  type Output = Vec<u8>;
+  type Error = String;
+  type JsEvent = JsBuffer;
+
+  fn perform(&self) -> Result<Self::Output, Self::Error>{
+    let info = ImageInfo::new(self.dims, self.depth.color_type(self.space), AlphaType::Premul, self.space.color_space());
+    to_bitmap_format(&self.format)
+      .and_then(|format|{
+        let img = images::raster_from_data(&info, Data::new_copy(&self.pixels), info.min_row_bytes())?;
+        img.encode(None, format, self.quality as u32)
+      })
+      .map(|data| data.as_bytes().to_vec())
+      .ok_or_else(|| format!("Could not encode image in {:?} format", self.format))
+  }
+
+  fn complete(self, mut cx:TaskContext, result:Result<Self::Output, Self::Error>) -> JsResult<JsBuffer>{
+    let data = match result{
+      Ok(data) => data,
+      Err(msg) => return cx.throw_error(msg)
     };
+    let mut buffer = JsBuffer::new(&mut cx, data.len() as u32)?;
+    cx.borrow_mut(&mut buffer, |buf_data| {
+      buf_data.as_mut_slice().copy_from_slice(&data);
+    });
+    Ok(buffer)
+  }
+}
+
+pub struct PageInfo{
+  // a snapshot of a page's content (including its layers) along with the details needed to export it
+  pub picture: Option<Picture>,
+  pub width: f32,
+  pub height: f32,
+  pub space: WorkingSpace,
+  pub depth: PixelFormat,
+}
+
+impl PageInfo{
+  pub fn framed(self, crop:Option<Rect>, scale:f32, matte:Option<&Paint>) -> Self{
+    // re-record the picture scaled, backed by the matte color, and clipped to the crop rect (in scaled
+    // coordinates) & shifted to its origin so rasterizing it only needs to allocate (and play back drawing
+    // into) a surface the size of the crop rather than the whole page
+    if crop.is_none() && scale == 1.0 && matte.is_none(){
+      return self
+    }
+    let crop = crop.unwrap_or_else(|| Rect::from_wh(self.width * scale, self.height * scale));
+    let bounds = Rect::from_wh(crop.width(), crop.height());
+    let picture = self.picture.and_then(|picture|{
+      let mut recorder = PictureRecorder::new();
+      let canvas = recorder.begin_recording(bounds, None);
+      canvas.clip_rect(bounds, None, None);
+      if let Some(matte) = matte{
+        canvas.draw_paint(matte);
+      }
+      canvas.translate((-crop.left, -crop.top));
+      canvas.scale((scale, scale));
+      canvas.draw_picture(&picture, None, None);
+      recorder.finish_recording_as_picture(Some(&bounds))
+    });
+    PageInfo{ picture, width:crop.width(), height:crop.height(), ..self }
+  }
+}
+
+fn frame_args<T:This>(cx: &mut CallContext<'_, T>, idx: usize) -> Result<(Option<Rect>, f32, Option<Paint>), Throw>{
+  // an export's optional crop rect, scale factor, and matte (either a css color or a list containing the
+  // size of a checkerboard's squares followed by its two colors)
+  let crop = match cx.argument_opt(idx as i32).and_then(|arg| arg.downcast::<JsArray>().ok()){
+    Some(arg) => match floats_in(&arg.to_vec(cx)?).as_slice(){
+      [left, top, width, height] => Some(Rect::from_xywh(*left, *top, *width, *height)),
+      _ => return cx.throw_type_error("Expected a crop rect of [left, top, width, height]")
+    },
+    None => None
+  };
+  let scale = float_arg_or(cx, idx + 1, 1.0);
+  let mut matte = Paint::default();
+  match cx.argument_opt(idx as i32 + 2){
+    Some(arg) if arg.is_a::<JsArray>() => {
+      let spec = arg.downcast::<JsArray>().or_throw(cx)?.to_vec(cx)?;
+      let size = floats_in(&spec).first().copied().unwrap_or(0.0);
+      let colors:Vec<Option<Color>> = strings_in(&spec).iter().map(|css| color_in(cx, css)).collect();
+      match colors.as_slice(){
+        [Some(a), Some(b)] if size > 0.0 => matte.set_shader(checkerboard(size, (*a, *b))),
+        _ => return cx.throw_type_error("Checkerboard mattes require a positive size and two valid CSS colors")
+      };
+    },
+    Some(arg) if arg.is_a::<JsString>() => {
+      let css = string_arg(cx, idx + 2, "matte")?;
+      match color_in(cx, &css){
+        Some(color) => matte.set_color(color),
+        None => return cx.throw_type_error(format!("Invalid matte color: {:?}", css))
+      };
+    },
+    _ => return Ok((crop, scale, None))
+  }
+  Ok((crop, scale, Some(matte)))
+}
 
-    match data {
-      Some(data) => fs::write(path, data.as_bytes()).map_err(|why|
-        format!("{}: \"{}\"", why, path.display())
-      ),
-      None => Err(format!("Unsupported file format: {:?}", file_format))
+fn pdf_metadata<T:This>(cx: &mut CallContext<'_, T>, idx: usize) -> Result<pdf::Metadata, Throw>{
+  // document info strings arrive as [title, author, subject, keywords, creator] followed by the pdf/a flag
+  // and the creation & modification dates (as lists of utc date components or null if omitted)
+  let mut metadata = pdf::Metadata::default();
+  if let Some(info) = cx.argument_opt(idx as i32).and_then(|arg| arg.downcast::<JsArray>().ok()){
+    if let [title, author, subject, keywords, creator] = strings_in(&info.to_vec(cx)?).as_slice(){
+      metadata.title = title.to_string();
+      metadata.author = author.to_string();
+      metadata.subject = subject.to_string();
+      metadata.keywords = keywords.to_string();
+      metadata.creator = creator.to_string();
     }
   }
+  metadata.pdf_a = bool_arg_or(cx, idx + 1, false);
+  if let Some(dates) = cx.argument_opt(idx as i32 + 2).and_then(|arg| arg.downcast::<JsArray>().ok()){
+    let dates = dates.to_vec(cx)?;
+    if let [creation, modified] = dates.as_slice(){
+      metadata.creation = date_in(cx, creation)?;
+      metadata.modified = date_in(cx, modified)?;
+    }
+  }
+  Ok(metadata)
+}
+
+fn date_in<T:This>(cx: &mut CallContext<'_, T>, val: &Handle<JsValue>) -> Result<Option<pdf::DateTime>, Throw>{
+  let terms = match val.downcast::<JsArray>(){
+    Ok(date) => floats_in(&date.to_vec(cx)?),
+    Err(_e) => return Ok(None)
+  };
+  Ok(match terms.as_slice(){
+    [year, month, day_of_week, day, hour, minute, second] => Some(pdf::DateTime{
+      time_zone_minutes:0, year:*year as u16, month:*month as u8, day_of_week:*day_of_week as u8,
+      day:*day as u8, hour:*hour as u8, minute:*minute as u8, second:*second as u8
+    }),
+    _ => None
+  })
+}
+
+fn pdf_document(pages:&[PageInfo], metadata:&pdf::Metadata) -> Data{
+  let mut bytes = vec![];
+  pages.iter().fold(pdf::new_document(&mut bytes, Some(metadata)), |doc, page|{
+    let mut doc = doc.begin_page((page.width as i32, page.height as i32), None);
+    let canvas = doc.canvas();
+    if let Some(picture) = &page.picture{
+      canvas.draw_picture(&picture, None, None);
+    }
+    doc.end_page()
+  }).close();
+  Data::new_copy(&bytes)
+}
+
+fn page_layers<'a, T:This>(cx: &mut CallContext<'a, T>, this: &Handle<JsCanvas>, page: Handle<'a, JsContext2D>)->Result<Vec<(f32, Handle<'a, JsContext2D>)>, Throw>{
+  // layers are tracked on the js side in a WeakMap from each page to a list of {zIndex, context} records
+  let layer_map = this
+      .get(cx, "constructor")?
+      .downcast::<JsFunction>().or_throw(cx)?
+      .get(cx, "layers")?
+      .downcast::<JsObject>().or_throw(cx)?;
 
+  let map_getter = layer_map
+      .get(cx, "get")?
+      .downcast::<JsFunction>().or_throw(cx)?;
+
+  let layers = match map_getter.call(cx, layer_map, vec![page.upcast::<JsObject>()])?.downcast::<JsArray>(){
+    Ok(layers) => layers.to_vec(cx)?,
+    Err(_e) => return Ok(vec![])
+  };
+
+  layers.iter().map(|layer| {
+    let layer = layer.downcast::<JsObject>().or_throw(cx)?;
+    let z_index = layer.get(cx, "zIndex")?.downcast::<JsNumber>().or_throw(cx)?.value() as f32;
+    let context = layer.get(cx, "context")?.downcast::<JsContext2D>().or_throw(cx)?;
+    Ok((z_index, context))
+  }).collect()
+}
+
+pub fn refresh_links<'a, T:This>(cx: &mut CallContext<'a, T>, context: &mut Handle<'a, JsContext2D>)->Result<(), Throw>{
+  // re-snapshot the canvases drawn with {live:true} (whose handles are kept in a list on the js side),
+  // skipping contexts that are already mid-refresh so that circular links can't recurse forever
+  let count = cx.borrow(context, |ctx| if ctx.resolving_links.get(){ 0 }else{ ctx.link_count() });
+  if count == 0{
+    return Ok(())
+  }
+
+  let sym = symbol(cx, "liveLinks")?;
+  let sources = context.get(cx, sym)?.downcast::<JsArray>().or_throw(cx)?.to_vec(cx)?;
+  cx.borrow(context, |ctx| ctx.resolving_links.set(true) );
+  let pictures:Result<Vec<Option<Picture>>, Throw> = sources.iter().take(count).map(|source|
+    match source.downcast::<JsCanvas>(){
+      Ok(canvas) => canvas_picture(cx, &canvas),
+      Err(_e) => Ok(None)
+    }
+  ).collect();
+  cx.borrow(context, |ctx| ctx.resolving_links.set(false) );
+
+  let pictures = pictures?;
+  cx.borrow_mut(context, |mut ctx| ctx.update_links(pictures) );
+  Ok(())
+}
+
+pub fn page_info<'a, T:This>(cx: &mut CallContext<'a, T>, this: &Handle<JsCanvas>, page: &mut Handle<'a, JsContext2D>)->Result<PageInfo, Throw>{
+  // snapshot the page, compositing any layers above (or, for negative z-indices, beneath) it
+  refresh_links(cx, page)?;
+  let layers = page_layers(cx, this, *page)?;
+  let (picture, width, height, space, depth) = cx.borrow_mut(page, |mut page|
+    (page.get_picture(None), page.width(), page.height(), page.working_space, page.pixel_format)
+  );
+  if layers.is_empty(){
+    return Ok(PageInfo{ picture, width, height, space, depth })
+  }
+
+  let mut pictures = vec![(0.0, picture)];
+  for (z_index, mut layer) in layers{
+    refresh_links(cx, &mut layer)?;
+    let picture = cx.borrow_mut(&mut layer, |mut layer| layer.get_picture(None) );
+    pictures.push((z_index, picture));
+  }
+  pictures.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+  let bounds = Rect::from_wh(width, height);
+  let mut recorder = PictureRecorder::new();
+  let canvas = recorder.begin_recording(bounds, None);
+  for picture in pictures.iter().filter_map(|(_, picture)| picture.as_ref()){
+    canvas.draw_picture(&picture, None, None);
+  }
+  let picture = recorder.finish_recording_as_picture(Some(&bounds));
+  Ok(PageInfo{ picture, width, height, space, depth })
+}
+
+pub fn canvas_picture<T:This>(cx: &mut CallContext<'_, T>, this: &Handle<JsCanvas>)->Result<Option<Picture>, Throw>{
+  let mut page = canvas_pages(cx, this)?[0];
+  Ok(page_info(cx, this, &mut page)?.picture)
 }
 
 pub fn canvas_pages<'a, T:This>(cx: &mut CallContext<'a, T>, this: &Handle<JsCanvas>)->Result<Vec<Handle<'a, JsContext2D>>, Throw>{
@@ -123,7 +417,7 @@ declare_types! {
       let width = if width < 0.0 { 300.0 } else { width };
       let height = if height < 0.0 { 150.0 } else { height };
 
-      Ok(Canvas{ width, height, density })
+      Ok(Canvas{ width, height, density, front:None })
     }
 
     method get_density(mut cx){
@@ -138,6 +432,7 @@ declare_types! {
       if width >= 0.0 {
         let dims = cx.borrow_mut(&mut this, |mut this| {
           this.width = width;
+          this.front = None;
           (this.width, this.height)
         });
 
@@ -160,6 +455,7 @@ declare_types! {
       if height >= 0.0 {
         let dims = cx.borrow_mut(&mut this, |mut this| {
           this.height = height;
+          this.front = None;
           (this.width, this.height)
         });
 
@@ -180,102 +476,31 @@ declare_types! {
     // Output
     //
 
-    method _saveAs(mut cx){
-      let this = cx.this();
-      let name_pattern = string_arg(&mut cx, 0, "filePath")?;
-      let sequence = !cx.argument::<JsValue>(1)?.is_a::<JsUndefined>();
-      let file_format = string_arg(&mut cx, 2, "format")?;
-      let quality = float_arg(&mut cx, 3, "quality")?;
-
-      if sequence {
-        let mut pages = canvas_pages(&mut cx, &this)?;
-        pages.reverse();
-
-        let padding = float_arg(&mut cx, 1, "padding")? as i32;
-        let padding = match padding {
-          -1 => (1.0 + (pages.len() as f32).log10().floor()) as usize,
-          _ => padding as usize
-        };
-
-        for pp in 0..pages.len() {
-          let mut page = &mut pages[pp];
-          let filename = name_pattern.replace("{}", format!("{:0width$}", pp+1, width=padding).as_str());
-          let io = cx.borrow(&this, |this|
-            cx.borrow_mut(&mut page, |mut page|{
-              this.write_page(&mut page, &filename, &file_format, quality)
-            })
-          );
-
-          if let Err(why) = io{
-            return cx.throw_error(why)
-          }
-        }
-      } else if file_format == "pdf" {
-        let mut pages = canvas_pages(&mut cx, &this)?;
-        pages.reverse();
-
-        let document = pages.iter_mut().fold(pdf::new_document(None), |doc, page|{
-          cx.borrow_mut(page, |mut page| {
-            let dims = (page.width() as i32, page.height() as i32);
-            let mut doc = doc.begin_page(dims, None);
-            let canvas = doc.canvas();
-            if let Some(picture) = page.get_picture(None){
-              canvas.draw_picture(&picture, None, None);
-            }
-            doc.end_page()
-          })
-        });
-
-        let path = Path::new(&name_pattern);
-        return match fs::write(path, document.close().as_bytes()){
-          Err(why) => cx.throw_error(format!("{}: \"{}\"", why, path.display())),
-          Ok(()) => Ok(cx.undefined().upcast())
-        }
-      } else {
-        let mut page = canvas_pages(&mut cx, &this)?[0];
-        let io = cx.borrow(&this, |this|
-          cx.borrow_mut(&mut page, |mut page|{
-            this.write_page(&mut page, &name_pattern, &file_format, quality)
-          })
-        );
-
-        if let Err(why) = io{
-          return cx.throw_error(why)
-        }
-      }
-
-      Ok(cx.undefined().upcast())
-    }
-
     method _toBuffer(mut cx){
       let this = cx.this();
       let file_format = string_arg(&mut cx, 0, "format")?;
       let quality = float_arg(&mut cx, 1, "quality")?;
       let page_idx = opt_float_arg(&mut cx, 2);
+      let (crop, scale, matte) = frame_args(&mut cx, 3)?;
+      let outline_text = bool_arg_or(&mut cx, 6, false);
+      let metadata = pdf_metadata(&mut cx, 7)?;
 
       let mut pages = canvas_pages(&mut cx, &this)?;
       let data = {
         if file_format=="pdf" && page_idx.is_none() {
-          Some(pages.iter_mut().rev().fold(pdf::new_document(None), |doc, page|{
-            cx.borrow_mut(page, |mut page| {
-              let dims = (page.width() as i32, page.height() as i32);
-              let mut doc = doc.begin_page(dims, None);
-              let canvas = doc.canvas();
-              if let Some(picture) = page.get_picture(None){
-                canvas.draw_picture(&picture, None, None);
-              }
-              doc.end_page()
-            })
-          }).close())
+          let mut infos = vec![];
+          for page in pages.iter_mut().rev(){
+            infos.push(page_info(&mut cx, &this, page)?.framed(crop, scale, matte.as_ref()));
+          }
+          Some(pdf_document(&infos, &metadata))
         }else{
           let page_idx = page_idx.unwrap_or(0.0);
+          let page = page_info(&mut cx, &this, &mut pages[page_idx as usize])?.framed(crop, scale, matte.as_ref());
           cx.borrow(&this, |this|
-            cx.borrow_mut(&mut pages[page_idx as usize], |mut page|
-              match page.get_picture(None) {
-                Some(picture) => this.encode_image(&picture, &file_format, page.width(), page.height(), quality),
-                None => None
-              }
-            )
+            match &page.picture {
+              Some(picture) => this.encode_image(&picture, &file_format, page.width, page.height, quality, page.space, page.depth, outline_text, &metadata),
+              None => None
+            }
           )
         }
       };
@@ -292,5 +517,259 @@ declare_types! {
       }
     }
 
+    method _encode(mut cx){
+      // rasterize the page synchronously but leave the (comparatively slow) compression to a worker thread
+      let this = cx.this();
+      let format = string_arg(&mut cx, 0, "format")?;
+      let quality = float_arg(&mut cx, 1, "quality")?;
+      let page_idx = opt_float_arg(&mut cx, 2).unwrap_or(0.0);
+      let (crop, scale, matte) = frame_args(&mut cx, 3)?;
+      let callback = cx.argument::<JsFunction>(6)?;
+
+      let bitmap_format = match to_bitmap_format(&format){
+        Some(bitmap_format) => bitmap_format,
+        None => return cx.throw_error(format!("Unsupported image format: {:?}", format))
+      };
+
+      let mut pages = canvas_pages(&mut cx, &this)?;
+      let PageInfo{picture, width, height, space, depth} = page_info(&mut cx, &this, &mut pages[page_idx as usize])?.framed(crop, scale, matte.as_ref());
+      let depth = if bitmap_format == EncodedImageFormat::PNG{ depth }else{ PixelFormat::RGBA8 };
+      let dims = (width as i32, height as i32);
+      let pixels = pool::take_surface_as(dims, space, depth).and_then(|mut surface|{
+        if let Some(picture) = &picture{
+          surface.canvas().draw_picture(picture, None, None);
+        }
+        let info = surface.image_info();
+        let mut pixels = vec![0u8; info.compute_min_byte_size()];
+        let success = surface.read_pixels(&info, &mut pixels, info.min_row_bytes(), (0, 0));
+        pool::recycle_surface(surface);
+        if success{ Some(pixels) }else{ None }
+      });
+
+      match pixels{
+        Some(pixels) => {
+          EncodeTask{ pixels, dims, space, depth, format, quality }.schedule(callback);
+          Ok(cx.undefined().upcast())
+        },
+        None => cx.throw_error("Could not render the page for encoding")
+      }
+    }
+
+    method _renderTiles(mut cx){
+      let skp = cx.argument::<JsBuffer>(0)?;
+      let format = string_arg(&mut cx, 1, "format")?;
+      let quality = float_arg(&mut cx, 2, "quality")?;
+      let space = to_working_space(&string_arg(&mut cx, 3, "colorSpace")?).unwrap_or(WorkingSpace::SRGB);
+      let size = float_arg(&mut cx, 4, "tileSize")?;
+      let specs = cx.argument::<JsArray>(5)?.to_vec(&mut cx)?;
+      let callback = cx.argument::<JsFunction>(6)?;
+
+      if to_bitmap_format(&format).is_none(){
+        return cx.throw_error(format!("Unsupported tile format: {:?}", format))
+      }
+
+      let skp = cx.borrow(&skp, |data| data.as_slice().to_vec() );
+      let tiles = floats_in(&specs).chunks_exact(3).map(|t| (t[0], t[1], t[2]) ).collect();
+      TileTask{ skp, format, quality, space, size, tiles }.schedule(callback);
+      Ok(cx.undefined().upcast())
+    }
+
+    method _renderInto(mut cx){
+      let this = cx.this();
+      let mut buffer = cx.argument::<JsBuffer>(0)?;
+      let type_name = string_arg(&mut cx, 1, "format")?;
+      let premultiplied = bool_arg(&mut cx, 2, "premultiplied")?;
+      let row_bytes = opt_float_arg(&mut cx, 3);
+      let page_idx = opt_float_arg(&mut cx, 4).unwrap_or(0.0);
+
+      let color_type = match to_color_type(&type_name){
+        Some(color_type) => color_type,
+        None => return cx.throw_type_error(format!(
+          "Unknown format \"{}\" (expected \"rgba\", \"bgra\", \"rgb565\", \"gray\", \"alpha\", \"rgba16\", or \"rgba32f\")", type_name
+        ))
+      };
+      let alpha_type = match (color_type, premultiplied){
+        (ColorType::RGB565, _) | (ColorType::Gray8, _) => AlphaType::Opaque,
+        (ColorType::Alpha8, _) | (_, true) => AlphaType::Premul,
+        _ => AlphaType::Unpremul
+      };
+
+      let mut pages = canvas_pages(&mut cx, &this)?;
+      let PageInfo{picture, width, height, space, depth} = page_info(&mut cx, &this, &mut pages[page_idx as usize])?;
+      let dims = (width as i32, height as i32);
+      let info = ImageInfo::new(dims, color_type, alpha_type, space.pixel_color_space());
+      let row_bytes = row_bytes.map(|n| n as usize).unwrap_or_else(|| info.min_row_bytes());
+      let byte_size = info.compute_byte_size(row_bytes);
+
+      let capacity = cx.borrow(&buffer, |data| data.len());
+      if row_bytes < info.min_row_bytes(){
+        return cx.throw_range_error(format!("rowBytes must be at least {} for a {}px wide canvas", info.min_row_bytes(), dims.0))
+      }else if capacity < byte_size{
+        return cx.throw_range_error(format!("Buffer is too small to hold the rendered page (needs {} bytes, got {})", byte_size, capacity))
+      }
+
+      let success = cx.borrow_mut(&mut buffer, |data| {
+        let pixels = &mut data.as_mut_slice::<u8>()[..byte_size];
+        match (color_type, alpha_type){
+          // premultiplied 32-bit formats are drawn directly into the caller's memory (unless the
+          // page needs to be composited in linear space)...
+          (ColorType::RGBA8888, AlphaType::Premul) | (ColorType::BGRA8888, AlphaType::Premul) if space != WorkingSpace::LinearSRGB => {
+            match surfaces::wrap_pixels(&info, pixels, Some(row_bytes), None){
+              Some(mut surface) => {
+                let canvas = surface.canvas();
+                canvas.clear(Color::TRANSPARENT);
+                if let Some(picture) = &picture{
+                  canvas.draw_picture(picture, None, None);
+                }
+                true
+              },
+              None => false
+            }
+          },
+          // ...while other formats are converted from an intermediate surface
+          _ => match pool::take_surface_as(dims, space, depth){
+            Some(mut surface) => {
+              if let Some(picture) = &picture{
+                surface.canvas().draw_picture(picture, None, None);
+              }
+              let success = surface.read_pixels(&info, pixels, row_bytes, (0, 0));
+              pool::recycle_surface(surface);
+              success
+            },
+            None => false
+          }
+        }
+      });
+
+      match success{
+        true => Ok(cx.undefined().upcast()),
+        false => cx.throw_error("Could not render the page into the buffer")
+      }
+    }
+
+    method _toTIFF(mut cx){
+      let this = cx.this();
+      let page_idx = opt_float_arg(&mut cx, 0).unwrap_or(0.0);
+      let (crop, scale, matte) = frame_args(&mut cx, 1)?;
+      let icc = match cx.argument_opt(4).and_then(|arg| arg.downcast::<JsBuffer>().ok()){
+        Some(icc) => Some(cx.borrow(&icc, |data| data.as_slice::<u8>().to_vec() )),
+        None => None
+      };
+      let intent_name = string_arg(&mut cx, 5, "intent")?;
+      let dpi = float_arg(&mut cx, 6, "dpi")?;
+
+      let intent = match cmyk::to_intent(&intent_name){
+        Some(intent) => intent,
+        None => return cx.throw_type_error(format!(
+          "Unknown intent \"{}\" (expected \"perceptual\", \"relative\", \"saturation\", or \"absolute\")", intent_name
+        ))
+      };
+
+      // pages are read back as unpremultiplied sRGB at their own bit depth (or, for cmyk conversion,
+      // as 8-bit color flattened onto white 'paper')
+      let mut pages = canvas_pages(&mut cx, &this)?;
+      let PageInfo{picture, width, height, space, depth} = page_info(&mut cx, &this, &mut pages[page_idx as usize])?.framed(crop, scale, matte.as_ref());
+      let (depth, background) = match icc{
+        Some(_) => (PixelFormat::RGBA8, Color::WHITE),
+        None => (depth, Color::TRANSPARENT)
+      };
+      let dims = (width as i32, height as i32);
+      let info = ImageInfo::new(dims, depth.data_type(), AlphaType::Unpremul, ColorSpace::new_srgb());
+      let mut pixels = vec![0u8; info.compute_min_byte_size()];
+      let rendered = pool::take_surface_as(dims, space, depth).map(|mut surface|{
+        let canvas = surface.canvas();
+        canvas.clear(background);
+        if let Some(picture) = &picture{
+          canvas.draw_picture(picture, None, None);
+        }
+        let success = surface.read_pixels(&info, &mut pixels, info.min_row_bytes(), (0, 0));
+        pool::recycle_surface(surface);
+        success
+      });
+      if rendered != Some(true){
+        return cx.throw_error("Could not render the page for TIFF export")
+      }
+
+      let (width, height) = (dims.0 as u32, dims.1 as u32);
+      let tiff = match (icc, depth){
+        (Some(icc), _) => match cmyk::to_cmyk(&pixels, &icc, intent){
+          Ok(cmyk) => tiff::encode(&cmyk, width, height, dpi, tiff::Samples::CMYK8, Some(&icc)),
+          Err(msg) => return cx.throw_error(msg)
+        },
+        (None, PixelFormat::RGBA8) => tiff::encode(&pixels, width, height, dpi, tiff::Samples::RGBA8, None),
+        (None, PixelFormat::RGBA16) => tiff::encode(&pixels, width, height, dpi, tiff::Samples::RGBA16, None),
+        (None, PixelFormat::RGBA16F) => tiff::encode(&pixels, width, height, dpi, tiff::Samples::RGBA32F, None),
+      };
+      let mut buffer = JsBuffer::new(&mut cx, tiff.len() as u32)?;
+      cx.borrow_mut(&mut buffer, |buf_data| {
+        buf_data.as_mut_slice().copy_from_slice(&tiff);
+      });
+      Ok(buffer.upcast())
+    }
+
+    method _contentBounds(mut cx){
+      // the geometric bounds (in canvas coordinates) of the draws recorded on the page and any of its layers
+      let this = cx.this();
+      let page_idx = opt_float_arg(&mut cx, 0).unwrap_or(0.0);
+      let density = cx.borrow(&this, |this| this.density );
+      let page = canvas_pages(&mut cx, &this)?[page_idx as usize];
+      let mut contexts = vec![page];
+      contexts.extend(page_layers(&mut cx, &this, page)?.into_iter().map(|(_, layer)| layer));
+
+      let mut extent:Option<IRect> = None;
+      for ctx in contexts{
+        let drawn = cx.borrow(&ctx, |ctx| ctx.drawn_bounds() );
+        extent = match (extent, drawn){
+          (Some(a), Some(b)) => Some(IRect::join(&a, &b)),
+          (a, b) => a.or(b)
+        };
+      }
+      match extent{
+        Some(bounds) => floats_to_array(&mut cx, &[
+          bounds.left as f32 / density, bounds.top as f32 / density,
+          bounds.width() as f32 / density, bounds.height() as f32 / density
+        ]),
+        None => Ok(cx.null().upcast())
+      }
+    }
+
+    method _loadPicture(mut cx){
+      let mut this = cx.this();
+      let buffer = cx.argument::<JsBuffer>(0)?;
+      let picture = cx.borrow(&buffer, |data| Picture::from_bytes(data.as_slice()) );
+      let picture = match picture{
+        Some(picture) => picture,
+        None => return cx.throw_error("Could not read picture data (is it a valid .skp file?)")
+      };
+
+      // adopt the picture's dimensions then draw its contents at the origin
+      let bounds = picture.cull_rect();
+      let dims = cx.borrow_mut(&mut this, |mut this| {
+        this.width = bounds.width().ceil();
+        this.height = bounds.height().ceil();
+        this.front = None;
+        (this.width * this.density, this.height * this.density)
+      });
+      canvas_context(&mut cx, &this, |ctx| {
+        ctx.resize(dims);
+        ctx.draw_picture(&picture, &bounds, &Rect::from_wh(dims.0, dims.1));
+      })?;
+      Ok(cx.undefined().upcast())
+    }
+
+    method _present(mut cx){
+      // copy the current page into the front buffer, which displays keep showing until the next call
+      let mut this = cx.this();
+      let picture = canvas_picture(&mut cx, &this)?;
+      cx.borrow_mut(&mut this, |mut this| this.front = picture );
+      Ok(cx.undefined().upcast())
+    }
+
+    method _discard(mut cx){
+      let mut this = cx.this();
+      cx.borrow_mut(&mut this, |mut this| this.front = None );
+      Ok(cx.undefined().upcast())
+    }
+
   }
 }
\ No newline at end of file
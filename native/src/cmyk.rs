@@ -0,0 +1,30 @@
+//
+// Conversion of rendered pages to CMYK (via an ICC output profile) for print workflows
+//
+use lcms2::{Profile, Transform, PixelFormat, Intent, ColorSpaceSignature};
+
+pub fn to_intent(intent_name:&str) -> Option<Intent>{
+  let intent = match intent_name.to_lowercase().as_str(){
+    "perceptual" => Intent::Perceptual,
+    "relative" | "relative-colorimetric" => Intent::RelativeColorimetric,
+    "saturation" => Intent::Saturation,
+    "absolute" | "absolute-colorimetric" => Intent::AbsoluteColorimetric,
+    _ => return None
+  };
+  Some(intent)
+}
+
+pub fn to_cmyk(rgba:&[u8], icc:&[u8], intent:Intent) -> Result<Vec<u8>, String>{
+  // convert unpremultiplied sRGB pixels (whose alpha channel is ignored) to 8-bit CMYK
+  let output = Profile::new_icc(icc).map_err(|_| "Could not read ICC profile data".to_string())?;
+  if output.color_space() != ColorSpaceSignature::CmykData{
+    return Err("Expected the ICC profile to describe a CMYK color space".to_string())
+  }
+
+  let transform = Transform::new(&Profile::new_srgb(), PixelFormat::RGBA_8, &output, PixelFormat::CMYK_8, intent)
+    .map_err(|_| "Could not create a color transform for the ICC profile".to_string())?;
+  let src:Vec<[u8; 4]> = rgba.chunks_exact(4).map(|px| [px[0], px[1], px[2], px[3]]).collect();
+  let mut dst = vec![[0u8; 4]; src.len()];
+  transform.transform_pixels(&src, &mut dst);
+  Ok(dst.concat())
+}
@@ -0,0 +1,447 @@
+//
+// CSS Color Level 4 parsing
+//
+// Colors are returned as non-premultiplied sRGB values in 'extended' range (i.e., components may fall
+// outside of 0–1 when they lie beyond the sRGB gamut) so they can be drawn into wider working spaces
+// without being clipped first.
+//
+
+use css_color::Rgba;
+use skia_safe::Color4f;
+
+type Matrix3 = [[f64; 3]; 3];
+
+const XYZ_TO_LINEAR_SRGB:Matrix3 = [
+  [ 3.2409699419045226,  -1.537383177570094,   -0.4986107602930034 ],
+  [-0.9692436362808796,   1.8759675015077202,   0.04155505740717559],
+  [ 0.05563007969699366, -0.20397695888897652,  1.0569715142428786 ],
+];
+
+const LINEAR_P3_TO_XYZ:Matrix3 = [
+  [0.4865709486482162, 0.26566769316909306, 0.1982172852343625],
+  [0.2289745640697488, 0.6917385218365064,  0.079286914093745 ],
+  [0.0,                0.04511338185890264, 1.043944368900976 ],
+];
+
+const LINEAR_A98_TO_XYZ:Matrix3 = [
+  [0.5766690429101305,  0.1855582379065463,  0.1882286462349947 ],
+  [0.29734497525053605, 0.6273635662554661,  0.07529145849399788],
+  [0.02703136138641234, 0.07068885253582723, 0.9913375368376388 ],
+];
+
+const LINEAR_REC2020_TO_XYZ:Matrix3 = [
+  [0.6369580483012914, 0.14461690358620832,  0.1688809751641721 ],
+  [0.2627002120112671, 0.6779980715188708,   0.05930171646986196],
+  [0.0,                0.028072693049087428, 1.060985057710791  ],
+];
+
+// Bradford chromatic adaptation from the D50 white point (used by Lab & LCH) to D65
+const D50_TO_D65:Matrix3 = [
+  [ 0.9554734527042182,   -0.023098536874261423, 0.0632593086610217  ],
+  [-0.028369706963208136,  1.0099954580058226,   0.021041398966943008],
+  [ 0.012314001688319899, -0.020507696433477912, 1.3303659366080753  ],
+];
+
+const D50_WHITE:[f64; 3] = [0.3457 / 0.3585, 1.0, (1.0 - 0.3457 - 0.3585) / 0.3585];
+
+fn mul(m:&Matrix3, v:[f64; 3]) -> [f64; 3]{
+  let row = |r:&[f64; 3]| r[0] * v[0] + r[1] * v[1] + r[2] * v[2];
+  [row(&m[0]), row(&m[1]), row(&m[2])]
+}
+
+// transfer functions are extended to negative values by mirroring them around zero
+fn srgb_to_linear(c:f64) -> f64{
+  let abs = c.abs();
+  let lin = if abs <= 0.04045 { abs / 12.92 } else { ((abs + 0.055) / 1.055).powf(2.4) };
+  lin.copysign(c)
+}
+
+fn linear_to_srgb(c:f64) -> f64{
+  let abs = c.abs();
+  let enc = if abs <= 0.0031308 { abs * 12.92 } else { 1.055 * abs.powf(1.0 / 2.4) - 0.055 };
+  enc.copysign(c)
+}
+
+fn a98_to_linear(c:f64) -> f64{
+  c.abs().powf(563.0 / 256.0).copysign(c)
+}
+
+fn rec2020_to_linear(c:f64) -> f64{
+  let (alpha, beta) = (1.09929682680944, 0.018053968510807);
+  let abs = c.abs();
+  let lin = if abs < beta * 4.5 { abs / 4.5 } else { ((abs + alpha - 1.0) / alpha).powf(1.0 / 0.45) };
+  lin.copysign(c)
+}
+
+fn xyz_to_srgb(xyz:[f64; 3]) -> [f64; 3]{
+  let [r, g, b] = mul(&XYZ_TO_LINEAR_SRGB, xyz);
+  [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b)]
+}
+
+fn lab_to_srgb(l:f64, a:f64, b:f64) -> [f64; 3]{
+  let (kappa, epsilon) = (24389.0 / 27.0, 216.0 / 24389.0);
+  let f1 = (l + 16.0) / 116.0;
+  let f0 = a / 500.0 + f1;
+  let f2 = f1 - b / 200.0;
+
+  let x = if f0.powi(3) > epsilon { f0.powi(3) } else { (116.0 * f0 - 16.0) / kappa };
+  let y = if l > kappa * epsilon { f1.powi(3) } else { l / kappa };
+  let z = if f2.powi(3) > epsilon { f2.powi(3) } else { (116.0 * f2 - 16.0) / kappa };
+  let xyz_d50 = [x * D50_WHITE[0], y * D50_WHITE[1], z * D50_WHITE[2]];
+  xyz_to_srgb(mul(&D50_TO_D65, xyz_d50))
+}
+
+fn oklab_to_srgb(l:f64, a:f64, b:f64) -> [f64; 3]{
+  let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+  let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+  let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+  [
+    linear_to_srgb( 4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_),
+    linear_to_srgb(-1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_),
+    linear_to_srgb(-0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_),
+  ]
+}
+
+fn hwb_to_srgb(hue:f64, white:f64, black:f64) -> [f64; 3]{
+  if white + black >= 1.0{
+    let gray = white / (white + black);
+    return [gray, gray, gray]
+  }
+
+  // start from the fully-saturated hue then mix in white & black
+  let channel = |n:f64| {
+    let k = (n + hue / 30.0).rem_euclid(12.0);
+    0.5 - 0.5 * (k - 3.0).min(9.0 - k).min(1.0).max(-1.0)
+  };
+  let scale = 1.0 - white - black;
+  [channel(0.0) * scale + white, channel(8.0) * scale + white, channel(4.0) * scale + white]
+}
+
+fn polar(chroma:f64, hue:f64) -> (f64, f64){
+  let radians = hue.to_radians();
+  (chroma * radians.cos(), chroma * radians.sin())
+}
+
+//
+// Tokenizing
+//
+
+#[derive(Clone, Copy)]
+enum Arg{
+  Number(f64),
+  Percent(f64),
+  Angle(f64),
+  None,
+}
+
+impl Arg{
+  // resolve a number or percentage, with 100% corresponding to `scale`
+  fn value(&self, scale:f64) -> Option<f64>{
+    match self{
+      Arg::Number(n) => Some(*n),
+      Arg::Percent(pct) => Some(pct / 100.0 * scale),
+      Arg::None => Some(0.0),
+      Arg::Angle(_) => None,
+    }
+  }
+
+  fn hue(&self) -> Option<f64>{
+    match self{
+      Arg::Number(deg) | Arg::Angle(deg) => Some(*deg),
+      Arg::None => Some(0.0),
+      Arg::Percent(_) => None,
+    }
+  }
+}
+
+fn parse_arg(token:&str) -> Option<Arg>{
+  let token = token.to_lowercase();
+  if token == "none"{
+    return Some(Arg::None)
+  }
+
+  let units = [("deg", 1.0), ("grad", 0.9), ("rad", 180.0 / std::f64::consts::PI), ("turn", 360.0)];
+  for (unit, factor) in units.iter(){
+    if let Some(num) = token.strip_suffix(unit){
+      return num.parse::<f64>().ok().map(|n| Arg::Angle(n * factor))
+    }
+  }
+
+  match token.strip_suffix('%'){
+    Some(num) => num.parse::<f64>().ok().map(Arg::Percent),
+    None => token.parse::<f64>().ok().map(Arg::Number),
+  }.filter(|arg| match arg{
+    Arg::Number(n) | Arg::Percent(n) => n.is_finite(),
+    _ => true
+  })
+}
+
+fn parse_function(css:&str) -> Option<(String, Vec<String>, Option<Arg>)>{
+  // split `name(a b c / alpha)` into its name, space-separated arguments, and optional alpha
+  let css = css.trim();
+  let open = css.find('(')?;
+  let body = css[open + 1..].strip_suffix(')')?;
+  let name = css[..open].trim().to_lowercase();
+
+  let (channels, alpha) = match body.split('/').collect::<Vec<_>>().as_slice(){
+    [channels] => (*channels, None),
+    [channels, alpha] => (*channels, Some(parse_arg(alpha.trim())?)),
+    _ => return None
+  };
+  let args = channels.split_whitespace().map(|s| s.to_string()).collect();
+  Some((name, args, alpha))
+}
+
+fn to_color(rgb:[f64; 3], alpha:Option<Arg>) -> Option<Color4f>{
+  let alpha = match alpha{
+    Some(arg) => arg.value(1.0)?.max(0.0).min(1.0),
+    None => 1.0
+  };
+  Some(Color4f::new(rgb[0] as f32, rgb[1] as f32, rgb[2] as f32, alpha as f32))
+}
+
+fn parse_level4(css:&str) -> Option<Color4f>{
+  let (name, args, alpha) = parse_function(css)?;
+
+  // the color() function's first argument names its color space
+  if name == "color"{
+    let (space, args) = args.split_first()?;
+    let vals = args.iter().map(|s| parse_arg(s)?.value(1.0)).collect::<Option<Vec<_>>>()?;
+    let [c1, c2, c3] = match vals.as_slice(){
+      [c1, c2, c3] => [*c1, *c2, *c3],
+      _ => return None
+    };
+    let rgb = match space.to_lowercase().as_str(){
+      "srgb" => [c1, c2, c3],
+      "srgb-linear" => [linear_to_srgb(c1), linear_to_srgb(c2), linear_to_srgb(c3)],
+      "display-p3" => xyz_to_srgb(mul(&LINEAR_P3_TO_XYZ, [srgb_to_linear(c1), srgb_to_linear(c2), srgb_to_linear(c3)])),
+      "a98-rgb" => xyz_to_srgb(mul(&LINEAR_A98_TO_XYZ, [a98_to_linear(c1), a98_to_linear(c2), a98_to_linear(c3)])),
+      "rec2020" => xyz_to_srgb(mul(&LINEAR_REC2020_TO_XYZ, [rec2020_to_linear(c1), rec2020_to_linear(c2), rec2020_to_linear(c3)])),
+      "xyz" | "xyz-d65" => xyz_to_srgb([c1, c2, c3]),
+      "xyz-d50" => xyz_to_srgb(mul(&D50_TO_D65, [c1, c2, c3])),
+      _ => return None
+    };
+    return to_color(rgb, alpha)
+  }
+
+  let args = args.iter().map(|s| parse_arg(s)).collect::<Option<Vec<_>>>()?;
+  let [a1, a2, a3] = match args.as_slice(){
+    [a1, a2, a3] => [*a1, *a2, *a3],
+    _ => return None
+  };
+
+  let rgb = match name.as_str(){
+    "lab" => lab_to_srgb(a1.value(100.0)?, a2.value(125.0)?, a3.value(125.0)?),
+    "lch" => {
+      let (a, b) = polar(a2.value(150.0)?.max(0.0), a3.hue()?);
+      lab_to_srgb(a1.value(100.0)?, a, b)
+    },
+    "oklab" => oklab_to_srgb(a1.value(1.0)?, a2.value(0.4)?, a3.value(0.4)?),
+    "oklch" => {
+      let (a, b) = polar(a2.value(0.4)?.max(0.0), a3.hue()?);
+      oklab_to_srgb(a1.value(1.0)?, a, b)
+    },
+    "hwb" => hwb_to_srgb(a1.hue()?, a2.value(100.0)? / 100.0, a3.value(100.0)? / 100.0),
+    _ => return None
+  };
+  to_color(rgb, alpha)
+}
+
+pub fn parse_color(css:&str) -> Option<Color4f>{
+  // legacy syntaxes (hex, names, rgb() & hsl()) are handled by css-color
+  match css.parse::<Rgba>(){
+    Ok(Rgba{red, green, blue, alpha}) => Some(Color4f::new(red, green, blue, alpha)),
+    Err(_) => parse_level4(css)
+  }
+}
+
+pub fn in_srgb_gamut(color:&Color4f) -> bool{
+  // allow for rounding errors from round-tripping through other spaces
+  [color.r, color.g, color.b].iter().all(|c| *c > -0.001 && *c < 1.001)
+}
+
+//
+// Gradient interpolation
+//
+
+const LINEAR_SRGB_TO_XYZ:Matrix3 = [
+  [0.41239079926595934, 0.357584339383878,   0.1804807884018343 ],
+  [0.21263900587151027, 0.715168678767756,   0.07219231536073371],
+  [0.01933081871559182, 0.11919477979462598, 0.9505321522496607 ],
+];
+
+const D65_TO_D50:Matrix3 = [
+  [ 1.0479298208405488,    0.022946793341019088, -0.05019222954313557 ],
+  [ 0.029627815688159344,  0.990434484573249,    -0.01707382502938514 ],
+  [-0.009243058152591178,  0.015055144896577895,  0.7518742899580008  ],
+];
+
+fn srgb_to_lab(rgb:[f64; 3]) -> [f64; 3]{
+  let (kappa, epsilon) = (24389.0 / 27.0, 216.0 / 24389.0);
+  let linear = [srgb_to_linear(rgb[0]), srgb_to_linear(rgb[1]), srgb_to_linear(rgb[2])];
+  let xyz = mul(&D65_TO_D50, mul(&LINEAR_SRGB_TO_XYZ, linear));
+  let f = |t:f64| if t > epsilon { t.cbrt() } else { (kappa * t + 16.0) / 116.0 };
+  let (fx, fy, fz) = (f(xyz[0] / D50_WHITE[0]), f(xyz[1] / D50_WHITE[1]), f(xyz[2] / D50_WHITE[2]));
+  [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn srgb_to_oklab(rgb:[f64; 3]) -> [f64; 3]{
+  let [r, g, b] = [srgb_to_linear(rgb[0]), srgb_to_linear(rgb[1]), srgb_to_linear(rgb[2])];
+  let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+  let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+  let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+  [
+    0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+    1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+    0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+  ]
+}
+
+fn to_polar([l, a, b]:[f64; 3]) -> [f64; 3]{
+  [l, (a * a + b * b).sqrt(), b.atan2(a).to_degrees().rem_euclid(360.0)]
+}
+
+fn from_polar([l, c, h]:[f64; 3]) -> [f64; 3]{
+  let (a, b) = polar(c, h);
+  [l, a, b]
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum InterpolationSpace{ SRGB, LinearSRGB, Lab, LCH, OKLab, OKLCH }
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum HueMethod{ Shorter, Longer, Increasing, Decreasing }
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Interpolation{
+  pub space: InterpolationSpace,
+  pub hue: HueMethod,
+  pub premultiplied: bool,
+}
+
+impl Default for Interpolation{
+  fn default() -> Self{
+    Interpolation{ space:InterpolationSpace::SRGB, hue:HueMethod::Shorter, premultiplied:false }
+  }
+}
+
+pub fn to_interpolation_space(space_name:&str) -> Option<InterpolationSpace>{
+  let space = match space_name.to_lowercase().as_str(){
+    "srgb" => InterpolationSpace::SRGB,
+    "srgb-linear" => InterpolationSpace::LinearSRGB,
+    "lab" => InterpolationSpace::Lab,
+    "lch" => InterpolationSpace::LCH,
+    "oklab" => InterpolationSpace::OKLab,
+    "oklch" => InterpolationSpace::OKLCH,
+    _ => return None
+  };
+  Some(space)
+}
+
+pub fn from_interpolation_space(space:InterpolationSpace) -> String{
+  match space{
+    InterpolationSpace::SRGB => "srgb",
+    InterpolationSpace::LinearSRGB => "srgb-linear",
+    InterpolationSpace::Lab => "lab",
+    InterpolationSpace::LCH => "lch",
+    InterpolationSpace::OKLab => "oklab",
+    InterpolationSpace::OKLCH => "oklch",
+  }.to_string()
+}
+
+pub fn to_hue_method(method_name:&str) -> Option<HueMethod>{
+  let method = match method_name.to_lowercase().as_str(){
+    "shorter" => HueMethod::Shorter,
+    "longer" => HueMethod::Longer,
+    "increasing" => HueMethod::Increasing,
+    "decreasing" => HueMethod::Decreasing,
+    _ => return None
+  };
+  Some(method)
+}
+
+pub fn from_hue_method(method:HueMethod) -> String{
+  match method{
+    HueMethod::Shorter => "shorter",
+    HueMethod::Longer => "longer",
+    HueMethod::Increasing => "increasing",
+    HueMethod::Decreasing => "decreasing",
+  }.to_string()
+}
+
+impl Interpolation{
+  fn is_polar(&self) -> bool{
+    matches!(self.space, InterpolationSpace::LCH | InterpolationSpace::OKLCH)
+  }
+
+  fn encode(&self, color:&Color4f) -> [f64; 3]{
+    let rgb = [color.r as f64, color.g as f64, color.b as f64];
+    match self.space{
+      InterpolationSpace::SRGB => rgb,
+      InterpolationSpace::LinearSRGB => [srgb_to_linear(rgb[0]), srgb_to_linear(rgb[1]), srgb_to_linear(rgb[2])],
+      InterpolationSpace::Lab => srgb_to_lab(rgb),
+      InterpolationSpace::LCH => to_polar(srgb_to_lab(rgb)),
+      InterpolationSpace::OKLab => srgb_to_oklab(rgb),
+      InterpolationSpace::OKLCH => to_polar(srgb_to_oklab(rgb)),
+    }
+  }
+
+  fn decode(&self, [c1, c2, c3]:[f64; 3], alpha:f64) -> Color4f{
+    let rgb = match self.space{
+      InterpolationSpace::SRGB => [c1, c2, c3],
+      InterpolationSpace::LinearSRGB => [linear_to_srgb(c1), linear_to_srgb(c2), linear_to_srgb(c3)],
+      InterpolationSpace::Lab => lab_to_srgb(c1, c2, c3),
+      InterpolationSpace::LCH => { let [l, a, b] = from_polar([c1, c2, c3]); lab_to_srgb(l, a, b) },
+      InterpolationSpace::OKLab => oklab_to_srgb(c1, c2, c3),
+      InterpolationSpace::OKLCH => { let [l, a, b] = from_polar([c1, c2, c3]); oklab_to_srgb(l, a, b) },
+    };
+    Color4f::new(rgb[0] as f32, rgb[1] as f32, rgb[2] as f32, alpha as f32)
+  }
+
+  pub fn mix(&self, from:&Color4f, to:&Color4f, t:f64) -> Color4f{
+    // blend two colors the way CSS gradients do: (optionally) in premultiplied form, with hues
+    // adjusted according to the hue interpolation method (and ignored for achromatic colors)
+    let (mut a, mut b) = (self.encode(from), self.encode(to));
+    let (alpha_a, alpha_b) = (from.a as f64, to.a as f64);
+
+    if self.is_polar(){
+      let achromatic = |c:&[f64; 3]| c[1] < 1e-4;
+      if achromatic(&a) { a[2] = b[2] }
+      if achromatic(&b) { b[2] = a[2] }
+      let delta = b[2] - a[2];
+      match self.hue{
+        HueMethod::Shorter if delta > 180.0 => a[2] += 360.0,
+        HueMethod::Shorter if delta < -180.0 => b[2] += 360.0,
+        HueMethod::Longer if delta > 0.0 && delta < 180.0 => a[2] += 360.0,
+        HueMethod::Longer if delta > -180.0 && delta <= 0.0 => b[2] += 360.0,
+        HueMethod::Increasing if delta < 0.0 => b[2] += 360.0,
+        HueMethod::Decreasing if delta > 0.0 => a[2] += 360.0,
+        _ => {}
+      }
+    }
+
+    // the hue channel of polar spaces isn't premultiplied
+    let premul_channels = match (self.premultiplied, self.is_polar()){
+      (false, _) => 0,
+      (true, true) => 2,
+      (true, false) => 3,
+    };
+    for i in 0..premul_channels{
+      a[i] *= alpha_a;
+      b[i] *= alpha_b;
+    }
+
+    let alpha = alpha_a + (alpha_b - alpha_a) * t;
+    let mut mixed = [0.0; 3];
+    for i in 0..3{
+      mixed[i] = a[i] + (b[i] - a[i]) * t;
+      if i < premul_channels && alpha > 0.0{
+        mixed[i] /= alpha;
+      }
+    }
+    if self.is_polar(){
+      mixed[2] = mixed[2].rem_euclid(360.0);
+    }
+    self.decode(mixed, alpha)
+  }
+}
@@ -0,0 +1,146 @@
+use neon::prelude::*;
+use skia_safe::{Image as SkImage, ImageInfo, ColorType, AlphaType, Data, images};
+use skia_safe::image::CachingHint;
+
+use crate::utils::*;
+use crate::image::{JsImage, image_arg, decode_frame};
+
+// the largest possible value returned by color_delta (black vs. white)
+const MAX_DELTA:f64 = 35215.0;
+
+struct Pixels{
+  width: usize,
+  height: usize,
+  data: Vec<u8>,
+}
+
+impl Pixels{
+  fn from_image(image:&SkImage) -> Option<Self>{
+    let (width, height) = (image.width(), image.height());
+    let info = ImageInfo::new((width, height), ColorType::RGBA8888, AlphaType::Unpremul, None);
+    let row_bytes = info.min_row_bytes();
+    let mut data = vec![0u8; info.compute_byte_size(row_bytes)];
+    match image.read_pixels(&info, &mut data, row_bytes, (0, 0), CachingHint::Disallow){
+      true => Some(Pixels{ width:width as usize, height:height as usize, data }),
+      false => None
+    }
+  }
+
+  fn at(&self, x:usize, y:usize) -> &[u8]{
+    let i = (y * self.width + x) * 4;
+    &self.data[i..i + 4]
+  }
+}
+
+fn color_delta(a:&[u8], b:&[u8]) -> f64{
+  // perceptual distance in YIQ space (after compositing both colors against white) as described in
+  // "Measuring perceived color difference using YIQ NTSC transmission color space" (Kotsarenko & Ramos)
+  let blend = |c:u8, alpha:u8| 255.0 + (c as f64 - 255.0) * alpha as f64 / 255.0;
+  let (r1, g1, b1) = (blend(a[0], a[3]), blend(a[1], a[3]), blend(a[2], a[3]));
+  let (r2, g2, b2) = (blend(b[0], b[3]), blend(b[1], b[3]), blend(b[2], b[3]));
+  let (dr, dg, db) = (r1 - r2, g1 - g2, b1 - b2);
+
+  let y = dr * 0.29889531 + dg * 0.58662247 + db * 0.11448223;
+  let i = dr * 0.59597799 - dg * 0.27417610 - db * 0.32180189;
+  let q = dr * 0.21147017 - dg * 0.52261711 + db * 0.31114694;
+  0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
+
+fn has_match_nearby(a:&Pixels, b:&Pixels, x:usize, y:usize, radius:usize, max_delta:f64) -> bool{
+  // look for a pixel in `b` within `radius` of (x, y) that's close enough to `a`'s pixel at (x, y)
+  let color = a.at(x, y);
+  let (x0, x1) = (x.saturating_sub(radius), (x + radius).min(b.width - 1));
+  let (y0, y1) = (y.saturating_sub(radius), (y + radius).min(b.height - 1));
+  (y0..=y1).any(|ny| (x0..=x1).any(|nx| color_delta(color, b.at(nx, ny)) <= max_delta))
+}
+
+pub struct Comparison{
+  pub mismatched: usize,
+  pub antialiased: usize,
+  pub max_difference: f64,
+  pub diff: Vec<u8>,
+}
+
+fn compare(a:&Pixels, b:&Pixels, threshold:f64, radius:usize) -> Comparison{
+  let max_delta = MAX_DELTA * threshold * threshold;
+  let mut result = Comparison{ mismatched:0, antialiased:0, max_difference:0.0, diff:vec![0; a.data.len()] };
+
+  for y in 0..a.height{
+    for x in 0..a.width{
+      let delta = color_delta(a.at(x, y), b.at(x, y));
+      result.max_difference = result.max_difference.max(delta / MAX_DELTA);
+
+      // matching pixels are drawn as a faded grayscale copy of the first image, differences
+      // in red, and differences that are explained by a small offset (i.e., antialiasing) in yellow
+      let color = if delta <= max_delta{
+        let px = a.at(x, y);
+        let gray = 0.29889531 * px[0] as f64 + 0.58662247 * px[1] as f64 + 0.11448223 * px[2] as f64;
+        let faded = (255.0 + (gray - 255.0) * 0.1 * px[3] as f64 / 255.0) as u8;
+        [faded, faded, faded, 255]
+      }else if radius > 0 && has_match_nearby(a, b, x, y, radius, max_delta) && has_match_nearby(b, a, x, y, radius, max_delta){
+        result.antialiased += 1;
+        [255, 255, 0, 255]
+      }else{
+        result.mismatched += 1;
+        [255, 0, 0, 255]
+      };
+
+      let i = (y * a.width + x) * 4;
+      result.diff[i..i + 4].copy_from_slice(&color);
+    }
+  }
+  result
+}
+
+fn pixels_arg(cx: &mut FunctionContext, idx:usize) -> Result<Pixels, neon::result::Throw>{
+  // accept encoded image data in a Buffer, or an Image or Canvas object
+  let arg = cx.argument::<JsValue>(idx)?;
+  let image = match arg.downcast::<JsBuffer>(){
+    Ok(buffer) => {
+      let bytes = cx.borrow(&buffer, |buf_data| Data::new_copy(buf_data.as_slice()));
+      decode_frame(&bytes, 0)
+    },
+    Err(_) => image_arg(cx, idx)?
+  };
+
+  match image.as_ref().and_then(Pixels::from_image){
+    Some(pixels) => Ok(pixels),
+    None => cx.throw_error(format!("Could not decode image #{}", idx + 1))
+  }
+}
+
+pub fn compare_images(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let a = pixels_arg(&mut cx, 0)?;
+  let b = pixels_arg(&mut cx, 1)?;
+  let threshold = float_arg(&mut cx, 2, "threshold")? as f64;
+  let radius = float_arg(&mut cx, 3, "antialiasTolerance")?.max(0.0) as usize;
+  let mut diff_image = cx.argument::<JsImage>(4)?;
+
+  if !(0.0..=1.0).contains(&threshold){
+    return cx.throw_range_error("The comparison threshold must be between 0 and 1")
+  }else if (a.width, a.height) != (b.width, b.height){
+    return cx.throw_range_error(format!(
+      "Image sizes do not match ({}×{} vs. {}×{})", a.width, a.height, b.width, b.height
+    ))
+  }
+
+  let result = compare(&a, &b, threshold, radius);
+  let info = ImageInfo::new((a.width as i32, a.height as i32), ColorType::RGBA8888, AlphaType::Unpremul, None);
+  let diff = images::raster_from_data(&info, Data::new_copy(&result.diff), info.min_row_bytes());
+  cx.borrow_mut(&mut diff_image, |mut img| img.image = diff );
+
+  let stats = JsObject::new(&mut cx);
+  let total = (a.width * a.height) as f64;
+  let (mismatched, antialiased) = (result.mismatched as f64, result.antialiased as f64);
+  for (key, val) in [
+    ("mismatched", mismatched),
+    ("antialiased", antialiased),
+    ("total", total),
+    ("ratio", if total > 0.0 { mismatched / total } else { 0.0 }),
+    ("maxDifference", result.max_difference.sqrt()),
+  ].iter(){
+    let val = cx.number(*val);
+    stats.set(&mut cx, *key, val)?;
+  }
+  Ok(stats)
+}
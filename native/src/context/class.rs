@@ -1,14 +1,14 @@
 use std::f32::consts::PI;
 use neon::prelude::*;
-use skia_safe::{Path, Matrix, Rect, PathDirection};
+use skia_safe::{Path, Matrix, Rect, Point, Color, TileMode, RSXform, Vertices, vertices::VertexMode, PathDirection, ImageInfo, AlphaType, BlendMode};
 use skia_safe::path::AddPathMode::Append;
-use skia_safe::textlayout::{TextDirection};
 use skia_safe::PaintStyle::{Fill, Stroke};
 
-use super::{Context2D, Dye};
-use crate::canvas::{JsCanvas, canvas_context};
+use super::{Context2D, Dye, DashMarker, PathEffectSpec, TextSpan, stash_ref, fetch_ref, trim_refs};
+use crate::canvas::{JsCanvas, canvas_context, canvas_picture, refresh_links};
 use crate::path::{Path2D, JsPath2D};
-use crate::image::{JsImage, JsImageData};
+use crate::image::{JsImage, JsImageData, image_arg};
+use crate::shader::JsCanvasShader;
 use crate::typography::*;
 use crate::utils::*;
 
@@ -41,6 +41,7 @@ declare_types! {
     method save(mut cx){
       let mut this = cx.this();
       cx.borrow_mut(&mut this, |mut this| this.push() );
+      trim_refs(&mut cx)?;
       Ok(cx.undefined().upcast())
     }
 
@@ -73,6 +74,18 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
+    method transformPerspective(mut cx){
+      let mut this = cx.this();
+      let px = float_arg(&mut cx, 0, "px")?;
+      let py = float_arg(&mut cx, 1, "py")?;
+      let matrix = Matrix::new_all(1.0, 0.0, 0.0, 0.0, 1.0, 0.0, px, py, 1.0);
+
+      cx.borrow_mut(&mut this, |mut this| {
+        this.with_matrix(|ctm| ctm.pre_concat(&matrix) );
+      });
+      Ok(cx.undefined().upcast())
+    }
+
     method translate(mut cx){
       let mut this = cx.this();
       let dx = float_arg(&mut cx, 0, "deltaX")?;
@@ -270,6 +283,49 @@ declare_types! {
 
     // hit testing ----------------------------------------------------------------------
 
+    method _clipBounds(mut cx){
+      let this = cx.this();
+      let local = bool_arg(&mut cx, 0, "local")?;
+      let bounds = cx.borrow(&this, |this| this.clip_bounds(local) );
+      floats_to_array(&mut cx, &[bounds.left, bounds.top, bounds.width(), bounds.height()])
+    }
+
+    method _contentBounds(mut cx){
+      let mut this = cx.this();
+      let local = bool_arg(&mut cx, 0, "local")?;
+      refresh_links(&mut cx, &mut this)?;
+      let bounds = cx.borrow_mut(&mut this, |mut this| this.content_bounds(local) );
+      floats_to_array(&mut cx, &[bounds.left, bounds.top, bounds.width(), bounds.height()])
+    }
+
+    method _localToDevice(mut cx){
+      let this = cx.this();
+      let nums = float_args(&mut cx, 0..2)?;
+      let point = cx.borrow(&this, |this| this.map_points(&nums)[0] );
+      floats_to_array(&mut cx, &[point.x, point.y])
+    }
+
+    method _deviceToLocal(mut cx){
+      let mut this = cx.this();
+      let nums = float_args(&mut cx, 0..2)?;
+      let point = cx.borrow_mut(&mut this, |mut this| this.in_local_coordinates(nums[0], nums[1]) );
+      floats_to_array(&mut cx, &[point.x, point.y])
+    }
+
+    method _stats(mut cx){
+      let mut this = cx.this();
+      let stats = cx.borrow_mut(&mut this, |mut this| this.stats() );
+      floats_to_array(&mut cx, &stats)
+    }
+
+    method _isRectVisible(mut cx){
+      let this = cx.this();
+      let nums = float_args(&mut cx, 0..4)?;
+      let rect = Rect::from_xywh(nums[0], nums[1], nums[2], nums[3]);
+      let visible = cx.borrow(&this, |this| this.is_rect_visible(rect) );
+      Ok(cx.boolean(visible).upcast())
+    }
+
     method isPointInPath(mut cx){
       let mut this = cx.this();
       let (mut container, shift) = match cx.argument::<JsValue>(0)?.is_a::<JsPath2D>(){
@@ -371,6 +427,41 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
+    method _fillRects(mut cx){
+      let mut this = cx.this();
+      let nums = float_buffer_arg(&mut cx, 0)?;
+      if nums.len() % 4 != 0{
+        return cx.throw_range_error("Rects must be specified as x, y, width, height quadruples")
+      }
+
+      let rects:Vec<Rect> = nums.chunks(4).map(|r| Rect::from_xywh(r[0], r[1], r[2], r[3])).collect();
+      cx.borrow_mut(&mut this, |mut this| {
+        let paint = this.paint_for_fill();
+        this.draw_rects(&rects, &paint);
+      });
+      Ok(cx.undefined().upcast())
+    }
+
+    method _drawPoints(mut cx){
+      let mut this = cx.this();
+      let mode_name = string_arg(&mut cx, 0, "mode")?;
+      let mode = match to_point_mode(&mode_name){
+        Some(mode) => mode,
+        None => return cx.throw_type_error(format!("Unknown point mode \"{}\" (expected \"points\", \"lines\", or \"polygon\")", mode_name))
+      };
+      let nums = float_buffer_arg(&mut cx, 1)?;
+      if nums.len() % 2 != 0{
+        return cx.throw_range_error("Points must be specified as x, y pairs")
+      }
+
+      let points:Vec<Point> = nums.chunks(2).map(|p| Point::new(p[0], p[1])).collect();
+      cx.borrow_mut(&mut this, |mut this| {
+        let paint = this.paint_for_stroke();
+        this.draw_points(mode, &points, &paint);
+      });
+      Ok(cx.undefined().upcast())
+    }
+
     method clearRect(mut cx){
       let mut this = cx.this();
       let nums = float_args(&mut cx, 0..4)?;
@@ -453,6 +544,94 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
+    method _getLineDashMarker(mut cx){
+      let this = cx.this();
+      let mut target = cx.argument::<JsPath2D>(0)?;
+      match cx.borrow(&this, |this| this.state.line_dash_marker.clone() ){
+        Some(marker) => {
+          cx.borrow_mut(&mut target, |mut target| target.path = marker.path );
+          let info = JsArray::new(&mut cx, 4);
+          let (spacing, offset) = (cx.number(marker.spacing), cx.number(marker.offset));
+          let (rotate, morph) = (cx.boolean(marker.rotate), cx.boolean(marker.morph));
+          info.set(&mut cx, 0, spacing)?;
+          info.set(&mut cx, 1, offset)?;
+          info.set(&mut cx, 2, rotate)?;
+          info.set(&mut cx, 3, morph)?;
+          Ok(info.upcast())
+        },
+        None => Ok(cx.null().upcast())
+      }
+    }
+
+    method _setLineDashMarker(mut cx){
+      let mut this = cx.this();
+      if cx.argument::<JsValue>(0)?.is_a::<JsNull>(){
+        cx.borrow_mut(&mut this, |mut this| this.state.line_dash_marker = None );
+        return Ok(cx.undefined().upcast())
+      }
+
+      let marker = cx.argument::<JsPath2D>(0)?;
+      let path = cx.borrow(&marker, |marker| marker.path.clone() );
+      let spacing = opt_float_arg(&mut cx, 1).unwrap_or(path.bounds().width());
+      let offset = float_arg(&mut cx, 2, "offset")?;
+      let rotate = bool_arg(&mut cx, 3, "rotate")?;
+      let morph = bool_arg(&mut cx, 4, "morph")?;
+      if !(spacing > 0.0) {
+        return cx.throw_range_error("Marker spacing must be greater than zero")
+      }
+
+      cx.borrow_mut(&mut this, |mut this| {
+        this.state.line_dash_marker = Some(DashMarker{ path, spacing, offset, rotate, morph });
+      });
+      Ok(cx.undefined().upcast())
+    }
+
+    method _getPathEffect(mut cx){
+      let this = cx.this();
+      let spec = cx.borrow(&this, |this| this.state.path_effect );
+      let (kind, values) = match spec{
+        Some(PathEffectSpec::Corner{radius}) => ("corner", vec![radius]),
+        Some(PathEffectSpec::Discrete{seg_length, deviation, seed}) => ("discrete", vec![seg_length, deviation, seed as f32]),
+        None => return Ok(cx.null().upcast())
+      };
+      let info = JsArray::new(&mut cx, 1 + values.len() as u32);
+      let kind = cx.string(kind);
+      info.set(&mut cx, 0, kind)?;
+      for (i, val) in values.iter().enumerate(){
+        let val = cx.number(*val);
+        info.set(&mut cx, i as u32 + 1, val)?;
+      }
+      Ok(info.upcast())
+    }
+
+    method _setPathEffect(mut cx){
+      let mut this = cx.this();
+      if cx.argument::<JsValue>(0)?.is_a::<JsNull>(){
+        cx.borrow_mut(&mut this, |mut this| this.state.path_effect = None );
+        return Ok(cx.undefined().upcast())
+      }
+
+      let kind = string_arg(&mut cx, 0, "type")?;
+      let spec = match kind.as_str(){
+        "corner" => {
+          let radius = float_arg(&mut cx, 1, "radius")?;
+          if !(radius > 0.0){ return cx.throw_range_error("Corner radius must be greater than zero") }
+          PathEffectSpec::Corner{radius}
+        },
+        "discrete" => {
+          let seg_length = float_arg(&mut cx, 1, "segLength")?;
+          let deviation = float_arg(&mut cx, 2, "deviation")?;
+          let seed = float_arg(&mut cx, 3, "seed")?.max(0.0) as u32;
+          if !(seg_length > 0.0){ return cx.throw_range_error("Segment length must be greater than zero") }
+          PathEffectSpec::Discrete{seg_length, deviation, seed}
+        },
+        _ => return cx.throw_type_error(format!("Unknown path effect \"{}\" (expected \"corner\" or \"discrete\")", kind))
+      };
+
+      cx.borrow_mut(&mut this, |mut this| this.state.path_effect = Some(spec) );
+      Ok(cx.undefined().upcast())
+    }
+
     // line style properties  -----------------------------------------------------------
 
     method get_lineCap(mut cx){
@@ -500,6 +679,22 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
+    method get_lineAlignment(mut cx){
+      let this = cx.this();
+      let mode = cx.borrow(&this, |this| this.state.line_alignment );
+      let name = from_line_alignment(mode);
+      Ok(cx.string(name).upcast())
+    }
+
+    method set_lineAlignment(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "lineAlignment")?;
+      if let Some(mode) = to_line_alignment(&name){
+        cx.borrow_mut(&mut this, |mut this|{ this.state.line_alignment = mode; });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
     method get_lineWidth(mut cx){
       let this = cx.this();
       let num = cx.borrow(&this, |this| this.state.paint.stroke_width() );
@@ -519,6 +714,28 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
+    method get_strokePrecision(mut cx){
+      let this = cx.this();
+      match cx.borrow(&this, |this| this.state.stroke_precision ){
+        Some(num) => Ok(cx.number(num as f64).upcast()),
+        None => Ok(cx.string("auto").upcast())
+      }
+    }
+
+    method set_strokePrecision(mut cx){
+      let mut this = cx.this();
+      let precision = match opt_float_arg(&mut cx, 0){
+        Some(num) if num > 0.0 => Some(num),
+        Some(_) => return Ok(cx.undefined().upcast()),
+        None => match opt_string_arg(&mut cx, 0).as_deref(){
+          Some("auto") => None,
+          _ => return Ok(cx.undefined().upcast())
+        }
+      };
+      cx.borrow_mut(&mut this, |mut this| this.state.stroke_precision = precision );
+      Ok(cx.undefined().upcast())
+    }
+
     method get_miterLimit(mut cx){
       let this = cx.this();
       let num = cx.borrow(&this, |this| this.state.paint.stroke_miter() );
@@ -540,18 +757,23 @@ declare_types! {
       let mut this = cx.this();
       let arg = cx.argument::<JsObject>(0)?;
       let canvas = arg.downcast::<JsCanvas>().ok();
+      let context = arg.downcast::<JsContext2D>().ok();
       let image = arg.downcast::<JsImage>().ok();
 
       let dims = if let Some(canvas) = canvas{
         cx.borrow(&canvas, |canvas| Some(
           (canvas.width as i32, canvas.height as i32)
         ))
+      }else if let Some(context) = context{
+        cx.borrow(&context, |ctx| Some(
+          (ctx.width() as i32, ctx.height() as i32)
+        ))
       }else if let Some(image) = image{
         cx.borrow(&image, |img| img.image.as_ref().map(|img|
           (img.width(), img.height())
         ))
       }else{
-        return cx.throw_type_error("Expected an Image or a Canvas argument")
+        return cx.throw_type_error("Expected an Image, Canvas, or CanvasRenderingContext2D argument")
       };
 
       let (width, height) = match dims{
@@ -576,17 +798,287 @@ declare_types! {
 
       if let Some(img) = image {
         cx.borrow_mut(&mut this, |mut this| {
-          cx.borrow(&img, |img| {
-            this.draw_image(&img.image, &src, &dst);
+          cx.borrow(&img, |img| match &img.picture {
+            Some(pict) => this.draw_picture(pict, &src, &dst),
+            None => this.draw_image(&img.image, &src, &dst)
           });
         });
-      }else if let Some(canvas) = canvas {
-        let mut drobble = canvas_context(&mut cx, &canvas, |ctx| ctx.get_drawable() )?;
-        cx.borrow_mut(&mut this, |mut this| {
-            this.draw_drawable(&mut drobble, &src, &dst);
-        });
+      }else{
+        // splice the source's vector content into this recording rather than rasterizing it
+        let pict = match (canvas, context){
+          (Some(canvas), _) => canvas_picture(&mut cx, &canvas)?,
+          (_, Some(mut context)) => cx.borrow_mut(&mut context, |mut ctx| ctx.get_picture(None) ),
+          _ => None
+        };
+        if let Some(pict) = pict{
+          cx.borrow_mut(&mut this, |mut this| {
+            this.draw_picture(&pict, &src, &dst);
+          });
+        }
+      }
+
+      Ok(cx.undefined().upcast())
+    }
+
+    method _linkTo(mut cx){
+      let mut this = cx.this();
+      let url = string_arg(&mut cx, 0, "url")?;
+      let rect = match cx.len() > 1{
+        true => {
+          let nums = float_args(&mut cx, 1..5)?;
+          Some(Rect::from_xywh(nums[0], nums[1], nums[2], nums[3]))
+        },
+        false => None
+      };
+      cx.borrow_mut(&mut this, |mut this| this.annotate_url(&url, rect) );
+      Ok(cx.undefined().upcast())
+    }
+
+    method _addDestination(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "name")?;
+      let nums = float_args(&mut cx, 1..3)?;
+      let point = Point::new(nums[0], nums[1]);
+      cx.borrow_mut(&mut this, |mut this| this.annotate_destination(&name, point) );
+      Ok(cx.undefined().upcast())
+    }
+
+    method _drawCanvas(mut cx){
+      let mut this = cx.this();
+      let canvas = cx.argument::<JsCanvas>(0)?;
+      let x = float_arg(&mut cx, 1, "x")?;
+      let y = float_arg(&mut cx, 2, "y")?;
+      let picture = canvas_picture(&mut cx, &canvas)?;
+
+      // hold onto the source canvas so its content can be refreshed each time this one is rendered
+      let sym = symbol(&mut cx, "liveLinks")?;
+      let links = match this.get(&mut cx, sym)?.downcast::<JsArray>(){
+        Ok(array) => array,
+        Err(_e) => {
+          let array = JsArray::new(&mut cx, 0);
+          this.set(&mut cx, sym, array)?;
+          array
+        }
+      };
+      let idx = cx.borrow(&this, |this| this.link_count() as f64 );
+      let (len, length) = (cx.number(idx + 1.0), cx.string("length"));
+      links.set(&mut cx, length, len)?;
+      links.set(&mut cx, idx as u32, canvas)?;
+
+      cx.borrow_mut(&mut this, |mut this| this.link_canvas(picture, Point::new(x, y)) );
+      Ok(cx.undefined().upcast())
+    }
+
+    method _drawImageNine(mut cx){
+      let mut this = cx.this();
+      let nums = float_args(&mut cx, 1..9)?;
+      let filter = match opt_string_arg(&mut cx, 9){
+        Some(name) => match to_filter_mode(&name){
+          Some(mode) => Some(mode),
+          None => return cx.throw_type_error(format!("Unknown filterMode \"{}\" (expected \"nearest\" or \"linear\")", name))
+        },
+        None => None
+      };
+
+      let image = image_arg(&mut cx, 0)?;
+
+      let image = match image{
+        Some(image) => image,
+        None => return cx.throw_error("Cannot draw incomplete image (has it finished loading?)")
+      };
+
+      // the center must lie within the image and leave room for the corners
+      let center = Rect::from_xywh(nums[0], nums[1], nums[2], nums[3]).round();
+      if !image.bounds().contains(&center) || center.is_empty(){
+        return cx.throw_range_error("The center rect must be non-empty and lie within the image bounds")
+      }
+
+      let dst = Rect::from_xywh(nums[4], nums[5], nums[6], nums[7]);
+      cx.borrow_mut(&mut this, |mut this| {
+        this.draw_image_nine(&image, &center, &dst, filter);
+      });
+
+      Ok(cx.undefined().upcast())
+    }
+
+    method _drawImageMesh(mut cx){
+      let mut this = cx.this();
+      let image = match image_arg(&mut cx, 0)?{
+        Some(image) => image,
+        None => return cx.throw_error("Cannot draw incomplete image (has it finished loading?)")
+      };
+
+      let points = cx.argument::<JsArray>(1)?.to_vec(&mut cx)?;
+      let uvs = cx.argument::<JsArray>(2)?.to_vec(&mut cx)?;
+      let (positions, texs) = (points_in(&points), points_in(&uvs));
+      if positions.len() * 2 != points.len() || texs.len() * 2 != uvs.len() {
+        return cx.throw_type_error("Mesh points & uvs must be lists of finite x/y coordinates")
+      }else if positions.len() != texs.len(){
+        return cx.throw_range_error(format!("Expected a uv for each of the {} mesh points (got {})", positions.len(), texs.len()))
+      }else if positions.len() > u16::MAX as usize{
+        return cx.throw_range_error(format!("Meshes are limited to {} points", u16::MAX))
+      }
+
+      let indices = match cx.argument_opt(3){
+        Some(arg) if arg.is_a::<JsArray>() => {
+          let list = cx.argument::<JsArray>(3)?.to_vec(&mut cx)?;
+          let nums = floats_in(&list);
+          if nums.len() != list.len() || nums.iter().any(|n| n.fract() != 0.0 || *n < 0.0 || *n as usize >= positions.len()){
+            return cx.throw_range_error("Mesh indices must be integers referring to the mesh points")
+          }
+          Some(nums.iter().map(|n| *n as u16).collect::<Vec<u16>>())
+        },
+        _ => None
+      };
+
+      // each consecutive trio of points (or indices) defines a triangle
+      let count = indices.as_ref().map(|idx| idx.len()).unwrap_or(positions.len());
+      if count % 3 != 0{
+        return cx.throw_range_error(format!("Meshes must consist of whole triangles (got {} vertices)", count))
+      }
+
+      cx.borrow_mut(&mut this, |mut this| {
+        this.draw_image_mesh(&image, &positions, &texs, indices.as_deref());
+      });
+
+      Ok(cx.undefined().upcast())
+    }
+
+    method _drawMesh(mut cx){
+      let mut this = cx.this();
+      let mode = match string_arg(&mut cx, 0, "mode")?.as_str(){
+        "triangles" => VertexMode::Triangles,
+        "strip" => VertexMode::TriangleStrip,
+        "fan" => VertexMode::TriangleFan,
+        other => return cx.throw_type_error(format!("Unknown mesh mode \"{}\" (expected \"triangles\", \"strip\", or \"fan\")", other))
+      };
+
+      let nums = float_buffer_arg(&mut cx, 1)?;
+      if nums.len() % 2 != 0{
+        return cx.throw_range_error("Mesh positions must be specified as x, y pairs")
+      }
+      let positions:Vec<Point> = nums.chunks(2).map(|p| Point::new(p[0], p[1])).collect();
+      if positions.len() > u16::MAX as usize{
+        return cx.throw_range_error(format!("Meshes are limited to {} points", u16::MAX))
+      }
+
+      // texture coordinates default to the positions themselves (i.e., canvas coordinates)
+      let tex_coords = match cx.argument_opt(2){
+        Some(arg) if arg.is_a::<JsBuffer>() => {
+          let nums = float_buffer_arg(&mut cx, 2)?;
+          if nums.len() % 2 != 0{
+            return cx.throw_range_error("Mesh texCoords must be specified as x, y pairs")
+          }
+          let texs:Vec<Point> = nums.chunks(2).map(|p| Point::new(p[0], p[1])).collect();
+          if texs.len() != positions.len(){
+            return cx.throw_range_error(format!("Expected texCoords for each of the {} mesh points (got {})", positions.len(), texs.len()))
+          }
+          texs
+        },
+        _ => positions.clone()
+      };
+
+      let colors = match cx.argument_opt(3){
+        Some(arg) if arg.is_a::<JsArray>() => {
+          let list = cx.argument::<JsArray>(3)?.to_vec(&mut cx)?;
+          let mut colors = vec![];
+          for css in strings_in(&list){
+            match color_in(&mut cx, &css){
+              Some(color) => colors.push(color),
+              None => return cx.throw_type_error(format!("Invalid vertex color \"{}\"", css))
+            }
+          }
+          if colors.len() != positions.len(){
+            return cx.throw_range_error(format!("Expected a color for each of the {} mesh points (got {})", positions.len(), list.len()))
+          }
+          Some(colors)
+        },
+        _ => None
+      };
+
+      let indices = match cx.argument_opt(4){
+        Some(arg) if arg.is_a::<JsArray>() => {
+          let list = cx.argument::<JsArray>(4)?.to_vec(&mut cx)?;
+          let nums = floats_in(&list);
+          if nums.len() != list.len() || nums.iter().any(|n| n.fract() != 0.0 || *n < 0.0 || *n as usize >= positions.len()){
+            return cx.throw_range_error("Mesh indices must be integers referring to the mesh points")
+          }
+          Some(nums.iter().map(|n| *n as u16).collect::<Vec<u16>>())
+        },
+        _ => None
+      };
+
+      let blend_mode = blend_mode_arg(&mut cx, 5, "blendMode")?;
+
+      // use the explicit shader if one was provided, otherwise paint with the current fillStyle
+      let shader_arg = cx.argument_opt(6).filter(|arg| !arg.is_a::<JsUndefined>() && !arg.is_a::<JsNull>());
+      let (image, dye) = match shader_arg{
+        Some(arg) if arg.is_a::<JsImage>() || arg.is_a::<JsCanvas>() => {
+          match image_arg(&mut cx, 6)?{
+            Some(image) => (Some(image), None),
+            None => return cx.throw_error("Cannot draw incomplete image (has it finished loading?)")
+          }
+        },
+        Some(arg) => match Dye::from_value(&mut cx, arg)?{
+          Some(dye) => (None, Some(dye)),
+          None => return cx.throw_type_error("Expected a color, CanvasGradient, CanvasPattern, CanvasShader, Image, or Canvas as the mesh shader")
+        },
+        None => (None, None)
+      };
+
+      cx.borrow_mut(&mut this, |mut this| {
+        let dye = dye.unwrap_or_else(|| this.state.fill_style.clone());
+        let (shader, fill) = match (image, dye){
+          (Some(image), _) => (image.to_shader((TileMode::Clamp, TileMode::Clamp), this.image_sampling(), None), Color::WHITE),
+          (None, Dye::Color(color)) => (None, color.to_color()),
+          (None, dye) => (dye.shader(), Color::WHITE)
+        };
+        // solid colors are applied to vertices that weren't given colors of their own
+        let colors = colors.unwrap_or_else(|| vec![fill; positions.len()]);
+        let vertices = Vertices::new_copy(mode, &positions, &tex_coords, &colors, indices.as_deref());
+        this.draw_mesh(&vertices, blend_mode, shader);
+      });
+      Ok(cx.undefined().upcast())
+    }
+
+    method _drawAtlas(mut cx){
+      let mut this = cx.this();
+      let image = match image_arg(&mut cx, 0)?{
+        Some(image) => image,
+        None => return cx.throw_error("Cannot draw incomplete image (has it finished loading?)")
+      };
+
+      let xforms = float_buffer_arg(&mut cx, 1)?;
+      let texs = float_buffer_arg(&mut cx, 2)?;
+      if xforms.len() % 4 != 0 || texs.len() % 4 != 0{
+        return cx.throw_range_error("Transforms & texture rects must consist of groups of four numbers")
+      }else if xforms.len() != texs.len(){
+        return cx.throw_range_error(format!("Expected a texture rect for each of the {} sprites (got {})", xforms.len() / 4, texs.len() / 4))
       }
 
+      let colors = match cx.argument_opt(3){
+        Some(arg) if arg.is_a::<JsArray>() => {
+          let list = cx.argument::<JsArray>(3)?.to_vec(&mut cx)?;
+          let mut colors = vec![];
+          for css in strings_in(&list){
+            match color_in(&mut cx, &css){
+              Some(color) => colors.push(color),
+              None => return cx.throw_type_error(format!("Invalid sprite color \"{}\"", css))
+            }
+          }
+          if colors.len() != xforms.len() / 4{
+            return cx.throw_range_error(format!("Expected a color for each of the {} sprites (got {})", xforms.len() / 4, list.len()))
+          }
+          Some(colors)
+        },
+        _ => None
+      };
+
+      let xforms:Vec<RSXform> = xforms.chunks(4).map(|x| RSXform::new(x[0], x[1], (x[2], x[3]))).collect();
+      let texs:Vec<Rect> = texs.chunks(4).map(|r| Rect::from_xywh(r[0], r[1], r[2], r[3])).collect();
+      cx.borrow_mut(&mut this, |mut this| {
+        this.draw_atlas(&image, &xforms, &texs, colors.as_deref());
+      });
       Ok(cx.undefined().upcast())
     }
 
@@ -596,15 +1088,21 @@ declare_types! {
       let y = float_arg(&mut cx, 1, "y")? as i32;
       let width = float_arg(&mut cx, 2, "width")? as i32;
       let height = float_arg(&mut cx, 3, "height")? as i32;
+      refresh_links(&mut cx, &mut this)?;
 
-      let buffer = JsBuffer::new(&mut cx, 4 * (width * height) as u32)?;
+      // high-bit-depth pages return 16-bit integer or 32-bit float channels
+      let color_type = cx.borrow(&this, |this| this.pixel_format.data_type() );
+      let bpp = ImageInfo::new((1, 1), color_type, AlphaType::Unpremul, None).bytes_per_pixel();
+      let buffer = JsBuffer::new(&mut cx, (bpp as i32 * width * height) as u32)?;
       cx.borrow(&buffer, |data| {
         cx.borrow_mut(&mut this, |mut this|{
           this.get_pixels(data.as_mut_slice(), (x, y), (width, height));
         })
       });
 
-      let args = vec![cx.number(width), cx.number(height)];
+      let args:Vec<Handle<JsValue>> = vec![
+        cx.number(width).upcast(), cx.number(height).upcast(), cx.string(from_color_type(color_type)).upcast()
+      ];
       let img_data = JsImageData::new(&mut cx, args)?;
       let attr = cx.string("data");
       img_data.set(&mut cx, attr, buffer)?;
@@ -657,10 +1155,20 @@ declare_types! {
     method set_imageSmoothingEnabled(mut cx){
       let mut this = cx.this();
       let flag = bool_arg(&mut cx, 0, "imageSmoothingEnabled")?;
-      cx.borrow_mut(&mut this, |mut this| {
-        this.state.image_smoothing_enabled = flag;
-        this.update_image_quality();
-      });
+      cx.borrow_mut(&mut this, |mut this| this.state.image_smoothing_enabled = flag );
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_snapToPixel(mut cx){
+      let this = cx.this();
+      let flag = cx.borrow(&this, |this| this.state.snap_to_pixel );
+      Ok(cx.boolean(flag).upcast())
+    }
+
+    method set_snapToPixel(mut cx){
+      let mut this = cx.this();
+      let flag = bool_arg(&mut cx, 0, "snapToPixel")?;
+      cx.borrow_mut(&mut this, |mut this| this.state.snap_to_pixel = flag );
       Ok(cx.undefined().upcast())
     }
 
@@ -675,10 +1183,7 @@ declare_types! {
       let mut this = cx.this();
       let name = string_arg(&mut cx, 0, "imageSmoothingQuality")?;
       if let Some(mode) = to_filter_quality(&name){
-        cx.borrow_mut(&mut this, |mut this|{
-          this.state.image_filter_quality = mode;
-          this.update_image_quality();
-        });
+        cx.borrow_mut(&mut this, |mut this| this.state.image_filter_quality = mode );
       }
       Ok(cx.undefined().upcast())
     }
@@ -727,38 +1232,175 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
-    method _measureText(mut cx){
+    method _fillAndStrokeText(mut cx){
       let mut this = cx.this();
       let text = string_arg(&mut cx, 0, "text")?;
-      let width = opt_float_arg(&mut cx, 1);
-      let text_metrics = cx.borrow_mut(&mut this, |mut this| this.measure_text(&text, width) );
+      let x = float_arg(&mut cx, 1, "x")?;
+      let y = float_arg(&mut cx, 2, "y")?;
+      let width = opt_float_arg(&mut cx, 3);
 
-      let results = JsArray::new(&mut cx, text_metrics.len() as u32);
-      for (i, info) in text_metrics.iter().enumerate(){
-        let line = floats_to_array(&mut cx, &info)?;
-        results.set(&mut cx, i as u32, line)?;
+      if width.is_none() && cx.len() > 3 && !cx.argument::<JsValue>(3)?.is_a::<JsUndefined>(){
+        // it's fine to include an ignored `undefined` but anything else is invalid
+        return Ok(cx.undefined().upcast())
       }
-      Ok(results.upcast())
-    }
 
-    // -- type properties ---------------------------------------------------------------
+      cx.borrow_mut(&mut this, |mut this|{
+        this.fill_and_stroke_text(&text, x, y, width);
+      });
 
-    method get_font(mut cx){
-      let this = cx.this();
-      let font_str = cx.borrow(&this, |this| this.state.font.clone() );
-      Ok(cx.string(font_str).upcast())
+      Ok(cx.undefined().upcast())
     }
 
-    method set_font(mut cx){
+    method _fillTextSpans(mut cx){
       let mut this = cx.this();
-      if let Some(spec) = font_arg(&mut cx, 0)?{
-        cx.borrow_mut(&mut this, |mut this|{ this.set_font(spec) });
+      let list = cx.argument::<JsArray>(0)?.to_vec(&mut cx)?;
+      let x = float_arg(&mut cx, 1, "x")?;
+      let y = float_arg(&mut cx, 2, "y")?;
+      let width = opt_float_arg(&mut cx, 3);
+
+      let mut spans = vec![];
+      for item in list{
+        let span = item.downcast_or_throw::<JsObject, _>(&mut cx)?;
+        let text = string_for_key(&mut cx, &span, "text")?;
+        let font = match span.get(&mut cx, "font")?.downcast::<JsObject>(){
+          Ok(font_desc) => Some(font_spec_in(&mut cx, &font_desc)?),
+          Err(_) => None
+        };
+        let fill_style = span.get(&mut cx, "fillStyle")?;
+        let dye = Dye::from_value(&mut cx, fill_style)?;
+        let underline = span.get(&mut cx, "underline")?.downcast::<JsBoolean>().map(|b| b.value()).unwrap_or(false);
+        spans.push(TextSpan{ text, font, dye, underline });
       }
+
+      cx.borrow_mut(&mut this, |mut this|{
+        this.draw_text_spans(&spans, x, y, width);
+      });
+
       Ok(cx.undefined().upcast())
     }
 
-    method get_textAlign(mut cx){
-      let this = cx.this();
+    method _drawGlyphs(mut cx){
+      let mut this = cx.this();
+      let texts = cx.argument::<JsArray>(0)?.to_vec(&mut cx)?;
+      let coords = float_buffer_arg(&mut cx, 1)?;
+      let font = match cx.argument::<JsValue>(2)?.downcast::<JsObject>(){
+        Ok(font_desc) => Some(font_spec_in(&mut cx, &font_desc)?),
+        Err(_) => None
+      };
+      if coords.len() != texts.len() * 3 {
+        return cx.throw_type_error("Expected an x, y, and rotation value for each glyph")
+      }
+
+      let mut glyphs = vec![];
+      for (text, pos) in texts.iter().zip(coords.chunks(3)){
+        let text = text.downcast_or_throw::<JsString, _>(&mut cx)?.value();
+        glyphs.push((text, pos[0], pos[1], pos[2]));
+      }
+
+      cx.borrow_mut(&mut this, |mut this|{
+        this.draw_glyphs(&glyphs, font);
+      });
+      Ok(cx.undefined().upcast())
+    }
+
+    method _outlineText(mut cx){
+      let mut this = cx.this();
+      let mut target = cx.argument::<JsPath2D>(0)?;
+      let text = string_arg(&mut cx, 1, "text")?;
+      let x = float_arg_or(&mut cx, 2, 0.0);
+      let y = float_arg_or(&mut cx, 3, 0.0);
+      let width = opt_float_arg(&mut cx, 4);
+
+      let outline = cx.borrow_mut(&mut this, |mut this| this.outline_text(&text, x, y, width) );
+      cx.borrow_mut(&mut target, |mut target| target.path = outline );
+      Ok(cx.undefined().upcast())
+    }
+
+    method _textBounds(mut cx){
+      let mut this = cx.this();
+      let text = string_arg(&mut cx, 0, "text")?;
+      let start = float_arg(&mut cx, 1, "start")?.max(0.0) as usize;
+      let end = float_arg(&mut cx, 2, "end")?.max(0.0) as usize;
+      let width = opt_float_arg(&mut cx, 3);
+      let rects = cx.borrow_mut(&mut this, |mut this| this.text_bounds(&text, start, end, width) );
+
+      let results = JsArray::new(&mut cx, rects.len() as u32);
+      for (i, rect) in rects.iter().enumerate(){
+        let bounds = floats_to_array(&mut cx, &[rect.left, rect.top, rect.width(), rect.height()])?;
+        results.set(&mut cx, i as u32, bounds)?;
+      }
+      Ok(results.upcast())
+    }
+
+    method _measureText(mut cx){
+      let mut this = cx.this();
+      let text = string_arg(&mut cx, 0, "text")?;
+      let width = opt_float_arg(&mut cx, 1);
+      let text_metrics = cx.borrow_mut(&mut this, |mut this| this.measure_text(&text, width) );
+
+      let results = JsArray::new(&mut cx, text_metrics.len() as u32);
+      for (i, info) in text_metrics.iter().enumerate(){
+        let line = floats_to_array(&mut cx, &info)?;
+        results.set(&mut cx, i as u32, line)?;
+      }
+      Ok(results.upcast())
+    }
+
+    method _textFallbacks(mut cx){
+      let mut this = cx.this();
+      let text = string_arg(&mut cx, 0, "text")?;
+      let fallbacks = cx.borrow_mut(&mut this, |mut this| this.text_fallbacks(&text) );
+
+      let results = JsArray::new(&mut cx, fallbacks.len() as u32);
+      for (i, (index, glyph, family)) in fallbacks.iter().enumerate(){
+        let index = cx.number(*index as f64);
+        let glyph = cx.string(glyph);
+        let family:Handle<JsValue> = match family{
+          Some(name) => cx.string(name).upcast(),
+          None => cx.null().upcast()
+        };
+
+        let info = JsObject::new(&mut cx);
+        let attr = cx.string("index"); info.set(&mut cx, attr, index)?;
+        let attr = cx.string("text"); info.set(&mut cx, attr, glyph)?;
+        let attr = cx.string("family"); info.set(&mut cx, attr, family)?;
+        results.set(&mut cx, i as u32, info)?;
+      }
+      Ok(results.upcast())
+    }
+
+    // -- type properties ---------------------------------------------------------------
+
+    method get_font(mut cx){
+      let this = cx.this();
+      let font_str = cx.borrow(&this, |this| this.state.font.clone() );
+      Ok(cx.string(font_str).upcast())
+    }
+
+    method set_font(mut cx){
+      let mut this = cx.this();
+      if let Some(spec) = font_arg(&mut cx, 0)?{
+        cx.borrow_mut(&mut this, |mut this|{ this.set_font(spec) });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_fontFallbacks(mut cx){
+      let this = cx.this();
+      let families = cx.borrow(&this, |this| this.state.font_fallbacks.clone() );
+      strings_to_array(&mut cx, &families)
+    }
+
+    method set_fontFallbacks(mut cx){
+      let mut this = cx.this();
+      let list = cx.argument::<JsArray>(0)?.to_vec(&mut cx)?;
+      let families = strings_in(&list);
+      cx.borrow_mut(&mut this, |mut this|{ this.state.font_fallbacks = families });
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_textAlign(mut cx){
+      let this = cx.this();
       let mode = cx.borrow(&this, |this| this.state.graf_style.text_align() );
       let name = from_text_align(mode);
       Ok(cx.string(name).upcast())
@@ -794,12 +1436,8 @@ declare_types! {
 
     method get_direction(mut cx){
       let this = cx.this();
-      let name = cx.borrow(&this, |this|
-        match this.state.graf_style.text_direction(){
-          TextDirection::LTR => "ltr",
-          TextDirection::RTL => "rtl",
-        }
-      );
+      let mode = cx.borrow(&this, |this| this.state.direction );
+      let name = from_text_direction(mode);
       Ok(cx.string(name).upcast())
     }
 
@@ -807,15 +1445,12 @@ declare_types! {
       let mut this = cx.this();
       let name = string_arg(&mut cx, 0, "direction")?;
 
-      let direction = match name.to_lowercase().as_str(){
-        "ltr" => Some(TextDirection::LTR),
-        "rtl" => Some(TextDirection::RTL),
-        _ => None
-      };
-
-      if let Some(dir) = direction{
+      if let Some(dir) = to_text_direction(&name){
         cx.borrow_mut(&mut this, |mut this|{
-          this.state.graf_style.set_text_direction(dir);
+          this.state.direction = dir;
+          if let Some(dir) = dir{
+            this.state.graf_style.set_text_direction(dir);
+          }
         })
       }
 
@@ -824,6 +1459,19 @@ declare_types! {
 
     // -- non-standard typography extensions --------------------------------------------
 
+    method get_fontHinting(mut cx){
+      let this = cx.this();
+      let flag = cx.borrow(&this, |this| this.state.font_hinting );
+      Ok(cx.boolean(flag).upcast())
+    }
+
+    method set_fontHinting(mut cx){
+      let mut this = cx.this();
+      let flag = bool_arg(&mut cx, 0, "fontHinting")?;
+      cx.borrow_mut(&mut this, |mut this| this.state.font_hinting = flag );
+      Ok(cx.undefined().upcast())
+    }
+
     method get_fontVariant(mut cx){
       let this = cx.this();
       let font_str = cx.borrow(&this, |this| this.state.font_variant.clone() );
@@ -842,6 +1490,246 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
+    method get_lineBreak(mut cx){
+      let this = cx.this();
+      let mode = cx.borrow(&this, |this| this.state.line_break );
+      let name = from_line_break(mode);
+      Ok(cx.string(name).upcast())
+    }
+
+    method set_lineBreak(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "lineBreak")?;
+      if let Some(mode) = to_line_break(&name){
+        cx.borrow_mut(&mut this, |mut this|{ this.state.line_break = mode; });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_lineHeight(mut cx){
+      let this = cx.this();
+      let height = cx.borrow(&this, |this| this.state.line_height );
+      match height{
+        LineHeight::Normal => Ok(cx.string("normal").upcast()),
+        LineHeight::Scale(scale) => Ok(cx.number(scale).upcast()),
+        LineHeight::Pixels(px) => Ok(cx.string(format!("{}px", px)).upcast()),
+      }
+    }
+
+    method set_lineHeight(mut cx){
+      let mut this = cx.this();
+      let kind = string_arg(&mut cx, 0, "lineHeight")?;
+      let height = match (kind.as_str(), opt_float_arg(&mut cx, 1)){
+        ("normal", _) => Some(LineHeight::Normal),
+        ("scale", Some(scale)) if scale > 0.0 => Some(LineHeight::Scale(scale)),
+        ("px", Some(px)) if px > 0.0 => Some(LineHeight::Pixels(px)),
+        _ => None
+      };
+      if let Some(height) = height{
+        cx.borrow_mut(&mut this, |mut this| this.state.line_height = height );
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_maxLines(mut cx){
+      let this = cx.this();
+      match cx.borrow(&this, |this| this.state.max_lines ){
+        Some(num) => Ok(cx.number(num as f64).upcast()),
+        None => Ok(cx.string("none").upcast())
+      }
+    }
+
+    method set_maxLines(mut cx){
+      let mut this = cx.this();
+      let max_lines = match opt_float_arg(&mut cx, 0){
+        Some(num) if num >= 1.0 => Some(num.floor() as usize),
+        Some(_) => return Ok(cx.undefined().upcast()),
+        None => match opt_string_arg(&mut cx, 0).as_deref(){
+          Some("none") => None,
+          _ => return Ok(cx.undefined().upcast())
+        }
+      };
+      cx.borrow_mut(&mut this, |mut this| this.state.max_lines = max_lines );
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_tabSize(mut cx){
+      let this = cx.this();
+      let size = cx.borrow(&this, |this| this.state.tab_size );
+      match size{
+        TabSize::Spaces(count) => Ok(cx.number(count).upcast()),
+        TabSize::Pixels(px) => Ok(cx.string(format!("{}px", px)).upcast()),
+      }
+    }
+
+    method set_tabSize(mut cx){
+      let mut this = cx.this();
+      let kind = string_arg(&mut cx, 0, "tabSize")?;
+      let size = match (kind.as_str(), opt_float_arg(&mut cx, 1)){
+        ("spaces", Some(count)) if count >= 0.0 => Some(TabSize::Spaces(count)),
+        ("px", Some(px)) if px >= 0.0 => Some(TabSize::Pixels(px)),
+        _ => None
+      };
+      if let Some(size) = size{
+        cx.borrow_mut(&mut this, |mut this| this.state.tab_size = size );
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_tabStops(mut cx){
+      let this = cx.this();
+      let stops = cx.borrow(&this, |this| this.state.tab_stops.clone() );
+      Ok(floats_to_array(&mut cx, &stops)?)
+    }
+
+    method set_tabStops(mut cx){
+      let mut this = cx.this();
+      let list = cx.argument::<JsArray>(0)?.to_vec(&mut cx)?;
+      let stops = floats_in(&list);
+
+      // ignore lists with non-numeric, negative, or out-of-order positions
+      if stops.len() == list.len() && stops.windows(2).all(|pair| pair[0] < pair[1]) && stops.iter().all(|stop| *stop > 0.0){
+        cx.borrow_mut(&mut this, |mut this| this.state.tab_stops = stops );
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_fontFeatures(mut cx){
+      let this = cx.this();
+      let features = cx.borrow(&this, |this| this.state.font_features.clone() );
+      let dict = JsObject::new(&mut cx);
+      for (feat, val) in features{
+        let val = cx.number(val);
+        dict.set(&mut cx, feat.as_str(), val)?;
+      }
+      Ok(dict.upcast())
+    }
+
+    method set_fontFeatures(mut cx){
+      let mut this = cx.this();
+      let arg = cx.argument::<JsObject>(0)?;
+      let features = font_features(&mut cx, &arg)?.into_iter()
+        .filter(|(feat, _)| feat.len() == 4 && feat.is_ascii())
+        .collect();
+      cx.borrow_mut(&mut this, |mut this| this.state.font_features = features );
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_textDecoration(mut cx){
+      let this = cx.this();
+      let decoration = cx.borrow(&this, |this| this.state.text_decoration.canonical.clone() );
+      Ok(cx.string(decoration).upcast())
+    }
+
+    method set_textDecoration(mut cx){
+      let mut this = cx.this();
+      if let Some(decoration) = decoration_arg(&mut cx, 0)?{
+        cx.borrow_mut(&mut this, |mut this|{ this.state.text_decoration = decoration });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method _getTextBackground(mut cx){
+      let this = cx.this();
+      let spec = match cx.borrow(&this, |this| this.state.text_background ){
+        Some(spec) => spec,
+        None => return Ok(cx.null().upcast())
+      };
+      let info = JsArray::new(&mut cx, 4);
+      let color = color_to_css(&mut cx, &spec.color)?;
+      info.set(&mut cx, 0, color)?;
+      for (i, val) in [spec.padding.0, spec.padding.1, spec.radius].iter().enumerate(){
+        let val = cx.number(*val);
+        info.set(&mut cx, i as u32 + 1, val)?;
+      }
+      Ok(info.upcast())
+    }
+
+    method _setTextBackground(mut cx){
+      let mut this = cx.this();
+      if cx.argument::<JsValue>(0)?.is_a::<JsNull>(){
+        cx.borrow_mut(&mut this, |mut this| this.state.text_background = None );
+        return Ok(cx.undefined().upcast())
+      }
+
+      // ignore invalid colors as well as negative padding or radius values
+      let color = color_arg(&mut cx, 0);
+      let pad_y = float_arg(&mut cx, 1, "padding")?;
+      let pad_x = float_arg(&mut cx, 2, "padding")?;
+      let radius = float_arg(&mut cx, 3, "radius")?;
+      if let Some(color) = color{
+        if [pad_y, pad_x, radius].iter().all(|val| val.is_finite() && *val >= 0.0){
+          cx.borrow_mut(&mut this, |mut this|{
+            this.state.text_background = Some(BackgroundSpec{ color, padding:(pad_y, pad_x), radius });
+          });
+        }
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_textIndent(mut cx){
+      let this = cx.this();
+      let indent = cx.borrow(&this, |this| this.state.text_indent );
+      Ok(cx.number(indent).upcast())
+    }
+
+    method set_textIndent(mut cx){
+      let mut this = cx.this();
+      let indent = float_arg(&mut cx, 0, "textIndent")?;
+      if indent.is_finite() && indent >= 0.0 {
+        cx.borrow_mut(&mut this, |mut this| this.state.text_indent = indent );
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_textOrientation(mut cx){
+      let this = cx.this();
+      let mode = cx.borrow(&this, |this| this.state.text_orientation );
+      let name = from_text_orientation(mode);
+      Ok(cx.string(name).upcast())
+    }
+
+    method set_textOrientation(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "textOrientation")?;
+      if let Some(mode) = to_text_orientation(&name){
+        cx.borrow_mut(&mut this, |mut this|{ this.state.text_orientation = mode; });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_textRendering(mut cx){
+      let this = cx.this();
+      let mode = cx.borrow(&this, |this| this.state.text_rendering );
+      let name = from_text_rendering(mode);
+      Ok(cx.string(name).upcast())
+    }
+
+    method set_textRendering(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "textRendering")?;
+      if let Some(mode) = to_text_rendering(&name){
+        cx.borrow_mut(&mut this, |mut this|{ this.state.text_rendering = mode; });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_paintOrder(mut cx){
+      let this = cx.this();
+      let mode = cx.borrow(&this, |this| this.state.paint_order );
+      let name = from_paint_order(mode);
+      Ok(cx.string(name).upcast())
+    }
+
+    method set_paintOrder(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "paintOrder")?;
+      if let Some(mode) = to_paint_order(&name){
+        cx.borrow_mut(&mut this, |mut this|{ this.state.paint_order = mode; });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
     method get_textTracking(mut cx){
       let this = cx.this();
       let tracking = cx.borrow(&this, |this| this.state.text_tracking );
@@ -872,6 +1760,38 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
+    method get_textOverflow(mut cx){
+      let this = cx.this();
+      let mode = cx.borrow(&this, |this| this.state.text_overflow );
+      let name = from_text_overflow(mode);
+      Ok(cx.string(name).upcast())
+    }
+
+    method set_textOverflow(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "textOverflow")?;
+      if let Some(mode) = to_text_overflow(&name){
+        cx.borrow_mut(&mut this, |mut this|{ this.state.text_overflow = mode; });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+
+    method get_writingMode(mut cx){
+      let this = cx.this();
+      let mode = cx.borrow(&this, |this| this.state.writing_mode );
+      let name = from_writing_mode(mode);
+      Ok(cx.string(name).upcast())
+    }
+
+    method set_writingMode(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "writingMode")?;
+      if let Some(mode) = to_writing_mode(&name){
+        cx.borrow_mut(&mut this, |mut this|{ this.state.writing_mode = mode; });
+      }
+      Ok(cx.undefined().upcast())
+    }
 
     //
     // Effects
@@ -881,13 +1801,26 @@ declare_types! {
 
     method get_filter(mut cx){
       let this = cx.this();
-      let filter = cx.borrow(&this, |this| this.state.filter.clone() );
-      Ok(cx.string(filter).upcast())
+      let (filter, has_effect) = cx.borrow(&this, |this|
+        (this.state.filter.clone(), this.state.filter_effect.is_some())
+      );
+      match has_effect {
+        true => fetch_ref(&mut cx, "filterShader"),
+        false => Ok(cx.string(filter).upcast())
+      }
     }
 
     method set_filter(mut cx){
       let mut this = cx.this();
-      if !cx.argument::<JsValue>(0)?.is_a::<JsNull>() {
+      let arg = cx.argument::<JsValue>(0)?;
+      if arg.is_a::<JsCanvasShader>() {
+        let effect = cx.argument::<JsCanvasShader>(0)?;
+        let effect = cx.borrow(&effect, |effect| effect.clone() );
+        stash_ref(&mut cx, "filterShader", arg)?;
+        cx.borrow_mut(&mut this, |mut this|{
+          this.set_filter_effect(effect);
+        });
+      }else if !arg.is_a::<JsNull>() {
         let (filter_text, filters) = filter_arg(&mut cx, 0)?;
         cx.borrow_mut(&mut this, |mut this|{
           this.set_filter(&filter_text, &filters);
@@ -898,6 +1831,23 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
+    method get_backdropFilter(mut cx){
+      let this = cx.this();
+      let filter = cx.borrow(&this, |this| this.state.backdrop_filter.clone() );
+      Ok(cx.string(filter).upcast())
+    }
+
+    method set_backdropFilter(mut cx){
+      let mut this = cx.this();
+      if !cx.argument::<JsValue>(0)?.is_a::<JsNull>() {
+        let (filter_text, filters) = filter_arg(&mut cx, 0)?;
+        cx.borrow_mut(&mut this, |mut this|{
+          this.set_backdrop_filter(&filter_text, &filters);
+        });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
     // -- compositing properties --------------------------------------------------------
 
     method get_globalAlpha(mut cx){
@@ -917,23 +1867,74 @@ declare_types! {
 
     method get_globalCompositeOperation(mut cx){
       let this = cx.this();
-      let mode = cx.borrow(&this, |this| this.state.global_composite_operation );
-      let name = from_blend_mode(mode);
+      let name = cx.borrow(&this, |this| match this.state.color_select{
+        Some(select) => from_color_select(select),
+        None => from_blend_mode(this.state.global_composite_operation)
+      });
       Ok(cx.string(name).upcast())
     }
 
     method set_globalCompositeOperation(mut cx){
       let mut this = cx.this();
       let name = string_arg(&mut cx, 0, "globalCompositeOperation")?;
-      if let Some(mode) = to_blend_mode(&name){
+      let select = to_color_select(&name);
+      let mode = match select{
+        Some(_) => Some(BlendMode::SrcOver),
+        None => to_blend_mode(&name)
+      };
+      if let Some(mode) = mode{
         cx.borrow_mut(&mut this, |mut this| {
           this.state.global_composite_operation = mode;
+          this.state.color_select = select;
           this.state.paint.set_blend_mode(mode);
         });
       }
       Ok(cx.undefined().upcast())
     }
 
+    method get_antialias(mut cx){
+      let this = cx.this();
+      let mode = cx.borrow(&this, |this| this.state.antialias );
+      Ok(cx.string(from_antialias(mode)).upcast())
+    }
+
+    method set_antialias(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "antialias")?;
+      if let Some(mode) = to_antialias(&name){
+        cx.borrow_mut(&mut this, |mut this|{
+          this.state.antialias = mode;
+          this.state.paint.set_anti_alias(mode != Antialias::None);
+        });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method _setFlattening(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "flatten")?;
+      let budget = opt_float_arg(&mut cx, 1).map(|mb| (mb.max(0.0) * 1024.0 * 1024.0) as usize);
+      let mode = match to_flatten(&name){
+        Some(mode) => mode,
+        None => return cx.throw_type_error(format!("Unknown flatten policy \"{}\" (expected \"auto\", \"never\", or \"always\")", name))
+      };
+      cx.borrow_mut(&mut this, |mut this| this.set_flattening(mode, budget) );
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_dither(mut cx){
+      let this = cx.this();
+      let flag = cx.borrow(&this, |this| this.state.paint.is_dither() );
+      Ok(cx.boolean(flag).upcast())
+    }
+
+    method set_dither(mut cx){
+      let mut this = cx.this();
+      let flag = bool_arg(&mut cx, 0, "dither")?;
+      cx.borrow_mut(&mut this, |mut this| { this.state.paint.set_dither(flag); });
+      Ok(cx.undefined().upcast())
+    }
+
     // -- dropshadow properties ---------------------------------------------------------
 
     method get_shadowBlur(mut cx){
@@ -953,16 +1954,32 @@ declare_types! {
 
     method get_shadowColor(mut cx){
       let this = cx.this();
-      let shadow_color = cx.borrow(&this, |this| this.state.shadow_color );
-      color_to_css(&mut cx, &shadow_color)
+      let (shadow_color, styled) = cx.borrow(&this, |this| (this.state.shadow_color, this.state.shadow_style.is_some()) );
+      match styled{
+        true => fetch_ref(&mut cx, "shadowShader"),
+        false => color_to_css(&mut cx, &shadow_color)
+      }
     }
 
     method set_shadowColor(mut cx){
       let mut this = cx.this();
-      if let Some(color) = color_arg(&mut cx, 0){
-        cx.borrow_mut(&mut this, |mut this| { this.state.shadow_color = color; });
-      }else{
-        eprintln!("Warning: Invalid shadow color (expected a css color string)");
+      let arg = cx.argument::<JsValue>(0)?;
+      match Dye::from_value(&mut cx, arg)?{
+        Some(Dye::Color(color)) => {
+          cx.borrow_mut(&mut this, |mut this| {
+            this.state.shadow_color = color.to_color();
+            this.state.shadow_style = None;
+          });
+        },
+        Some(dye) => {
+          // the dye supplies the shadow's color so the mask itself is drawn in opaque white
+          stash_ref(&mut cx, "shadowShader", arg)?;
+          cx.borrow_mut(&mut this, |mut this| {
+            this.state.shadow_color = Color::WHITE;
+            this.state.shadow_style = Some(dye);
+          });
+        },
+        None => eprintln!("Warning: Invalid shadow color (expected a css color string, CanvasGradient, CanvasPattern, or CanvasShader)")
       }
 
       Ok(cx.undefined().upcast())
@@ -994,5 +2011,228 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
+    method get_shadowSpread(mut cx){
+      let this = cx.this();
+      let num = cx.borrow(&this, |this| this.state.shadow_spread );
+      Ok(cx.number(num as f64).upcast())
+    }
+
+    method set_shadowSpread(mut cx){
+      let mut this = cx.this();
+      let num = float_arg(&mut cx, 0, "shadowSpread")?;
+      cx.borrow_mut(&mut this, |mut this| this.state.shadow_spread = num );
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_shadowInset(mut cx){
+      let this = cx.this();
+      let flag = cx.borrow(&this, |this| this.state.shadow_inset );
+      Ok(cx.boolean(flag).upcast())
+    }
+
+    method set_shadowInset(mut cx){
+      let mut this = cx.this();
+      let flag = bool_arg(&mut cx, 0, "shadowInset")?;
+      cx.borrow_mut(&mut this, |mut this| this.state.shadow_inset = flag );
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_renderPass(mut cx){
+      let this = cx.this();
+      let pass = cx.borrow(&this, |this| this.state.render_pass );
+      let name = from_render_pass(pass);
+      Ok(cx.string(name).upcast())
+    }
+
+    method set_renderPass(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "renderPass")?;
+      if let Some(pass) = to_render_pass(&name){
+        cx.borrow_mut(&mut this, |mut this|{ this.state.render_pass = pass; });
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_shadowsIgnoreTransform(mut cx){
+      let this = cx.this();
+      let flag = cx.borrow(&this, |this| this.state.shadows_ignore_transform );
+      Ok(cx.boolean(flag).upcast())
+    }
+
+    method set_shadowsIgnoreTransform(mut cx){
+      let mut this = cx.this();
+      let flag = bool_arg(&mut cx, 0, "shadowsIgnoreTransform")?;
+      cx.borrow_mut(&mut this, |mut this| this.state.shadows_ignore_transform = flag );
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_dashesIgnoreTransform(mut cx){
+      let this = cx.this();
+      let flag = cx.borrow(&this, |this| this.state.dashes_ignore_transform );
+      Ok(cx.boolean(flag).upcast())
+    }
+
+    method set_dashesIgnoreTransform(mut cx){
+      let mut this = cx.this();
+      let flag = bool_arg(&mut cx, 0, "dashesIgnoreTransform")?;
+      cx.borrow_mut(&mut this, |mut this| this.state.dashes_ignore_transform = flag );
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_strokeWidthIsAbsolute(mut cx){
+      let this = cx.this();
+      let flag = cx.borrow(&this, |this| this.state.stroke_width_absolute );
+      Ok(cx.boolean(flag).upcast())
+    }
+
+    method set_strokeWidthIsAbsolute(mut cx){
+      let mut this = cx.this();
+      let flag = bool_arg(&mut cx, 0, "strokeWidthIsAbsolute")?;
+      cx.borrow_mut(&mut this, |mut this| this.state.stroke_width_absolute = flag );
+      Ok(cx.undefined().upcast())
+    }
+
+    method _getColorSpace(mut cx){
+      let this = cx.this();
+      let space = cx.borrow(&this, |this| this.working_space );
+      Ok(cx.string(from_working_space(space)).upcast())
+    }
+
+    method _setColorSpace(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "colorSpace")?;
+      match to_working_space(&name){
+        Some(space) => {
+          cx.borrow_mut(&mut this, |mut this| this.set_working_space(space) );
+          Ok(cx.undefined().upcast())
+        },
+        None => cx.throw_type_error(format!(
+          "Unknown color space \"{}\" (expected \"srgb\", \"display-p3\", or \"srgb-linear\")", name
+        ))
+      }
+    }
+
+    method _getPixelFormat(mut cx){
+      let this = cx.this();
+      let format = cx.borrow(&this, |this| this.pixel_format );
+      Ok(cx.string(from_pixel_format(format)).upcast())
+    }
+
+    method _setPixelFormat(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "pixelFormat")?;
+      match to_pixel_format(&name){
+        Some(format) => {
+          cx.borrow_mut(&mut this, |mut this| this.set_pixel_format(format) );
+          Ok(cx.undefined().upcast())
+        },
+        None => cx.throw_type_error(format!(
+          "Unknown pixel format \"{}\" (expected \"rgba8\", \"rgba16\", or \"rgba16f\")", name
+        ))
+      }
+    }
+
+    //
+    // Command history
+    //
+
+    method get_recordHistory(mut cx){
+      let this = cx.this();
+      let flag = cx.borrow(&this, |this| this.history.borrow().enabled );
+      Ok(cx.boolean(flag).upcast())
+    }
+
+    method set_recordHistory(mut cx){
+      let mut this = cx.this();
+      let flag = bool_arg(&mut cx, 0, "recordHistory")?;
+      cx.borrow_mut(&mut this, |this| {
+        this.history.borrow_mut().enabled = flag;
+        if !flag { this.clear_history() }
+      });
+      Ok(cx.undefined().upcast())
+    }
+
+    method _history(mut cx){
+      let this = cx.this();
+      let entries = cx.borrow(&this, |this| {
+        this.history.borrow().entries.iter().map(|entry|{
+          let style = match entry.style{
+            Fill => "fill",
+            Stroke => "stroke",
+            _ => "fill+stroke"
+          };
+          let bounds = [entry.bounds.left, entry.bounds.top, entry.bounds.width(), entry.bounds.height()];
+          let flags = [entry.shader, entry.filter, entry.shadow];
+          (entry.op.clone(), bounds, style, entry.color, entry.line_width, from_blend_mode(entry.blend_mode), flags)
+        }).collect::<Vec<_>>()
+      });
+
+      let results = JsArray::new(&mut cx, entries.len() as u32);
+      for (i, (op, bounds, style, color, line_width, blend_mode, flags)) in entries.iter().enumerate(){
+        let info = JsObject::new(&mut cx);
+        let op:Handle<JsValue> = match op{
+          Some(name) => cx.string(name).upcast(),
+          None => cx.null().upcast()
+        };
+        let bounds = floats_to_array(&mut cx, bounds)?;
+        info.set(&mut cx, "op", op)?;
+        info.set(&mut cx, "bounds", bounds)?;
+
+        let paint = JsObject::new(&mut cx);
+        let style = cx.string(style);
+        let css = color_to_css(&mut cx, &color.with_a(255))?;
+        let alpha = cx.number(color.a() as f64 / 255.0);
+        let line_width = cx.number(*line_width);
+        let blend_mode = cx.string(blend_mode);
+        paint.set(&mut cx, "style", style)?;
+        paint.set(&mut cx, "color", css)?;
+        paint.set(&mut cx, "alpha", alpha)?;
+        paint.set(&mut cx, "lineWidth", line_width)?;
+        paint.set(&mut cx, "blendMode", blend_mode)?;
+        for (key, flag) in ["shader", "filter", "shadow"].iter().zip(flags.iter()){
+          let flag = cx.boolean(*flag);
+          paint.set(&mut cx, *key, flag)?;
+        }
+        info.set(&mut cx, "paint", paint)?;
+        results.set(&mut cx, i as u32, info)?;
+      }
+      Ok(results.upcast())
+    }
+
+    method _labelHistory(mut cx){
+      // tag any entries added since the last call with the name of the method that created them
+      let this = cx.this();
+      let name = string_arg(&mut cx, 0, "op")?;
+      cx.borrow(&this, |this| {
+        let mut history = this.history.borrow_mut();
+        for entry in history.entries.iter_mut().rev().take_while(|entry| entry.op.is_none()){
+          entry.op = Some(name.clone());
+        }
+      });
+      Ok(cx.undefined().upcast())
+    }
+
+    method _replay(mut cx){
+      let this = cx.this();
+      let canvas = cx.argument::<JsCanvas>(0)?;
+      let indices = cx.argument::<JsArray>(1)?.to_vec(&mut cx)?;
+      let indices:Vec<usize> = floats_in(&indices).iter().map(|i| *i as usize).collect();
+
+      let (bounds, picts) = cx.borrow(&this, |this| {
+        let history = this.history.borrow();
+        let picts:Vec<_> = indices.iter().filter_map(|i|
+          history.entries.get(*i).map(|entry| entry.picture.clone())
+        ).collect();
+        (this.bounds, picts)
+      });
+
+      canvas_context(&mut cx, &canvas, |ctx| {
+        for pict in &picts{
+          ctx.draw_picture(pict, &bounds, &bounds);
+        }
+      })?;
+      Ok(cx.undefined().upcast())
+    }
+
  }
 }
\ No newline at end of file
@@ -3,28 +3,36 @@
 #![allow(unused_variables)]
 #![allow(dead_code)]
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::ops::Range;
 use neon::prelude::*;
 use neon::object::This;
 use neon::result::Throw;
 use skia_safe::{Canvas as SkCanvas, Surface, Paint, Path, PathOp, Image, ImageInfo,
-                Matrix, Rect, Point, IPoint, Size, ISize, Color, Color4f, ColorType,
-                PaintStyle, BlendMode, FilterQuality, AlphaType, TileMode, ClipOp,
-                image_filters, color_filters, table_color_filter, dash_path_effect,
-                Data, PictureRecorder, Picture, Drawable};
-use skia_safe::textlayout::{Paragraph, ParagraphBuilder, ParagraphStyle, TextStyle, TextShadow, RectHeightStyle, RectWidthStyle};
-use skia_safe::canvas::SrcRectConstraint::Strict;
-use skia_safe::path::FillType;
+                Matrix, M44, Rect, surfaces, RRect, IRect, Point, IPoint, Size, ISize, Color, Color4f, ColorType,
+                PaintStyle, BlendMode, SamplingOptions, FilterMode, AlphaType, TileMode, ClipOp,
+                image_filters, color_filters, path_utils, dash_path_effect, path_1d_path_effect,
+                corner_path_effect, discrete_path_effect, PathEffect,
+                Data, PictureRecorder, Picture, ImageFilter, Shader, ColorChannel,
+                ContourMeasureIter, Font, FontMetrics, Vertices, vertices::VertexMode, RSXform, TextBlobBuilder,
+                canvas::PointMode, QuickReject, RoundOut, PaintJoin, PaintCap};
+use skia_safe::textlayout::{Paragraph, ParagraphBuilder, ParagraphStyle, TextStyle, TextShadow, RectHeightStyle, RectWidthStyle,
+                            TextAlign, TextDecoration, TextDirection, PlaceholderStyle, PlaceholderAlignment, TextBaseline};
+use skia_safe::canvas::{SaveLayerRec, SrcRectConstraint::Strict};
+use skia_safe::runtime_effect::{RuntimeEffect, ChildPtr};
+use skia_safe::PathFillType as FillType;
 
 use crate::utils::*;
 use crate::typography::*;
+use crate::pool;
 use crate::gradient::{CanvasGradient, JsCanvasGradient};
 use crate::pattern::{CanvasPattern, JsCanvasPattern};
+use crate::shader::{CanvasShader, JsCanvasShader};
 
 const BLACK:Color = Color::BLACK;
 const TRANSPARENT:Color = Color::TRANSPARENT;
 const GALLEY:f32 = 100_000.0;
+const BUDGET_CHECK_INTERVAL:usize = 64;
 
 pub mod class;
 pub use class::JsContext2D;
@@ -36,6 +44,95 @@ pub struct Context2D{
   state: State,
   stack: Vec<State>,
   path: Path,
+  pixels: RefCell<PixelBatch>,
+  raster: RefCell<Option<Surface>>,
+  extent: Cell<Option<Rect>>,
+  drawn: Cell<Option<Rect>>,
+  flatten: Flatten,
+  picture_budget: Option<usize>,
+  ops_since_check: Cell<usize>,
+  op_count: Cell<usize>,
+  pub history: RefCell<History>,
+  pub working_space: WorkingSpace,
+  pub pixel_format: PixelFormat,
+  links: Vec<LiveLink>,
+  pub resolving_links: Cell<bool>,
+}
+
+pub struct LiveLink{
+  // the content recorded prior to drawing a live-linked canvas, followed by that canvas's placement
+  // and its most recent snapshot (which is refreshed whenever this context's canvas is rendered)
+  prefix: Option<Picture>,
+  matrix: Matrix,
+  clip: Path,
+  paint: Paint,
+  origin: Point,
+  source: Option<Picture>,
+}
+
+#[derive(Default)]
+pub struct History{
+  // when enabled, each drawing operation is also recorded on its own so it can be inspected & replayed
+  pub enabled: bool,
+  pub entries: Vec<HistoryEntry>,
+  region: Option<Rect>,
+}
+
+pub struct HistoryEntry{
+  pub op: Option<String>,
+  pub picture: Picture,
+  pub bounds: Rect,
+  pub style: PaintStyle,
+  pub color: Color,
+  pub line_width: f32,
+  pub blend_mode: BlendMode,
+  pub shader: bool,
+  pub filter: bool,
+  pub shadow: bool,
+}
+
+#[derive(Default)]
+pub struct PixelBatch{
+  // a raster layer that consecutive putImageData calls write into before being recorded together
+  surface: Option<Surface>,
+  dirty: Vec<IRect>,
+}
+
+#[derive(Clone)]
+pub struct DashMarker{
+  // a shape stamped repeatedly along strokes (or along their dashes if a line dash is also set)
+  pub path: Path,
+  pub spacing: f32,
+  pub offset: f32,
+  pub rotate: bool,
+  pub morph: bool,
+}
+
+impl DashMarker{
+  pub fn effect(&self) -> Option<PathEffect>{
+    // morphing bends the marker's geometry to follow curves rather than just turning it
+    let style = match (self.morph, self.rotate){
+      (true, _) => path_1d_path_effect::Style::Morph,
+      (false, true) => path_1d_path_effect::Style::Rotate,
+      (false, false) => path_1d_path_effect::Style::Translate
+    };
+    path_1d_path_effect::new(&self.path, self.spacing, self.offset, style)
+  }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PathEffectSpec{
+  Corner{radius:f32},
+  Discrete{seg_length:f32, deviation:f32, seed:u32},
+}
+
+impl PathEffectSpec{
+  pub fn effect(&self) -> Option<PathEffect>{
+    match *self{
+      PathEffectSpec::Corner{radius} => corner_path_effect::new(radius),
+      PathEffectSpec::Discrete{seg_length, deviation, seed} => discrete_path_effect::new(seg_length, deviation, Some(seed)),
+    }
+  }
 }
 
 #[derive(Clone)]
@@ -48,26 +145,59 @@ pub struct State{
   stroke_style: Dye,
   shadow_blur: f32,
   shadow_color: Color,
+  shadow_style: Option<Dye>,
   shadow_offset: Point,
+  shadow_spread: f32,
+  shadow_inset: bool,
+  shadows_ignore_transform: bool,
+  render_pass: RenderPass,
+  stroke_width_absolute: bool,
+  dashes_ignore_transform: bool,
 
   stroke_width: f32,
   line_dash_offset: f32,
   line_dash_list: Vec<f32>,
+  line_dash_marker: Option<DashMarker>,
+  path_effect: Option<PathEffectSpec>,
+  stroke_precision: Option<f32>,
+  line_alignment: LineAlignment,
 
   global_alpha: f32,
   global_composite_operation: BlendMode,
+  color_select: Option<ColorSelect>,
   image_filter_quality: FilterQuality,
   image_smoothing_enabled: bool,
+  antialias: Antialias,
+  snap_to_pixel: bool,
   filter:String,
+  filter_effect:Option<CanvasShader>,
+  backdrop_filter:String,
+  backdrop:Option<ImageFilter>,
 
   font: String,
   font_variant: String,
-  font_features: Vec<String>,
+  font_features: Vec<(String, i32)>,
+  font_fallbacks: Vec<String>,
+  text_decoration: DecorationSpec,
+  text_background: Option<BackgroundSpec>,
+  line_height: LineHeight,
+  tab_size: TabSize,
+  tab_stops: Vec<f32>,
+  text_indent: f32,
+  line_break: LineBreak,
+  writing_mode: WritingMode,
+  text_orientation: TextOrientation,
+  text_rendering: TextRendering,
+  paint_order: PaintOrder,
+  font_hinting: bool,
   char_style: TextStyle,
   graf_style: ParagraphStyle,
+  direction: Option<TextDirection>,
   text_baseline: Baseline,
   text_tracking: i32,
   text_wrap: bool,
+  text_overflow: TextOverflow,
+  max_lines: Option<usize>,
 }
 
 impl Default for State {
@@ -78,8 +208,7 @@ impl Default for State {
       .set_color(BLACK)
       .set_anti_alias(true)
       .set_stroke_width(1.0)
-      .set_style(PaintStyle::Fill)
-      .set_filter_quality(FilterQuality::Low);
+      .set_style(PaintStyle::Fill);
 
     let graf_style = ParagraphStyle::new();
     let mut char_style = TextStyle::new();
@@ -90,38 +219,82 @@ impl Default for State {
       matrix: Matrix::new_identity(),
 
       paint,
-      stroke_style: Dye::Color(BLACK),
-      fill_style: Dye::Color(BLACK),
+      stroke_style: Dye::Color(BLACK.into()),
+      fill_style: Dye::Color(BLACK.into()),
       stroke_width: 1.0,
       line_dash_offset: 0.0,
       line_dash_list: vec![],
+      line_dash_marker: None,
+      path_effect: None,
+      stroke_precision: None,
+      line_alignment: LineAlignment::Center,
 
       global_alpha: 1.0,
       global_composite_operation: BlendMode::SrcOver,
+      color_select: None,
       image_filter_quality: FilterQuality::Low,
       image_smoothing_enabled: true,
+      antialias: Antialias::Grayscale,
+      snap_to_pixel: false,
       filter: "none".to_string(),
+      filter_effect: None,
+      backdrop_filter: "none".to_string(),
+      backdrop: None,
 
       shadow_blur: 0.0,
       shadow_color: TRANSPARENT,
+      shadow_style: None,
       shadow_offset: (0.0, 0.0).into(),
+      shadow_spread: 0.0,
+      shadow_inset: false,
+      shadows_ignore_transform: true,
+      render_pass: RenderPass::Normal,
+      stroke_width_absolute: false,
+      dashes_ignore_transform: false,
 
       font: "10px sans-serif".to_string(),
       font_variant: "normal".to_string(),
       font_features:vec![],
+      font_fallbacks:vec![],
+      text_decoration: DecorationSpec::default(),
+      text_background: None,
+      line_height: LineHeight::Normal,
+      tab_size: TabSize::Spaces(8.0),
+      tab_stops: vec![],
+      text_indent: 0.0,
+      line_break: LineBreak::Normal,
+      writing_mode: WritingMode::Horizontal,
+      text_orientation: TextOrientation::Mixed,
+      text_rendering: TextRendering::Auto,
+      paint_order: PaintOrder::FillFirst,
+      font_hinting: true,
       char_style,
       graf_style,
+      direction: None,
       text_baseline: Baseline::Alphabetic,
       text_tracking: 0,
-      text_wrap: false
+      text_wrap: false,
+      text_overflow: TextOverflow::Clip,
+      max_lines: None,
     }
   }
 }
 
+impl Drop for Context2D{
+  fn drop(&mut self){
+    // hand this context's raster buffers & recorder back for reuse by the next canvas
+    self.invalidate_raster();
+    if let Some(surface) = self.pixels.get_mut().surface.take(){
+      pool::recycle_surface(surface);
+    }
+    pool::recycle_recorder(std::mem::replace(self.recorder.get_mut(), PictureRecorder::new()));
+  }
+}
+
 impl Context2D{
   pub fn new(bounds: Rect, library: &Rc<RefCell<FontLibrary>>) -> Self {
-    let mut recorder = PictureRecorder::new();
-    recorder.begin_recording(bounds, None, None);
+    let mut recorder = pool::take_recorder();
+    recorder.begin_recording(bounds, None);
     if let Some(canvas) = recorder.recording_canvas() {
       canvas.save(); // start at depth 2
     }
@@ -134,6 +307,19 @@ impl Context2D{
       path: Path::new(),
       stack: vec![],
       state: State::default(),
+      pixels: RefCell::new(PixelBatch::default()),
+      raster: RefCell::new(None),
+      extent: Cell::new(None),
+      drawn: Cell::new(None),
+      flatten: Flatten::Auto,
+      picture_budget: None,
+      ops_since_check: Cell::new(0),
+      op_count: Cell::new(0),
+      history: RefCell::new(History::default()),
+      working_space: WorkingSpace::SRGB,
+      pixel_format: PixelFormat::RGBA8,
+      links: vec![],
+      resolving_links: Cell::new(false),
     }
   }
 
@@ -153,50 +339,155 @@ impl Context2D{
   }
 
   pub fn with_canvas<F>(&self, f:F)
-    where F:FnOnce(&mut SkCanvas)
+    where F:FnOnce(&SkCanvas)
   {
+    self.flush_pixels();
     let mut recorder = self.recorder.borrow_mut();
     if let Some(canvas) = recorder.recording_canvas() {
       f(canvas);
     }
   }
 
+  fn is_culled(&self, extent:&Rect, paint:&Paint) -> bool{
+    // shadows, filters, path effects, and blend modes that reach beyond the shape can all affect
+    // pixels outside of its bounds, so only ordinary draws are candidates for being skipped
+    if self.paint_for_shadow(paint).is_some() || paint.image_filter().is_some()
+    || paint.mask_filter().is_some() || paint.path_effect().is_some()
+    || self.state.color_select.is_some()
+    || matches!(self.state.global_composite_operation,
+                BlendMode::SrcIn | BlendMode::SrcOut | BlendMode::DstIn | BlendMode::DstOut |
+                BlendMode::DstATop | BlendMode::Src | BlendMode::Modulate){
+      return false
+    }
+
+    // pad the extent to cover miter joins & square caps (and hairlines' one-pixel width)
+    let outset = match paint.style(){
+      PaintStyle::Fill => 0.0,
+      _ => match paint.stroke_width(){
+        width if width > 0.0 => width / 2.0 * paint.stroke_miter().max(std::f32::consts::SQRT_2),
+        _ => 1.0 / self.state.matrix.min_scale().max(f32::EPSILON)
+      }
+    };
+
+    let mut culled = false;
+    let extent = extent.with_outset((outset + 1.0, outset + 1.0));
+    self.with_canvas(|canvas| culled = canvas.quick_reject(&extent) );
+    culled
+  }
+
+  fn draw_bounds(&self, extent:Option<Rect>, paint:&Paint) -> Rect{
+    // the device-space region an operation can touch, estimated the way skia does for its own
+    // bounding-box hierarchies: by growing the shape's extent by the stroke and any image filters
+    // (shapes whose extent is unknown or whose effects can't be measured fill the whole clip)
+    let clip = self.clip_bounds(false);
+    let extent = match extent{
+      Some(extent) if paint.path_effect().is_none() && self.state.color_select.is_none()
+        && !matches!(self.state.global_composite_operation,
+                     BlendMode::SrcIn | BlendMode::SrcOut | BlendMode::DstIn | BlendMode::DstOut |
+                     BlendMode::DstATop | BlendMode::Src | BlendMode::Modulate) => extent,
+      _ => return clip
+    };
+
+    // strokes extend by half their width (or further at miter joins & square caps)
+    let (mut outset, mut hairline) = (0.0, false);
+    if paint.style() != PaintStyle::Fill{
+      let mut factor:f32 = 1.0;
+      if paint.stroke_join() == PaintJoin::Miter{ factor = paint.stroke_miter().max(1.0) }
+      if paint.stroke_cap() == PaintCap::Square{ factor = factor.max(std::f32::consts::SQRT_2) }
+      outset = paint.stroke_width() / 2.0 * factor;
+      hairline = paint.stroke_width() == 0.0;
+    }
+    let shape = extent.with_outset((outset, outset));
+    let measure = |paint:&Paint, matrix:&Matrix| -> Option<Rect>{
+      let local = match paint.image_filter(){
+        Some(filter) if filter.can_compute_fast_bounds() => filter.compute_fast_bounds(shape),
+        Some(_) => return None,
+        None => shape
+      };
+      let (device, _) = matrix.map_rect(local);
+      Some(if hairline{ device.with_outset((1.0, 1.0)) }else{ device })
+    };
+
+    let pass = self.state.render_pass;
+    let mut bounds = Rect::new_empty();
+    if pass != RenderPass::Shadow{
+      match measure(paint, &self.state.matrix){
+        Some(rect) => bounds.join(rect),
+        None => return clip
+      }
+    }
+    if pass != RenderPass::Alpha{
+      if let Some(shadow_paint) = self.paint_for_shadow(paint){
+        match measure(&shadow_paint, &self.shadow_matrix()){
+          Some(rect) => bounds.join(rect),
+          None => return clip
+        }
+      }
+    }
+    match bounds.intersect(clip){
+      true => bounds,
+      false => Rect::new_empty()
+    }
+  }
+
+  fn note_drawn(&self, rect:Rect){
+    if rect.is_empty(){ return }
+    let mut drawn = self.drawn.get().unwrap_or_default();
+    drawn.join(rect);
+    self.drawn.set(Some(drawn));
+  }
+
+  pub fn drawn_bounds(&self) -> Option<IRect>{
+    // the geometric bounds of everything recorded on the page so far, limited to the canvas
+    self.flush_pixels();
+    let drawn:IRect = self.drawn.get()?.round_out();
+    IRect::intersect(&drawn, &IRect::from_wh(self.bounds.width().ceil() as i32, self.bounds.height().ceil() as i32))
+  }
+
   pub fn render_to_canvas<F>(&self, paint:&Paint, f:F)
-    where F:Fn(&mut SkCanvas, &Paint)
+    where F:Fn(&SkCanvas, &Paint)
   {
+    // skip operations that fall entirely outside the canvas or clipping region
+    let extent = self.extent.take();
+    if let Some(extent) = &extent{
+      if self.is_culled(extent, &paint){ return }
+    }
+
+    self.flush_pixels();
+    self.note_drawn(self.draw_bounds(extent, &paint));
+    self.invalidate_raster();
+    self.log_operation(&paint, &f, true);
+
+    // the 'shadow' pass omits the shapes themselves and the 'alpha' pass omits their shadows
+    let pass = self.state.render_pass;
+    let (shapes, shadows) = (pass != RenderPass::Shadow, pass != RenderPass::Alpha);
+    let paint = &self.paint_for_pass(paint);
     match self.state.global_composite_operation{
+      _ if self.state.color_select.is_some() => {
+        // the whole-color comparison modes need the shape's final pixels, so record it (and its
+        // shadow) in isolation before compositing it with the canvas
+        let mut layer_paint = paint.clone();
+        layer_paint.set_blend_mode(BlendMode::SrcOver);
+        if let (Some(pict), Some(select)) = (self.isolate(&layer_paint, &f, shapes, shadows), self.state.color_select){
+          self.composite_by_color(&pict, select);
+        }
+      },
       BlendMode::SrcIn | BlendMode::SrcOut |
       BlendMode::DstIn | BlendMode::DstOut |
-      BlendMode::DstATop | BlendMode::Src =>{
+      BlendMode::DstATop | BlendMode::Src |
+      BlendMode::Modulate =>{
         // for blend modes that affect regions of the canvas outside of the bounds of the object
         // being drawn, create an intermediate picture before drawing to the canvas
-        let mut layer_paint = self.state.paint.clone();
+        let mut layer_paint = self.paint_for_pass(&self.base_paint());
         layer_paint.set_blend_mode(BlendMode::SrcOver);
-        let mut layer_recorder = PictureRecorder::new();
-        layer_recorder.begin_recording(self.bounds, None, None);
-        if let Some(layer) = layer_recorder.recording_canvas() {
-          // draw the dropshadow (if applicable)
-          if let Some(shadow_paint) = self.paint_for_shadow(&layer_paint){
-            layer.save();
-            layer.set_matrix(&Matrix::translate(self.state.shadow_offset));
-            layer.concat(&self.state.matrix);
-            f(layer, &shadow_paint);
-            layer.restore();
-          }
-
-          // draw normally
-          layer.set_matrix(&self.state.matrix);
-          f(layer, &layer_paint);
-        }
-
 
         // transfer the picture contents to the canvas in a single operation, applying the blend
         // mode to the whole canvas (regardless of the bounds of the text/path being drawn)
-        if let Some(pict) = layer_recorder.finish_recording_as_picture(Some(&self.bounds)){
+        if let Some(pict) = self.isolate(&layer_paint, &f, shapes, shadows){
           let mut recorder = self.recorder.borrow_mut();
           if let Some(canvas) = recorder.recording_canvas() {
             canvas.save();
-            canvas.set_matrix(&Matrix::new_identity());
+            canvas.reset_matrix();
             canvas.draw_picture(&pict, None, Some(&paint));
             canvas.restore();
           }
@@ -207,21 +498,201 @@ impl Context2D{
         let mut recorder = self.recorder.borrow_mut();
         if let Some(canvas) = recorder.recording_canvas() {
           // only call the closure if there's an active dropshadow
-          if let Some(shadow_paint) = self.paint_for_shadow(&paint){
-            canvas.save();
-            canvas.set_matrix(&Matrix::translate(self.state.shadow_offset));
-            canvas.concat(&self.state.matrix);
-            f(canvas, &shadow_paint);
-            canvas.restore();
+          if shadows && !self.state.shadow_inset{
+            self.render_shadow(canvas, &paint, &f);
           }
 
           // draw with the normal paint
-          f(canvas, &paint);
+          if shapes{
+            f(canvas, &paint);
+          }
+
+          // inner shadows are drawn atop the shape
+          if shadows && self.state.shadow_inset{
+            self.render_shadow(canvas, &paint, &f);
+          }
         }
 
       }
     };
 
+    self.op_count.set(self.op_count.get() + 1);
+    self.enforce_budget();
+  }
+
+  fn isolate<F>(&self, layer_paint:&Paint, f:&F, shapes:bool, shadows:bool) -> Option<Picture>
+    where F:Fn(&SkCanvas, &Paint)
+  {
+    // record a single drawing operation (and its shadow) on an otherwise empty page
+    let mut layer_recorder = PictureRecorder::new();
+    layer_recorder.begin_recording(self.bounds, None);
+    if let Some(layer) = layer_recorder.recording_canvas() {
+      // draw the dropshadow (if applicable)
+      if shadows && !self.state.shadow_inset{
+        self.render_shadow(layer, &layer_paint, f);
+      }
+
+      // draw normally
+      layer.set_matrix(&M44::from(&self.state.matrix));
+      if shapes{
+        f(layer, &layer_paint);
+      }
+
+      // inner shadows are drawn atop the shape
+      if shadows && self.state.shadow_inset{
+        self.render_shadow(layer, &layer_paint, f);
+      }
+    }
+    layer_recorder.finish_recording_as_picture(Some(&self.bounds))
+  }
+
+  fn composite_by_color(&self, layer:&Picture, select:ColorSelect){
+    // restart the recording with its prior contents then cover the clipping region with the result
+    // of comparing the layer to a snapshot of the canvas (including any linked canvases beneath it)
+    let mut recorder = self.recorder.borrow_mut();
+    let tail = recorder.finish_recording_as_picture(Some(&self.bounds));
+    let backdrop = self.compose_links(tail.clone(), None);
+    recorder.begin_recording(self.bounds, None);
+    if let Some(canvas) = recorder.recording_canvas() {
+      if let Some(tail) = &tail {
+        canvas.draw_picture(&tail, None, None);
+      }
+
+      canvas.save();
+      set_clip_and_matrix(canvas, &self.state.clip, &self.state.matrix);
+
+      canvas.save();
+      canvas.reset_matrix();
+      match backdrop.and_then(|backdrop| color_select_shader(layer, &backdrop, select)){
+        Some(shader) => {
+          let mut paint = Paint::default();
+          paint.set_shader(shader);
+          paint.set_blend_mode(BlendMode::Src);
+          canvas.draw_rect(self.bounds, &paint);
+        },
+        None => { canvas.draw_picture(layer, None, None); }
+      }
+      canvas.restore();
+    }
+  }
+
+  pub fn set_flattening(&mut self, flatten:Flatten, budget:Option<usize>){
+    self.flatten = flatten;
+    self.picture_budget = budget;
+    self.ops_since_check.set(0);
+  }
+
+  fn enforce_budget(&self){
+    // periodically measure the recording and replace it with a bitmap if it has outgrown the budget
+    let count = self.ops_since_check.get() + 1;
+    let due = match (self.flatten, self.picture_budget){
+      (Flatten::Always, _) => true,
+      (Flatten::Auto, Some(_)) => count >= BUDGET_CHECK_INTERVAL,
+      _ => false
+    };
+    if !due{
+      self.ops_since_check.set(count);
+      return
+    }
+    self.ops_since_check.set(0);
+
+    let mut recorder = self.recorder.borrow_mut();
+    let snapshot = match recorder.finish_recording_as_picture(Some(&self.bounds)){
+      Some(snapshot) => snapshot,
+      None => return
+    };
+    let over_budget = match (self.flatten, self.picture_budget){
+      (Flatten::Always, _) => true,
+      (_, Some(budget)) => snapshot.approximate_bytes_used() > budget,
+      _ => false
+    };
+    let flattened = match over_budget{
+      true => {
+        let dims = ISize::new(self.bounds.width().ceil() as i32, self.bounds.height().ceil() as i32);
+        pool::take_surface_as(dims, self.working_space, self.pixel_format).map(|mut surface|{
+          surface.canvas().draw_picture(&snapshot, None, None);
+          let image = surface.image_snapshot();
+          pool::recycle_surface(surface);
+          image
+        })
+      },
+      false => None
+    };
+
+    recorder.begin_recording(self.bounds, None);
+    if let Some(canvas) = recorder.recording_canvas() {
+      // carry the existing content over (as a bitmap if it's been flattened)...
+      match flattened{
+        Some(image) => { canvas.draw_image(&image, (0.0, 0.0), None); },
+        None => { canvas.draw_picture(&snapshot, None, None); }
+      }
+
+      // ...along with the current ctm/clip state
+      canvas.save();
+      set_clip_and_matrix(canvas, &self.state.clip, &self.state.matrix);
+    }
+  }
+
+  fn log_operation<F>(&self, paint:&Paint, f:&F, shadow:bool)
+    where F:Fn(&SkCanvas, &Paint)
+  {
+    let mut history = self.history.borrow_mut();
+    if !history.enabled{ return }
+
+    // re-record the operation in isolation (with the current transform & clip)
+    let mut recorder = PictureRecorder::new();
+    recorder.begin_recording(self.bounds, None);
+    if let Some(canvas) = recorder.recording_canvas() {
+      set_clip_and_matrix(canvas, &self.state.clip, &self.state.matrix);
+      if shadow && !self.state.shadow_inset{ self.render_shadow(canvas, &paint, f); }
+      f(canvas, &paint);
+      if shadow && self.state.shadow_inset{ self.render_shadow(canvas, &paint, f); }
+    }
+
+    // pad the shape's extent to account for the stroke (if any) and limit it to the canvas
+    let bounds = match history.region.take(){
+      Some(region) => {
+        let outset = match paint.style(){
+          PaintStyle::Fill => 0.0,
+          _ => paint.stroke_width() * self.state.matrix.max_scale().max(1.0) / 2.0
+        };
+        let mut bounds = region.with_outset((outset, outset));
+        match bounds.intersect(self.bounds){
+          true => bounds,
+          false => Rect::default()
+        }
+      },
+      None => self.bounds
+    };
+    if let Some(picture) = recorder.finish_recording_as_picture(Some(&self.bounds)){
+      history.entries.push(HistoryEntry{
+        op: None, picture, bounds,
+        style: paint.style(),
+        color: paint.color(),
+        line_width: paint.stroke_width(),
+        blend_mode: paint.blend_mode_or(BlendMode::SrcOver),
+        shader: paint.shader().is_some(),
+        filter: paint.image_filter().is_some(),
+        shadow: shadow && self.paint_for_shadow(&paint).is_some(),
+      });
+    }
+  }
+
+  pub fn clear_history(&self){
+    let mut history = self.history.borrow_mut();
+    history.entries.clear();
+    history.region = None;
+  }
+
+  fn render_shadow<F>(&self, canvas:&SkCanvas, paint:&Paint, f:&F)
+    where F:Fn(&SkCanvas, &Paint)
+  {
+    if let Some(shadow_paint) = self.paint_for_shadow(paint){
+      canvas.save();
+      canvas.set_matrix(&M44::from(self.shadow_matrix()));
+      f(canvas, &shadow_paint);
+      canvas.restore();
+    }
   }
 
   pub fn with_matrix<F>(&mut self, f:F)
@@ -229,7 +700,7 @@ impl Context2D{
   {
     f(&mut self.state.matrix);
     self.with_canvas(|canvas| {
-      canvas.set_matrix(&self.state.matrix);
+      canvas.set_matrix(&M44::from(&self.state.matrix));
     });
   }
 
@@ -254,13 +725,40 @@ impl Context2D{
     self.stack = vec![];
     self.state = State::default();
 
-    // erase any existing content
-    let mut new_recorder = PictureRecorder::new();
-    new_recorder.begin_recording(self.bounds, None, None);
-    self.recorder.replace(new_recorder);
+    // erase any existing content (returning its surfaces & recorder to the pool)
+    if let Some(surface) = self.pixels.replace(PixelBatch::default()).surface{
+      pool::recycle_surface(surface);
+    }
+    self.invalidate_raster();
+    self.clear_history();
+    self.links.clear();
+    self.op_count.set(0);
+    self.drawn.set(None);
+    let mut new_recorder = pool::take_recorder();
+    new_recorder.begin_recording(self.bounds, None);
+    pool::recycle_recorder(self.recorder.replace(new_recorder));
     self.reset_canvas();
   }
 
+  pub fn set_working_space(&mut self, space:WorkingSpace){
+    // cached rasters are in the old color space, so commit any pending pixels and start fresh
+    self.discard_rasters();
+    self.working_space = space;
+  }
+
+  pub fn set_pixel_format(&mut self, format:PixelFormat){
+    self.discard_rasters();
+    self.pixel_format = format;
+  }
+
+  fn discard_rasters(&mut self){
+    self.flush_pixels();
+    self.invalidate_raster();
+    if let Some(surface) = self.pixels.replace(PixelBatch::default()).surface{
+      pool::recycle_surface(surface);
+    }
+  }
+
   pub fn push(&mut self){
     let new_state = self.state.clone();
     self.stack.push(new_state);
@@ -273,10 +771,7 @@ impl Context2D{
 
       self.reset_canvas();
       self.with_canvas(|canvas|{
-        canvas.set_matrix(&self.state.matrix);
-        if !self.state.clip.is_empty(){
-          canvas.clip_path(&self.state.clip, ClipOp::Intersect, true /* antialias */);
-        }
+        set_clip_and_matrix(canvas, &self.state.clip, &self.state.matrix);
       });
     }
   }
@@ -286,8 +781,11 @@ impl Context2D{
     let inverse = self.state.matrix.invert().unwrap();
     let path = self.path.with_transform(&inverse);
 
+    let clip = self.stroke_clip(&path, &paint);
+    let precision = self.state.stroke_precision;
+    self.render_backdrop(&path);
     self.render_to_canvas(&paint, |canvas, paint| {
-      canvas.draw_path(&path, &paint);
+      draw_aligned_path(canvas, &path, &paint, clip, precision);
     });
   }
 
@@ -300,19 +798,60 @@ impl Context2D{
       }
     };
 
+    // the clip is kept in device space so later transforms don't move it
     clip.set_fill_type(rule);
+    let device_clip = clip.with_transform(&self.state.matrix);
     if self.state.clip.is_empty(){
-      self.state.clip = clip.clone();
-    }else if let Some(new_clip) = self.state.clip.op(&clip, PathOp::Intersect){
+      self.state.clip = device_clip;
+    }else if let Some(new_clip) = self.state.clip.op(&device_clip, PathOp::Intersect){
       self.state.clip = new_clip;
     }
 
-    let do_aa = true;
+    let do_aa = self.state.antialias != Antialias::None;
     self.with_canvas(|canvas| {
       canvas.clip_path(&clip, ClipOp::Intersect, do_aa);
     });
   }
 
+  pub fn clip_bounds(&self, local:bool) -> Rect{
+    // the extent of the current clip (or of the whole canvas if there isn't one) in device or user space
+    let mut bounds = self.bounds;
+    if !self.state.clip.is_empty(){
+      if !bounds.intersect(self.state.clip.bounds()){
+        return Rect::new_empty()
+      }
+    }
+
+    match (local, self.state.matrix.invert()){
+      (true, Some(inverse)) => inverse.map_rect(bounds).0,
+      (true, None) => Rect::new_empty(),
+      (false, _) => bounds
+    }
+  }
+
+  pub fn content_bounds(&mut self, local:bool) -> Rect{
+    // the geometric extent of everything drawn so far (measured the same way as a trimmed export) in device or
+    // user space
+    let bounds = match self.drawn_bounds(){
+      Some(bounds) => Rect::from_irect(bounds),
+      None => return Rect::new_empty()
+    };
+
+    match (local, self.state.matrix.invert()){
+      (true, Some(inverse)) => inverse.map_rect(bounds).0,
+      (true, None) => Rect::new_empty(),
+      (false, _) => bounds
+    }
+  }
+
+  pub fn is_rect_visible(&self, rect:Rect) -> bool{
+    // a conservative test (based on bounding boxes) for whether drawing within the rect could be seen
+    let (rect, _) = self.state.matrix.map_rect(rect.sorted());
+    let clip = self.clip_bounds(false);
+    !clip.is_empty() && rect.left <= clip.right && rect.right >= clip.left
+                     && rect.top <= clip.bottom && rect.bottom >= clip.top
+  }
+
   pub fn hit_test_path(&mut self, path: &mut Path, point:impl Into<Point>, rule:Option<FillType>, style: PaintStyle) -> bool {
     let point = point.into();
     let point = self.in_local_coordinates(point.x, point.y);
@@ -323,8 +862,8 @@ impl Context2D{
     let is_in = match style{
       PaintStyle::Stroke => {
         let paint = self.paint_for_stroke();
-        let precision = 0.3; // this is what Chrome uses to compute this
-        match paint.get_fill_path(&path, None, Some(precision)){
+        let precision = self.state.stroke_precision.unwrap_or(0.3); // 0.3 is what Chrome uses to compute this
+        match fill_path(&path, &paint, precision){
           Some(traced_path) => traced_path.contains(point),
           None => path.contains(point)
         }
@@ -338,90 +877,180 @@ impl Context2D{
 
 
   pub fn draw_rect(&mut self, rect:&Rect, paint: &Paint){
+    let path = Path::rect(rect, None);
+    let clip = self.stroke_clip(&path, &paint);
+    let precision = self.state.stroke_precision;
+    self.render_backdrop(&path);
     self.render_to_canvas(&paint, |canvas, paint| {
-      canvas.draw_rect(&rect, &paint);
+      match (clip, precision){
+        (None, None) => { canvas.draw_rect(&rect, &paint); },
+        _ => draw_aligned_path(canvas, &path, &paint, clip, precision)
+      }
     });
   }
 
-  pub fn clear_rect(&mut self, rect:&Rect){
-    self.with_canvas(|canvas| {
-      let mut paint = Paint::default();
-      paint.set_style(PaintStyle::Fill);
-      paint.set_blend_mode(BlendMode::Clear);
-      canvas.draw_rect(&rect, &paint);
+  pub fn draw_rects(&mut self, rects:&[Rect], paint: &Paint){
+    // draw the whole batch in a single pass (sharing one shadow & backdrop layer)
+    let mut region = Path::new();
+    for rect in rects{ region.add_rect(rect, None); }
+    self.render_backdrop(&region);
+    self.render_to_canvas(&paint, |canvas, paint| {
+      for rect in rects{ canvas.draw_rect(&rect, &paint); }
     });
   }
 
-  pub fn draw_drawable(&mut self, drobble:&mut Option<Drawable>, src_rect:&Rect, dst_rect:&Rect){
-    let mut paint = self.state.paint.clone();
-    paint.set_color(self.color_with_alpha(&BLACK));
-
-    if let Some(mut drobble) = drobble.as_mut(){
-      self.push();
-      self.with_canvas(|canvas| {
-        let size = ISize::new(dst_rect.width() as i32, dst_rect.height() as i32);
-        let mag = Point::new(dst_rect.width()/src_rect.width(), dst_rect.height()/src_rect.height());
-        let mut matrix = Matrix::new_identity();
-        matrix.pre_scale( (mag.x, mag.y), None )
-              .pre_translate((-src_rect.x(), -src_rect.y()));
-
-        if let Some(shadow_paint) = self.paint_for_shadow(&paint){
-          if let Some(mut surface) = Surface::new_raster_n32_premul(size){
-            surface.canvas().draw_drawable(&mut drobble, Some(&matrix));
-            canvas.draw_image(&surface.image_snapshot(), (dst_rect.x(), dst_rect.y()), Some(&shadow_paint));
-          }
-        }
+  pub fn draw_points(&mut self, mode:PointMode, points:&[Point], paint: &Paint){
+    let mut bounds = Rect::from_bounds(points).unwrap_or_default();
+    let outset = paint.stroke_width().max(1.0);
+    bounds.outset((outset, outset));
+    self.render_backdrop(&Path::rect(bounds, None));
+    self.render_to_canvas(&paint, |canvas, paint| {
+      canvas.draw_points(mode, points, &paint);
+    });
+  }
 
-        matrix.pre_translate((dst_rect.x()/mag.x, dst_rect.y()/mag.y));
-        canvas.clip_rect(dst_rect, ClipOp::Intersect, true)
-              .draw_drawable(&mut drobble, Some(&matrix));
-      });
-      self.pop();
+  pub fn clear_rect(&mut self, rect:&Rect){
+    self.invalidate_raster();
+    let mut paint = Paint::default();
+    paint.set_style(PaintStyle::Fill);
+    paint.set_blend_mode(BlendMode::Clear);
+
+    if self.history.borrow().enabled{
+      self.history.borrow_mut().region = Some(self.state.matrix.map_rect(rect).0);
     }
+    self.log_operation(&paint, &|canvas:&SkCanvas, paint:&Paint| { canvas.draw_rect(&rect, &paint); }, false);
+    self.op_count.set(self.op_count.get() + 1);
+    self.with_canvas(|canvas| {
+      canvas.draw_rect(&rect, &paint);
+    });
   }
 
   pub fn draw_image(&mut self, img:&Option<Image>, src_rect:&Rect, dst_rect:&Rect){
-    let mut canvas_paint = self.state.paint.clone();
+    let mut canvas_paint = self.base_paint();
     canvas_paint
       .set_alpha_f(self.state.global_alpha);
 
     if let Some(image) = &img {
+      let dst_rect = self.snapped_rect(dst_rect);
+      if dst_rect.width() == 0.0 || dst_rect.height() == 0.0 {
+        return
+      }
+
+      // bicubic sampling aliases badly when shrinking by more than half, so reduce the image first
+      let m = &self.state.matrix;
+      let target = (dst_rect.width().abs() * m.scale_x().hypot(m.skew_y()), dst_rect.height().abs() * m.skew_x().hypot(m.scale_y()));
+      let reduced = match self.state.image_smoothing_enabled && self.state.image_filter_quality == FilterQuality::High{
+        true => downsample(&image, src_rect, target),
+        false => None
+      };
+      let (image, src_rect) = match &reduced{
+        Some((image, rect)) => (image, rect),
+        None => (image, src_rect)
+      };
+
+      let sampling = self.image_sampling();
+      self.render_backdrop(&Path::rect(dst_rect, None));
       self.render_to_canvas(&canvas_paint, |canvas, paint| {
-        canvas.draw_image_rect(&image, Some((src_rect, Strict)), dst_rect, &paint);
+        canvas.draw_image_rect_with_sampling_options(&image, Some((src_rect, Strict)), &dst_rect, sampling, &paint);
       });
     }
   }
 
-  pub fn get_drawable(&mut self) -> Option<Drawable> {
-    // stop the recorder to take a snapshot then restart it again
-    let mut recorder = self.recorder.borrow_mut();
-    let mut drobble = recorder.finish_recording_as_drawable();
-    recorder.begin_recording(self.bounds, None, None);
+  pub fn snapped_rect(&self, rect:&Rect) -> Rect{
+    // align the rect's edges with the device pixel grid (as long as the transform doesn't rotate or skew)
+    let matrix = &self.state.matrix;
+    if !self.state.snap_to_pixel || !matrix.rect_stays_rect(){
+      return *rect
+    }
+    let (device, _) = matrix.map_rect(rect);
+    let snapped = Rect::new(device.left.round(), device.top.round(), device.right.round(), device.bottom.round());
+    match matrix.invert(){
+      Some(inverse) => inverse.map_rect(snapped).0,
+      None => *rect
+    }
+  }
 
-    if let Some(canvas) = recorder.recording_canvas() {
-      // fill the newly restarted recorder with the snapshot content...
-      if let Some(mut palimpsest) = drobble.as_mut() {
-        canvas.draw_drawable(&mut palimpsest, None);
-      }
+  pub fn draw_image_nine(&mut self, image:&Image, center:&IRect, dst_rect:&Rect, filter:Option<FilterMode>){
+    let mut canvas_paint = self.base_paint();
+    canvas_paint.set_alpha_f(self.state.global_alpha);
+    let filter = filter.unwrap_or(self.image_sampling().filter);
 
-      // ...and the current ctm/clip state
-      canvas.save();
-      canvas.set_matrix(&self.state.matrix);
-      if !self.state.clip.is_empty(){
-        canvas.clip_path(&self.state.clip, ClipOp::Intersect, true /* antialias */);
-      }
+    // the corners are drawn unscaled while the edges & center stretch to fill the dst rect
+    self.render_backdrop(&Path::rect(dst_rect, None));
+    self.render_to_canvas(&canvas_paint, |canvas, paint| {
+      canvas.draw_image_nine(&image, center, dst_rect, filter, Some(&paint));
+    });
+  }
+
+  pub fn draw_image_mesh(&mut self, image:&Image, positions:&[Point], texs:&[Point], indices:Option<&[u16]>){
+    // texture the triangles by mapping each vertex to its uv coordinate in the image
+    // (white vertex colors leave the image's pixels unchanged when modulated)
+    let colors = vec![Color::WHITE; positions.len()];
+    let vertices = Vertices::new_copy(VertexMode::Triangles, positions, texs, &colors, indices);
+    let shader = image.to_shader((TileMode::Clamp, TileMode::Clamp), self.image_sampling(), None);
+    self.draw_mesh(&vertices, BlendMode::Modulate, Some(shader));
+  }
+
+  pub fn draw_mesh(&mut self, vertices:&Vertices, blend_mode:BlendMode, shader:Option<Shader>){
+    // without a shader the triangles are filled with their (interpolated) vertex colors alone
+    let mut canvas_paint = self.base_paint();
+    canvas_paint.set_alpha_f(self.state.global_alpha);
+    if let Some(shader) = shader{
+      canvas_paint.set_shader(shader);
     }
 
+    let bounds = Path::rect(vertices.bounds(), None);
+    self.render_backdrop(&bounds);
+    self.render_to_canvas(&canvas_paint, |canvas, paint| {
+      canvas.draw_vertices(&vertices, blend_mode, &paint);
+    });
+  }
+
+  pub fn draw_atlas(&mut self, image:&Image, xforms:&[RSXform], texs:&[Rect], colors:Option<&[Color]>){
+    let mut canvas_paint = self.base_paint();
+    canvas_paint.set_alpha_f(self.state.global_alpha);
 
-    drobble
+    // the area covered by the sprites (used for backdrop filters and the atlas's cull rect)
+    let mut region = Path::new();
+    for (xform, tex) in xforms.iter().zip(texs.iter()){
+      let matrix = Matrix::new_all(xform.scos, -xform.ssin, xform.tx, xform.ssin, xform.scos, xform.ty, 0.0, 0.0, 1.0);
+      region.add_poly(&matrix.map_rect_to_quad(Rect::from_wh(tex.width(), tex.height())), true);
+    }
+    let cull = *region.bounds();
+
+    // each sprite's tint color (if any) is multiplied with its pixels
+    let sampling = self.image_sampling();
+    self.render_backdrop(&region);
+    self.render_to_canvas(&canvas_paint, |canvas, paint| {
+      canvas.draw_atlas(image, xforms, texs, colors, BlendMode::Modulate, sampling, cull, Some(&paint));
+    });
   }
 
+  pub fn draw_picture(&mut self, pict:&Picture, src_rect:&Rect, dst_rect:&Rect){
+    let mut paint = self.base_paint();
+    paint.set_alpha_f(self.state.global_alpha);
+
+    // map the src rect onto the dst rect, drawing the vector content at the destination's resolution
+    let mag = Point::new(dst_rect.width()/src_rect.width(), dst_rect.height()/src_rect.height());
+    let mut matrix = Matrix::translate((dst_rect.x(), dst_rect.y()));
+    matrix.pre_scale( (mag.x, mag.y), None )
+          .pre_translate((-src_rect.x(), -src_rect.y()));
+
+    self.render_backdrop(&Path::rect(dst_rect, None));
+    self.render_to_canvas(&paint, |canvas, paint| {
+      canvas.save();
+      canvas.clip_rect(dst_rect, ClipOp::Intersect, true);
+      canvas.draw_picture(pict, Some(&matrix), Some(&paint));
+      canvas.restore();
+    });
+  }
 
   pub fn get_picture(&mut self, cull: Option<&Rect>) -> Option<Picture> {
     // stop the recorder to take a snapshot then restart it again
+    self.flush_pixels();
     let mut recorder = self.recorder.borrow_mut();
     let snapshot = recorder.finish_recording_as_picture(cull.or(Some(&self.bounds)));
-    recorder.begin_recording(self.bounds, None, None);
+    recorder.begin_recording(self.bounds, None);
 
     if let Some(canvas) = recorder.recording_canvas() {
       // fill the newly restarted recorder with the snapshot content...
@@ -431,124 +1060,881 @@ impl Context2D{
 
       // ...and the current ctm/clip state
       canvas.save();
-      canvas.set_matrix(&self.state.matrix);
-      if !self.state.clip.is_empty(){
-        canvas.clip_path(&self.state.clip, ClipOp::Intersect, true /* antialias */);
-      }
+      set_clip_and_matrix(canvas, &self.state.clip, &self.state.matrix);
     }
-    snapshot
+    drop(recorder);
+    self.compose_links(snapshot, cull)
   }
 
-  pub fn get_pixels(&mut self, buffer: &mut [u8], origin: impl Into<IPoint>, size: impl Into<ISize>){
-    let origin = origin.into();
-    let size = size.into();
-    let info = ImageInfo::new(size, ColorType::RGBA8888, AlphaType::Unpremul, None);
+  pub fn annotate_url(&mut self, url:&str, rect:Option<Rect>){
+    // hyperlinks don't affect the bitmap but are emitted as clickable regions in pdf & svg exports
+    // (when no rect is given, the current path's bounds are used, which are already in device coordinates)
+    let (rect, matrix) = match rect{
+      Some(rect) => (rect, self.state.matrix),
+      None => (self.path.bounds(), Matrix::new_identity())
+    };
+    if rect.is_empty(){ return }
 
-    if let Some(pict) = self.get_picture(None) {
-      if let Some(mut bitmap_surface) = Surface::new_raster_n32_premul(size){
-        let shift = Matrix::translate((-origin.x as f32, -origin.y as f32));
-        bitmap_surface.canvas().draw_picture(&pict, Some(&shift), None);
-        bitmap_surface.read_pixels(&info, buffer, info.min_row_bytes(), (0,0));
-      }
-    }
+    let data = Data::new_copy(format!("{}\0", url).as_bytes());
+    self.with_canvas(|canvas|{
+      canvas.save();
+      canvas.set_matrix(&M44::from(matrix));
+      canvas.annotate_rect_with_url(rect, &data);
+      canvas.restore();
+    });
+    self.op_count.set(self.op_count.get() + 1);
   }
 
-  pub fn blit_pixels(&mut self, buffer: &[u8], info: &ImageInfo, src_rect:&Rect, dst_rect:&Rect){
-    // works just like draw_image in terms of src/dst rects, but clears the dst_rect and then draws
-    // without clips, transforms, alpha, blend, or shadows
-    let data = unsafe{ Data::new_bytes(buffer) };
-    if let Some(bitmap) = Image::from_raster_data(&info, data, info.min_row_bytes()) {
-      self.push();
-      self.reset_canvas();
-      self.with_canvas(|canvas| {
-        let mut paint = Paint::default();
-        let mut eraser = Paint::default();
-        eraser.set_blend_mode(BlendMode::Clear);
-        canvas.draw_image_rect(&bitmap, Some((src_rect, Strict)), dst_rect, &eraser);
-        canvas.draw_image_rect(&bitmap, Some((src_rect, Strict)), dst_rect, &paint);
-      });
-      self.pop();
-    }
+  pub fn annotate_destination(&mut self, name:&str, point:Point){
+    // named destinations mark a spot (in device coordinates) that pdf outlines can point to
+    let data = Data::new_copy(format!("{}\0", name).as_bytes());
+    self.with_canvas(|canvas|{
+      canvas.save();
+      canvas.reset_matrix();
+      canvas.annotate_named_destination(point, &data);
+      canvas.restore();
+    });
+    self.op_count.set(self.op_count.get() + 1);
   }
 
-  pub fn set_font(&mut self, spec: FontSpec){
-    let mut library = self.library.borrow_mut();
-    if let Some(new_style) = library.update_style(&self.state.char_style, &spec){
-      self.state.font = spec.canonical;
-      self.state.font_variant = spec.variant.to_string();
-      self.state.char_style = new_style;
+  pub fn link_canvas(&mut self, source:Option<Picture>, origin:Point){
+    // end the current recording so the source's latest content can be spliced in after it at render time
+    self.flush_pixels();
+    self.invalidate_raster();
+    let mut recorder = self.recorder.borrow_mut();
+    let prefix = recorder.finish_recording_as_picture(Some(&self.bounds));
+    recorder.begin_recording(self.bounds, None);
+    if let Some(canvas) = recorder.recording_canvas() {
+      canvas.save();
+      set_clip_and_matrix(canvas, &self.state.clip, &self.state.matrix);
     }
-  }
+    drop(recorder);
 
-  pub fn set_font_variant(&mut self, variant:&str, features:&[(String, i32)]){
-    let mut library = self.library.borrow_mut();
-    let new_style = library.update_features(&self.state.char_style, features);
-    self.state.font_variant = variant.to_string();
-    self.state.char_style = new_style;
+    // the linked canvas's current extent (or the whole clip if it hasn't been snapshotted yet) counts as drawn
+    let mut region = self.clip_bounds(false);
+    if let Some(source) = &source{
+      let (placed, _) = self.state.matrix.map_rect(source.cull_rect().with_offset(origin));
+      if !region.intersect(placed){ region = Rect::new_empty() }
+    }
+    self.note_drawn(region);
+
+    let mut paint = self.base_paint();
+    paint.set_alpha_f(self.state.global_alpha);
+    self.links.push(LiveLink{
+      prefix, paint, origin, source,
+      matrix: self.state.matrix,
+      clip: self.state.clip.clone(),
+    });
+    self.op_count.set(self.op_count.get() + 1);
   }
 
-  pub fn typeset(&mut self, text: &str, width:f32, paint: Paint) -> Paragraph {
-    let mut char_style = self.state.char_style.clone();
-    char_style.set_foreground_color(Some(paint));
+  pub fn link_count(&self) -> usize{
+    self.links.len()
+  }
 
-    let mut graf_style = self.state.graf_style.clone();
-    let text = match self.state.text_wrap{
-      true => text.to_string(),
-      false => {
-        graf_style.set_max_lines(1);
-        text.replace("\n", " ")
+  pub fn update_links(&mut self, sources:Vec<Option<Picture>>){
+    self.invalidate_raster();
+    for (link, source) in self.links.iter_mut().zip(sources){
+      if source.is_some(){
+        link.source = source;
       }
-    };
+    }
+  }
 
-    let mut library = self.library.borrow_mut();
-    let collection = library.collect_fonts(&char_style);
-    let mut paragraph_builder = ParagraphBuilder::new(&graf_style, collection);
-    paragraph_builder.push_style(&char_style);
-    paragraph_builder.add_text(&text);
+  fn compose_links(&self, tail:Option<Picture>, cull:Option<&Rect>) -> Option<Picture>{
+    if self.links.is_empty(){
+      return tail
+    }
 
-    let mut paragraph = paragraph_builder.build();
-    paragraph.layout(width);
-    paragraph
+    let mut recorder = PictureRecorder::new();
+    let canvas = recorder.begin_recording(self.bounds, None);
+    for link in &self.links{
+      if let Some(prefix) = &link.prefix{
+        canvas.draw_picture(&prefix, None, None);
+      }
+      if let Some(source) = &link.source{
+        canvas.save();
+        set_clip_and_matrix(canvas, &link.clip, &link.matrix);
+        canvas.clip_rect(source.cull_rect().with_offset(link.origin), ClipOp::Intersect, true);
+        canvas.draw_picture(&source, Some(&Matrix::translate(link.origin)), Some(&link.paint));
+        canvas.restore();
+      }
+    }
+    if let Some(tail) = &tail{
+      canvas.draw_picture(&tail, None, None);
+    }
+    recorder.finish_recording_as_picture(cull.or(Some(&self.bounds)))
   }
 
-  pub fn draw_text(&mut self, text: &str, x: f32, y: f32, width: Option<f32>, paint: Paint){
-    let width = width.unwrap_or(GALLEY);
+  pub fn stats(&mut self) -> [f32; 5]{
+    // the recording's size along with the current path's complexity and save/restore depth
+    let bytes = self.get_picture(None).map(|pict| pict.approximate_bytes_used()).unwrap_or(0);
+    [self.op_count.get() as f32, bytes as f32, self.path.count_verbs() as f32, self.path.count_points() as f32, self.stack.len() as f32]
+  }
 
-    let mut text_paint = paint.clone();
+  pub fn take_picture(&mut self) -> Option<Picture> {
+    // like get_picture, but leave the canvas blank afterward (while preserving the ctm/clip state)
+    self.flush_pixels();
+    self.invalidate_raster();
+    self.op_count.set(0);
+    self.drawn.set(None);
+    let mut recorder = self.recorder.borrow_mut();
+    let snapshot = recorder.finish_recording_as_picture(Some(&self.bounds));
+    recorder.begin_recording(self.bounds, None);
+
+    if let Some(canvas) = recorder.recording_canvas() {
+      canvas.save();
+      set_clip_and_matrix(canvas, &self.state.clip, &self.state.matrix);
+    }
+    drop(recorder);
+    let snapshot = self.compose_links(snapshot, None);
+    self.links.clear();
+    snapshot
+  }
+
+  pub fn get_pixels(&mut self, buffer: &mut [u8], origin: impl Into<IPoint>, size: impl Into<ISize>){
+    let origin = origin.into();
+    let size = size.into();
+    let info = ImageInfo::new(size, self.pixel_format.data_type(), AlphaType::Unpremul, self.working_space.pixel_color_space());
+
+    // rasterize the whole canvas once and reuse it for subsequent reads until something new is drawn
+    self.flush_pixels();
+    if self.raster.borrow().is_none(){
+      let dims = ISize::new(self.bounds.width().ceil() as i32, self.bounds.height().ceil() as i32);
+      let raster = self.get_picture(None).and_then(|pict| {
+        let mut bitmap_surface = pool::take_surface_as(dims, self.working_space, self.pixel_format)?;
+        bitmap_surface.canvas().draw_picture(&pict, None, None);
+        Some(bitmap_surface)
+      });
+      self.raster.replace(raster);
+    }
+
+    // regions outside the canvas bounds are left transparent
+    buffer.iter_mut().for_each(|byte| *byte = 0);
+    if let Some(bitmap_surface) = self.raster.borrow_mut().as_mut(){
+      bitmap_surface.read_pixels(&info, buffer, info.min_row_bytes(), origin);
+    }
+  }
+
+  pub fn invalidate_raster(&self){
+    // discard the cached rasterization used by getImageData
+    if let Some(surface) = self.raster.replace(None){
+      pool::recycle_surface(surface);
+    }
+  }
+
+  pub fn blit_pixels(&mut self, buffer: &[u8], info: &ImageInfo, src_rect:&Rect, dst_rect:&Rect){
+    // pixels are copied 1:1 so only the dirty region (clipped to the canvas) needs to be transferred
+    let offset = IPoint::new((dst_rect.left - src_rect.left).round() as i32, (dst_rect.top - src_rect.top).round() as i32);
+    let canvas_bounds = IRect::from_wh(self.bounds.width().ceil() as i32, self.bounds.height().ceil() as i32);
+    let dst = IRect::intersect(&src_rect.round(), &info.bounds())
+      .and_then(|src| IRect::intersect(&src.with_offset(offset), &canvas_bounds));
+    let (src, dst) = match dst{
+      Some(dst) if !dst.is_empty() => (dst.with_offset((-offset.x, -offset.y)), dst),
+      _ => return
+    };
+
+    // write the sub-rect into the batch's raster layer without making an intermediate image
+    let row_bytes = info.min_row_bytes();
+    let start = src.top as usize * row_bytes + src.left as usize * info.bytes_per_pixel();
+    let sub_info = info.with_dimensions(src.size()).with_color_space(self.working_space.pixel_color_space());
+    let mut batch = self.pixels.borrow_mut();
+    if batch.surface.is_none(){
+      batch.surface = pool::take_surface_as(canvas_bounds.size(), self.working_space, self.pixel_format);
+    }
+    if let Some(surface) = batch.surface.as_mut(){
+      if surface.canvas().write_pixels(&sub_info, &buffer[start..], row_bytes, (dst.left, dst.top)){
+        batch.dirty.push(dst);
+      }
+    }
+  }
+
+  pub fn flush_pixels(&self){
+    // record any pending putImageData regions before the next drawing operation
+    let (image, dirty) = {
+      let mut batch = self.pixels.borrow_mut();
+      if batch.dirty.is_empty(){ return }
+      self.invalidate_raster();
+      let dirty = std::mem::take(&mut batch.dirty);
+      match batch.surface.as_mut(){
+        Some(surface) => (surface.image_snapshot(), dirty),
+        None => return
+      }
+    };
+
+    // draw without clips, transforms, alpha, blend, or shadows, then restore the current state
+    let mut recorder = self.recorder.borrow_mut();
+    if let Some(canvas) = recorder.recording_canvas() {
+      canvas.restore_to_count(1);
+      canvas.save();
+
+      let paint = Paint::default();
+      let mut eraser = Paint::default();
+      eraser.set_blend_mode(BlendMode::Clear);
+      for rect in dirty.iter().map(|r| Rect::from_irect(*r)){
+        canvas.draw_image_rect(&image, Some((&rect, Strict)), &rect, &eraser);
+        canvas.draw_image_rect(&image, Some((&rect, Strict)), &rect, &paint);
+        self.note_drawn(rect);
+      }
+
+      set_clip_and_matrix(canvas, &self.state.clip, &self.state.matrix);
+    }
+  }
+
+  pub fn set_font(&mut self, spec: FontSpec){
+    let mut library = self.library.borrow_mut();
+    if let Some(new_style) = library.update_style(&self.state.char_style, &spec){
+      self.state.font = spec.canonical;
+      self.state.font_variant = spec.variant.to_string();
+      self.state.char_style = new_style;
+    }
+  }
+
+  pub fn set_font_variant(&mut self, variant:&str, features:&[(String, i32)]){
+    let mut library = self.library.borrow_mut();
+    let new_style = library.update_features(&self.state.char_style, features);
+    self.state.font_variant = variant.to_string();
+    self.state.char_style = new_style;
+  }
+
+  pub fn text_style(&self, paint: Paint) -> TextStyle {
+    let mut char_style = self.state.char_style.clone();
+    self.decorate(&mut char_style, paint.color());
+    char_style.set_foreground_paint(&paint);
+    char_style
+  }
+
+  fn indent_placeholder(&self) -> PlaceholderStyle{
+    PlaceholderStyle::new(self.state.text_indent, 0.0, PlaceholderAlignment::Baseline, TextBaseline::Alphabetic, 0.0)
+  }
+
+  fn indent_offset(&self) -> usize{
+    // the number of bytes the indentation placeholder's U+FFFC character adds to the text
+    if self.state.text_indent > 0.0 { '\u{FFFC}'.len_utf8() } else { 0 }
+  }
+
+  pub fn decorate(&self, style:&mut TextStyle, color:Color){
+    let decoration = &self.state.text_decoration;
+    if decoration.ty.is_empty(){
+      return
+    }
+
+    style.set_decoration_type(decoration.ty);
+    style.set_decoration_style(decoration.style);
+    style.set_decoration_color(decoration.color.unwrap_or(color));
+    if let Some(thickness) = decoration.thickness{
+      // skia measures the line thickness as a multiple of the font's own underline thickness
+      let metrics = style.font_metrics();
+      let base = metrics.underline_thickness().unwrap_or(1.0).max(f32::EPSILON);
+      style.set_decoration_thickness_multiplier(thickness / base);
+    }
+  }
+
+  fn paragraph_style(&self, runs:&[(String, TextStyle)]) -> ParagraphStyle {
+    // an ‘inherited’ direction is resolved from the text itself (which also affects the alignment factor)
+    let mut graf_style = self.state.graf_style.clone();
+    if self.state.direction.is_none(){
+      graf_style.set_text_direction(base_direction(runs.iter().map(|(text, _)| text.as_str())));
+    }
+    graf_style
+  }
+
+  fn alignment_factor(&self, runs:&[(String, TextStyle)]) -> f32 {
+    get_alignment_factor(&self.paragraph_style(runs))
+  }
+
+  pub fn typeset_runs(&mut self, runs:&[(String, TextStyle)], width:f32) -> Paragraph {
+    let graf_style = self.paragraph_style(runs);
+    self.typeset_with_style(runs, width, graf_style)
+  }
+
+  fn with_typeset<R>(&mut self, runs:&[(String, TextStyle)], width:f32, f:impl FnOnce(&mut Self, &mut Paragraph, &mut Vec<(usize, Point, Paragraph)>) -> R) -> R {
+    // reuse the layout from an earlier fillText() or measureText() call with identical text & settings
+    let key = self.typeset_key(runs, width);
+    let cached = self.library.borrow_mut().paragraph_cache.take(&key);
+    let (mut paragraph, mut hyphens) = match cached{
+      Some(typeset) => typeset,
+      None => {
+        let paragraph = self.typeset_runs(runs, width);
+        let hyphens = self.hyphens(&paragraph, runs);
+        (paragraph, hyphens)
+      }
+    };
+    let result = f(self, &mut paragraph, &mut hyphens);
+    self.library.borrow_mut().paragraph_cache.insert(key, (paragraph, hyphens));
+    result
+  }
+
+  fn typeset_key(&self, runs:&[(String, TextStyle)], width:f32) -> TypesetKey {
+    TypesetKey{
+      runs: runs.to_vec(),
+      width,
+      graf_style: self.paragraph_style(runs),
+      features: self.state.font_features.clone(),
+      fallbacks: self.state.font_fallbacks.clone(),
+      line_height: self.state.line_height,
+      tab_size: self.state.tab_size,
+      tab_stops: self.state.tab_stops.clone(),
+      indent: self.state.text_indent,
+      line_break: self.state.line_break,
+      tracking: self.state.text_tracking,
+      wrap: self.state.text_wrap,
+      overflow: self.state.text_overflow,
+      max_lines: self.state.max_lines,
+      rendering: self.state.text_rendering,
+      hinting: self.state.font_hinting,
+    }
+  }
+
+  fn typeset_with_style(&mut self, runs:&[(String, TextStyle)], width:f32, mut graf_style:ParagraphStyle) -> Paragraph {
+    match (self.state.text_wrap, self.state.max_lines){
+      (false, _) => { graf_style.set_max_lines(1); },
+      (true, Some(max)) => { graf_style.set_max_lines(max); },
+      _ => {}
+    }
+    if self.state.text_overflow == TextOverflow::Ellipsis{
+      // truncate the final line (rather than omitting its last word) when the text doesn't fit the width
+      graf_style.set_ellipsis("\u{2026}");
+    }
+    if !self.state.font_hinting || self.state.text_rendering == TextRendering::GeometricPrecision{
+      graf_style.turn_hinting_off();
+    }
+    let rendering_features:&[(&str, i32)] = match self.state.text_rendering{
+      TextRendering::OptimizeSpeed => &[("kern", 0), ("liga", 0), ("calt", 0)],
+      TextRendering::OptimizeLegibility => &[("kern", 1), ("liga", 1)],
+      _ => &[]
+    };
+
+    let runs:Vec<(String, TextStyle)> = runs.iter().map(|(text, style)|{
+      let mut style = style.clone();
+      style.set_font_families(&self.font_stack(&style));
+      for (feat, val) in rendering_features{
+        style.add_font_feature(feat, *val);
+      }
+      for (feat, val) in &self.state.font_features{
+        style.add_font_feature(feat, *val);
+      }
+      match self.state.line_height{
+        LineHeight::Scale(scale) => { style.set_height(scale).set_height_override(true); },
+        LineHeight::Pixels(px) => { style.set_height(px / style.font_size()).set_height_override(true); },
+        LineHeight::Normal => {}
+      }
+      let text = match self.state.text_wrap{
+        true => text.to_string(),
+        false => text.replace("\n", " ")
+      };
+      (text, style)
+    }).collect();
+
+    let runs = match runs.iter().any(|(text, _)| text.contains('\t')){
+      true => self.expand_tabs(runs),
+      false => runs
+    };
+
+    // a single run can use a collection specific to its (possibly variable) font
+    let mut library = self.library.borrow_mut();
+    let collection = match runs.as_slice(){
+      [(_, char_style)] => library.collect_fonts(&char_style),
+      _ => library.collection.clone()
+    };
+    let mut paragraph_builder = ParagraphBuilder::new(&graf_style, collection);
+    if self.state.text_indent > 0.0 && !runs.is_empty(){
+      // inset the first line using an empty, fixed-width placeholder
+      paragraph_builder.push_style(&runs[0].1);
+      paragraph_builder.add_placeholder(&self.indent_placeholder());
+      paragraph_builder.pop();
+    }
+    for (text, char_style) in &runs{
+      paragraph_builder.push_style(&char_style);
+      paragraph_builder.add_text(&text);
+      paragraph_builder.pop();
+    }
+
+    // leave room at the end of each line for the hyphen that may be drawn after a soft-hyphen break
+    let soft_hyphens = self.state.text_wrap && runs.iter().any(|(text, _)| text.contains(SOFT_HYPHEN));
+    let measure = match (soft_hyphens, runs.first()){
+      (true, Some((_, style))) if width < GALLEY => width - library.hyphen(style).max_intrinsic_width(),
+      _ => width
+    };
+
+    let mut paragraph = paragraph_builder.build();
+    paragraph.layout(measure);
+    paragraph
+  }
+
+  fn expand_tabs(&mut self, runs:Vec<(String, TextStyle)>) -> Vec<(String, TextStyle)>{
+    // replace each tab with a space whose letter-spacing carries the line over to the next tab stop
+    // (the positions are tracked from the start of each hard line, so they don't account for wrapping)
+    let mut expanded = vec![];
+    let mut x = self.state.text_indent;
+    for (text, style) in runs{
+      let space = self.run_width(" ", &style);
+      let interval = match self.state.tab_size{
+        TabSize::Spaces(count) => count * space,
+        TabSize::Pixels(px) => px
+      };
+
+      let mut segment = String::new();
+      for c in text.chars(){
+        match c{
+          '\t' => {
+            if !segment.is_empty(){
+              x += self.run_width(&segment, &style);
+              expanded.push((std::mem::take(&mut segment), style.clone()));
+            }
+            let stop = self.next_tab_stop(x + space / 2.0, interval).max(x);
+            let mut tab_style = style.clone();
+            tab_style.set_letter_spacing(tab_style.letter_spacing() + stop - x - space);
+            expanded.push((" ".to_string(), tab_style));
+            x = stop;
+          },
+          '\n' => {
+            segment.push(c);
+            expanded.push((std::mem::take(&mut segment), style.clone()));
+            x = 0.0;
+          },
+          _ => segment.push(c)
+        }
+      }
+      if !segment.is_empty(){
+        x += self.run_width(&segment, &style);
+        expanded.push((segment, style));
+      }
+    }
+    expanded
+  }
+
+  fn next_tab_stop(&self, x:f32, interval:f32) -> f32{
+    // use the first explicit stop past `x`, then continue at multiples of the tab size
+    match self.state.tab_stops.iter().find(|stop| **stop >= x){
+      Some(stop) => *stop,
+      None if interval > 0.0 => (x / interval).ceil() * interval,
+      None => x
+    }
+  }
+
+  fn run_width(&mut self, text:&str, style:&TextStyle) -> f32{
+    // the advance of a single-line run (including any trailing whitespace)
+    let collection = self.library.borrow_mut().collect_fonts(style);
+    let mut paragraph_builder = ParagraphBuilder::new(&ParagraphStyle::new(), collection);
+    paragraph_builder.push_style(style);
+    paragraph_builder.add_text(text);
+    let mut paragraph = paragraph_builder.build();
+    paragraph.layout(GALLEY);
+    paragraph.get_rects_for_range(0..text.len(), RectHeightStyle::Tight, RectWidthStyle::Tight)
+      .iter().map(|textbox| textbox.rect.right).fold(0.0, f32::max)
+  }
+
+  pub fn hyphens(&mut self, paragraph:&Paragraph, runs:&[(String, TextStyle)]) -> Vec<(usize, Point, Paragraph)>{
+    // find the lines that were broken at a soft-hyphen and typeset a visible hyphen to follow each one
+    if !self.state.text_wrap{
+      return vec![]
+    }
+
+    let shift = self.indent_offset();
+    let mut library = self.library.borrow_mut();
+    let line_count = paragraph.line_number();
+    paragraph.get_line_metrics().iter().filter(|line| line.line_number + 1 < line_count).filter_map(|line|{
+      let end = line.end_excluding_whitespaces.checked_sub(shift)?;
+      let mut offset = 0;
+      for (text, style) in runs{
+        if end > offset && end <= offset + text.len(){
+          let idx = end - offset;
+          return match text.get(..idx).map(|head| head.ends_with(SOFT_HYPHEN)){
+            Some(true) => {
+              let hyphen = library.hyphen(style);
+              let origin = Point::new((line.left + line.width) as f32, (line.baseline as f32) - hyphen.alphabetic_baseline());
+              Some((line.line_number, origin, hyphen))
+            },
+            _ => None
+          }
+        }
+        offset += text.len();
+      }
+      None
+    }).collect()
+  }
+
+  pub fn font_stack(&self, style:&TextStyle) -> Vec<String>{
+    // the style's own families followed by any of the ctx.fontFallbacks not already listed
+    // and finally the library's color emoji font (so emoji don't need to be requested explicitly)
+    let mut families:Vec<String> = style.font_families().iter().map(|f| f.to_string()).collect();
+    let emoji = self.library.borrow().emoji.clone();
+    for family in self.state.font_fallbacks.iter().chain(emoji.iter()){
+      if !families.contains(family){
+        families.push(family.clone());
+      }
+    }
+    families
+  }
+
+  pub fn text_fallbacks(&mut self, text: &str) -> Vec<(usize, String, Option<String>)>{
+    // find the characters the primary font can't render and report which family (if any) will
+    // be substituted for each one, with `None` signifying a .notdef ‘tofu’ glyph
+    let style = self.state.char_style.font_style();
+    let families = self.font_stack(&self.state.char_style);
+    let families:Vec<&str> = families.iter().map(|f| f.as_str()).collect();
+    let mut library = self.library.borrow_mut();
+    let typefaces = library.collection.find_typefaces(&families, style);
+
+    let mut results = vec![];
+    let mut index = 0;
+    for c in text.chars(){
+      let (pos, unichar) = (index, c as i32);
+      index += c.len_utf16();
+      if c.is_whitespace() || c.is_control(){
+        continue
+      }
+
+      match typefaces.iter().position(|face| face.unichar_to_glyph(unichar) != 0){
+        Some(0) => {},
+        Some(i) => results.push((pos, c.to_string(), Some(typefaces[i].family_name()))),
+        None => results.push((pos, c.to_string(), library.fallback_family(c, style)))
+      }
+    }
+    results
+  }
+
+  pub fn draw_text(&mut self, text: &str, x: f32, y: f32, width: Option<f32>, paint: Paint){
+    if self.state.writing_mode != WritingMode::Horizontal{
+      return self.draw_vertical_text(text, x, y, width, paint)
+    }
+    let width = width.unwrap_or(GALLEY);
+
+    let mut text_paint = paint.clone();
     text_paint.set_blend_mode(BlendMode::SrcOver);
 
-    let mut paragraph = self.typeset(&text, width, text_paint);
-    let mut point = Point::new(x, y);
+    let runs = [(text.to_string(), self.text_style(text_paint))];
     let metrics = self.state.char_style.font_metrics();
-    let offset = get_baseline_offset(&metrics, self.state.text_baseline);
-    point.y += offset - paragraph.alphabetic_baseline();
-    point.x += width * get_alignment_factor(&self.state.graf_style);
+    let len = text.len() + self.indent_offset();
+    self.with_typeset(&runs, width, |ctx, paragraph, hyphens|{
+      ctx.draw_paragraph(paragraph, &runs, hyphens, len, &metrics, (x, y), width, &paint, None);
+    });
+  }
 
-    let mut bounds = paragraph.get_rects_for_range(0..text.len(), RectHeightStyle::IncludeLineSpacingBottom, RectWidthStyle::Tight)
-      .iter().map(|textbox| textbox.rect)
-      .fold(Rect::new_empty(), Rect::join2);
-    bounds.outset((paint.stroke_width(), paint.stroke_width()));
+  pub fn fill_and_stroke_text(&mut self, text: &str, x: f32, y: f32, width: Option<f32>){
+    let fill = self.paint_for_fill();
+    let stroke = self.paint_for_stroke();
+    if self.state.writing_mode != WritingMode::Horizontal{
+      let paints = match self.state.paint_order{
+        PaintOrder::FillFirst => [fill, stroke],
+        PaintOrder::StrokeFirst => [stroke, fill],
+      };
+      for paint in paints.iter(){
+        self.draw_vertical_text(text, x, y, width, paint.clone());
+      }
+      return
+    }
+    let width = width.unwrap_or(GALLEY);
+
+    // typeset the text with each paint (sharing a single background & shadow) and draw them in paint-order
+    let (mut fill_paint, mut stroke_paint) = (fill.clone(), stroke.clone());
+    fill_paint.set_blend_mode(BlendMode::SrcOver);
+    stroke_paint.set_blend_mode(BlendMode::SrcOver);
 
-    // render the text once into a picture we can use for the shadow as well
+    let runs = [(text.to_string(), self.text_style(fill_paint))];
+    let outline_runs = [(text.to_string(), self.text_style(stroke_paint))];
+    let metrics = self.state.char_style.font_metrics();
+    let len = text.len() + self.indent_offset();
+    self.with_typeset(&runs, width, |ctx, paragraph, hyphens|{
+      ctx.with_typeset(&outline_runs, width, |ctx, outline, outline_hyphens|{
+        let stroke = Some((outline, outline_hyphens.as_mut_slice(), &stroke));
+        ctx.draw_paragraph(paragraph, &runs, hyphens, len, &metrics, (x, y), width, &fill, stroke);
+      });
+    });
+  }
+
+  pub fn draw_vertical_text(&mut self, text: &str, x: f32, y: f32, height: Option<f32>, paint: Paint){
+    let mut text_paint = paint.clone();
+    text_paint.set_blend_mode(BlendMode::SrcOver);
+    let style = self.text_style(text_paint);
+    let alignment = self.alignment_factor(&[(text.to_string(), style.clone())]);
+    let em = style.font_size();
+    let leading = match self.state.line_height{
+      LineHeight::Scale(scale) => scale * em,
+      LineHeight::Pixels(px) => px,
+      LineHeight::Normal => style.height() * em
+    };
+    let height = height.unwrap_or(GALLEY);
+    let sideways = |c:char| match self.state.text_orientation{
+      TextOrientation::Mixed => !is_upright(c),
+      TextOrientation::Upright => false,
+      TextOrientation::Sideways => true,
+    };
+
+    // split the text into columns, each containing a list of upright characters and sideways runs
+    let mut columns:Vec<Vec<(String, bool)>> = vec![vec![]];
+    for c in text.chars(){
+      if c == '\n' && self.state.text_wrap{
+        columns.push(vec![]);
+        continue
+      }
+
+      let c = if c == '\n' { ' ' }else{ c };
+      let rotated = sideways(c);
+      let column = columns.last_mut().unwrap();
+      match column.last_mut(){
+        Some((run, true)) if rotated => run.push(c),
+        _ => column.push((c.to_string(), rotated))
+      }
+    }
+
+    // typeset each piece and stack them along the column (wrapping or truncating at the `height`)
+    // with the first column inset by the textIndent rather than each piece getting its own indentation
+    let indent = std::mem::replace(&mut self.state.text_indent, 0.0);
+    let mut pieces:Vec<(Point, Paragraph, bool)> = vec![];
+    let mut col_x = 0.0;
+    let mut full = false;
+    for (i, column) in columns.into_iter().enumerate(){
+      let mut col_pieces:Vec<(Point, Paragraph, bool)> = vec![];
+      let mut cursor = if i == 0 { indent }else{ 0.0 };
+      for (chunk, rotated) in column{
+        let para = self.typeset_runs(&[(chunk, style.clone())], GALLEY);
+        let advance = if rotated{ para.max_intrinsic_width() }else{ em };
+        if cursor + advance > height && cursor > 0.0{
+          if !self.state.text_wrap{ full = true; break }
+          self.align_column(&mut col_pieces, cursor, alignment, &mut pieces);
+          col_x += if self.state.writing_mode==WritingMode::VerticalRL{ -leading }else{ leading };
+          cursor = 0.0;
+        }
+        let origin = match rotated{
+          true => Point::new(col_x + para.height() / 2.0, cursor),
+          false => Point::new(col_x - para.max_intrinsic_width() / 2.0, cursor - (para.height() - em) / 2.0),
+        };
+        col_pieces.push((origin, para, rotated));
+        cursor += advance;
+      }
+      self.align_column(&mut col_pieces, cursor, alignment, &mut pieces);
+      if full{ break }
+      col_x += if self.state.writing_mode==WritingMode::VerticalRL{ -leading }else{ leading };
+    }
+    self.state.text_indent = indent;
+
+    let bounds = pieces.iter().map(|(pt, para, rotated)| match rotated{
+      true => Rect::from_xywh(pt.x - para.height(), pt.y, para.height(), para.max_intrinsic_width()),
+      false => Rect::from_xywh(pt.x, pt.y, para.max_intrinsic_width(), para.height())
+    }).fold(Rect::new_empty(), Rect::join2).with_outset((paint.stroke_width(), paint.stroke_width()));
+
+    // render the pieces into a picture (rotating the sideways runs 90° clockwise)
     let mut recorder = PictureRecorder::new();
-    recorder.begin_recording(bounds, None, None);
+    recorder.begin_recording(bounds, None);
     if let Some(canvas) = recorder.recording_canvas() {
-      paragraph.paint(canvas, (0.0,0.0));
+      for (pt, para, rotated) in pieces.iter_mut(){
+        canvas.save();
+        canvas.translate(*pt);
+        if *rotated{ canvas.rotate(90.0, None); }
+        para.paint(canvas, (0.0, 0.0));
+        canvas.restore();
+      }
 
       if let Some(pict) = recorder.finish_recording_as_picture(Some(&bounds)){
-        let position = Matrix::translate(point);
+        let position = Matrix::translate((x, y));
+        self.render_backdrop(&Path::rect(bounds.with_offset((x, y)), None));
         self.render_to_canvas(&paint, |canvas, paint| {
           canvas.draw_picture(&pict, Some(&position), Some(&paint));
         });
       }
     }
+  }
+
+  pub fn draw_glyphs(&mut self, glyphs:&[(String, f32, f32, f32)], font:Option<FontSpec>){
+    let paint = self.paint_for_fill();
+    let mut text_paint = paint.clone();
+    text_paint.set_blend_mode(BlendMode::SrcOver);
+    let mut style = self.text_style(text_paint.clone());
+    if let Some(spec) = font{
+      if let Some(font_style) = self.library.borrow_mut().update_style(&style, &spec){
+        style = font_style;
+      }
+    }
+    style.set_font_families(&self.font_stack(&style));
+    let font = match self.library.borrow_mut().font_for(&style){
+      Some(font) => font,
+      None => return
+    };
 
+    // shape all the graphemes as a single line, then note which one each glyph belongs to (via the byte
+    // offset of its cluster) and where its left edge falls so the caller's positions can be applied
+    let text:String = glyphs.iter().map(|(text, ..)| text.as_str()).collect();
+    let starts:Vec<usize> = glyphs.iter().scan(0, |offset, (text, ..)|{
+      let start = *offset;
+      *offset += text.len();
+      Some(start)
+    }).collect();
+    let direction = self.state.direction.unwrap_or_else(|| base_direction(glyphs.iter().map(|(text, ..)| text.as_str())));
+    let font_mgr = self.library.borrow().font_mgr();
+    let runs = shape_glyphs(&text, &font, &font_mgr, direction == TextDirection::LTR);
+
+    let grapheme_of = |cluster:u32| starts.iter().rposition(|start| *start <= cluster as usize).unwrap_or(0);
+    let mut anchors = vec![f32::INFINITY; glyphs.len()];
+    for run in &runs{
+      for (pos, cluster) in run.positions.iter().zip(run.clusters.iter()){
+        let idx = grapheme_of(*cluster);
+        anchors[idx] = anchors[idx].min(pos.x);
+      }
+    }
+
+    // draw every glyph in a single text blob, rotating each grapheme around its point on the baseline
+    let mut builder = TextBlobBuilder::new();
+    for run in &runs{
+      let (ids, xforms) = builder.alloc_run_rsxform(&run.font, run.glyphs.len());
+      for (i, (pos, cluster)) in run.positions.iter().zip(run.clusters.iter()).enumerate(){
+        let idx = grapheme_of(*cluster);
+        let (_, x, y, rotation) = glyphs[idx];
+        let (dx, dy) = (pos.x - anchors[idx], pos.y);
+        let (sin, cos) = rotation.sin_cos();
+        ids[i] = run.glyphs[i];
+        xforms[i] = RSXform::new(cos, sin, (x + dx * cos - dy * sin, y + dx * sin + dy * cos));
+      }
+    }
+    let blob = match builder.make(){
+      Some(blob) => blob,
+      None => return
+    };
+
+    let bounds = blob.bounds().with_outset((paint.stroke_width(), paint.stroke_width()));
+    if bounds.is_empty(){
+      return
+    }
+
+    // render all the glyphs into a single picture so they're drawn (and shadowed) as one unit
+    let mut recorder = PictureRecorder::new();
+    recorder.begin_recording(bounds, None);
+    if let Some(canvas) = recorder.recording_canvas() {
+      canvas.draw_text_blob(&blob, (0.0, 0.0), &text_paint);
+
+      if let Some(pict) = recorder.finish_recording_as_picture(Some(&bounds)){
+        self.render_backdrop(&Path::rect(bounds, None));
+        self.render_to_canvas(&paint, |canvas, paint| {
+          canvas.draw_picture(&pict, None, Some(&paint));
+        });
+      }
+    }
   }
 
-  pub fn measure_text(&mut self, text: &str, width:Option<f32>) -> Vec<Vec<f32>>{
+  fn align_column(&self, column:&mut Vec<(Point, Paragraph, bool)>, length:f32, alignment:f32, pieces:&mut Vec<(Point, Paragraph, bool)>){
+    // shift the column along the y-axis to reflect the textAlign setting and add it to the list
+    let offset = length * alignment;
+    pieces.extend(column.drain(..).map(|(pt, para, rotated)| (Point::new(pt.x, pt.y + offset), para, rotated)));
+  }
+
+  pub fn draw_text_spans(&mut self, spans: &[TextSpan], x: f32, y: f32, width: Option<f32>){
+    let width = width.unwrap_or(GALLEY);
     let paint = self.paint_for_fill();
-    let mut paragraph = self.typeset(&text, width.unwrap_or(GALLEY), paint);
 
+    // each span inherits the context's font & fill unless it specifies its own
+    let runs:Vec<(String, TextStyle)> = spans.iter().map(|span|{
+      let mut char_style = match &span.font{
+        Some(spec) => self.library.borrow_mut().update_style(&self.state.char_style, spec),
+        None => None
+      }.unwrap_or_else(|| self.state.char_style.clone());
+
+      let mut text_paint = paint.clone();
+      text_paint.set_blend_mode(BlendMode::SrcOver);
+      if let Some(dye) = &span.dye{
+        text_paint.set_shader(None);
+        dye.mix_into(&mut text_paint, self.state.global_alpha);
+      }
+
+      self.decorate(&mut char_style, text_paint.color());
+      if span.underline{
+        char_style.set_decoration_type(char_style.decoration_type() | TextDecoration::UNDERLINE);
+        if self.state.text_decoration.ty.is_empty(){
+          char_style.set_decoration_color(text_paint.color());
+        }
+      }
+      char_style.set_foreground_paint(&text_paint);
+      (span.text.clone(), char_style)
+    }).collect();
+
+    if let Some((_, first)) = runs.first(){
+      let metrics = first.font_metrics();
+      let len = runs.iter().map(|(text, _)| text.len()).sum::<usize>() + self.indent_offset();
+      self.with_typeset(&runs, width, |ctx, paragraph, hyphens|{
+        ctx.draw_paragraph(paragraph, &runs, hyphens, len, &metrics, (x, y), width, &paint, None);
+      });
+    }
+  }
+
+  fn draw_paragraph(&mut self, paragraph:&mut Paragraph, runs:&[(String, TextStyle)], hyphens:&mut [(usize, Point, Paragraph)], len:usize, metrics:&FontMetrics, origin:(f32, f32), width:f32, paint:&Paint, stroke:Option<(&mut Paragraph, &mut [(usize, Point, Paragraph)], &Paint)>){
+    let mut point = Point::from(origin);
+    let offset = get_baseline_offset(&metrics, self.state.text_baseline);
+    point.y += offset - paragraph.alphabetic_baseline();
+    point.x += width * self.alignment_factor(runs);
+
+    let mut bounds = paragraph.get_rects_for_range(0..len, RectHeightStyle::IncludeLineSpacingBottom, RectWidthStyle::Tight)
+      .iter().map(|textbox| textbox.rect)
+      .chain(hyphens.iter().map(|(_, pt, hyphen)| Rect::from_xywh(pt.x, pt.y, hyphen.max_intrinsic_width(), hyphen.height())))
+      .fold(Rect::new_empty(), Rect::join2);
+    let outset = stroke.as_ref().map(|(.., stroke)| stroke.stroke_width()).unwrap_or(paint.stroke_width());
+    bounds.outset((outset, outset));
+
+    // any highlight boxes are drawn beneath the text (regardless of the paint order)
+    if let Some(spec) = self.state.text_background{
+      let mut highlight = Path::new();
+      for rect in self.line_backgrounds(paragraph, &hyphens, spec.padding){
+        highlight.add_rrect(RRect::new_rect_xy(rect.with_offset(point), spec.radius, spec.radius), None);
+      }
+      if !highlight.is_empty(){
+        let mut bg_paint = self.base_paint();
+        bg_paint.set_color(self.color_with_alpha(&spec.color));
+        self.render_backdrop(&highlight);
+        self.render_to_canvas(&bg_paint, |canvas, paint| {
+          canvas.draw_path(&highlight, &paint);
+        });
+      }
+    }
+
+    // render the text into pictures we can use for the shadows as well
+    let position = Matrix::translate(point);
+    let draw_text = |ctx:&mut Self, pict:&Picture, paint:&Paint|{
+      ctx.render_backdrop(&Path::rect(bounds.with_offset(point), None));
+      ctx.render_to_canvas(paint, |canvas, paint| {
+        canvas.draw_picture(pict, Some(&position), Some(&paint));
+      });
+    };
+
+    let fill = record_paragraph(paragraph, hyphens, bounds);
+    let outline = stroke.and_then(|(outline, hyphens, stroke)|
+      record_paragraph(outline, hyphens, bounds).map(|pict| (pict, stroke))
+    );
+    match (fill, outline){
+      (Some(fill), Some((outline, stroke))) => match self.state.paint_order{
+        PaintOrder::FillFirst => { draw_text(self, &fill, paint); draw_text(self, &outline, stroke); },
+        PaintOrder::StrokeFirst => { draw_text(self, &outline, stroke); draw_text(self, &fill, paint); },
+      },
+      (Some(fill), None) => draw_text(self, &fill, paint),
+      _ => {}
+    }
+  }
+
+  fn line_backgrounds(&self, paragraph:&Paragraph, hyphens:&[(usize, Point, Paragraph)], padding:(f32, f32)) -> Vec<Rect>{
+    // a padded box around each non-empty line, extended to cover the hyphen that may follow it
+    let (pad_y, pad_x) = padding;
+    paragraph.get_line_metrics().iter().filter_map(|line|{
+      let indent = if line.line_number == 0 { self.state.text_indent } else { 0.0 };
+      let hyphen = hyphens.iter().find(|(num, ..)| *num == line.line_number)
+        .map(|(_, _, hyphen)| hyphen.max_intrinsic_width()).unwrap_or(0.0);
+      let left = line.left as f32 + indent;
+      let right = (line.left + line.width) as f32 + hyphen;
+      if right <= left { return None }
+
+      let top = (line.baseline - line.ascent) as f32;
+      let bottom = (line.baseline + line.descent) as f32;
+      Some(Rect::new(left - pad_x, top - pad_y, right + pad_x, bottom + pad_y))
+    }).collect()
+  }
+
+  pub fn measure_text(&mut self, text: &str, width:Option<f32>) -> Vec<Vec<f32>>{
+    let mut paint = self.paint_for_fill();
+    paint.set_blend_mode(BlendMode::SrcOver);
+    let runs = [(text.to_string(), self.text_style(paint))];
+    self.with_typeset(&runs, width.unwrap_or(GALLEY), |ctx, paragraph, hyphens|{
+      ctx.text_metrics(text, &runs, paragraph, hyphens)
+    })
+  }
+
+  fn text_metrics(&self, text: &str, runs:&[(String, TextStyle)], paragraph:&Paragraph, hyphens:&[(usize, Point, Paragraph)]) -> Vec<Vec<f32>>{
     let font_metrics = self.state.char_style.font_metrics();
     let offset = get_baseline_offset(&font_metrics, self.state.text_baseline);
     let hang = get_baseline_offset(&font_metrics, Baseline::Hanging) - offset;
@@ -556,149 +1942,230 @@ impl Context2D{
     let ideo = get_baseline_offset(&font_metrics, Baseline::Ideographic) - offset;
     let ascent = norm - font_metrics.ascent;
     let descent = font_metrics.descent - norm;
-    let alignment = get_alignment_factor(&self.state.graf_style);
+    let alignment = self.alignment_factor(runs);
 
     if paragraph.line_number() == 0 {
       return vec![vec![0.0, 0.0, 0.0, 0.0, 0.0, ascent, descent, ascent, descent, hang, norm, ideo]]
     }
 
+    // note the byte-range and UTF-16 index of each character (as positioned within the paragraph's text)
+    let shift = self.indent_offset();
+    let mut utf16 = 0;
+    let clusters:Vec<(Range<usize>, usize)> = text.char_indices().filter_map(|(idx, c)|{
+      let cluster = (idx + shift..idx + shift + c.len_utf8(), utf16);
+      utf16 += c.len_utf16();
+      if c == '\n' { None }else{ Some(cluster) }
+    }).collect();
+
     // find the bounds and text-range for each individual line
     let origin = paragraph.get_line_metrics()[0].baseline;
-    let line_rects:Vec<(Rect, Range<usize>, f32)> = paragraph.get_line_metrics().iter().map(|line|{
+    let line_rects:Vec<(Rect, Range<usize>, f32, Vec<f32>)> = paragraph.get_line_metrics().iter().map(|line|{
       let baseline = line.baseline - origin;
+      let hyphen = hyphens.iter().find(|(num, ..)| *num == line.line_number)
+        .map(|(.., hyphen)| hyphen.max_intrinsic_width()).unwrap_or(0.0);
       let rect = Rect::new(line.left as f32, (baseline - line.ascent) as f32,
-                          (line.width - line.left) as f32, (baseline + line.descent) as f32);
-      let range = string_idx_range(text, line.start_index, line.end_excluding_whitespaces);
-      (rect.with_offset((alignment*rect.width(), offset)), range, baseline as f32)
+                          (line.width - line.left) as f32 + hyphen, (baseline + line.descent) as f32);
+      let range = string_idx_range(text, line.start_index.saturating_sub(shift), line.end_excluding_whitespaces.saturating_sub(shift).max(1));
+      let dx = alignment * rect.width();
+
+      // collect the x-position, advance, and UTF-16 index of each character in the line
+      let glyphs = clusters.iter()
+        .filter(|(bytes, _)| bytes.start >= line.start_index && bytes.start < line.end_index)
+        .filter_map(|(bytes, index)|{
+          let boxes = paragraph.get_rects_for_range(bytes.clone(), RectHeightStyle::Tight, RectWidthStyle::Tight);
+          let left = boxes.iter().map(|textbox| textbox.rect.left).reduce(f32::min)?;
+          let right = boxes.iter().map(|textbox| textbox.rect.right).reduce(f32::max)?;
+          Some(vec![left + dx, right - left, *index as f32])
+        }).flatten().collect();
+
+      (rect.with_offset((dx, offset)), range, baseline as f32, glyphs)
     }).collect();
 
     // take their union to find the bounds for the whole text run
-    let (bounds, chars) = line_rects.iter().fold((Rect::new_empty(), 0), |(union, indices), (rect, range, _)|
+    let (bounds, chars) = line_rects.iter().fold((Rect::new_empty(), 0), |(union, indices), (rect, range, ..)|
       (Rect::join2(union, rect), range.end)
     );
 
     // return a list-of-lists whose first entry is the whole-run font metrics and subsequent entries are
-    // line-rect/range values (with the js side responsible for restructuring the whole bundle)
+    // line-rect/range values followed by per-character position/advance/index triples (with the js side
+    // responsible for restructuring the whole bundle)
     let mut results = vec![vec![
       bounds.width(), bounds.left, bounds.right, -bounds.top, bounds.bottom,
       ascent, descent, ascent, descent, hang, norm, ideo
     ]];
-    line_rects.iter().for_each(|(rect, range, baseline)|{
-      results.push(vec![rect.left, rect.top, rect.width(), rect.height(),
-                        *baseline, range.start as f32, range.end as f32])
+    line_rects.iter().for_each(|(rect, range, baseline, glyphs)|{
+      let mut line = vec![rect.left, rect.top, rect.width(), rect.height(),
+                          *baseline, range.start as f32, range.end as f32];
+      line.extend(glyphs);
+      results.push(line)
     });
     results
   }
 
-  pub fn set_filter(&mut self, filter_text:&str, specs:&[FilterSpec]){
-    // matrices and formulæ taken from: https://www.w3.org/TR/filter-effects-1/
-    let filter = specs.iter().fold(None, |chain, next_filter|
-      match next_filter {
-        FilterSpec::Shadow{ offset, blur, color } => {
-          let sigma = *blur / 2.0;
-          image_filters::drop_shadow(*offset, (sigma, sigma), *color, chain, None)
-        },
-        FilterSpec::Plain{ name, value } => match name.as_ref() {
-          "blur" => {
-            image_filters::blur((*value, *value), TileMode::Clamp, chain, None)
-          },
-          "brightness" => {
-            let amt = value.max(0.0);
-            let color_matrix = color_filters::matrix_row_major(&[
-              amt,  0.0,  0.0,  0.0, 0.0,
-              0.0,  amt,  0.0,  0.0, 0.0,
-              0.0,  0.0,  amt,  0.0, 0.0,
-              0.0,  0.0,  0.0,  1.0, 0.0
-            ]);
-            image_filters::color_filter(color_matrix, chain, None)
-          },
-          "contrast" => {
-            let amt = value.max(0.0);
-            let mut ramp = [0u8; 256];
-            for (i, val) in ramp.iter_mut().take(256).enumerate() {
-              let orig = i as f32;
-              *val = (127.0 + amt * orig - (127.0 * amt )) as u8;
+  pub fn text_bounds(&mut self, text: &str, start:usize, end:usize, width:Option<f32>) -> Vec<Rect>{
+    // convert the UTF-16 range to a byte range within the paragraph's text
+    let shift = self.indent_offset();
+    let mut utf16 = 0;
+    let mut bytes = text.len()..text.len();
+    for (idx, c) in text.char_indices(){
+      if utf16 <= start { bytes.start = idx }
+      if utf16 < end { bytes.end = idx + c.len_utf8() }
+      utf16 += c.len_utf16();
+    }
+    if start >= utf16 || end <= start{
+      return vec![]
+    }
+
+    let width = width.unwrap_or(GALLEY);
+    let paint = self.paint_for_fill();
+    let runs = [(text.to_string(), self.text_style(paint))];
+    let paragraph = self.typeset_runs(&runs, width);
+
+    // position the rects relative to the origin point that would be passed to fillText()
+    let metrics = self.state.char_style.font_metrics();
+    let dx = width * self.alignment_factor(&runs);
+    let dy = get_baseline_offset(&metrics, self.state.text_baseline) - paragraph.alphabetic_baseline();
+    paragraph.get_rects_for_range(bytes.start + shift..bytes.end + shift, RectHeightStyle::Max, RectWidthStyle::Tight)
+      .iter().map(|textbox| textbox.rect.with_offset((dx, dy)))
+      .collect()
+  }
+
+  pub fn outline_text(&mut self, text: &str, x: f32, y: f32, width: Option<f32>) -> Path{
+    let width = width.unwrap_or(GALLEY);
+    let paint = self.paint_for_fill();
+    let runs = [(text.to_string(), self.text_style(paint))];
+    let paragraph = self.typeset_runs(&runs, width);
+    let hyphens = self.hyphens(&paragraph, &runs);
+
+    let metrics = self.state.char_style.font_metrics();
+    let mut point = Point::new(x, y);
+    point.y += get_baseline_offset(&metrics, self.state.text_baseline) - paragraph.alphabetic_baseline();
+    point.x += width * self.alignment_factor(&runs);
+    self.glyph_outlines(&paragraph, &runs, &hyphens, point)
+  }
+
+  fn glyph_outlines(&mut self, paragraph:&Paragraph, runs:&[(String, TextStyle)], hyphens:&[(usize, Point, Paragraph)], point:Point) -> Path{
+    // shape each word and trace its glyphs at the position the paragraph's layout gave it, along with
+    // any decoration lines (which aren't part of the glyphs themselves)
+    let ltr = self.paragraph_style(runs).text_direction() == TextDirection::LTR;
+    let font_mgr = self.library.borrow().font_mgr();
+    let lines = paragraph.get_line_metrics();
+    let mut outline = Path::new();
+
+    let mut fonts = vec![];
+    let mut start = self.indent_offset();
+    for (text, style) in runs{
+      let mut style = style.clone();
+      style.set_font_families(&self.font_stack(&style));
+      let font = self.library.borrow_mut().font_for(&style);
+      fonts.push((start..start + text.len(), text.as_str(), style, font));
+      start += text.len();
+    }
+
+    for line in lines.iter(){
+      let baseline = line.baseline as f32;
+      for (range, text, style, font) in fonts.iter(){
+        let (lo, hi) = (line.start_index.max(range.start), line.end_index.min(range.end));
+        let (segment, font) = match (text.get(lo - range.start..hi.max(lo) - range.start), font){
+          (Some(segment), Some(font)) if lo < hi => (segment, font),
+          _ => continue
+        };
+
+        for (offset, word) in words(segment){
+          let left = paragraph.get_rects_for_range(lo + offset..lo + offset + word.len(), RectHeightStyle::Tight, RectWidthStyle::Tight)
+            .iter().map(|textbox| textbox.rect.left).fold(f32::INFINITY, f32::min);
+          if !left.is_finite(){ continue } // elided by an ellipsis
+
+          let origin = point + Point::new(left, baseline);
+          for run in shape_glyphs(word, font, &font_mgr, ltr){
+            for ((glyph, pos), cluster) in run.glyphs.iter().zip(run.positions.iter()).zip(run.clusters.iter()){
+              // letter-spacing is applied by the paragraph, not the shaper
+              let preceding = word.get(..*cluster as usize).map(|s| s.chars().count()).unwrap_or(0);
+              let tracking = Point::new(style.letter_spacing() * preceding as f32, 0.0);
+              if let Some(glyph_path) = run.font.get_path(*glyph){
+                outline.add_path(&glyph_path, origin + *pos + tracking, None);
+              }
             }
-            let table = Some(&ramp);
-            let color_table = table_color_filter::from_argb(None, table, table, table);
-            image_filters::color_filter(color_table, chain, None)
-          },
-          "grayscale" => {
-            let amt = 1.0 - value.max(0.0).min(1.0);
-            let color_matrix = color_filters::matrix_row_major(&[
-              (0.2126 + 0.7874 * amt), (0.7152 - 0.7152  * amt), (0.0722 - 0.0722 * amt), 0.0, 0.0,
-              (0.2126 - 0.2126 * amt), (0.7152 + 0.2848  * amt), (0.0722 - 0.0722 * amt), 0.0, 0.0,
-              (0.2126 - 0.2126 * amt), (0.7152 - 0.7152  * amt), (0.0722 + 0.9278 * amt), 0.0, 0.0,
-               0.0,                     0.0,                      0.0,                    1.0, 0.0
-            ]);
-            image_filters::color_filter(color_matrix, chain, None)
-          },
-          "invert" => {
-            let amt = value.max(0.0).min(1.0);
-            let mut ramp = [0u8; 256];
-            for (i, val) in ramp.iter_mut().take(256).enumerate().map(|(i,v)| (i as f32, v)) {
-              let (orig, inv) = (i, 255.0-i);
-              *val = (orig * (1.0 - amt) + inv * amt) as u8;
+          }
+        }
+
+        if !style.decoration_type().is_empty(){
+          let (left, right) = paragraph.get_rects_for_range(lo..hi, RectHeightStyle::Tight, RectWidthStyle::Tight)
+            .iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(l, r), textbox| (l.min(textbox.rect.left), r.max(textbox.rect.right)));
+          if left < right{
+            for rect in decoration_lines(style, font, left, right, baseline){
+              outline.add_rect(rect.with_offset(point), None);
             }
-            let table = Some(&ramp);
-            let color_table = table_color_filter::from_argb(None, table, table, table);
-            image_filters::color_filter(color_table, chain, None)
-          },
-          "opacity" => {
-            let amt = value.max(0.0).min(1.0);
-            let color_matrix = color_filters::matrix_row_major(&[
-              1.0,  0.0,  0.0,  0.0,  0.0,
-              0.0,  1.0,  0.0,  0.0,  0.0,
-              0.0,  0.0,  1.0,  0.0,  0.0,
-              0.0,  0.0,  0.0,  amt,  0.0
-            ]);
-            image_filters::color_filter(color_matrix, chain, None)
-          },
-          "saturate" => {
-            let amt = value.max(0.0);
-            let color_matrix = color_filters::matrix_row_major(&[
-              (0.2126 + 0.7874 * amt), (0.7152 - 0.7152 * amt), (0.0722 - 0.0722 * amt), 0.0, 0.0,
-              (0.2126 - 0.2126 * amt), (0.7152 + 0.2848 * amt), (0.0722 - 0.0722 * amt), 0.0, 0.0,
-              (0.2126 - 0.2126 * amt), (0.7152 - 0.7152 * amt), (0.0722 + 0.9278 * amt), 0.0, 0.0,
-               0.0,                     0.0,                     0.0,                    1.0, 0.0
-            ]);
-            image_filters::color_filter(color_matrix, chain, None)
-          },
-          "sepia" => {
-            let amt = 1.0 - value.max(0.0).min(1.0);
-            let color_matrix = color_filters::matrix_row_major(&[
-              (0.393 + 0.607 * amt), (0.769 - 0.769 * amt), (0.189 - 0.189 * amt), 0.0, 0.0,
-              (0.349 - 0.349 * amt), (0.686 + 0.314 * amt), (0.168 - 0.168 * amt), 0.0, 0.0,
-              (0.272 - 0.272 * amt), (0.534 - 0.534 * amt), (0.131 + 0.869 * amt), 0.0, 0.0,
-               0.0,                   0.0,                   0.0,                  1.0, 0.0
-            ]);
-            image_filters::color_filter(color_matrix, chain, None)
-          },
-          "hue-rotate" => {
-            let cos = to_radians(*value).cos();
-            let sin = to_radians(*value).sin();
-            let color_matrix = color_filters::matrix_row_major(&[
-              (0.213 + cos*0.787 - sin*0.213), (0.715 - cos*0.715 - sin*0.715), (0.072 - cos*0.072 + sin*0.928), 0.0, 0.0,
-              (0.213 - cos*0.213 + sin*0.143), (0.715 + cos*0.285 + sin*0.140), (0.072 - cos*0.072 - sin*0.283), 0.0, 0.0,
-              (0.213 - cos*0.213 - sin*0.787), (0.715 - cos*0.715 + sin*0.715), (0.072 + cos*0.928 + sin*0.072), 0.0, 0.0,
-               0.0,                             0.0,                             0.0,                            1.0, 0.0
-            ]);
-            image_filters::color_filter(color_matrix, chain, None)
-          },
-          _ => chain
+          }
         }
       }
-    );
+    }
+
+    // add the hyphens drawn after soft-hyphen line breaks in the font of the line's final run
+    for (line_number, pt, hyphen) in hyphens{
+      let end = lines.get(*line_number).map(|line| line.end_excluding_whitespaces).unwrap_or(0);
+      let font = fonts.iter().find(|(range, ..)| range.start < end && end <= range.end).and_then(|(.., font)| font.as_ref());
+      if let Some(font) = font{
+        let origin = point + *pt + Point::new(0.0, hyphen.alphabetic_baseline());
+        for run in shape_glyphs("-", font, &font_mgr, ltr){
+          for (glyph, pos) in run.glyphs.iter().zip(run.positions.iter()){
+            if let Some(glyph_path) = run.font.get_path(*glyph){
+              outline.add_path(&glyph_path, origin + *pos, None);
+            }
+          }
+        }
+      }
+    }
+    outline
+  }
 
+  pub fn set_filter(&mut self, filter_text:&str, specs:&[FilterSpec]){
+    let filter = filter_chain(specs);
     self.state.paint.set_image_filter(filter);
     self.state.filter = filter_text.to_string();
+    self.state.filter_effect = None;
   }
 
-  pub fn update_image_quality(&mut self){
-    self.state.paint.set_filter_quality(match self.state.image_smoothing_enabled{
-      true => self.state.image_filter_quality,
-      false => FilterQuality::None
-    });
+  pub fn set_filter_effect(&mut self, effect:CanvasShader){
+    self.state.paint.set_image_filter(None);
+    self.state.filter = "none".to_string();
+    self.state.filter_effect = Some(effect);
+  }
+
+  pub fn set_backdrop_filter(&mut self, filter_text:&str, specs:&[FilterSpec]){
+    self.state.backdrop = filter_chain(specs);
+    self.state.backdrop_filter = filter_text.to_string();
+  }
+
+  pub fn render_backdrop(&self, region:&Path){
+    self.invalidate_raster();
+    self.extent.set(Some(*region.bounds()));
+
+    // note the device-space extent of the next operation for its history entry
+    let mut history = self.history.borrow_mut();
+    if history.enabled{
+      history.region = Some(self.state.matrix.map_rect(region.bounds()).0);
+    }
+    drop(history);
+
+    // filter the existing canvas content that lies beneath the shape about to be drawn by
+    // using an empty, backdrop-initialized layer clipped to the shape's outline
+    if let Some(backdrop) = &self.state.backdrop{
+      self.with_canvas(|canvas|{
+        canvas.save();
+        canvas.clip_path(&region, ClipOp::Intersect, true);
+        canvas.save_layer(&SaveLayerRec::default().backdrop(&backdrop));
+        canvas.restore();
+        canvas.restore();
+      });
+    }
+  }
+
+  pub fn image_sampling(&self) -> SamplingOptions{
+    match self.state.image_smoothing_enabled{
+      true => self.state.image_filter_quality.sampling(),
+      false => FilterQuality::None.sampling()
+    }
   }
 
   pub fn color_with_alpha(&self, src:&Color) -> Color{
@@ -707,48 +2174,504 @@ impl Context2D{
     color.to_color()
   }
 
-  pub fn paint_for_fill(&self) -> Paint{
+  pub fn base_paint(&self) -> Paint{
+    // shader-based filters are rebuilt on every draw so uniform changes take effect
     let mut paint = self.state.paint.clone();
+    if let Some(effect) = &self.state.filter_effect{
+      paint.set_image_filter(effect.image_filter());
+    }
+    paint
+  }
+
+  pub fn paint_for_fill(&self) -> Paint{
+    let mut paint = self.base_paint();
 
     let dye = &self.state.fill_style;
     let alpha = self.state.global_alpha;
     dye.mix_into(&mut paint, alpha);
 
+    if let Some(effect) = self.state.path_effect.as_ref().and_then(|spec| spec.effect()){
+      paint.set_path_effect(effect);
+    }
+
     paint
   }
 
   pub fn paint_for_stroke(&self) -> Paint{
-    let mut paint = self.state.paint.clone();
+    let mut paint = self.base_paint();
     paint.set_style(PaintStyle::Stroke);
 
     let dye = &self.state.stroke_style;
     let alpha = self.state.global_alpha;
     dye.mix_into(&mut paint, alpha);
 
-    if !self.state.line_dash_list.is_empty() {
-      let dash = dash_path_effect::new(&self.state.line_dash_list, self.state.line_dash_offset);
-      paint.set_path_effect(dash);
+    // counteract the CTM's scale factor to keep the line width (and/or dash pattern) constant in
+    // device pixels (non-uniform scales use the geometric mean of their horizontal & vertical factors)
+    let scale = match self.state.stroke_width_absolute || self.state.dashes_ignore_transform{
+      true => {
+        let m = &self.state.matrix;
+        (m.scale_x().hypot(m.skew_y()) * m.skew_x().hypot(m.scale_y())).sqrt().max(f32::EPSILON)
+      },
+      false => 1.0
+    };
+    if self.state.stroke_width_absolute{
+      paint.set_stroke_width(self.state.stroke_width / scale);
+    }
+
+    let dash = match self.state.line_dash_list.is_empty(){
+      true => None,
+      false => match self.state.dashes_ignore_transform{
+        true => {
+          let intervals:Vec<f32> = self.state.line_dash_list.iter().map(|len| len / scale).collect();
+          dash_path_effect::new(&intervals, self.state.line_dash_offset / scale)
+        },
+        false => dash_path_effect::new(&self.state.line_dash_list, self.state.line_dash_offset)
+      }
+    };
+    let marker = self.state.line_dash_marker.as_ref().and_then(|marker| marker.effect());
+    let shape = self.state.path_effect.as_ref().and_then(|spec| spec.effect());
+
+    // the path's outline is reshaped first, then dashed, then decorated with markers
+    let effect = compose_effects(marker, compose_effects(dash, shape));
+    if let Some(effect) = effect{
+      paint.set_path_effect(effect);
     }
 
     paint
   }
 
+  pub fn stroke_clip(&self, path:&Path, paint:&Paint) -> Option<ClipOp>{
+    // inner & outer alignment only make sense for strokes whose contours are all closed
+    let closed = ContourMeasureIter::from_path(path, false, None).all(|contour| contour.is_closed());
+    match (paint.style(), self.state.line_alignment){
+      (PaintStyle::Stroke, LineAlignment::Inner) if closed => Some(ClipOp::Intersect),
+      (PaintStyle::Stroke, LineAlignment::Outer) if closed => Some(ClipOp::Difference),
+      _ => None
+    }
+  }
+
+  pub fn shadow_matrix(&self) -> Matrix{
+    // by default the shadow offset is applied in device space (so it neither scales nor rotates
+    // along with the shape), otherwise it is treated as a vector in the local coordinate system
+    // (inner shadows apply their offset within the filter instead)
+    let offset = match self.state.shadow_inset{
+      true => Matrix::new_identity(),
+      false => Matrix::translate(self.state.shadow_offset)
+    };
+    match self.state.shadows_ignore_transform{
+      true => Matrix::concat(&offset, &self.state.matrix),
+      false => Matrix::concat(&self.state.matrix, &offset)
+    }
+  }
+
+  pub fn paint_for_pass(&self, paint:&Paint) -> Paint {
+    // the 'alpha' pass renders everything as an opaque white silhouette that retains its original alpha
+    let mut paint = paint.clone();
+    if self.state.render_pass == RenderPass::Alpha{
+      let whiten = color_filters::matrix_row_major(&[
+        0.0, 0.0, 0.0, 0.0, 1.0,
+        0.0, 0.0, 0.0, 0.0, 1.0,
+        0.0, 0.0, 0.0, 0.0, 1.0,
+        0.0, 0.0, 0.0, 1.0, 0.0
+      ], None);
+      paint.set_image_filter(image_filters::color_filter(whiten, paint.image_filter(), None));
+    }
+    paint
+  }
+
   pub fn paint_for_shadow(&self, base_paint:&Paint) -> Option<Paint> {
-    let State {shadow_color, shadow_blur, shadow_offset, ..} = self.state;
-    let sigma = shadow_blur / 2.0;
+    let State {shadow_color, shadow_blur, shadow_offset, shadow_spread, shadow_inset, shadows_ignore_transform, ..} = self.state;
+    let shadow_style = &self.state.shadow_style;
+    if shadow_color.a() == 0 || (shadow_blur == 0.0 && shadow_offset.is_zero() && shadow_spread == 0.0){
+      return None
+    }
 
-    match shadow_color.a() > 0 && !(shadow_blur == 0.0 && shadow_offset.is_zero()){
+    // the filters are applied in local coordinates, so counteract the CTM's scale factor
+    // to keep the blur & spread constant in device space (as browsers do)
+    let scale = match shadows_ignore_transform{
       true => {
-        let mut paint = base_paint.clone();
-        if let Some(filter) = image_filters::drop_shadow_only((0.0, 0.0), (sigma, sigma), shadow_color, None, None){
-          paint.set_image_filter(filter); // this also knocks out any ctx.filter settings as a side-effect
-        }
-        Some(paint)
+        let m = &self.state.matrix;
+        (m.scale_x().hypot(m.skew_y()).max(f32::EPSILON), m.skew_x().hypot(m.scale_y()).max(f32::EPSILON))
+      },
+      false => (1.0, 1.0)
+    };
+    let sigma = (shadow_blur / 2.0 / scale.0, shadow_blur / 2.0 / scale.1);
+
+    // spread grows the shape's silhouette before blurring it (or shrinks the ‘hole’ of an inner shadow)
+    let spread = if shadow_inset{ -shadow_spread }else{ shadow_spread };
+    let radii = (spread.abs() / scale.0, spread.abs() / scale.1);
+    let silhouette = match spread{
+      s if s > 0.0 => image_filters::dilate(radii, None, None),
+      s if s < 0.0 => image_filters::erode(radii, None, None),
+      _ => None
+    };
+
+    // gradient & pattern shadows use the blurred silhouette as a mask for the dye's shader
+    let tint = |shadow:Option<ImageFilter>| match shadow_style.as_ref().and_then(|dye| dye.shader()){
+      Some(shader) => image_filters::blend(BlendMode::SrcIn, shadow, image_filters::shader(shader, None), None),
+      None => shadow
+    };
+
+    let filter = match shadow_inset{
+      false => tint(image_filters::drop_shadow_only((0.0, 0.0), sigma, shadow_color, silhouette, None)),
+      true => {
+        // cast a shadow from the inverse of the shape's alpha, then clip it to the shape itself
+        let offset = match shadows_ignore_transform{
+          true => self.state.matrix.invert().map(|inv| inv.map_vector(shadow_offset)).unwrap_or(shadow_offset),
+          false => shadow_offset
+        };
+        let invert_alpha = color_filters::matrix_row_major(&[
+          0.0, 0.0, 0.0,  0.0, 0.0,
+          0.0, 0.0, 0.0,  0.0, 0.0,
+          0.0, 0.0, 0.0,  0.0, 0.0,
+          0.0, 0.0, 0.0, -1.0, 1.0
+        ], None);
+        let hole = image_filters::color_filter(invert_alpha, silhouette, None);
+        let shadow = tint(image_filters::drop_shadow_only(offset, sigma, shadow_color, hole, None));
+        image_filters::blend(BlendMode::SrcIn, None, shadow, None)
+      }
+    };
+
+    let mut paint = base_paint.clone();
+    if let Some(filter) = filter{
+      paint.set_image_filter(filter); // this also knocks out any ctx.filter settings as a side-effect
+    }
+    Some(paint)
+  }
+
+}
+
+pub fn downsample(image:&Image, src:&Rect, target:(f32, f32)) -> Option<(Image, Rect)>{
+  // halve the image repeatedly (averaging each 2×2 block of pixels) until it's within a factor of two
+  // of the size it will be drawn at, so the final resampling step doesn't skip over any of its pixels
+  if !(target.0 > 0.0 && target.1 > 0.0) || src.is_empty(){
+    return None
+  }
+
+  // (neither dimension is reduced below a single pixel)
+  let halve = |size:f32, goal:f32| size >= goal * 2.0 && size > 1.0;
+  let (mut width, mut height) = (src.width(), src.height());
+  if !halve(width, target.0) && !halve(height, target.1) {
+    return None
+  }
+
+  let (mut current, mut rect) = (image.clone(), *src);
+  let paint = Paint::default();
+  let sampling = FilterQuality::Low.sampling();
+  while halve(width, target.0) || halve(height, target.1) {
+    if halve(width, target.0) { width /= 2.0 }
+    if halve(height, target.1) { height /= 2.0 }
+    let info = ImageInfo::new_n32_premul((width.ceil().max(1.0) as i32, height.ceil().max(1.0) as i32), image.image_info().color_space());
+    let mut surface = surfaces::raster(&info, None, None)?;
+    surface.canvas().draw_image_rect_with_sampling_options(&current, Some((&rect, Strict)), Rect::from_wh(width, height), sampling, &paint);
+    current = surface.image_snapshot();
+    rect = Rect::from_wh(width, height);
+  }
+  Some((current, rect))
+}
+
+fn words(text:&str) -> Vec<(usize, &str)>{
+  // the byte offsets and contents of each whitespace-delimited word
+  let mut words = vec![];
+  let mut start = None;
+  for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))){
+    match (start, c.is_whitespace()){
+      (None, false) => start = Some(i),
+      (Some(s), true) => { words.push((s, &text[s..i])); start = None },
+      _ => {}
+    }
+  }
+  words
+}
+
+fn decoration_lines(style:&TextStyle, font:&Font, left:f32, right:f32, baseline:f32) -> Vec<Rect>{
+  // the underline, overline, and line-through rects skia draws alongside a run of glyphs
+  let (_, metrics) = font.metrics();
+  let thickness = metrics.underline_thickness().unwrap_or(font.size() / 18.0) * style.decoration_thickness_multiplier();
+  [
+    (TextDecoration::UNDERLINE, metrics.underline_position().unwrap_or(thickness)),
+    (TextDecoration::OVERLINE, metrics.ascent),
+    (TextDecoration::LINE_THROUGH, metrics.strikeout_position().unwrap_or(metrics.x_height / -2.0)),
+  ].iter().filter(|(line, _)| style.decoration_type().contains(*line)).map(|(_, dy)|
+    Rect::new(left, baseline + dy, right, baseline + dy + thickness)
+  ).collect()
+}
+
+fn compose_effects(outer:Option<PathEffect>, inner:Option<PathEffect>) -> Option<PathEffect>{
+  match (outer, inner){
+    (Some(outer), Some(inner)) => Some(PathEffect::compose(outer, inner)),
+    (outer, inner) => outer.or(inner)
+  }
+}
+
+fn record_paragraph(paragraph:&mut Paragraph, hyphens:&mut [(usize, Point, Paragraph)], bounds:Rect) -> Option<Picture>{
+  // a picture of the typeset text (and any hyphens following its lines) drawn at the origin
+  let mut recorder = PictureRecorder::new();
+  recorder.begin_recording(bounds, None);
+  let canvas = recorder.recording_canvas()?;
+  paragraph.paint(canvas, (0.0, 0.0));
+  for (_, pt, hyphen) in hyphens.iter_mut(){
+    hyphen.paint(canvas, *pt);
+  }
+  recorder.finish_recording_as_picture(Some(&bounds))
+}
+
+fn set_clip_and_matrix(canvas:&SkCanvas, clip:&Path, matrix:&Matrix){
+  // clips are stored in device space, so apply them before the transform
+  canvas.reset_matrix();
+  if !clip.is_empty(){
+    canvas.clip_path(clip, ClipOp::Intersect, true /* antialias */);
+  }
+  canvas.set_matrix(&M44::from(matrix));
+}
+
+pub fn draw_aligned_path(canvas:&SkCanvas, path:&Path, paint:&Paint, clip:Option<ClipOp>, precision:Option<f32>){
+  match clip{
+    Some(clip_op) => {
+      // stroke at double the width then clip away the half that falls inside (or outside) the path
+      let mut paint = paint.clone();
+      paint.set_stroke_width(paint.stroke_width() * 2.0);
+      canvas.save();
+      canvas.clip_path(&path, clip_op, true);
+      draw_precise_path(canvas, &path, &paint, precision);
+      canvas.restore();
+    },
+    None => draw_precise_path(canvas, &path, &paint, precision)
+  }
+}
+
+fn fill_path(path:&Path, paint:&Paint, precision:f32) -> Option<Path>{
+  // the outline of a stroke, traced as finely as if it were drawn at the given scale factor
+  let mut outline = Path::new();
+  match path_utils::fill_path_with_paint(path, paint, &mut outline, None, Matrix::scale((precision, precision))){
+    true => Some(outline),
+    false => None
+  }
+}
+
+fn draw_precise_path(canvas:&SkCanvas, path:&Path, paint:&Paint, precision:Option<f32>){
+  // with an explicit precision, strokes are converted to outlines up front (rather than letting
+  // skia pick a tolerance based on the current transform) and then filled
+  let outline = match (paint.style(), precision){
+    (PaintStyle::Stroke, Some(precision)) => fill_path(&path, &paint, precision),
+    _ => None
+  };
+  match outline{
+    Some(outline) => {
+      let mut paint = paint.clone();
+      paint.set_style(PaintStyle::Fill);
+      paint.set_path_effect(None::<PathEffect>);
+      canvas.draw_path(&outline, &paint);
+    },
+    None => { canvas.draw_path(&path, &paint); }
+  }
+}
+
+//
+// Image filter chains for ctx.filter & ctx.backdropFilter
+//
+
+pub fn filter_chain(specs:&[FilterSpec]) -> Option<ImageFilter>{
+  // matrices and formulæ taken from: https://www.w3.org/TR/filter-effects-1/
+  specs.iter().fold(None, |chain, next_filter|
+    match next_filter {
+      FilterSpec::Shadow{ offset, blur, color } => {
+        let sigma = *blur / 2.0;
+        image_filters::drop_shadow(*offset, (sigma, sigma), *color, chain, None)
+      },
+      FilterSpec::Plain{ name, value } => match name.as_ref() {
+        "blur" => {
+          image_filters::blur((*value, *value), TileMode::Clamp, chain, None)
+        },
+        "brightness" => {
+          let amt = value.max(0.0);
+          let color_matrix = color_filters::matrix_row_major(&[
+            amt,  0.0,  0.0,  0.0, 0.0,
+            0.0,  amt,  0.0,  0.0, 0.0,
+            0.0,  0.0,  amt,  0.0, 0.0,
+            0.0,  0.0,  0.0,  1.0, 0.0
+          ], None);
+          image_filters::color_filter(color_matrix, chain, None)
+        },
+        "contrast" => {
+          let amt = value.max(0.0);
+          let mut ramp = [0u8; 256];
+          for (i, val) in ramp.iter_mut().take(256).enumerate() {
+            let orig = i as f32;
+            *val = (127.0 + amt * orig - (127.0 * amt )) as u8;
+          }
+          let table = Some(&ramp);
+          match color_filters::table_argb(None, table, table, table){
+            Some(color_table) => image_filters::color_filter(color_table, chain, None),
+            None => chain
+          }
+        },
+        "grayscale" => {
+          let amt = 1.0 - value.max(0.0).min(1.0);
+          let color_matrix = color_filters::matrix_row_major(&[
+            (0.2126 + 0.7874 * amt), (0.7152 - 0.7152  * amt), (0.0722 - 0.0722 * amt), 0.0, 0.0,
+            (0.2126 - 0.2126 * amt), (0.7152 + 0.2848  * amt), (0.0722 - 0.0722 * amt), 0.0, 0.0,
+            (0.2126 - 0.2126 * amt), (0.7152 - 0.7152  * amt), (0.0722 + 0.9278 * amt), 0.0, 0.0,
+             0.0,                     0.0,                      0.0,                    1.0, 0.0
+          ], None);
+          image_filters::color_filter(color_matrix, chain, None)
+        },
+        "invert" => {
+          let amt = value.max(0.0).min(1.0);
+          let mut ramp = [0u8; 256];
+          for (i, val) in ramp.iter_mut().take(256).enumerate().map(|(i,v)| (i as f32, v)) {
+            let (orig, inv) = (i, 255.0-i);
+            *val = (orig * (1.0 - amt) + inv * amt) as u8;
+          }
+          let table = Some(&ramp);
+          match color_filters::table_argb(None, table, table, table){
+            Some(color_table) => image_filters::color_filter(color_table, chain, None),
+            None => chain
+          }
+        },
+        "opacity" => {
+          let amt = value.max(0.0).min(1.0);
+          let color_matrix = color_filters::matrix_row_major(&[
+            1.0,  0.0,  0.0,  0.0,  0.0,
+            0.0,  1.0,  0.0,  0.0,  0.0,
+            0.0,  0.0,  1.0,  0.0,  0.0,
+            0.0,  0.0,  0.0,  amt,  0.0
+          ], None);
+          image_filters::color_filter(color_matrix, chain, None)
+        },
+        "saturate" => {
+          let amt = value.max(0.0);
+          let color_matrix = color_filters::matrix_row_major(&[
+            (0.2126 + 0.7874 * amt), (0.7152 - 0.7152 * amt), (0.0722 - 0.0722 * amt), 0.0, 0.0,
+            (0.2126 - 0.2126 * amt), (0.7152 + 0.2848 * amt), (0.0722 - 0.0722 * amt), 0.0, 0.0,
+            (0.2126 - 0.2126 * amt), (0.7152 - 0.7152 * amt), (0.0722 + 0.9278 * amt), 0.0, 0.0,
+             0.0,                     0.0,                     0.0,                    1.0, 0.0
+          ], None);
+          image_filters::color_filter(color_matrix, chain, None)
+        },
+        "sepia" => {
+          let amt = 1.0 - value.max(0.0).min(1.0);
+          let color_matrix = color_filters::matrix_row_major(&[
+            (0.393 + 0.607 * amt), (0.769 - 0.769 * amt), (0.189 - 0.189 * amt), 0.0, 0.0,
+            (0.349 - 0.349 * amt), (0.686 + 0.314 * amt), (0.168 - 0.168 * amt), 0.0, 0.0,
+            (0.272 - 0.272 * amt), (0.534 - 0.534 * amt), (0.131 + 0.869 * amt), 0.0, 0.0,
+             0.0,                   0.0,                   0.0,                  1.0, 0.0
+          ], None);
+          image_filters::color_filter(color_matrix, chain, None)
+        },
+        "hue-rotate" => {
+          let cos = to_radians(*value).cos();
+          let sin = to_radians(*value).sin();
+          let color_matrix = color_filters::matrix_row_major(&[
+            (0.213 + cos*0.787 - sin*0.213), (0.715 - cos*0.715 - sin*0.715), (0.072 - cos*0.072 + sin*0.928), 0.0, 0.0,
+            (0.213 - cos*0.213 + sin*0.143), (0.715 + cos*0.285 + sin*0.140), (0.072 - cos*0.072 - sin*0.283), 0.0, 0.0,
+            (0.213 - cos*0.213 - sin*0.787), (0.715 - cos*0.715 + sin*0.715), (0.072 + cos*0.928 + sin*0.072), 0.0, 0.0,
+             0.0,                             0.0,                             0.0,                            1.0, 0.0
+          ], None);
+          image_filters::color_filter(color_matrix, chain, None)
+        },
+        _ => chain
+      },
+      FilterSpec::Noise{ name, freq, octaves, seed } => {
+        // fill the content drawn so far with noise, keeping its alpha as a mask
+        let noise = perlin_noise(name == "fractal-noise", *freq, *octaves, *seed);
+        image_filters::blend(BlendMode::SrcIn, chain, noise, None)
+      },
+      FilterSpec::Displace{ scale, freq, octaves, seed } => {
+        // offset each pixel by the red & green channels of a turbulence field (à la feDisplacementMap)
+        let noise = perlin_noise(false, *freq, *octaves, *seed);
+        image_filters::displacement_map((ColorChannel::R, ColorChannel::G), *scale, noise, chain, None)
+      },
+      FilterSpec::Matrix{ values } => {
+        let color_matrix = color_filters::matrix_row_major(values, None);
+        image_filters::color_filter(color_matrix, chain, None)
+      },
+      FilterSpec::Lighting{ specular, light, coords, surface, color } => {
+        // use the content's alpha as a bump map, then clip the lighting to the content's shape and
+        // either multiply (diffuse) or add (specular) it to the original colors
+        let lit = lighting_filter(*specular, light, coords, *surface, *color, chain.clone());
+        let lit = image_filters::blend(BlendMode::SrcIn, chain.clone(), lit, None);
+        let mode = if *specular { BlendMode::Plus }else{ BlendMode::Multiply };
+        image_filters::blend(mode, chain, lit, None)
       }
-      false => None
     }
+  )
+}
+
+fn lighting_filter(specular:bool, light:&str, coords:&[f32], surface:f32, color:Color, input:Option<ImageFilter>) -> Option<ImageFilter>{
+  // lighting constants use the SVG defaults (diffuse & specular constants of 1, specular exponent of 1)
+  let (constant, shininess) = (1.0, 1.0);
+  match (light, coords){
+    ("distant", [azimuth, elevation]) => {
+      let (az, el) = (to_radians(*azimuth), to_radians(*elevation));
+      let direction = (az.cos() * el.cos(), az.sin() * el.cos(), el.sin());
+      match specular{
+        true => image_filters::distant_lit_specular(direction, color, surface, constant, shininess, input, None),
+        false => image_filters::distant_lit_diffuse(direction, color, surface, constant, input, None)
+      }
+    },
+    ("point", [x, y, z]) => {
+      let location = (*x, *y, *z);
+      match specular{
+        true => image_filters::point_lit_specular(location, color, surface, constant, shininess, input, None),
+        false => image_filters::point_lit_diffuse(location, color, surface, constant, input, None)
+      }
+    },
+    ("spot", [x, y, z, tx, ty, tz, exponent, cone]) => {
+      let (location, target) = ((*x, *y, *z), (*tx, *ty, *tz));
+      match specular{
+        true => image_filters::spot_lit_specular(location, target, *exponent, *cone, color, surface, constant, shininess, input, None),
+        false => image_filters::spot_lit_diffuse(location, target, *exponent, *cone, color, surface, constant, input, None)
+      }
+    },
+    _ => input
   }
+}
 
+fn perlin_noise(fractal:bool, freq:(f32, f32), octaves:usize, seed:f32) -> Option<ImageFilter>{
+  let shader = match fractal{
+    true => Shader::fractal_perlin_noise(freq, octaves, seed, None),
+    false => Shader::turbulence_perlin_noise(freq, octaves, seed, None)
+  };
+  shader.and_then(|shader| image_filters::shader(shader, None))
+}
+
+const COLOR_SELECT_SKSL:&str = r#"
+  uniform shader layer;
+  uniform shader backdrop;
+  uniform half lighter;
+
+  half sum(half3 color){ return color.r + color.g + color.b; }
+
+  half4 main(float2 xy){
+    half4 src = layer.eval(xy), dst = backdrop.eval(xy);
+    half3 cs = src.a > 0.0 ? src.rgb / src.a : half3(0.0);
+    half3 cb = dst.a > 0.0 ? dst.rgb / dst.a : half3(0.0);
+    bool keep = lighter > 0.5 ? sum(cs) >= sum(cb) : sum(cs) <= sum(cb);
+    half3 mixed = (1.0 - dst.a) * cs + dst.a * (keep ? cs : cb);
+    return half4(src.a * mixed + (1.0 - src.a) * dst.rgb, src.a + (1.0 - src.a) * dst.a);
+  }
+"#;
+
+fn color_select_shader(layer:&Picture, backdrop:&Picture, select:ColorSelect) -> Option<Shader>{
+  // like photoshop, choose whichever of the source & backdrop colors has the greater (or lesser)
+  // sum of its channels then composite the result source-over the backdrop
+  let effect = RuntimeEffect::make_for_shader(COLOR_SELECT_SKSL, None).ok()?;
+  let lighter:f32 = match select{ ColorSelect::Lighter => 1.0, ColorSelect::Darker => 0.0 };
+  let tiling = (TileMode::Decal, TileMode::Decal);
+  let children:[ChildPtr; 2] = [layer.to_shader(tiling, FilterMode::Nearest, None, None).into(), backdrop.to_shader(tiling, FilterMode::Nearest, None, None).into()];
+  effect.make_shader(Data::new_copy(&lighter.to_ne_bytes()), &children, None)
+}
+
+//
+// Styled runs of text for ctx.fillTextSpans
+//
+
+pub struct TextSpan{
+  pub text: String,
+  pub font: Option<FontSpec>,
+  pub dye: Option<Dye>,
+  pub underline: bool,
 }
 
 //
@@ -757,28 +2680,33 @@ impl Context2D{
 
 #[derive(Clone)]
 pub enum Dye{
-  Color(Color),
+  Color(Color4f),
   Gradient(CanvasGradient),
-  Pattern(CanvasPattern)
+  Pattern(CanvasPattern),
+  Shader(CanvasShader)
 }
 
 impl Dye{
   pub fn new<'a, T: This+Class>(cx: &mut CallContext<'a, T>, value: Handle<'a, JsValue>, style: PaintStyle) -> Result<Option<Self>, Throw> {
     let stash = if style == PaintStyle::Fill{ "fillShader" } else { "strokeShader" };
-    match value{
-      arg if arg.is_a::<JsCanvasGradient>() => {
-        let gradient = cx.argument::<JsCanvasGradient>(0)?;
-        stash_ref(cx, stash, arg)?;
-        Ok(Some(cx.borrow(&gradient, |gradient| Dye::Gradient(gradient.clone()) )))
-      },
-      arg if arg.is_a::<JsCanvasPattern>() => {
-        let pattern = cx.argument::<JsCanvasPattern>(0)?;
-        stash_ref(cx, stash, arg)?;
-        Ok(Some(cx.borrow(&pattern, |pattern| Dye::Pattern(pattern.clone()) )))
-      },
-      _ => {
-        Ok(color_arg(cx, 0).map(Dye::Color))
-      }
+    let dye = Dye::from_value(cx, value)?;
+    if matches!(dye, Some(Dye::Gradient(..)) | Some(Dye::Pattern(..)) | Some(Dye::Shader(..))){
+      stash_ref(cx, stash, value)?;
+    }
+    Ok(dye)
+  }
+
+  pub fn from_value<'a, T: This>(cx: &mut CallContext<'a, T>, value: Handle<'a, JsValue>) -> Result<Option<Self>, Throw> {
+    if let Ok(gradient) = value.downcast::<JsCanvasGradient>(){
+      Ok(Some(cx.borrow(&gradient, |gradient| Dye::Gradient(gradient.clone()) )))
+    }else if let Ok(pattern) = value.downcast::<JsCanvasPattern>(){
+      Ok(Some(cx.borrow(&pattern, |pattern| Dye::Pattern(pattern.clone()) )))
+    }else if let Ok(shader) = value.downcast::<JsCanvasShader>(){
+      Ok(Some(cx.borrow(&shader, |shader| Dye::Shader(shader.clone()) )))
+    }else if let Ok(css) = value.downcast::<JsString>(){
+      Ok(color4f_in(cx, &css.value()).map(Dye::Color))
+    }else{
+      Ok(None)
     }
   }
 
@@ -787,16 +2715,26 @@ impl Dye{
     match self{
       Dye::Gradient(..) => fetch_ref(cx, cache),
       Dye::Pattern(..)  => fetch_ref(cx, cache),
-      Dye::Color(color) => color_to_css(cx, &color)
+      Dye::Shader(..)   => fetch_ref(cx, cache),
+      Dye::Color(color) => color4f_to_css(cx, &color)
+    }
+  }
+
+  pub fn shader(&self) -> Option<Shader>{
+    match self {
+      Dye::Color(_) => None,
+      Dye::Gradient(gradient) => gradient.shader(),
+      Dye::Pattern(pattern) => pattern.shader(),
+      Dye::Shader(shader) => shader.shader()
     }
   }
 
   pub fn mix_into(&self, paint: &mut Paint, alpha: f32){
     match self {
       Dye::Color(color) => {
-        let mut color:Color4f = color.clone().into();
+        let mut color = *color;
         color.a *= alpha;
-        paint.set_color(color.to_color());
+        paint.set_color4f(color, None);
       },
       Dye::Gradient(gradient) =>{
         paint.set_shader(gradient.shader())
@@ -806,6 +2744,10 @@ impl Dye{
         paint.set_shader(pattern.shader())
              .set_alpha_f(alpha);
       }
+      Dye::Shader(shader) =>{
+        paint.set_shader(shader.shader())
+             .set_alpha_f(alpha);
+      }
     };
   }
 }
@@ -835,6 +2777,22 @@ pub fn stash_ref<'a, T: This+Class>(cx: &mut CallContext<'a, T>, queue_name:&str
   Ok(cx.undefined())
 }
 
+pub fn trim_refs<'a, T: This+Class>(cx: &mut CallContext<'a, T>) -> JsResult<'a, JsUndefined>{
+  // drop refs left over from previously restored stack frames so a newly saved state doesn't inherit them
+  let this = cx.this().downcast::<JsContext2D>().or_throw(cx)?;
+  let depth = cx.borrow(&this, |this| this.stack.len() as f64);
+  for queue_name in &["fillShader", "strokeShader", "filterShader", "shadowShader"]{
+    let sym = symbol(cx, queue_name)?;
+    if let Ok(queue) = this.get(cx, sym)?.downcast::<JsArray>(){
+      let length = cx.string("length");
+      let len = queue.get(cx, length)?.downcast::<JsNumber>().or_throw(cx)?.value();
+      let len = cx.number(len.min(depth));
+      queue.set(cx, length, len)?;
+    }
+  }
+  Ok(cx.undefined())
+}
+
 pub fn fetch_ref<'a, T: This+Class>(cx: &mut CallContext<'a, T>, queue_name:&str) -> JsResult<'a, JsValue>{
   let this = cx.this().downcast::<JsContext2D>().or_throw(cx)?;
   let sym = symbol(cx, queue_name)?;
@@ -850,4 +2808,3 @@ pub fn fetch_ref<'a, T: This+Class>(cx: &mut CallContext<'a, T>, queue_name:&str
     Err(_e) => Ok(cx.undefined().upcast())
   }
 }
-
@@ -1,16 +1,20 @@
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use neon::prelude::*;
-use skia_safe::{Shader, Color, Point, TileMode, gradient_shader, gradient_shader::GradientShaderColors::Colors};
+use skia_safe::{Shader, Color4f, Point, TileMode, gradient_shader, gradient_shader::GradientShaderColors::ColorsInSpace};
 
 use crate::utils::*;
+use crate::color::*;
+
+// the number of samples taken between each pair of stops when interpolating in a non-sRGB space
+const STOP_SAMPLES:usize = 16;
 
 enum Gradient{
   Linear{
     start:Point,
     end:Point,
     stops:Vec<f32>,
-    colors:Vec<Color>,
+    colors:Vec<Color4f>,
   },
   Radial{
     start_point:Point,
@@ -18,32 +22,71 @@ enum Gradient{
     end_point:Point,
     end_radius:f32,
     stops:Vec<f32>,
-    colors:Vec<Color>,
+    colors:Vec<Color4f>,
   }
 }
 
 #[derive(Clone)]
 pub struct CanvasGradient{
-  gradient:Rc<RefCell<Gradient>>
+  gradient:Rc<RefCell<Gradient>>,
+  interpolation:Rc<Cell<Interpolation>>,
+  hints:Rc<RefCell<Vec<f32>>>,
 }
 
 impl CanvasGradient{
   pub fn shader(&self) -> Option<Shader>{
+    let flags = match self.interpolation.get().premultiplied{
+      true => Some(gradient_shader::Flags::INTERPOLATE_COLORS_IN_PREMUL),
+      false => None
+    };
     match &*self.gradient.borrow(){
       Gradient::Linear{start, end, stops, colors} => {
-        gradient_shader::linear((*start, *end), Colors(&colors), Some(stops.as_slice()), TileMode::Clamp, None, None)
+        let (stops, colors) = self.resample(stops, colors);
+        gradient_shader::linear((*start, *end), ColorsInSpace(&colors, None), Some(stops.as_slice()), TileMode::Clamp, flags, None)
       },
       Gradient::Radial{start_point, start_radius, end_point, end_radius, stops, colors} => {
+        let (stops, colors) = self.resample(stops, colors);
         gradient_shader::two_point_conical(
           *start_point, *start_radius,
           *end_point, *end_radius,
-          Colors(&colors), Some(stops.as_slice()),
-          TileMode::Clamp, None, None)
+          ColorsInSpace(&colors, None), Some(stops.as_slice()),
+          TileMode::Clamp, flags, None)
       }
     }
   }
 
-  pub fn add_color_stop(&mut self, offset: f32, color:Color){
+  fn resample(&self, stops:&[f32], colors:&[Color4f]) -> (Vec<f32>, Vec<Color4f>){
+    // skia only interpolates linearly in sRGB, so approximate other spaces (and the eased
+    // transitions around color hints) by adding intermediate stops
+    let interpolation = self.interpolation.get();
+    let hints = self.hints.borrow();
+    if stops.len() < 2 || (interpolation.space == InterpolationSpace::SRGB && hints.is_empty()) {
+      return (stops.to_vec(), colors.to_vec())
+    }
+
+    let (mut new_stops, mut new_colors) = (vec![stops[0]], vec![colors[0]]);
+    for i in 1..stops.len(){
+      let (start, end) = (stops[i - 1], stops[i]);
+      let hint = hints.iter().find(|&&h| h > start && h < end).map(|h| (h - start) / (end - start));
+      if end > start && (hint.is_some() || interpolation.space != InterpolationSpace::SRGB) {
+        for n in 1..STOP_SAMPLES{
+          let t = n as f32 / STOP_SAMPLES as f32;
+          // a hint marks where the mix is 50/50, using the easing curve from css-images-4
+          let weight = match hint{
+            Some(h) => t.powf(0.5_f32.ln() / h.ln()),
+            None => t
+          };
+          new_stops.push(start + (end - start) * t);
+          new_colors.push(interpolation.mix(&colors[i - 1], &colors[i], weight as f64));
+        }
+      }
+      new_stops.push(end);
+      new_colors.push(colors[i]);
+    }
+    (new_stops, new_colors)
+  }
+
+  pub fn add_color_stop(&mut self, offset: f32, color:Color4f){
     let gradient = &mut *self.gradient.borrow_mut();
 
     let stops = match gradient{
@@ -88,13 +131,17 @@ declare_types! {
                                    and \"createRadialGradient\" methods instead)")
       };
 
-      Ok(CanvasGradient{ gradient:Rc::new(RefCell::new(gradient)) })
+      Ok(CanvasGradient{
+        gradient:Rc::new(RefCell::new(gradient)),
+        interpolation:Rc::new(Cell::new(Interpolation::default())),
+        hints:Rc::new(RefCell::new(vec![])),
+      })
     }
 
     method addColorStop(mut cx){
       let mut this = cx.this();
       let offset = float_arg(&mut cx, 0, "offset")?;
-      let color = color_arg(&mut cx, 1);
+      let color = color4f_arg(&mut cx, 1);
 
       if offset < 0.0 || offset > 1.0 {
         let err = JsError::range_error(&mut cx, "Color stop offsets must be between 0 and 1")?;
@@ -107,5 +154,87 @@ declare_types! {
       Ok(cx.undefined().upcast())
     }
 
+    method addColorHint(mut cx){
+      let this = cx.this();
+      let offset = float_arg(&mut cx, 0, "offset")?;
+
+      if offset < 0.0 || offset > 1.0 {
+        let err = JsError::range_error(&mut cx, "Color hint offsets must be between 0 and 1")?;
+        return cx.throw(err)
+      }
+
+      cx.borrow(&this, |this| {
+        let mut hints = this.hints.borrow_mut();
+        let idx = hints.binary_search_by(|n| n.partial_cmp(&offset).unwrap()).unwrap_or_else(|x| x);
+        hints.insert(idx, offset);
+      });
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_premultiplied(mut cx){
+      let this = cx.this();
+      let flag = cx.borrow(&this, |this| this.interpolation.get().premultiplied );
+      Ok(cx.boolean(flag).upcast())
+    }
+
+    method set_premultiplied(mut cx){
+      let this = cx.this();
+      let flag = bool_arg(&mut cx, 0, "premultiplied")?;
+      cx.borrow(&this, |this| {
+        let mut interpolation = this.interpolation.get();
+        interpolation.premultiplied = flag;
+        this.interpolation.set(interpolation);
+      });
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_interpolation(mut cx){
+      let this = cx.this();
+      let space = cx.borrow(&this, |this| this.interpolation.get().space );
+      Ok(cx.string(from_interpolation_space(space)).upcast())
+    }
+
+    method set_interpolation(mut cx){
+      let this = cx.this();
+      let name = string_arg(&mut cx, 0, "interpolation")?;
+      match to_interpolation_space(&name){
+        Some(space) => {
+          cx.borrow(&this, |this| {
+            let mut interpolation = this.interpolation.get();
+            interpolation.space = space;
+            this.interpolation.set(interpolation);
+          });
+          Ok(cx.undefined().upcast())
+        },
+        None => cx.throw_type_error(format!(
+          "Unknown interpolation space \"{}\" (expected \"srgb\", \"srgb-linear\", \"lab\", \"lch\", \"oklab\", or \"oklch\")", name
+        ))
+      }
+    }
+
+    method get_hue(mut cx){
+      let this = cx.this();
+      let method = cx.borrow(&this, |this| this.interpolation.get().hue );
+      Ok(cx.string(from_hue_method(method)).upcast())
+    }
+
+    method set_hue(mut cx){
+      let this = cx.this();
+      let name = string_arg(&mut cx, 0, "hue")?;
+      match to_hue_method(&name){
+        Some(hue) => {
+          cx.borrow(&this, |this| {
+            let mut interpolation = this.interpolation.get();
+            interpolation.hue = hue;
+            this.interpolation.set(interpolation);
+          });
+          Ok(cx.undefined().upcast())
+        },
+        None => cx.throw_type_error(format!(
+          "Unknown hue interpolation method \"{}\" (expected \"shorter\", \"longer\", \"increasing\", or \"decreasing\")", name
+        ))
+      }
+    }
+
   }
 }
\ No newline at end of file
@@ -0,0 +1,255 @@
+#![allow(unused_mut)]
+#![allow(unused_imports)]
+use std::cell::RefCell;
+use neon::prelude::*;
+use skia_safe::{Surface, surfaces, Color, Matrix, Rect, ImageInfo, ColorType, AlphaType};
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::event::{Event, WindowEvent, ElementState, MouseButton, KeyboardInput, MouseScrollDelta};
+use winit::event_loop::{EventLoop, ControlFlow};
+use winit::platform::run_return::EventLoopExtRunReturn;
+use winit::window::{Window as WinitWindow, WindowBuilder, WindowId};
+use softbuffer::GraphicsContext;
+
+use crate::utils::*;
+use crate::canvas::{JsCanvas, canvas_picture};
+
+thread_local!(
+  // winit only allows a single event loop per process (and it must live on the main thread)
+  static EVENT_LOOP: RefCell<Option<EventLoop<()>>> = RefCell::new(None);
+);
+
+fn with_event_loop<F, U>(f:F) -> U where F:FnOnce(&mut EventLoop<()>) -> U {
+  EVENT_LOOP.with(|cell| {
+    let mut event_loop = cell.borrow_mut();
+    f(event_loop.get_or_insert_with(EventLoop::new))
+  })
+}
+
+fn window_key(id:WindowId) -> String{
+  format!("{:?}", id)
+}
+
+pub struct Window{
+  handle: Option<(WinitWindow, GraphicsContext)>,
+  title: String,
+  background: Color,
+}
+
+impl Window{
+  pub fn new(width:f32, height:f32, title:&str, background:Color) -> Result<Self, String>{
+    with_event_loop(|event_loop| {
+      let window = WindowBuilder::new()
+        .with_title(title)
+        .with_inner_size(LogicalSize::new(width, height))
+        .build(event_loop)
+        .map_err(|e| e.to_string())?;
+      let context = unsafe{ GraphicsContext::new(&window, &window) }
+        .map_err(|e| e.to_string())?;
+      Ok(Window{ handle:Some((window, context)), title:title.to_string(), background })
+    })
+  }
+
+  pub fn id(&self) -> Option<String>{
+    self.handle.as_ref().map(|(window, _)| window_key(window.id()))
+  }
+
+  pub fn present(&mut self, pict:Option<skia_safe::Picture>){
+    let background = self.background;
+    if let Some((window, context)) = &mut self.handle{
+      let PhysicalSize{width, height} = window.inner_size();
+      if width == 0 || height == 0 { return }
+
+      // softbuffer expects 0RGB words, which matches skia's BGRA byte order on little-endian systems
+      let info = ImageInfo::new((width as i32, height as i32), ColorType::BGRA8888, AlphaType::Premul, None);
+      let mut surface = match surfaces::raster(&info, None, None){
+        Some(surface) => surface,
+        None => return
+      };
+
+      let canvas = surface.canvas();
+      canvas.clear(background);
+      if let Some(pict) = pict{
+        // scale the canvas to fit the window, preserving its aspect ratio
+        let bounds = pict.cull_rect();
+        let scale = (width as f32 / bounds.width()).min(height as f32 / bounds.height());
+        let (dx, dy) = ((width as f32 - bounds.width() * scale) / 2.0, (height as f32 - bounds.height() * scale) / 2.0);
+        let mut matrix = Matrix::scale((scale, scale));
+        matrix.post_translate((dx, dy));
+        canvas.draw_picture(&pict, Some(&matrix), None);
+      }
+
+      let mut buffer = vec![0u32; (width * height) as usize];
+      let row_bytes = width as usize * 4;
+      let pixels = unsafe{
+        std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, buffer.len() * 4)
+      };
+      if surface.read_pixels(&info, pixels, row_bytes, (0, 0)){
+        context.set_buffer(&buffer, width as u16, height as u16);
+      }
+    }
+  }
+
+  pub fn close(&mut self){
+    self.handle = None;
+  }
+}
+
+enum UiEvent{
+  Close,
+  Resize(f64, f64),
+  Mouse(&'static str, f64, f64, i32),
+  Wheel(f64, f64),
+  Key(&'static str, String),
+  Focus(bool),
+}
+
+fn mouse_button(button:MouseButton) -> i32{
+  match button{
+    MouseButton::Left => 0,
+    MouseButton::Middle => 1,
+    MouseButton::Right => 2,
+    MouseButton::Other(n) => n as i32,
+  }
+}
+
+pub fn pump_events(mut cx: FunctionContext) -> JsResult<JsArray> {
+  // drain whatever events are pending without blocking node's own event loop
+  let mut queue:Vec<(String, UiEvent)> = vec![];
+  let mut cursor = (0.0, 0.0);
+  with_event_loop(|event_loop| {
+    event_loop.run_return(|event, _, control_flow| {
+      *control_flow = ControlFlow::Poll;
+      match event{
+        Event::WindowEvent{ window_id, event } => {
+          let key = window_key(window_id);
+          let ui_event = match event{
+            WindowEvent::CloseRequested => Some(UiEvent::Close),
+            WindowEvent::Resized(size) => Some(UiEvent::Resize(size.width as f64, size.height as f64)),
+            WindowEvent::Focused(focused) => Some(UiEvent::Focus(focused)),
+            WindowEvent::CursorMoved{ position, .. } => {
+              cursor = (position.x, position.y);
+              Some(UiEvent::Mouse("mousemove", position.x, position.y, 0))
+            },
+            WindowEvent::MouseInput{ state, button, .. } => {
+              let kind = if state == ElementState::Pressed { "mousedown" } else { "mouseup" };
+              Some(UiEvent::Mouse(kind, cursor.0, cursor.1, mouse_button(button)))
+            },
+            WindowEvent::MouseWheel{ delta, .. } => match delta{
+              MouseScrollDelta::LineDelta(dx, dy) => Some(UiEvent::Wheel(dx as f64, dy as f64)),
+              MouseScrollDelta::PixelDelta(pos) => Some(UiEvent::Wheel(pos.x, pos.y)),
+            },
+            WindowEvent::KeyboardInput{ input:KeyboardInput{ state, virtual_keycode:Some(code), .. }, .. } => {
+              let kind = if state == ElementState::Pressed { "keydown" } else { "keyup" };
+              Some(UiEvent::Key(kind, format!("{:?}", code)))
+            },
+            WindowEvent::ReceivedCharacter(c) => Some(UiEvent::Key("input", c.to_string())),
+            _ => None
+          };
+          if let Some(ui_event) = ui_event{
+            queue.push((key, ui_event));
+          }
+        },
+        Event::MainEventsCleared => *control_flow = ControlFlow::Exit,
+        _ => {}
+      }
+    });
+  });
+
+  let events = JsArray::new(&mut cx, queue.len() as u32);
+  for (i, (key, ui_event)) in queue.into_iter().enumerate(){
+    let obj = JsObject::new(&mut cx);
+    let id = cx.string(key);
+    obj.set(&mut cx, "window", id)?;
+
+    let kind = match ui_event{
+      UiEvent::Close => "close",
+      UiEvent::Focus(focused) => if focused { "focus" } else { "blur" },
+      UiEvent::Resize(width, height) => {
+        let (width, height) = (cx.number(width), cx.number(height));
+        obj.set(&mut cx, "width", width)?;
+        obj.set(&mut cx, "height", height)?;
+        "resize"
+      },
+      UiEvent::Mouse(kind, x, y, button) => {
+        let (x, y, button) = (cx.number(x), cx.number(y), cx.number(button));
+        obj.set(&mut cx, "x", x)?;
+        obj.set(&mut cx, "y", y)?;
+        obj.set(&mut cx, "button", button)?;
+        kind
+      },
+      UiEvent::Wheel(dx, dy) => {
+        let (dx, dy) = (cx.number(dx), cx.number(dy));
+        obj.set(&mut cx, "deltaX", dx)?;
+        obj.set(&mut cx, "deltaY", dy)?;
+        "wheel"
+      },
+      UiEvent::Key(kind, key) => {
+        let key = cx.string(key);
+        obj.set(&mut cx, "key", key)?;
+        kind
+      },
+    };
+    let kind = cx.string(kind);
+    obj.set(&mut cx, "type", kind)?;
+    events.set(&mut cx, i as u32, obj)?;
+  }
+  Ok(events)
+}
+
+declare_types! {
+  pub class JsWindow for Window {
+    init(mut cx) {
+      let width = float_arg(&mut cx, 0, "width")?;
+      let height = float_arg(&mut cx, 1, "height")?;
+      let title = string_arg_or(&mut cx, 2, "");
+      let background = color_arg(&mut cx, 3).unwrap_or(Color::WHITE);
+
+      match Window::new(width, height, &title, background){
+        Ok(window) => Ok(window),
+        Err(msg) => cx.throw_error(format!("Could not open window: {}", msg))
+      }
+    }
+
+    method get_id(mut cx){
+      let this = cx.this();
+      match cx.borrow(&this, |this| this.id()){
+        Some(id) => Ok(cx.string(id).upcast()),
+        None => Ok(cx.undefined().upcast())
+      }
+    }
+
+    method _draw(mut cx){
+      let mut this = cx.this();
+      let canvas = cx.argument::<JsCanvas>(0)?;
+      let front = cx.borrow(&canvas, |canvas| canvas.front.clone());
+      let pict = match front{
+        Some(pict) => Some(pict),
+        None => canvas_picture(&mut cx, &canvas)?
+      };
+      cx.borrow_mut(&mut this, |mut this| this.present(pict) );
+      Ok(cx.undefined().upcast())
+    }
+
+    method get_title(mut cx){
+      let this = cx.this();
+      let title = cx.borrow(&this, |this| this.title.clone());
+      Ok(cx.string(title).upcast())
+    }
+
+    method set_title(mut cx){
+      let mut this = cx.this();
+      let title = string_arg(&mut cx, 0, "title")?;
+      cx.borrow_mut(&mut this, |mut this| {
+        if let Some((window, _)) = &this.handle{ window.set_title(&title) }
+        this.title = title;
+      });
+      Ok(cx.undefined().upcast())
+    }
+
+    method _close(mut cx){
+      let mut this = cx.this();
+      cx.borrow_mut(&mut this, |mut this| this.close() );
+      Ok(cx.undefined().upcast())
+    }
+  }
+}
@@ -5,37 +5,277 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 use neon::prelude::*;
-use skia_safe::{Image as SkImage, ImageInfo, ColorType, AlphaType, Data, Bitmap};
+use neon::object::This;
+use neon::result::Throw;
+use skia_safe::{Image as SkImage, ImageInfo, ColorType, AlphaType, Data, Bitmap, Picture, PictureRecorder, Rect, Paint, Codec, FontMgr, codec, svg, surfaces, images};
+use skia_safe::codec::EncodedOrigin;
+use skia_safe::image::CachingHint;
+use skia_safe::images::BitDepth;
 
 use crate::utils::*;
+use crate::canvas::{JsCanvas, canvas_context, canvas_picture};
 
 pub struct Image{
   src:String,
-  pub image:Option<SkImage>
+  pub image:Option<SkImage>,
+  pub picture:Option<Picture>,
+  data:Option<Data>,
+  orientation:EncodedOrigin
+}
+
+impl Image{
+  fn adopt(&mut self, decoded:Image){
+    // take on another image's decoded contents while keeping our own src
+    self.image = decoded.image;
+    self.picture = decoded.picture;
+    self.data = decoded.data;
+    self.orientation = decoded.orientation;
+  }
+}
+
+fn svg_attr(tag:&str, name:&str) -> Option<String>{
+  // find an attribute's value within an element's opening tag (ignoring attributes that merely end with `name`)
+  for quote in &['"', '\''] {
+    let pattern = format!("{}={}", name, quote);
+    let mut from = 0;
+    while let Some(idx) = tag[from..].find(&pattern).map(|i| i + from){
+      if tag[..idx].ends_with(char::is_whitespace){
+        return tag[idx + pattern.len()..].split(*quote).next().map(|val| val.trim().to_string())
+      }
+      from = idx + pattern.len();
+    }
+  }
+  None
+}
+
+fn svg_dimensions(svg:&str) -> (f32, f32){
+  // use the root element's width & height (or its viewBox) as the intrinsic size, defaulting to 300×150
+  let tag = match svg.find("<svg"){
+    Some(start) => &svg[start..start + svg[start..].find('>').unwrap_or(svg.len() - start)],
+    None => ""
+  };
+  let length = |name| svg_attr(tag, name).and_then(|val|
+    val.trim_end_matches("px").parse::<f32>().ok().filter(|n| *n > 0.0)
+  );
+  let view_box = svg_attr(tag, "viewBox").map(|val|
+    val.split(|c:char| c.is_whitespace() || c == ',').filter_map(|n| n.parse::<f32>().ok()).collect::<Vec<f32>>()
+  ).filter(|dims| dims.len() == 4 && dims[2] > 0.0 && dims[3] > 0.0);
+
+  match (length("width"), length("height"), view_box){
+    (Some(w), Some(h), _) => (w, h),
+    (Some(w), None, Some(vb)) => (w, w * vb[3] / vb[2]),
+    (None, Some(h), Some(vb)) => (h * vb[2] / vb[3], h),
+    (None, None, Some(vb)) => (vb[2], vb[3]),
+    (w, h, None) => (w.unwrap_or(300.0), h.unwrap_or(150.0)),
+  }
+}
+
+fn svg_picture(bytes:&[u8]) -> Option<Picture>{
+  // render svg documents to a picture (rather than a bitmap) so they stay sharp when scaled
+  let text = std::str::from_utf8(bytes).ok()?;
+  if !text.contains("<svg"){
+    return None
+  }
+
+  let mut dom = svg::Dom::from_bytes(bytes, FontMgr::new()).ok()?;
+  let (width, height) = svg_dimensions(text);
+  dom.set_container_size((width, height));
+
+  let bounds = Rect::from_wh(width, height);
+  let mut recorder = PictureRecorder::new();
+  dom.render(recorder.begin_recording(bounds, None));
+  recorder.finish_recording_as_picture(Some(&bounds))
+}
+
+fn orient(image:SkImage, origin:EncodedOrigin) -> Option<SkImage>{
+  // rotate/flip the decoded pixels so the image is upright according to its EXIF orientation
+  if origin == EncodedOrigin::TopLeft{
+    return Some(image)
+  }
+
+  let dims = match origin.swaps_width_height(){
+    true => (image.height(), image.width()),
+    false => (image.width(), image.height())
+  };
+  let info = ImageInfo::new_n32_premul(dims, image.image_info().color_space());
+  let mut surface = surfaces::raster(&info, None, None)?;
+  let canvas = surface.canvas();
+  canvas.concat(&origin.to_matrix(dims));
+  canvas.draw_image(&image, (0, 0), None);
+  Some(surface.image_snapshot())
+}
+
+pub fn image_arg<T: This>(cx: &mut CallContext<'_, T>, idx: usize) -> Result<Option<SkImage>, Throw>{
+  // accept either an Image or a Canvas (whose current page is rasterized at its full size)
+  let arg = cx.argument::<JsObject>(idx)?;
+  if let Ok(img) = arg.downcast::<JsImage>(){
+    Ok(cx.borrow(&img, |img| img.image.clone()))
+  }else if let Ok(canvas) = arg.downcast::<JsCanvas>(){
+    let dims = cx.borrow(&canvas, |canvas| (canvas.width as i32, canvas.height as i32));
+    Ok(canvas_picture(cx, &canvas)?.and_then(|pict|
+      images::deferred_from_picture(pict, dims, None, None, BitDepth::U8, None, None)
+    ))
+  }else{
+    cx.throw_type_error("Expected an Image or a Canvas argument")
+  }
+}
+
+pub fn decode_frame(data:&Data, index:usize) -> Option<SkImage>{
+  // let the codec composite the frame with any prior frames it depends on
+  let mut codec = Codec::from_data(data.clone())?;
+  if index >= codec.get_frame_count().max(1){
+    return None
+  }
+
+  // keep the image's embedded color profile (if any) so wide-gamut images aren't clipped to sRGB
+  let info = ImageInfo::new_n32_premul(codec.dimensions(), codec.info().color_space());
+  let row_bytes = info.min_row_bytes();
+  let mut pixels = vec![0u8; info.compute_byte_size(row_bytes)];
+  let options = codec::Options{
+    zero_initialized: codec::ZeroInitialized::Yes, subset: None, frame_index: index, prior_frame: None
+  };
+
+  match codec.get_pixels_with_options(&info, &mut pixels, row_bytes, Some(&options)){
+    codec::Result::Success | codec::Result::IncompleteInput => {
+      images::raster_from_data(&info, Data::new_copy(&pixels), row_bytes)
+        .and_then(|image| orient(image, codec.origin()))
+    },
+    _ => None
+  }
+}
+
+fn rasterize(bytes:&[u8]) -> Option<(Vec<u8>, (i32, i32))>{
+  // decode the first frame into raw pixels that can be handed back from a worker thread
+  let image = decode_frame(&Data::new_copy(bytes), 0)?;
+  let info = ImageInfo::new_n32_premul((image.width(), image.height()), image.image_info().color_space());
+  let row_bytes = info.min_row_bytes();
+  let mut pixels = vec![0u8; info.compute_byte_size(row_bytes)];
+  match image.read_pixels(&info, &mut pixels, row_bytes, (0, 0), CachingHint::Disallow){
+    true => Some((pixels, (image.width(), image.height()))),
+    false => None
+  }
+}
+
+struct DecodeTask{
+  bytes: Vec<u8>
+}
+
+impl Task for DecodeTask{
+  // svg documents are parsed on the main thread (yielding None), bitmaps are decoded in the background
+  type Output = Option<(Vec<u8>, (i32, i32))>;
+  type Error = String;
+  type JsEvent = JsImage;
+
+  fn perform(&self) -> Result<Self::Output, Self::Error>{
+    decode_pixels(&self.bytes)
+  }
+
+  fn complete(self, mut cx:TaskContext, result:Result<Self::Output, Self::Error>) -> JsResult<JsImage>{
+    let decoded = match result.and_then(|pixels| finish_decoding(&self.bytes, pixels)){
+      Ok(decoded) => decoded,
+      Err(msg) => return cx.throw_error(msg)
+    };
+
+    let args:Vec<Handle<JsValue>> = vec![];
+    let mut img = JsImage::new(&mut cx, args)?;
+    cx.borrow_mut(&mut img, |mut img| img.adopt(decoded));
+    Ok(img)
+  }
+}
+
+fn decode_pixels(bytes:&[u8]) -> Result<Option<(Vec<u8>, (i32, i32))>, String>{
+  // the portion of the decoding that can safely be performed off the main thread
+  match std::str::from_utf8(bytes).map(|text| text.contains("<svg")){
+    Ok(true) => Ok(None),
+    _ => rasterize(bytes).map(Some).ok_or_else(|| "Could not decode image data".to_string())
+  }
+}
+
+fn finish_decoding(bytes:&[u8], decoded:Option<(Vec<u8>, (i32, i32))>) -> Result<Image, String>{
+  let data = Data::new_copy(bytes);
+  let (image, picture, orientation) = match decoded{
+    Some((pixels, dims)) => {
+      let codec = Codec::from_data(data.clone());
+      let color_space = codec.as_ref().and_then(|codec| codec.info().color_space());
+      let origin = codec.map(|codec| codec.origin()).unwrap_or(EncodedOrigin::TopLeft);
+      let info = ImageInfo::new_n32_premul(dims, color_space);
+      let row_bytes = info.min_row_bytes();
+      (images::raster_from_data(&info, Data::new_copy(&pixels), row_bytes), None, origin)
+    },
+    None => match svg_picture(bytes){
+      Some(pict) => {
+        let bounds = pict.cull_rect();
+        let dims = (bounds.width().ceil() as i32, bounds.height().ceil() as i32);
+        let image = images::deferred_from_picture(pict.clone(), dims, None, None, BitDepth::U8, None, None);
+        (image, Some(pict), EncodedOrigin::TopLeft)
+      },
+      None => return Err("Could not decode SVG document".to_string())
+    }
+  };
+
+  let data = if picture.is_some(){ None }else{ Some(data) };
+  Ok(Image{ src:"".to_string(), image, picture, data, orientation })
 }
 
 declare_types! {
   pub class JsImage for Image {
     init(_) {
-      Ok(Image{ src:"".to_string(), image:None })
+      Ok(Image{ src:"".to_string(), image:None, picture:None, data:None, orientation:EncodedOrigin::TopLeft })
     }
 
     constructor(mut cx){
       Ok(None)
     }
 
-    method set_data(mut cx){
+    method _decode(mut cx){
+      let buffer = cx.argument::<JsBuffer>(0)?;
+      let callback = cx.argument::<JsFunction>(1)?;
+      let bytes = cx.borrow(&buffer, |buf_data| buf_data.as_slice().to_vec() );
+      DecodeTask{ bytes }.schedule(callback);
+      Ok(cx.undefined().upcast())
+    }
+
+    method _decodeSync(mut cx){
+      // decode on the main thread (for images whose `decoding` attribute isn't 'async')
       let mut this = cx.this();
       let buffer = cx.argument::<JsBuffer>(0)?;
-      let data = cx.borrow(&buffer, |buf_data| {
-        Data::new_copy(buf_data.as_slice())
+      let bytes = cx.borrow(&buffer, |buf_data| buf_data.as_slice().to_vec() );
+      match decode_pixels(&bytes).and_then(|pixels| finish_decoding(&bytes, pixels)){
+        Ok(decoded) => cx.borrow_mut(&mut this, |mut this| this.adopt(decoded)),
+        Err(msg) => return cx.throw_error(msg)
+      }
+      Ok(cx.undefined().upcast())
+    }
+
+    method _adopt(mut cx){
+      let mut this = cx.this();
+      let decoded = cx.argument::<JsImage>(0)?;
+      let decoded = cx.borrow(&decoded, |img| Image{
+        src: "".to_string(), image:img.image.clone(), picture:img.picture.clone(), data:img.data.clone(), orientation:img.orientation
       });
-      let success = cx.borrow_mut(&mut this, |mut this| {
-        this.image = SkImage::from_encoded(data, None);
-        this.image.is_some()
+      cx.borrow_mut(&mut this, |mut this| this.adopt(decoded));
+      Ok(cx.undefined().upcast())
+    }
+
+    method _transfer(mut cx){
+      // move the canvas's current contents into this image (as vectors), leaving the canvas blank
+      let mut this = cx.this();
+      let canvas = cx.argument::<JsCanvas>(0)?;
+      let pict = canvas_context(&mut cx, &canvas, |ctx| ctx.take_picture() )?;
+      let image = pict.as_ref().and_then(|pict| {
+        let bounds = pict.cull_rect();
+        let dims = (bounds.width().ceil() as i32, bounds.height().ceil() as i32);
+        images::deferred_from_picture(pict.clone(), dims, None, None, BitDepth::U8, None, None)
       });
 
-      Ok(cx.boolean(success).upcast())
+      cx.borrow_mut(&mut this, |mut this| {
+        this.src = "".to_string();
+        this.image = image;
+        this.picture = pict;
+        this.data = None;
+        this.orientation = EncodedOrigin::TopLeft;
+      });
+      Ok(cx.undefined().upcast())
     }
 
     method get_src(mut cx){
@@ -47,7 +287,14 @@ declare_types! {
     method set_src(mut cx){
       let mut this = cx.this();
       let src = string_arg(&mut cx, 0, "src")?;
-      cx.borrow_mut(&mut this, |mut this| this.src = src.clone() );
+      cx.borrow_mut(&mut this, |mut this| {
+        // discard the previous contents until the new src has been decoded
+        this.src = src.clone();
+        this.image = None;
+        this.picture = None;
+        this.data = None;
+        this.orientation = EncodedOrigin::TopLeft;
+      });
       Ok(cx.undefined().upcast())
     }
 
@@ -87,20 +334,114 @@ declare_types! {
       Ok(cx.boolean(complete).upcast())
     }
 
+    method get_orientation(mut cx){
+      let this = cx.this();
+      let orientation = cx.borrow(&this, |this| this.orientation as i32);
+      Ok(cx.number(orientation).upcast())
+    }
+
+    method get_frameCount(mut cx){
+      let this = cx.this();
+      let count = cx.borrow(&this, |this| {
+        match (&this.image, &this.data){
+          (Some(_), Some(data)) => Codec::from_data(data.clone()).map(|mut codec| codec.get_frame_count().max(1)).unwrap_or(1),
+          (Some(_), None) => 1,
+          _ => 0
+        }
+      });
+      Ok(cx.number(count as f64).upcast())
+    }
+
+    method frameDuration(mut cx){
+      let this = cx.this();
+      let index = float_arg(&mut cx, 0, "index")? as usize;
+      let duration = cx.borrow(&this, |this| {
+        this.data.as_ref()
+          .and_then(|data| Codec::from_data(data.clone()))
+          .and_then(|mut codec| codec.get_frame_info(index))
+          .map(|info| info.duration)
+      });
+
+      match duration{
+        Some(ms) => Ok(cx.number(ms as f64).upcast()),
+        None => Ok(cx.undefined().upcast())
+      }
+    }
+
+    method decodeFrame(mut cx){
+      let mut this = cx.this();
+      let index = float_arg(&mut cx, 0, "index")?;
+      if index < 0.0 || index.fract() != 0.0{
+        return cx.throw_range_error("Frame index must be a non-negative integer")
+      }
+
+      let frame = cx.borrow(&this, |this| {
+        this.data.as_ref().and_then(|data| decode_frame(data, index as usize))
+      });
+
+      match frame{
+        Some(image) => {
+          cx.borrow_mut(&mut this, |mut this| this.image = Some(image) );
+          Ok(this.upcast())
+        },
+        None => cx.throw_range_error(format!("Could not decode frame {}", index))
+      }
+    }
+
   }
 }
 
 
 pub struct ImageData{
   pub width: f32,
-  pub height: f32
+  pub height: f32,
+  color_type: ColorType,
+  premultiplied: bool
 }
 
 impl ImageData{
   pub fn get_info(&self) -> ImageInfo {
     let dims = (self.width as i32, self.height as i32);
-    ImageInfo::new(dims, ColorType::RGBA8888, AlphaType::Unpremul, None)
+    let alpha_type = match (self.color_type, self.premultiplied){
+      (ColorType::RGB565, _) | (ColorType::Gray8, _) => AlphaType::Opaque,
+      (ColorType::Alpha8, _) | (_, true) => AlphaType::Premul,
+      _ => AlphaType::Unpremul
+    };
+    ImageInfo::new(dims, self.color_type, alpha_type, None)
+  }
+}
+
+fn image_data_pixels<'a>(cx: &mut MethodContext<'a, JsImageData>) -> Result<Option<SkImage>, Throw>{
+  // wrap the js-side pixel buffer in an image so skia can resample or convert it
+  let this = cx.this();
+  let info = cx.borrow(&this, |this| this.get_info() );
+  let buffer = this.get(cx, "data")?.downcast_or_throw::<JsBuffer, _>(cx)?;
+  Ok(cx.borrow(&buffer, |data| {
+    images::raster_from_data(&info, Data::new_copy(data.as_slice()), info.min_row_bytes())
+  }))
+}
+
+fn image_data_from<'a>(cx: &mut MethodContext<'a, JsImageData>, image:&SkImage, color_type:ColorType, premultiplied:bool) -> JsResult<'a, JsValue>{
+  // read the image's pixels into a new ImageData with the specified format
+  let args:Vec<Handle<JsValue>> = vec![
+    cx.number(image.width()).upcast(), cx.number(image.height()).upcast(),
+    cx.string(from_color_type(color_type)).upcast(), cx.boolean(premultiplied).upcast()
+  ];
+  let img_data = JsImageData::new(cx, args)?;
+  let info = cx.borrow(&img_data, |img_data| img_data.get_info() );
+  let row_bytes = info.min_row_bytes();
+
+  let buffer = JsBuffer::new(cx, info.compute_byte_size(row_bytes) as u32)?;
+  let success = cx.borrow(&buffer, |data| {
+    image.read_pixels(&info, data.as_mut_slice::<u8>(), row_bytes, (0, 0), CachingHint::Disallow)
+  });
+  if !success{
+    return cx.throw_error("Could not convert pixel data")
   }
+
+  let attr = cx.string("data");
+  img_data.set(cx, attr, buffer)?;
+  Ok(img_data.upcast())
 }
 
 declare_types! {
@@ -108,12 +449,21 @@ declare_types! {
     init(mut cx) {
       let width = float_arg(&mut cx, 0, "width")?;
       let height = float_arg(&mut cx, 1, "height")?;
+      let type_name = string_arg_or(&mut cx, 2, "rgba");
+      let premultiplied = bool_arg_or(&mut cx, 3, false);
 
       if width<=0.0 || height <=0.0{
         return cx.throw_range_error("Cannot allocate a buffer of this size")
       }
 
-      Ok(ImageData{ width, height })
+      let color_type = match to_color_type(&type_name){
+        Some(color_type) => color_type,
+        None => return cx.throw_type_error(format!(
+          "Unknown colorType \"{}\" (expected \"rgba\", \"bgra\", \"rgb565\", \"gray\", \"alpha\", \"rgba16\", or \"rgba32f\")", type_name
+        ))
+      };
+
+      Ok(ImageData{ width, height, color_type, premultiplied })
     }
 
     method get_width(mut cx){
@@ -128,6 +478,18 @@ declare_types! {
       Ok(cx.number(height).upcast())
     }
 
+    method get_colorType(mut cx){
+      let this = cx.this();
+      let color_type = cx.borrow(&this, |this| this.color_type );
+      Ok(cx.string(from_color_type(color_type)).upcast())
+    }
+
+    method get_premultiplied(mut cx){
+      let this = cx.this();
+      let flag = cx.borrow(&this, |this| this.premultiplied );
+      Ok(cx.boolean(flag).upcast())
+    }
+
     // setters are noops
     method set_width(mut cx){
       let arg = cx.argument::<JsValue>(0)?;
@@ -139,6 +501,62 @@ declare_types! {
       Ok(arg)
     }
 
+    method _bytesPerPixel(mut cx){
+      let this = cx.this();
+      let bpp = cx.borrow(&this, |this| this.get_info().bytes_per_pixel() );
+      Ok(cx.number(bpp as f64).upcast())
+    }
+
+    method _resize(mut cx){
+      let this = cx.this();
+      let width = float_arg(&mut cx, 0, "width")?.floor() as i32;
+      let height = float_arg(&mut cx, 1, "height")?.floor() as i32;
+      let name = string_arg_or(&mut cx, 2, "low");
+      let sampling = match to_filter_quality(&name){
+        Some(quality) => quality.sampling(),
+        None => return cx.throw_type_error(format!("Unknown resize quality \"{}\"", name))
+      };
+
+      if width <= 0 || height <= 0{
+        return cx.throw_range_error("Cannot allocate a buffer of this size")
+      }
+
+      // resample into a surface of the same color type (so high-bit-depth values aren't quantized
+      // to 8 bits), then return to the original alpha format
+      let (color_type, premultiplied) = cx.borrow(&this, |this| (this.color_type, this.premultiplied) );
+      let alpha_type = match color_type{
+        ColorType::RGB565 | ColorType::Gray8 => AlphaType::Opaque,
+        _ => AlphaType::Premul
+      };
+      let scaled = image_data_pixels(&mut cx)?.and_then(|image| {
+        let info = ImageInfo::new((width, height), color_type, alpha_type, None);
+        let mut surface = surfaces::raster(&info, None, None)?;
+        let paint = Paint::default();
+        surface.canvas().draw_image_rect_with_sampling_options(&image, None, Rect::from_iwh(width, height), sampling, &paint);
+        Some(surface.image_snapshot())
+      });
+
+      match scaled{
+        Some(image) => image_data_from(&mut cx, &image, color_type, premultiplied),
+        None => cx.throw_error("Could not resize pixel data")
+      }
+    }
+
+    method _convert(mut cx){
+      let this = cx.this();
+      let (type_name, premultiplied) = cx.borrow(&this, |this| (from_color_type(this.color_type), this.premultiplied) );
+      let type_name = string_arg_or(&mut cx, 0, &type_name);
+      let premultiplied = bool_arg_or(&mut cx, 1, premultiplied);
+      let color_type = match to_color_type(&type_name){
+        Some(color_type) => color_type,
+        None => return cx.throw_type_error(format!("Unknown colorType \"{}\"", type_name))
+      };
+
+      match image_data_pixels(&mut cx)?{
+        Some(image) => image_data_from(&mut cx, &image, color_type, premultiplied),
+        None => cx.throw_error("Could not convert pixel data")
+      }
+    }
 
   }
-}
\ No newline at end of file
+}
@@ -1,22 +1,40 @@
 use neon::prelude::*;
 
 mod canvas;
+mod cmyk;
+mod color;
+mod compare;
 mod context;
 mod path;
 mod image;
 mod gradient;
 mod pattern;
+mod pool;
+mod shader;
+mod texture;
+mod tiff;
 mod typography;
 mod utils;
+mod woff;
+#[cfg(feature = "window")]
+mod gui;
 
 register_module!(mut m, {
   m.export_class::<crate::canvas::JsCanvas>("Canvas")?;
   m.export_class::<crate::context::JsContext2D>("CanvasRenderingContext2D")?;
   m.export_class::<crate::gradient::JsCanvasGradient>("CanvasGradient")?;
   m.export_class::<crate::pattern::JsCanvasPattern>("CanvasPattern")?;
+  m.export_class::<crate::shader::JsCanvasShader>("CanvasShader")?;
+  m.export_class::<crate::texture::JsTextureGenerator>("TextureGenerator")?;
   m.export_class::<crate::path::JsPath2D>("Path2D")?;
   m.export_class::<crate::image::JsImage>("Image")?;
   m.export_class::<crate::image::JsImageData>("ImageData")?;
   m.export_class::<crate::typography::JsFontLibrary>("FontLibrary")?;
+  m.export_function("compare", crate::compare::compare_images)?;
+  #[cfg(feature = "window")]
+  {
+    m.export_class::<crate::gui::JsWindow>("Window")?;
+    m.export_function("pumpEvents", crate::gui::pump_events)?;
+  }
   Ok(())
 });
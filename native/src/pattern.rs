@@ -2,11 +2,13 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 use neon::prelude::*;
-use skia_safe::{shaders, Shader, Matrix, TileMode::{Decal, Repeat}};
+use skia_safe::{shaders, Shader, FilterMode, Matrix, Paint, PaintStyle, Rect, PictureRecorder, TileMode::{Decal, Repeat}};
 
 use crate::utils::*;
 use crate::image::{Image, JsImage};
-use crate::canvas::{Canvas, JsCanvas, canvas_pages};
+use crate::canvas::{Canvas, JsCanvas, canvas_picture};
+use crate::path::JsPath2D;
+use crate::context::Dye;
 
 #[derive(Clone)]
 pub struct CanvasPattern{
@@ -20,6 +22,17 @@ impl CanvasPattern{
   }
 }
 
+fn dye_arg<T: This>(cx: &mut CallContext<'_, T>, idx: usize) -> Result<Option<Dye>, Throw>{
+  let arg = cx.argument::<JsValue>(idx as i32)?;
+  if arg.is_a::<JsNull>() || arg.is_a::<JsUndefined>(){
+    return Ok(None)
+  }
+  match Dye::from_value(cx, arg)?{
+    Some(dye) => Ok(Some(dye)),
+    None => cx.throw_type_error("Expected a CSS color, CanvasGradient, CanvasPattern, or CanvasShader")
+  }
+}
+
 declare_types! {
   pub class JsCanvasPattern for CanvasPattern {
     init(mut cx) {
@@ -42,17 +55,51 @@ declare_types! {
         src if src.is_a::<JsImage>() => {
           let src = cx.argument::<JsImage>(0)?;
           cx.borrow(&src, |src| {
-            src.image.as_ref().map(|image| image.to_shader((tile_x, tile_y), None))
+            src.image.as_ref().and_then(|image| image.to_shader((tile_x, tile_y), FilterQuality::Low.sampling(), None))
           })
         }
         src if src.is_a::<JsCanvas>() => {
           let src = cx.argument::<JsCanvas>(0)?;
-          let mut context = canvas_pages(&mut cx, &src)?[0];
-          cx.borrow_mut(&mut context, |mut ctx| {
-            ctx.get_picture(None).map(|pict|
-              pict.to_shader((tile_x, tile_y), None, None)
-            )
-          })
+          canvas_picture(&mut cx, &src)?.map(|pict|
+            pict.to_shader((tile_x, tile_y), FilterMode::Linear, None, None)
+          )
+        }
+        src if src.is_a::<JsPath2D>() => {
+          // paths are recorded as vector tiles so the pattern stays crisp at any scale
+          let src = cx.argument::<JsPath2D>(0)?;
+          let path = cx.borrow(&src, |src| src.path.clone());
+          let fill = dye_arg(&mut cx, 2)?;
+          let stroke = dye_arg(&mut cx, 3)?;
+          let line_width = float_arg(&mut cx, 4, "lineWidth")?;
+
+          let outset = if stroke.is_some(){ line_width / 2.0 }else{ 0.0 };
+          let bounds = path.bounds();
+          let width = opt_float_arg(&mut cx, 5).unwrap_or(bounds.right + outset);
+          let height = opt_float_arg(&mut cx, 6).unwrap_or(bounds.bottom + outset);
+          if !(width > 0.0 && height > 0.0){
+            return cx.throw_range_error("Pattern tiles must be larger than zero")
+          }
+
+          let tile = Rect::from_wh(width, height);
+          let mut recorder = PictureRecorder::new();
+          let canvas = recorder.begin_recording(tile, None);
+          if let Some(dye) = fill{
+            let mut paint = Paint::default();
+            paint.set_anti_alias(true);
+            dye.mix_into(&mut paint, 1.0);
+            canvas.draw_path(&path, &paint);
+          }
+          if let Some(dye) = stroke{
+            let mut paint = Paint::default();
+            paint.set_anti_alias(true)
+                 .set_style(PaintStyle::Stroke)
+                 .set_stroke_width(line_width);
+            dye.mix_into(&mut paint, 1.0);
+            canvas.draw_path(&path, &paint);
+          }
+          recorder.finish_recording_as_picture(Some(&tile)).map(|pict|
+            pict.to_shader((tile_x, tile_y), FilterMode::Linear, None, None)
+          )
         }
         _ => None
       };
@@ -61,7 +108,7 @@ declare_types! {
         Some(stamp) => Ok(CanvasPattern{
           shader: Rc::new(RefCell::new(stamp.clone())), stamp
         }),
-        None => cx.throw_type_error("CanvasPatterns require a source Image or a Canvas")
+        None => cx.throw_type_error("CanvasPatterns require a source Image, Canvas, or Path2D")
       }
     }
 
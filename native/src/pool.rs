@@ -0,0 +1,110 @@
+#![allow(dead_code)]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use skia_safe::{Surface, surfaces, PictureRecorder, ColorType, AlphaType, ImageInfo, ISize, Color};
+
+use crate::utils::{WorkingSpace, PixelFormat};
+
+// recycled surfaces are capped both per size and in total so an idle process doesn't hoard memory
+const SURFACES_PER_SIZE:usize = 4;
+const MAX_POOLED_BYTES:usize = 256 * 1024 * 1024;
+const MAX_RECORDERS:usize = 16;
+
+#[derive(Default)]
+struct Pool{
+  surfaces: HashMap<(i32, i32, ColorType), Vec<Surface>>,
+  recorders: Vec<PictureRecorder>,
+  bytes: usize,
+}
+
+thread_local!(
+  static POOL: RefCell<Pool> = RefCell::new(Pool::default());
+);
+
+fn byte_size(surface:&mut Surface) -> usize{
+  let info = surface.image_info();
+  info.compute_min_byte_size()
+}
+
+pub fn take_surface(dims:impl Into<ISize>) -> Option<Surface>{
+  // return a transparent n32 raster surface, reusing a previously released one of the same size if possible
+  let dims = dims.into();
+  let key = (dims.width, dims.height, ColorType::N32);
+  let recycled = POOL.with(|pool| {
+    let mut pool = pool.borrow_mut();
+    let mut surface = pool.surfaces.get_mut(&key).and_then(|stack| stack.pop())?;
+    pool.bytes -= byte_size(&mut surface);
+    Some(surface)
+  });
+
+  match recycled{
+    Some(mut surface) => {
+      let canvas = surface.canvas();
+      canvas.restore_to_count(1);
+      canvas.reset_matrix();
+      canvas.clear(Color::TRANSPARENT);
+      Some(surface)
+    },
+    None => surfaces::raster_n32_premul(dims)
+  }
+}
+
+pub fn take_surface_in(dims:impl Into<ISize>, space:WorkingSpace) -> Option<Surface>{
+  // only untagged (i.e., sRGB) surfaces are pooled, other color spaces get a new one each time
+  match space{
+    WorkingSpace::SRGB => take_surface(dims),
+    _ => {
+      let info = ImageInfo::new(dims.into(), space.color_type(), AlphaType::Premul, space.color_space());
+      surfaces::raster(&info, None, None)
+    }
+  }
+}
+
+pub fn take_surface_as(dims:impl Into<ISize>, space:WorkingSpace, format:PixelFormat) -> Option<Surface>{
+  // high-bit-depth surfaces are likewise allocated on demand
+  match format{
+    PixelFormat::RGBA8 => take_surface_in(dims, space),
+    _ => {
+      let info = ImageInfo::new(dims.into(), format.color_type(space), AlphaType::Premul, space.color_space());
+      surfaces::raster(&info, None, None)
+    }
+  }
+}
+
+pub fn recycle_surface(mut surface:Surface){
+  let info = surface.image_info();
+  if info.color_space().is_some() || info.color_type() != ColorType::N32{ return }
+  let key = (info.width(), info.height(), info.color_type());
+  let size = byte_size(&mut surface);
+  POOL.with(|pool| {
+    let mut pool = pool.borrow_mut();
+    if pool.bytes + size > MAX_POOLED_BYTES { return }
+    let stack = pool.surfaces.entry(key).or_insert_with(Vec::new);
+    if stack.len() < SURFACES_PER_SIZE{
+      stack.push(surface);
+      pool.bytes += size;
+    }
+  });
+}
+
+pub fn take_recorder() -> PictureRecorder{
+  POOL.with(|pool| pool.borrow_mut().recorders.pop())
+      .unwrap_or_else(PictureRecorder::new)
+}
+
+pub fn recycle_recorder(mut recorder:PictureRecorder){
+  // end any recording that's still underway so the recorder starts fresh when reused
+  if recorder.recording_canvas().is_some(){
+    recorder.finish_recording_as_picture(None);
+  }
+  POOL.with(|pool| {
+    let mut pool = pool.borrow_mut();
+    if pool.recorders.len() < MAX_RECORDERS{
+      pool.recorders.push(recorder);
+    }
+  });
+}
+
+pub fn drain(){
+  POOL.with(|pool| pool.replace(Pool::default()));
+}
@@ -0,0 +1,111 @@
+#![allow(unused_imports)]
+use std::rc::Rc;
+use std::cell::RefCell;
+use neon::prelude::*;
+use skia_safe::{Shader, ColorFilter, ImageFilter, Data, image_filters};
+use skia_safe::runtime_effect::{RuntimeEffect, uniform::Type as UniformType};
+
+use crate::utils::*;
+
+#[derive(Clone)]
+pub struct CanvasShader{
+  effect: RuntimeEffect,
+  uniforms: Rc<RefCell<Vec<u8>>>
+}
+
+impl CanvasShader{
+  pub fn new(sksl: &str) -> Result<Self, String>{
+    // SkSL with a `main(float2 coord)` signature can be used as a fill, while
+    // `main(half4 color)` programs are only usable as color filters
+    let effect = RuntimeEffect::make_for_shader(sksl, None)
+      .or_else(|_| RuntimeEffect::make_for_color_filer(sksl, None))?;
+    let uniforms = vec![0u8; effect.uniform_size()];
+    Ok(CanvasShader{ effect, uniforms:Rc::new(RefCell::new(uniforms)) })
+  }
+
+  pub fn uniform_names(&self) -> Vec<String>{
+    self.effect.uniforms().iter().map(|u| u.name().to_string()).collect()
+  }
+
+  pub fn set_uniform(&mut self, name: &str, values: &[f32]) -> Result<(), String>{
+    let uniform = match self.effect.find_uniform(name){
+      Some(uniform) => uniform,
+      None => return Err(format!("Unknown uniform \"{}\"", name))
+    };
+
+    let slots = uniform.size_in_bytes() / 4;
+    if values.len() != slots {
+      return Err(format!("Uniform \"{}\" expects {} values (got {})", name, slots, values.len()))
+    }
+
+    let is_int = matches!(uniform.ty(), UniformType::Int | UniformType::Int2 | UniformType::Int3 | UniformType::Int4);
+    let mut buffer = self.uniforms.borrow_mut();
+    for (i, val) in values.iter().enumerate(){
+      let bytes = match is_int {
+        true => (*val as i32).to_ne_bytes(),
+        false => val.to_ne_bytes()
+      };
+      let offset = uniform.offset() + i * 4;
+      buffer[offset..offset+4].copy_from_slice(&bytes);
+    }
+    Ok(())
+  }
+
+  fn uniform_data(&self) -> Data{
+    Data::new_copy(&self.uniforms.borrow())
+  }
+
+  pub fn shader(&self) -> Option<Shader>{
+    match self.effect.allow_shader(){
+      true => self.effect.make_shader(self.uniform_data(), &[], None),
+      false => None
+    }
+  }
+
+  pub fn color_filter(&self) -> Option<ColorFilter>{
+    match self.effect.allow_color_filter(){
+      true => self.effect.make_color_filter(self.uniform_data(), None),
+      false => None
+    }
+  }
+
+  pub fn image_filter(&self) -> Option<ImageFilter>{
+    // color-filter programs regrade the content being drawn, shader programs replace it
+    match self.color_filter(){
+      Some(color_filter) => image_filters::color_filter(color_filter, None, None),
+      None => self.shader().and_then(|shader| image_filters::shader(shader, None))
+    }
+  }
+}
+
+declare_types! {
+  pub class JsCanvasShader for CanvasShader {
+    init(mut cx) {
+      let sksl = string_arg(&mut cx, 0, "sksl")?;
+      match CanvasShader::new(&sksl){
+        Ok(shader) => Ok(shader),
+        Err(why) => cx.throw_error(format!("Could not compile shader: {}", why))
+      }
+    }
+
+    method get_uniforms(mut cx){
+      let this = cx.this();
+      let names = cx.borrow(&this, |this| this.uniform_names() );
+      strings_to_array(&mut cx, &names)
+    }
+
+    method _setUniform(mut cx){
+      let mut this = cx.this();
+      let name = string_arg(&mut cx, 0, "name")?;
+      let values = cx.argument::<JsArray>(1)?.to_vec(&mut cx)?;
+      let values = floats_in(&values);
+      let result = cx.borrow_mut(&mut this, |mut this| this.set_uniform(&name, &values) );
+
+      match result {
+        Ok(()) => Ok(cx.undefined().upcast()),
+        Err(why) => cx.throw_error(why)
+      }
+    }
+
+  }
+}
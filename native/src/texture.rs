@@ -0,0 +1,147 @@
+#![allow(unused_imports)]
+use neon::prelude::*;
+use neon::object::This;
+use skia_safe::{Shader, surfaces, FilterMode, Paint, Color, Rect, ISize, PictureRecorder, TileMode::Repeat};
+
+use crate::utils::*;
+use crate::image::JsImage;
+use crate::context::Dye;
+
+pub struct TextureGenerator{
+  width: i32,
+  height: i32,
+}
+
+impl TextureGenerator{
+  pub fn render(&self, paint:&Paint) -> Option<skia_safe::Image>{
+    let mut surface = surfaces::raster_n32_premul((self.width, self.height))?;
+    surface.canvas().draw_paint(paint);
+    Some(surface.image_snapshot())
+  }
+
+  pub fn noise(&self, fractal:bool, freq:(f32, f32), octaves:usize, seed:f32, tileable:bool) -> Option<Shader>{
+    // stitching the noise to the texture's dimensions lets it repeat seamlessly as a pattern
+    let tile_size = match tileable{
+      true => Some(ISize::new(self.width, self.height)),
+      false => None
+    };
+    match fractal{
+      true => Shader::fractal_perlin_noise(freq, octaves, seed, tile_size),
+      false => Shader::turbulence_perlin_noise(freq, octaves, seed, tile_size)
+    }
+  }
+}
+
+pub fn checkerboard(size:f32, colors:(Color, Color)) -> Option<Shader>{
+  // also used to back transparent pages with a checkerboard 'matte' at export time
+  let mut recorder = PictureRecorder::new();
+  let bounds = Rect::from_wh(size * 2.0, size * 2.0);
+  let canvas = recorder.begin_recording(bounds, None);
+  let mut paint = Paint::default();
+
+  paint.set_color(colors.0);
+  canvas.draw_paint(&paint);
+  paint.set_color(colors.1);
+  canvas.draw_rect(Rect::from_xywh(size, 0.0, size, size), &paint);
+  canvas.draw_rect(Rect::from_xywh(0.0, size, size, size), &paint);
+
+  let pict = recorder.finish_recording_as_picture(Some(&bounds))?;
+  Some(pict.to_shader((Repeat, Repeat), FilterMode::Nearest, None, None))
+}
+
+fn fill_image<'a, T: This+Class>(cx: &mut CallContext<'a, T>, idx:usize, image:Option<skia_safe::Image>) -> JsResult<'a, JsValue>{
+  let mut img = cx.argument::<JsImage>(idx)?;
+  match image{
+    Some(image) => {
+      cx.borrow_mut(&mut img, |mut img| img.image = Some(image) );
+      Ok(cx.undefined().upcast())
+    },
+    None => cx.throw_error("Could not generate texture")
+  }
+}
+
+declare_types! {
+  pub class JsTextureGenerator for TextureGenerator {
+    init(mut cx) {
+      let width = float_arg(&mut cx, 0, "width")?.floor() as i32;
+      let height = float_arg(&mut cx, 1, "height")?.floor() as i32;
+      if width <= 0 || height <= 0 {
+        return cx.throw_range_error("Texture dimensions must be greater than zero")
+      }
+      Ok(TextureGenerator{ width, height })
+    }
+
+    method get_width(mut cx){
+      let this = cx.this();
+      let width = cx.borrow(&this, |this| this.width);
+      Ok(cx.number(width as f64).upcast())
+    }
+
+    method get_height(mut cx){
+      let this = cx.this();
+      let height = cx.borrow(&this, |this| this.height);
+      Ok(cx.number(height as f64).upcast())
+    }
+
+    method _noise(mut cx){
+      let this = cx.this();
+      let kind = string_arg(&mut cx, 1, "type")?;
+      let freq = float_args(&mut cx, 2..4)?;
+      let octaves = float_arg(&mut cx, 4, "octaves")?.max(1.0) as usize;
+      let seed = float_arg(&mut cx, 5, "seed")?;
+      let tileable = bool_arg(&mut cx, 6, "tileable")?;
+
+      let fractal = match kind.as_str(){
+        "fractal" => true,
+        "turbulence" => false,
+        _ => return cx.throw_type_error(format!("Unknown noise type \"{}\" (expected \"fractal\" or \"turbulence\")", kind))
+      };
+
+      let image = cx.borrow(&this, |this| {
+        this.noise(fractal, (freq[0], freq[1]), octaves, seed, tileable).and_then(|shader| {
+          let mut paint = Paint::default();
+          paint.set_shader(shader);
+          this.render(&paint)
+        })
+      });
+      fill_image(&mut cx, 0, image)
+    }
+
+    method _checkerboard(mut cx){
+      let this = cx.this();
+      let size = float_arg(&mut cx, 1, "size")?;
+      let colors = match (color_arg(&mut cx, 2), color_arg(&mut cx, 3)){
+        (Some(a), Some(b)) => (a, b),
+        _ => return cx.throw_type_error("Checkerboards require two valid CSS colors")
+      };
+      if size <= 0.0 {
+        return cx.throw_range_error("Checkerboard squares must be larger than zero")
+      }
+
+      let image = cx.borrow(&this, |this| {
+        checkerboard(size, colors).and_then(|shader| {
+          let mut paint = Paint::default();
+          paint.set_shader(shader);
+          this.render(&paint)
+        })
+      });
+      fill_image(&mut cx, 0, image)
+    }
+
+    method _fill(mut cx){
+      let this = cx.this();
+      let style = cx.argument::<JsValue>(1)?;
+      let dye = match Dye::from_value(&mut cx, style)?{
+        Some(dye) => dye,
+        None => return cx.throw_type_error("Expected a CSS color, CanvasGradient, CanvasPattern, or CanvasShader")
+      };
+
+      let image = cx.borrow(&this, |this| {
+        let mut paint = Paint::default();
+        dye.mix_into(&mut paint, 1.0);
+        this.render(&paint)
+      });
+      fill_image(&mut cx, 0, image)
+    }
+  }
+}
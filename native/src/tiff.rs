@@ -0,0 +1,102 @@
+//
+// A minimal TIFF encoder for the pixel layouts that skia's own codecs can't write (CMYK & high bit-depth RGBA)
+//
+use std::io::Write;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Samples{ CMYK8, RGBA8, RGBA16, RGBA32F }
+
+impl Samples{
+  fn bits(&self) -> u16{
+    match self{
+      Samples::RGBA16 => 16,
+      Samples::RGBA32F => 32,
+      _ => 8
+    }
+  }
+}
+
+// tiff field types
+const SHORT:u16 = 3;
+const LONG:u16 = 4;
+const RATIONAL:u16 = 5;
+const UNDEFINED:u16 = 7;
+
+pub fn encode(pixels:&[u8], width:u32, height:u32, dpi:f32, samples:Samples, icc:Option<&[u8]>) -> Vec<u8>{
+  // a single-strip, deflate-compressed, little-endian tiff (so 16- & 32-bit samples are expected to be in
+  // the native byte order of the platforms skia runs on) with an optional embedded color profile
+  let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+  let strip = match encoder.write_all(pixels).and_then(|_| encoder.finish()){
+    Ok(strip) => strip,
+    Err(_) => return vec![]
+  };
+  let icc = icc.unwrap_or(&[]);
+  let cmyk = samples == Samples::CMYK8;
+  let float = samples == Samples::RGBA32F;
+
+  // lay out the header & directory followed by the out-of-line values they point to
+  let entries = 14 + float as u32 + !icc.is_empty() as u32;
+  let ifd_len = 2 + entries * 12 + 4;
+  let bits_at = 8 + ifd_len;
+  let format_at = bits_at + 8;
+  let res_at = format_at + 8;
+  let icc_at = res_at + 8;
+  let strip_at = icc_at + icc.len() as u32 + (icc.len() % 2) as u32;
+  let dpi = (dpi * 100.0).round() as u32;
+
+  let mut fields:Vec<(u16, u16, u32, u32)> = vec![
+    (256, LONG, 1, width),                    // ImageWidth
+    (257, LONG, 1, height),                   // ImageLength
+    (258, SHORT, 4, bits_at),                 // BitsPerSample
+    (259, SHORT, 1, 8),                       // Compression (deflate)
+    (262, SHORT, 1, if cmyk{ 5 }else{ 2 }),   // PhotometricInterpretation (separated or rgb)
+    (273, LONG, 1, strip_at),                 // StripOffsets
+    (277, SHORT, 1, 4),                       // SamplesPerPixel
+    (278, LONG, 1, height),                   // RowsPerStrip
+    (279, LONG, 1, strip.len() as u32),       // StripByteCounts
+    (282, RATIONAL, 1, res_at),               // XResolution
+    (283, RATIONAL, 1, res_at),               // YResolution
+    (284, SHORT, 1, 1),                       // PlanarConfiguration (chunky)
+    (296, SHORT, 1, 2),                       // ResolutionUnit (inches)
+    match cmyk{
+      true => (332, SHORT, 1, 1),             // InkSet (cmyk)
+      false => (338, SHORT, 1, 2),            // ExtraSamples (unassociated alpha)
+    },
+  ];
+  if float{
+    fields.push((339, SHORT, 4, format_at));  // SampleFormat (ieee floating point)
+  }
+  if !icc.is_empty(){
+    fields.push((34675, UNDEFINED, icc.len() as u32, icc_at)); // ICCProfile
+  }
+
+  let mut tiff:Vec<u8> = Vec::with_capacity(strip_at as usize + strip.len());
+  tiff.extend_from_slice(b"II");
+  tiff.extend_from_slice(&42u16.to_le_bytes());
+  tiff.extend_from_slice(&8u32.to_le_bytes());
+
+  tiff.extend_from_slice(&(entries as u16).to_le_bytes());
+  for (tag, kind, count, value) in fields.iter(){
+    tiff.extend_from_slice(&tag.to_le_bytes());
+    tiff.extend_from_slice(&kind.to_le_bytes());
+    tiff.extend_from_slice(&count.to_le_bytes());
+    match (*kind, *count){
+      // single short values are left-justified within the 4-byte value field
+      (SHORT, 1) => tiff.extend_from_slice(&[(*value as u16).to_le_bytes(), [0, 0]].concat()),
+      _ => tiff.extend_from_slice(&value.to_le_bytes())
+    }
+  }
+  tiff.extend_from_slice(&0u32.to_le_bytes());
+
+  let sample_format:u16 = if float{ 3 }else{ 1 };
+  for _ in 0..4{ tiff.extend_from_slice(&samples.bits().to_le_bytes()) }
+  for _ in 0..4{ tiff.extend_from_slice(&sample_format.to_le_bytes()) }
+  tiff.extend_from_slice(&dpi.to_le_bytes());
+  tiff.extend_from_slice(&100u32.to_le_bytes());
+  tiff.extend_from_slice(icc);
+  tiff.resize(strip_at as usize, 0);
+  tiff.extend_from_slice(&strip);
+  tiff
+}
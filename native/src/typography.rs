@@ -6,18 +6,21 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use std::fs;
 use std::path::Path;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use neon::prelude::*;
 use neon::result::Throw;
 use neon::object::This;
 
-use skia_safe::{FontMgr, FontMetrics, FontArguments, Typeface, Data};
+use skia_safe::{FontMgr, FontMetrics, FontArguments, Typeface, Data, Color, Font, GlyphId, Point};
+use skia_safe::shaper::{Shaper, RunHandler, run_handler::{RunInfo, Buffer}};
 use skia_safe::font_style::{FontStyle, Weight, Width, Slant};
 use skia_safe::font_arguments::{VariationPosition, variation_position::{Coordinate}};
 use skia_safe::textlayout::{FontCollection, TypefaceFontProvider, TextStyle, TextAlign,
-                            TextDirection, ParagraphStyle};
+                            TextDirection, ParagraphStyle, TextDecoration, TextDecorationStyle,
+                            Paragraph, ParagraphBuilder};
 
 use crate::utils::*;
+use crate::woff::decode_font;
 
 pub struct FontSpec{
   families: Vec<String>,
@@ -34,6 +37,11 @@ pub fn font_arg<'a, T: This>(cx: &mut CallContext<'a, T>, idx: usize) -> Result<
   if arg.is_a::<JsNull>(){ return Ok(None) }
 
   let font_desc = cx.argument::<JsObject>(idx as i32)?;
+  Ok(Some(font_spec_in(cx, &font_desc)?))
+}
+
+pub fn font_spec_in<'a, T: This>(cx: &mut CallContext<'a, T>, font_desc: &Handle<JsObject>) -> Result<FontSpec, Throw> {
+  let font_desc = *font_desc;
   let families = strings_at_key(cx, &font_desc, "family")?;
   let canonical = string_for_key(cx, &font_desc, "canonical")?;
   let variant = string_for_key(cx, &font_desc, "variant")?;
@@ -48,7 +56,7 @@ pub fn font_arg<'a, T: This>(cx: &mut CallContext<'a, T>, idx: usize) -> Result<
   let features = font_features(cx, &feat_obj)?;
 
   let style = FontStyle::new(weight, width, slant);
-  Ok(Some(FontSpec{ families, size, leading, style, features, variant, canonical}))
+  Ok(FontSpec{ families, size, leading, style, features, variant, canonical})
 }
 
 pub fn font_features<T: This>(cx: &mut CallContext<'_, T>, obj: &Handle<JsObject>) -> Result<Vec<(String, i32)>, Throw>{
@@ -65,6 +73,64 @@ pub fn font_features<T: This>(cx: &mut CallContext<'_, T>, obj: &Handle<JsObject
   Ok(features)
 }
 
+#[derive(Clone)]
+pub struct DecorationSpec{
+  pub ty: TextDecoration,
+  pub style: TextDecorationStyle,
+  pub color: Option<Color>,
+  pub thickness: Option<f32>,
+  pub canonical: String
+}
+
+#[derive(Copy, Clone)]
+pub struct BackgroundSpec{
+  pub color: Color,
+  pub padding: (f32, f32),
+  pub radius: f32,
+}
+
+impl Default for DecorationSpec{
+  fn default() -> Self{
+    DecorationSpec{
+      ty: TextDecoration::NO_DECORATION, style: TextDecorationStyle::Solid,
+      color: None, thickness: None, canonical: "none".to_string()
+    }
+  }
+}
+
+pub fn decoration_arg<'a, T: This>(cx: &mut CallContext<'a, T>, idx: usize) -> Result<Option<DecorationSpec>, Throw> {
+  let decor_desc = cx.argument::<JsObject>(idx as i32)?;
+  let canonical = string_for_key(cx, &decor_desc, "canonical")?;
+  let lines = strings_at_key(cx, &decor_desc, "line")?;
+  let style = to_decoration_style(&string_for_key(cx, &decor_desc, "style")?);
+  let thickness = decor_desc.get(cx, "thickness")?.downcast::<JsNumber>().ok().map(|n| n.value() as f32);
+  let color = match decor_desc.get(cx, "color")?.downcast::<JsString>(){
+    Ok(css) => match color_in(cx, &css.value()){
+      Some(color) => Some(color),
+      None => return Ok(None) // ignore the whole declaration if the color is invalid
+    },
+    Err(_) => None
+  };
+
+  let ty = lines.iter().fold(TextDecoration::NO_DECORATION, |ty, line| ty | match line.as_str(){
+    "underline" => TextDecoration::UNDERLINE,
+    "overline" => TextDecoration::OVERLINE,
+    "line-through" => TextDecoration::LINE_THROUGH,
+    _ => TextDecoration::NO_DECORATION
+  });
+  Ok(Some(DecorationSpec{ ty, style, color, thickness, canonical }))
+}
+
+pub fn to_decoration_style(style_name:&str) -> TextDecorationStyle{
+  match style_name.to_lowercase().as_str(){
+    "double" => TextDecorationStyle::Double,
+    "dotted" => TextDecorationStyle::Dotted,
+    "dashed" => TextDecorationStyle::Dashed,
+    "wavy" => TextDecorationStyle::Wavy,
+    _ => TextDecorationStyle::Solid
+  }
+}
+
 pub fn typeface_details<'a, T: This>(cx: &mut CallContext<'a, T>, filename:&str, font: &Typeface, alias:Option<String>) -> JsResult<'a, JsObject> {
   let style = font.font_style();
 
@@ -86,6 +152,31 @@ pub fn typeface_details<'a, T: This>(cx: &mut CallContext<'a, T>, filename:&str,
   Ok(dict)
 }
 
+pub fn typeface_instance(font:&Typeface, weight:Option<f32>, style:Option<String>) -> Typeface{
+  // for variable fonts, pin the weight & italic axes to the values specified when the font
+  // was registered (static fonts are always described by their own metadata)
+  let mut coords = vec![];
+  if let Some(params) = font.variation_design_parameters(){
+    for param in params {
+      let chars = vec![param.tag.a(), param.tag.b(), param.tag.c(), param.tag.d()];
+      let value = match (String::from_utf8(chars).unwrap().as_str(), &weight, style.as_deref()){
+        ("wght", Some(weight), _) => *weight,
+        ("ital", _, Some("italic")) => 1.0,
+        ("slnt", _, Some("oblique")) => param.min,
+        _ => continue
+      };
+      coords.push(Coordinate{ axis: param.tag, value: value.max(param.min).min(param.max) });
+    }
+  }
+
+  if coords.is_empty(){
+    return font.clone()
+  }
+  let v_pos = VariationPosition { coordinates: &coords };
+  let args = FontArguments::new().set_variation_design_position(v_pos);
+  font.clone_with_arguments(&args).unwrap_or_else(|| font.clone())
+}
+
 pub fn typeface_wght_range(font:&Typeface) -> Vec<i32>{
   let mut wghts = vec![];
   if let Some(params) = font.variation_design_parameters(){
@@ -157,7 +248,7 @@ pub fn to_text_align(mode_name:&str) -> Option<TextAlign>{
     "left" => TextAlign::Left,
     "right" => TextAlign::Right,
     "center" => TextAlign::Center,
-    // "justify" => TextAlign::Justify,
+    "justify" => TextAlign::Justify,
     "start" => TextAlign::Start,
     "end" => TextAlign::End,
     _ => return None
@@ -176,24 +267,181 @@ pub fn from_text_align(mode:TextAlign) -> String{
   }.to_string()
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum WritingMode{ Horizontal, VerticalRL, VerticalLR }
+
+pub fn to_writing_mode(mode_name:&str) -> Option<WritingMode>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "horizontal-tb" => WritingMode::Horizontal,
+    "vertical-rl" => WritingMode::VerticalRL,
+    "vertical-lr" => WritingMode::VerticalLR,
+    _ => return None
+  };
+  Some(mode)
+}
+
+pub fn from_writing_mode(mode:WritingMode) -> String{
+  match mode{
+    WritingMode::Horizontal => "horizontal-tb",
+    WritingMode::VerticalRL => "vertical-rl",
+    WritingMode::VerticalLR => "vertical-lr",
+  }.to_string()
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TextOrientation{ Mixed, Upright, Sideways }
+
+pub fn to_text_orientation(mode_name:&str) -> Option<TextOrientation>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "mixed" => TextOrientation::Mixed,
+    "upright" => TextOrientation::Upright,
+    "sideways" => TextOrientation::Sideways,
+    _ => return None
+  };
+  Some(mode)
+}
+
+pub fn from_text_orientation(mode:TextOrientation) -> String{
+  match mode{
+    TextOrientation::Mixed => "mixed",
+    TextOrientation::Upright => "upright",
+    TextOrientation::Sideways => "sideways",
+  }.to_string()
+}
+
+pub fn is_upright(c:char) -> bool{
+  // scripts that remain upright in vertical text when the orientation is 'mixed'
+  matches!(c as u32,
+    0x1100..=0x11FF |   // hangul jamo
+    0x2E80..=0xA4CF |   // cjk radicals, punctuation, kana, bopomofo & ideographs
+    0xAC00..=0xD7AF |   // hangul syllables
+    0xF900..=0xFAFF |   // cjk compatibility ideographs
+    0xFE30..=0xFE4F |   // cjk compatibility forms
+    0xFF00..=0xFFEF |   // half- & full-width forms
+    0x1F000..=0x1FAFF | // emoji & symbols
+    0x20000..=0x3FFFF   // cjk extensions
+  )
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TextRendering{ Auto, OptimizeSpeed, OptimizeLegibility, GeometricPrecision }
+
+pub fn to_text_rendering(mode_name:&str) -> Option<TextRendering>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "auto" => TextRendering::Auto,
+    "optimizespeed" => TextRendering::OptimizeSpeed,
+    "optimizelegibility" => TextRendering::OptimizeLegibility,
+    "geometricprecision" => TextRendering::GeometricPrecision,
+    _ => return None
+  };
+  Some(mode)
+}
+
+pub fn from_text_rendering(mode:TextRendering) -> String{
+  match mode{
+    TextRendering::Auto => "auto",
+    TextRendering::OptimizeSpeed => "optimizeSpeed",
+    TextRendering::OptimizeLegibility => "optimizeLegibility",
+    TextRendering::GeometricPrecision => "geometricPrecision",
+  }.to_string()
+}
+
+pub const SOFT_HYPHEN:char = '\u{AD}';
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum LineBreak{ Normal, Strict, Hyphenate }
+
+pub fn to_line_break(mode_name:&str) -> Option<LineBreak>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "normal" => LineBreak::Normal,
+    "strict" => LineBreak::Strict,
+    "hyphenate" => LineBreak::Hyphenate,
+    _ => return None
+  };
+  Some(mode)
+}
+
+pub fn from_line_break(mode:LineBreak) -> String{
+  match mode{
+    LineBreak::Normal => "normal",
+    LineBreak::Strict => "strict",
+    LineBreak::Hyphenate => "hyphenate",
+  }.to_string()
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TextOverflow{ Clip, Ellipsis }
+
+pub fn to_text_overflow(mode_name:&str) -> Option<TextOverflow>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "clip" => TextOverflow::Clip,
+    "ellipsis" => TextOverflow::Ellipsis,
+    _ => return None
+  };
+  Some(mode)
+}
+
+pub fn from_text_overflow(mode:TextOverflow) -> String{
+  match mode{
+    TextOverflow::Clip => "clip",
+    TextOverflow::Ellipsis => "ellipsis",
+  }.to_string()
+}
+
+pub fn to_text_direction(mode_name:&str) -> Option<Option<TextDirection>>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "ltr" => Some(TextDirection::LTR),
+    "rtl" => Some(TextDirection::RTL),
+    "inherit" => None,
+    _ => return None
+  };
+  Some(mode)
+}
+
+pub fn from_text_direction(mode:Option<TextDirection>) -> String{
+  match mode{
+    Some(TextDirection::LTR) => "ltr",
+    Some(TextDirection::RTL) => "rtl",
+    None => "inherit"
+  }.to_string()
+}
+
+pub fn base_direction<'a>(text:impl Iterator<Item=&'a str>) -> TextDirection{
+  // use the first ‘strong’ character to determine the paragraph direction (defaulting to left-to-right)
+  for c in text.flat_map(|s| s.chars()){
+    if matches!(c as u32, 0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF){
+      return TextDirection::RTL
+    }else if c.is_alphabetic(){
+      return TextDirection::LTR
+    }
+  }
+  TextDirection::LTR
+}
+
 pub fn get_alignment_factor(graf_style:&ParagraphStyle) -> f32 {
   match graf_style.text_direction() {
     TextDirection::LTR => match graf_style.text_align() {
       TextAlign::Left | TextAlign::Start => 0.0,
       TextAlign::Right | TextAlign::End => -1.0,
       TextAlign::Center => -0.5,
-      TextAlign::Justify => 0.0 // unsupported
+      TextAlign::Justify => 0.0 // the final line is start-aligned
     },
     TextDirection::RTL => match graf_style.text_align() {
       TextAlign::Left | TextAlign::End => 0.0,
       TextAlign::Right | TextAlign::Start => -1.0,
       TextAlign::Center => -0.5,
-      TextAlign::Justify => 0.0 // unsupported
+      TextAlign::Justify => -1.0
     }
   }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
+pub enum LineHeight{ Normal, Scale(f32), Pixels(f32) }
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TabSize{ Spaces(f32), Pixels(f32) }
+
+#[derive(Copy, Clone, PartialEq)]
 pub enum Baseline{ Top, Hanging, Middle, Alphabetic, Ideographic, Bottom }
 
 pub fn to_text_baseline(mode_name:&str) -> Option<Baseline>{
@@ -244,23 +492,178 @@ impl CollectionKey{
   }
 }
 
+// the text & styles (along with the context's typographic settings) that determine a paragraph's layout
+#[derive(PartialEq)]
+pub struct TypesetKey{
+  pub runs: Vec<(String, TextStyle)>,
+  pub width: f32,
+  pub graf_style: ParagraphStyle,
+  pub features: Vec<(String, i32)>,
+  pub fallbacks: Vec<String>,
+  pub line_height: LineHeight,
+  pub tab_size: TabSize,
+  pub tab_stops: Vec<f32>,
+  pub indent: f32,
+  pub line_break: LineBreak,
+  pub tracking: i32,
+  pub wrap: bool,
+  pub overflow: TextOverflow,
+  pub max_lines: Option<usize>,
+  pub rendering: TextRendering,
+  pub hinting: bool,
+}
+
+// a laid-out paragraph along with the hyphens drawn after any of its lines that break at a soft-hyphen
+pub type Typeset = (Paragraph, Vec<(usize, Point, Paragraph)>);
+
+const PARAGRAPH_CACHE_SIZE:usize = 128;
+
+#[derive(Default)]
+pub struct ParagraphCache{
+  // a least-recently-used list (with the most recent entries at the end)
+  entries: VecDeque<(TypesetKey, Typeset)>,
+}
+
+impl ParagraphCache{
+  pub fn take(&mut self, key:&TypesetKey) -> Option<Typeset>{
+    // remove the entry while it's in use (the caller is expected to insert it again when done)
+    let idx = self.entries.iter().position(|(k, _)| k == key)?;
+    self.entries.remove(idx).map(|(_, typeset)| typeset)
+  }
+
+  pub fn insert(&mut self, key:TypesetKey, typeset:Typeset){
+    if self.entries.len() >= PARAGRAPH_CACHE_SIZE{
+      self.entries.pop_front();
+    }
+    self.entries.push_back((key, typeset));
+  }
+
+  pub fn clear(&mut self){
+    self.entries.clear();
+  }
+}
+
+// platform emoji fonts, in order of preference
+const EMOJI_FAMILIES:[&str; 6] = [
+  "Apple Color Emoji", "Segoe UI Emoji", "Noto Color Emoji", "Twemoji Mozilla", "JoyPixels", "EmojiOne Color"
+];
+
+pub fn system_emoji_family(font_mgr:&FontMgr) -> Option<String>{
+  EMOJI_FAMILIES.iter()
+    .find(|family| font_mgr.match_family(family).count() > 0)
+    .map(|family| family.to_string())
+}
+
+// a sequence of shaped glyphs (positioned relative to the start of the text) and the font they use
+pub struct GlyphRun{
+  pub font: Font,
+  pub glyphs: Vec<GlyphId>,
+  pub positions: Vec<Point>,
+  pub clusters: Vec<u32>,
+}
+
+#[derive(Default)]
+struct GlyphCollector{
+  runs: Vec<GlyphRun>,
+  origin: Point,
+}
+
+impl RunHandler for GlyphCollector{
+  fn begin_line(&mut self){}
+  fn run_info(&mut self, _info:&RunInfo){}
+  fn commit_run_info(&mut self){}
+  fn commit_line(&mut self){}
+
+  fn run_buffer(&mut self, info:&RunInfo) -> Buffer{
+    let count = info.glyph_count;
+    let origin = self.origin;
+    self.runs.push(GlyphRun{
+      font: info.font.clone(), glyphs: vec![0; count], positions: vec![Point::default(); count], clusters: vec![0; count]
+    });
+    let run = self.runs.last_mut().unwrap();
+    Buffer{ glyphs:&mut run.glyphs, positions:&mut run.positions, offsets:None, clusters:Some(&mut run.clusters), point:origin }
+  }
+
+  fn commit_run_buffer(&mut self, info:&RunInfo){
+    self.origin.x += info.advance.x;
+  }
+}
+
+pub fn shape_glyphs(text:&str, font:&Font, font_mgr:&FontMgr, ltr:bool) -> Vec<GlyphRun>{
+  // lay out a single line of text (substituting fallback fonts for any glyphs the font lacks)
+  let mut collector = GlyphCollector::default();
+  Shaper::new(font_mgr.clone()).shape(text, font, ltr, f32::MAX, &mut collector);
+  collector.runs
+}
+
+pub fn is_color_font(font:&Typeface) -> bool{
+  // bitmap (CBDT & sbix) and vector (COLR) color glyph tables
+  [b"CBDT", b"sbix", b"COLR"].iter().any(|tag|
+    font.get_table_size(u32::from_be_bytes(**tag)).unwrap_or(0) > 0
+  )
+}
+
 pub struct FontLibrary{
   pub fonts: Vec<(Typeface, Option<String>)>,
   pub collection: FontCollection,
+  pub emoji: Option<String>,
+  pub paragraph_cache: ParagraphCache,
+  pub system_fonts: bool,
+  files: Vec<(Typeface, String)>,
+  font_mgr: FontMgr,
   collection_cache: HashMap<CollectionKey, FontCollection>,
+  fallback_cache: HashMap<(char, i32, Slant), Option<String>>,
 }
 
 impl Default for FontLibrary{
   fn default() -> Self{
-    let mut library = FontCollection::new();
-    library.set_default_font_manager(FontMgr::new(), None);
-    FontLibrary{ collection: library, collection_cache:HashMap::new(), fonts:vec![] }
+    FontLibrary::new(true)
   }
 }
 
 impl FontLibrary{
+  pub fn new(system_fonts:bool) -> Self{
+    // without system fonts, only typefaces added via use() are matched (and no fallback fonts are consulted)
+    // (the platform's font manager is only instantiated once since scanning the system fonts is slow)
+    let mut library = FontCollection::new();
+    let (font_mgr, emoji) = match system_fonts{
+      true => {
+        let font_mgr = FontMgr::new();
+        library.set_default_font_manager(font_mgr.clone(), None);
+        let emoji = system_emoji_family(&font_mgr);
+        (font_mgr, emoji)
+      },
+      false => (TypefaceFontProvider::new().into(), None)
+    };
+    FontLibrary{
+      collection: library, collection_cache:HashMap::new(), paragraph_cache:ParagraphCache::default(),
+      fallback_cache:HashMap::new(), files:vec![], fonts:vec![], font_mgr, emoji, system_fonts
+    }
+  }
+
+  pub fn font_mgr(&self) -> FontMgr{
+    // the platform's font manager or, when system fonts are disabled, one with no families at all
+    self.font_mgr.clone()
+  }
+
+  pub fn font_for(&mut self, style:&TextStyle) -> Option<Font>{
+    // the primary font a style's text will be shaped with
+    let families = style.font_families();
+    let families:Vec<&str> = families.iter().collect();
+    let typeface = self.collect_fonts(style).find_typefaces(&families, style.font_style()).into_iter().next()?;
+    Some(Font::from_typeface(typeface, style.font_size()))
+  }
+
+  pub fn fallback_family(&mut self, c:char, style:FontStyle) -> Option<String>{
+    // the system font that will be substituted for a character none of the requested families contain
+    let font_mgr = &self.font_mgr;
+    self.fallback_cache.entry((c, *style.weight(), style.slant())).or_insert_with(||
+      font_mgr.match_family_style_character("", style, &[], c as i32).map(|face| face.family_name())
+    ).clone()
+  }
+
   fn families(&self) -> Vec<String>{
-    let font_mgr = FontMgr::new();
+    let font_mgr = self.font_mgr();
     let count = font_mgr.count_families();
     let mut names:Vec<String> = (0..count).map(|i| font_mgr.family_name(i)).collect();
     for (font, alias) in &self.fonts {
@@ -274,13 +677,40 @@ impl FontLibrary{
     names
   }
 
+  fn file_for(&self, font:&Typeface) -> Option<String>{
+    // only the paths of fonts loaded via FontLibrary.use() are known (system fonts are opaque)
+    self.files.iter().find_map(|(face, file)|
+      if Typeface::equal(font, face){ Some(file.clone()) }else{ None }
+    )
+  }
+
+  fn family_files(&self, family:&str) -> Vec<String>{
+    let mut files:Vec<String> = self.fonts.iter().filter(|(font, alias)|
+      alias.clone().unwrap_or_else(|| font.family_name()) == family
+    ).filter_map(|(font, _)| self.file_for(font)).collect();
+    files.sort();
+    files.dedup();
+    files
+  }
+
+  fn match_font(&mut self, spec:&FontSpec) -> Option<(Typeface, Option<String>, Option<String>)>{
+    // find the face that text in the given font would be drawn with (along with its alias & file path)
+    let families:Vec<&str> = spec.families.iter().map(|s| s.as_str()).collect();
+    let font = self.collection.find_typefaces(&families, spec.style).into_iter().next()?;
+    let alias = self.fonts.iter().find_map(|(face, alias)|
+      if Typeface::equal(&font, face){ alias.clone() }else{ None }
+    );
+    let file = self.file_for(&font);
+    Some((font, alias, file))
+  }
+
   fn family_details(&self, family:&str) -> (Vec<f32>, Vec<String>, Vec<String>){
     // merge the system fonts and our dynamically added fonts into one list of FontStyles
     let mut dynamic = TypefaceFontProvider::new();
     for (font, alias) in &self.fonts{
-      dynamic.register_typeface(font.clone(), alias.clone());
+      dynamic.register_typeface(font.clone(), alias.as_deref());
     }
-    let std_mgr = FontMgr::new();
+    let std_mgr = self.font_mgr();
     let dyn_mgr:FontMgr = dynamic.into();
     let mut std_set = std_mgr.match_family(&family);
     let mut dyn_set = dyn_mgr.match_family(&family);
@@ -291,7 +721,7 @@ impl FontLibrary{
     // set up a collection to query for variable fonts who specify their weights
     // via the 'wght' axis rather than through distinct files with different FontStyles
     let mut var_fc = FontCollection::new();
-    var_fc.set_default_font_manager(FontMgr::new(), None);
+    var_fc.set_default_font_manager(self.font_mgr(), None);
     var_fc.set_asset_font_manager(Some(dyn_mgr));
 
     // pull style values out of each matching font
@@ -319,16 +749,24 @@ impl FontLibrary{
     (weights, widths, styles)
   }
 
-  fn add_typeface(&mut self, font:Typeface, alias:Option<String>){
+  fn add_typeface(&mut self, font:Typeface, alias:Option<String>, file:&str){
+    // user-supplied color fonts take precedence over the system's emoji font
+    if is_color_font(&font){
+      self.emoji = alias.clone().or_else(|| Some(font.family_name()));
+    }
+    if !file.is_empty(){
+      self.files.push((font.clone(), file.to_string()));
+    }
     self.fonts.push((font, alias));
 
     let mut assets = TypefaceFontProvider::new();
     for (font, alias) in &self.fonts {
-      assets.register_typeface(font.clone(), alias.as_ref());
+      assets.register_typeface(font.clone(), alias.as_deref());
     }
 
     self.collection.set_asset_font_manager(Some(assets.into()));
     self.collection_cache.drain();
+    self.paragraph_cache.clear();
   }
 
   pub fn update_style(&mut self, orig_style:&TextStyle, spec: &FontSpec) -> Option<TextStyle>{
@@ -400,10 +838,10 @@ impl FontLibrary{
             let face = font.clone_with_arguments(&args).unwrap();
 
             let mut dynamic = TypefaceFontProvider::new();
-            dynamic.register_typeface(face, alias);
+            dynamic.register_typeface(face, alias.as_deref());
 
             let mut collection = FontCollection::new();
-            collection.set_default_font_manager(FontMgr::new(), None);
+            collection.set_default_font_manager(self.font_mgr(), None);
             collection.set_asset_font_manager(Some(dynamic.into()));
             self.collection_cache.insert(key, collection.clone());
             return collection
@@ -416,41 +854,90 @@ impl FontLibrary{
     self.collection.clone()
   }
 
+  pub fn hyphen(&mut self, style: &TextStyle) -> Paragraph {
+    // typeset the visible hyphen drawn at the end of lines broken at a soft-hyphen
+    let collection = self.collect_fonts(style);
+    let mut paragraph_builder = ParagraphBuilder::new(&ParagraphStyle::new(), collection);
+    paragraph_builder.push_style(style);
+    paragraph_builder.add_text("-");
+    let mut paragraph = paragraph_builder.build();
+    paragraph.layout(f32::MAX);
+    paragraph
+  }
+
 }
 
 
+thread_local!(
+  // the system fonts are scanned once per thread and shared by all the canvases created on it
+  static THREAD_LIBRARY: Rc<RefCell<FontLibrary>> = Rc::new(RefCell::new(FontLibrary::default()));
+
+  // font files are always parsed by the platform's font manager (even when its families are disabled)
+  static FONT_LOADER: FontMgr = FontMgr::new();
+);
+
 pub struct SharedFontLibrary{
-  // in practice the FontLibrary will always be a singleton, so base the js object
-  // on a refcell that can be borrowed by all the Context2Ds
+  // the js object is a handle to either the thread's global library or an isolated one
+  // (which can be used to give particular canvases their own set of fonts)
   pub library:Rc<RefCell<FontLibrary>>
 }
 
 impl Default for SharedFontLibrary{
   fn default() -> Self{
+    SharedFontLibrary{ library: THREAD_LIBRARY.with(Rc::clone) }
+  }
+}
+
+impl SharedFontLibrary{
+  pub fn isolated() -> Self{
     SharedFontLibrary{ library: Rc::new(RefCell::new(FontLibrary::default())) }
   }
 }
 
 declare_types! {
   pub class JsFontLibrary for SharedFontLibrary {
-    init(_) {
-      Ok( SharedFontLibrary::default() )
+    init(mut cx) {
+      match bool_arg_or(&mut cx, 0, false){
+        true => Ok( SharedFontLibrary::isolated() ),
+        false => Ok( SharedFontLibrary::default() )
+      }
     }
 
     method get_families(mut cx){
       let this = cx.this();
       let families = cx.borrow(&this, |this| {
-        let library = this.library.borrow_mut();
+        let library = this.library.borrow();
         library.families()
       });
       Ok(strings_to_array(&mut cx, &families)?)
     }
 
+    method get_emoji(mut cx){
+      let this = cx.this();
+      let family = cx.borrow(&this, |this| this.library.borrow().emoji.clone() );
+      match family{
+        Some(name) => Ok(cx.string(name).upcast()),
+        None => Ok(cx.null().upcast())
+      }
+    }
+
+    method set_emoji(mut cx){
+      let this = cx.this();
+      let family = opt_string_arg(&mut cx, 0);
+      cx.borrow(&this, |this| {
+        // cached layouts may have used the previous emoji font as a fallback
+        let mut library = this.library.borrow_mut();
+        library.emoji = family;
+        library.paragraph_cache.clear();
+      });
+      Ok(cx.undefined().upcast())
+    }
+
     method has(mut cx){
       let this = cx.this();
       let family = string_arg(&mut cx, 0, "familyName")?;
       let found = cx.borrow(&this, |this| {
-        let library = this.library.borrow_mut();
+        let library = this.library.borrow();
         library.families().contains(&family)
       });
 
@@ -461,7 +948,7 @@ declare_types! {
       let this = cx.this();
       let family = cx.argument::<JsString>(0)?.value();
       let (weights, widths, styles) = cx.borrow(&this, |this| {
-        let library = this.library.borrow_mut();
+        let library = this.library.borrow();
         library.family_details(&family)
       });
 
@@ -469,6 +956,8 @@ declare_types! {
         return Ok(cx.undefined().upcast())
       }
 
+      let files = cx.borrow(&this, |this| this.library.borrow().family_files(&family) );
+      let files = strings_to_array(&mut cx, &files)?;
       let name = cx.string(family);
       let weights = floats_to_array(&mut cx, &weights)?;
       let widths = strings_to_array(&mut cx, &widths)?;
@@ -479,39 +968,99 @@ declare_types! {
       let attr = cx.string("weights"); details.set(&mut cx, attr, weights)?;
       let attr = cx.string("widths"); details.set(&mut cx, attr, widths)?;
       let attr = cx.string("styles"); details.set(&mut cx, attr, styles)?;
+      let attr = cx.string("files"); details.set(&mut cx, attr, files)?;
 
       Ok(details.upcast())
     }
 
+    method get_systemFonts(mut cx){
+      let this = cx.this();
+      let enabled = cx.borrow(&this, |this| this.library.borrow().system_fonts );
+      Ok(cx.boolean(enabled).upcast())
+    }
+
+    method _reset(mut cx){
+      let this = cx.this();
+      let system_fonts = bool_arg(&mut cx, 0, "systemFonts")?;
+      cx.borrow(&this, |this| {
+        // canvases hold a reference to the library rather than a copy, so they pick up the change too
+        let mut library = this.library.borrow_mut();
+        *library = FontLibrary::new(system_fonts);
+      });
+      Ok(cx.undefined().upcast())
+    }
+
+    method _match(mut cx){
+      let this = cx.this();
+      let font_desc = cx.argument::<JsObject>(0)?;
+      let spec = font_spec_in(&mut cx, &font_desc)?;
+      let found = cx.borrow(&this, |this| this.library.borrow_mut().match_font(&spec) );
+
+      match found{
+        Some((font, alias, file)) => {
+          let file = file.unwrap_or_default();
+          let details = typeface_details(&mut cx, &file, &font, alias)?;
+          if file.is_empty(){
+            let null = cx.null();
+            details.set(&mut cx, "file", null)?;
+          }
+          Ok(details.upcast())
+        },
+        None => Ok(cx.null().upcast())
+      }
+    }
+
     method _addFamily(mut cx){
       let this = cx.this();
       let alias = opt_string_arg(&mut cx, 0);
-      let filenames = cx.argument::<JsArray>(1)?.to_vec(&mut cx)?;
-      let results = JsArray::new(&mut cx, filenames.len() as u32);
-
-      for (i, filename) in strings_in(&filenames).iter().enumerate(){
-        let path = Path::new(&filename);
-        let typeface = match fs::read(path){
-          Err(why) => {
-            return cx.throw_error(format!("{}: \"{}\"", why, path.display()))
+      let sources = cx.argument::<JsArray>(1)?.to_vec(&mut cx)?;
+      let results = JsArray::new(&mut cx, sources.len() as u32);
+
+      for (i, source) in sources.iter().enumerate(){
+        // fonts are passed as either file paths or {data, file, family, weight, style} descriptors
+        let (filename, bytes, family, weight, style) = match source.downcast::<JsString>(){
+          Ok(filename) => {
+            let filename = filename.value();
+            match fs::read(Path::new(&filename)){
+              Ok(bytes) => (filename, bytes, None, None, None),
+              Err(why) => return cx.throw_error(format!("{}: \"{}\"", why, filename))
+            }
           },
-          Ok(bytes) => Typeface::from_data(Data::new_copy(&bytes), None)
+          Err(_) => {
+            let desc = source.downcast_or_throw::<JsObject, _>(&mut cx)?;
+            let buffer = desc.get(&mut cx, "data")?.downcast_or_throw::<JsBuffer, _>(&mut cx)?;
+            let bytes = cx.borrow(&buffer, |buf_data| buf_data.as_slice::<u8>().to_vec());
+            let filename = string_for_key(&mut cx, &desc, "file")?;
+            let family = desc.get(&mut cx, "family")?.downcast::<JsString>().ok().map(|s| s.value());
+            let weight = desc.get(&mut cx, "weight")?.downcast::<JsNumber>().ok().map(|n| n.value() as f32);
+            let style = desc.get(&mut cx, "style")?.downcast::<JsString>().ok().map(|s| s.value());
+            (filename, bytes, family, weight, style)
+          }
         };
 
-        match typeface {
+        // webfonts are unpacked into plain TrueType/OpenType data first
+        let bytes = match decode_font(bytes){
+          Ok(bytes) => bytes,
+          Err(why) => return cx.throw_error(format!("{} in {}", why, filename))
+        };
+
+        let family = family.or_else(|| alias.clone());
+        match FONT_LOADER.with(|loader| loader.new_from_data(&bytes, None)) {
           Some(font) => {
+            let font = typeface_instance(&font, weight, style);
+
             // add family/weight/width/slant details to return value
-            let details = typeface_details(&mut cx, &filename, &font, alias.clone())?;
+            let details = typeface_details(&mut cx, &filename, &font, family.clone())?;
             results.set(&mut cx, i as u32, details)?;
 
             // register the typeface
             cx.borrow(&this, |this| {
               let mut library = this.library.borrow_mut();
-              library.add_typeface(font, alias.clone());
+              library.add_typeface(font, family, &filename);
             });
           },
           None => {
-            return cx.throw_error(format!("Could not decode font data in {}", path.display()))
+            return cx.throw_error(format!("Could not decode font data in {}", filename))
           }
         }
       }
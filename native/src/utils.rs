@@ -8,7 +8,6 @@ use core::ops::Range;
 use neon::prelude::*;
 use neon::result::Throw;
 use neon::object::This;
-use css_color::Rgba;
 use skia_safe::{
   Path, Matrix, Point, Color, Color4f, RGB, Rect, FontArguments,
   font_style::{FontStyle, Weight, Width, Slant},
@@ -16,6 +15,7 @@ use skia_safe::{
 };
 
 use crate::path::{JsPath2D};
+use crate::color::{parse_color, in_srgb_gamut};
 
 //
 // meta-helpers
@@ -195,6 +195,20 @@ pub fn floats_in(vals: &[Handle<JsValue>]) -> Vec<f32>{
       .collect()
 }
 
+pub fn float_buffer_arg<T: This>(cx: &mut CallContext<'_, T>, idx: usize) -> Result<Vec<f32>, Throw>{
+  // reads a Float32Array that has been passed in as a Buffer view over its bytes
+  let buffer = cx.argument::<JsBuffer>(idx as i32)?;
+  let nums = cx.borrow(&buffer, |data| {
+    data.as_slice::<u8>().chunks_exact(4)
+        .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect::<Vec<f32>>()
+  });
+  match nums.iter().all(|n| n.is_finite()){
+    true => Ok(nums),
+    false => cx.throw_type_error("Coordinates must be finite numbers")
+  }
+}
+
 pub fn opt_float_arg<T: This>(cx: &mut CallContext<'_, T>, idx: usize) -> Option<f32>{
   match cx.argument_opt(idx as i32) {
     Some(arg) => match arg.downcast::<JsNumber>() {
@@ -260,15 +274,20 @@ pub fn float_args<T: This>(cx: &mut CallContext<'_, T>, rng: Range<usize>) -> Re
 //
 
 
+pub fn color4f_in<'a, T: This>(cx: &mut CallContext<'a, T>, css:&str) -> Option<Color4f> {
+  // colors outside of the sRGB gamut are represented by extended-range components
+  parse_color(css)
+}
+
+pub fn color4f_arg<'a, T: This>(cx: &mut CallContext<'a, T>, idx: usize) -> Option<Color4f> {
+  match opt_string_arg(cx, idx){
+    Some(css) => color4f_in(cx, &css),
+    None => None
+  }
+}
+
 pub fn color_in<'a, T: This>(cx: &mut CallContext<'a, T>, css:&str) -> Option<Color> {
-  css.parse::<Rgba>().ok().map(|Rgba{red, green, blue, alpha}|
-    Color::from_argb(
-      (alpha*255.0).round() as u8,
-      (red*255.0).round() as u8,
-      (green*255.0).round() as u8,
-      (blue*255.0).round() as u8,
-    )
-  )
+  color4f_in(cx, css).map(|color| color.to_color())
 }
 
 pub fn color_arg<'a, T: This>(cx: &mut CallContext<'a, T>, idx: usize) -> Option<Color> {
@@ -291,6 +310,19 @@ pub fn color_to_css<'a, T: This+Class>(cx: &mut CallContext<'a, T>, color:&Color
   Ok(cx.string(css).upcast())
 }
 
+pub fn color4f_to_css<'a, T: This+Class>(cx: &mut CallContext<'a, T>, color:&Color4f) -> JsResult<'a, JsValue> {
+  // wide-gamut colors are serialized with extended-range sRGB components to avoid losing information
+  if in_srgb_gamut(color){
+    return color_to_css(cx, &color.to_color())
+  }
+  let fmt = |c:f32| format!("{:.4}", c).trim_end_matches('0').trim_end_matches('.').to_string();
+  let css = match color.a < 1.0 {
+    true => format!("color(srgb {} {} {} / {})", fmt(color.r), fmt(color.g), fmt(color.b), fmt(color.a)),
+    false => format!("color(srgb {} {} {})", fmt(color.r), fmt(color.g), fmt(color.b))
+  };
+  Ok(cx.string(css).upcast())
+}
+
 //
 // Matrices
 //
@@ -370,6 +402,10 @@ pub fn path2d_arg_opt<'a, T: This+Class>(cx: &mut CallContext<'a, T>, idx:usize)
 pub enum FilterSpec{
   Plain{name:String, value:f32},
   Shadow{offset:Point, blur:f32, color:Color},
+  Noise{name:String, freq:(f32, f32), octaves:usize, seed:f32},
+  Displace{scale:f32, freq:(f32, f32), octaves:usize, seed:f32},
+  Matrix{values:[f32; 20]},
+  Lighting{specular:bool, light:String, coords:Vec<f32>, surface:f32, color:Color},
 }
 
 pub fn filter_arg<'a, T: This>(cx: &mut CallContext<'a, T>, idx: usize) -> Result<(String, Vec<FilterSpec>), Throw> {
@@ -391,6 +427,43 @@ pub fn filter_arg<'a, T: This>(cx: &mut CallContext<'a, T>, idx: usize) -> Resul
           });
         }
       },
+      "turbulence" | "fractal-noise" => {
+        let values = obj.get(cx, key)?.downcast_or_throw::<JsArray, _>(cx)?;
+        if let [fx, fy, octaves, seed] = floats_in(&values.to_vec(cx)?).as_slice(){
+          filters.push(FilterSpec::Noise{
+            name:name.to_string(), freq:(*fx, *fy), octaves:*octaves as usize, seed:*seed
+          });
+        }
+      },
+      "displace" => {
+        let values = obj.get(cx, key)?.downcast_or_throw::<JsArray, _>(cx)?;
+        if let [scale, fx, fy, octaves, seed] = floats_in(&values.to_vec(cx)?).as_slice(){
+          filters.push(FilterSpec::Displace{
+            scale:*scale, freq:(*fx, *fy), octaves:*octaves as usize, seed:*seed
+          });
+        }
+      },
+      "color-matrix" => {
+        let values = obj.get(cx, key)?.downcast_or_throw::<JsArray, _>(cx)?;
+        let mut matrix = [0.0; 20];
+        let values = floats_in(&values.to_vec(cx)?);
+        if values.len() == matrix.len(){
+          matrix.copy_from_slice(&values);
+          filters.push(FilterSpec::Matrix{values:matrix});
+        }
+      },
+      "diffuse-lighting" | "specular-lighting" => {
+        let values = obj.get(cx, key)?.downcast_or_throw::<JsArray, _>(cx)?.to_vec(cx)?;
+        if let [light, .., color_str] = strings_in(&values).as_slice(){
+          if let Some(color) = color_in(cx, color_str) {
+            let mut coords = floats_in(&values);
+            let surface = coords.pop().unwrap_or(1.0);
+            filters.push(FilterSpec::Lighting{
+              specular:name == "specular-lighting", light:light.to_string(), coords, surface, color
+            });
+          }
+        }
+      },
       _ => {
         let value = obj.get(cx, key)?.downcast_or_throw::<JsNumber, _>(cx)?.value();
         filters.push(FilterSpec::Plain{
@@ -406,6 +479,17 @@ pub fn filter_arg<'a, T: This>(cx: &mut CallContext<'a, T>, idx: usize) -> Resul
 // Skia Enums
 //
 
+use skia_safe::canvas::PointMode;
+pub fn to_point_mode(mode_name:&str) -> Option<PointMode>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "points" => PointMode::Points,
+    "lines" => PointMode::Lines,
+    "polygon" => PointMode::Polygon,
+    _ => return None
+  };
+  Some(mode)
+}
+
 use skia_safe::{TileMode};
 pub fn to_tile_mode(mode_name:&str) -> Option<TileMode>{
   let mode = match mode_name.to_lowercase().as_str(){
@@ -418,12 +502,159 @@ pub fn to_tile_mode(mode_name:&str) -> Option<TileMode>{
   Some(mode)
 }
 
-use skia_safe::{FilterQuality};
+use skia_safe::{ColorType};
+pub fn to_color_type(type_name:&str) -> Option<ColorType>{
+  let color_type = match type_name.to_lowercase().as_str(){
+    "rgba" => ColorType::RGBA8888,
+    "bgra" => ColorType::BGRA8888,
+    "rgb565" => ColorType::RGB565,
+    "gray" => ColorType::Gray8,
+    "alpha" => ColorType::Alpha8,
+    "rgba16" => ColorType::R16G16B16A16UNorm,
+    "rgba32f" => ColorType::RGBAF32,
+    _ => return None
+  };
+  Some(color_type)
+}
+
+pub fn from_color_type(color_type:ColorType) -> String{
+  match color_type{
+    ColorType::BGRA8888 => "bgra",
+    ColorType::RGB565 => "rgb565",
+    ColorType::Gray8 => "gray",
+    ColorType::Alpha8 => "alpha",
+    ColorType::R16G16B16A16UNorm => "rgba16",
+    ColorType::RGBAF32 => "rgba32f",
+    _ => "rgba"
+  }.to_string()
+}
+
+use skia_safe::{ColorSpace, Data};
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WorkingSpace{ SRGB, DisplayP3, LinearSRGB }
+
+pub fn to_working_space(space_name:&str) -> Option<WorkingSpace>{
+  let space = match space_name.to_lowercase().as_str(){
+    "srgb" => WorkingSpace::SRGB,
+    "display-p3" => WorkingSpace::DisplayP3,
+    "srgb-linear" | "linear-srgb" => WorkingSpace::LinearSRGB,
+    _ => return None
+  };
+  Some(space)
+}
+
+pub fn from_working_space(space:WorkingSpace) -> String{
+  match space{
+    WorkingSpace::SRGB => "srgb",
+    WorkingSpace::DisplayP3 => "display-p3",
+    WorkingSpace::LinearSRGB => "srgb-linear",
+  }.to_string()
+}
+
+fn display_p3() -> ColorSpace{
+  // skia-safe has no binding for SkColorSpace::MakeRGB, so build P3 from its serialized form:
+  // a 4-byte version header, the (sRGB) transfer function, and the gamut's to-XYZ-D50 matrix
+  let transfer_fn = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045, 0.0, 0.0];
+  let to_xyz_d50 = [
+     0.515102,   0.291965,  0.157153,
+     0.241182,   0.692236,  0.0665819,
+    -0.00104941, 0.0418818, 0.784378,
+  ];
+  let mut bytes:Vec<u8> = vec![1, 0, 0, 0];
+  for val in transfer_fn.iter().chain(to_xyz_d50.iter()){
+    bytes.extend_from_slice(&(*val as f32).to_ne_bytes());
+  }
+  ColorSpace::deserialize(Data::new_copy(&bytes))
+}
+
+impl WorkingSpace{
+  pub fn color_space(&self) -> Option<ColorSpace>{
+    // the default sRGB space leaves surfaces untagged so drawing behaves exactly as before
+    match self{
+      WorkingSpace::SRGB => None,
+      WorkingSpace::DisplayP3 => Some(display_p3()),
+      WorkingSpace::LinearSRGB => Some(ColorSpace::new_srgb_linear()),
+    }
+  }
+
+  pub fn pixel_color_space(&self) -> Option<ColorSpace>{
+    // ImageData is either sRGB or P3 (8-bit linear values would band badly)
+    match self{
+      WorkingSpace::LinearSRGB => Some(ColorSpace::new_srgb()),
+      _ => self.color_space()
+    }
+  }
+
+  pub fn color_type(&self) -> ColorType{
+    match self{
+      WorkingSpace::LinearSRGB => ColorType::RGBAF16,
+      _ => ColorType::N32
+    }
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PixelFormat{ RGBA8, RGBA16, RGBA16F }
+
+pub fn to_pixel_format(format_name:&str) -> Option<PixelFormat>{
+  let format = match format_name.to_lowercase().as_str(){
+    "rgba8" => PixelFormat::RGBA8,
+    "rgba16" => PixelFormat::RGBA16,
+    "rgba16f" => PixelFormat::RGBA16F,
+    _ => return None
+  };
+  Some(format)
+}
+
+pub fn from_pixel_format(format:PixelFormat) -> String{
+  match format{
+    PixelFormat::RGBA8 => "rgba8",
+    PixelFormat::RGBA16 => "rgba16",
+    PixelFormat::RGBA16F => "rgba16f",
+  }.to_string()
+}
+
+impl PixelFormat{
+  pub fn color_type(&self, space:WorkingSpace) -> ColorType{
+    // the bit depth of the surfaces a page is rasterized into
+    match self{
+      PixelFormat::RGBA8 => space.color_type(),
+      PixelFormat::RGBA16 => ColorType::R16G16B16A16UNorm,
+      PixelFormat::RGBA16F => ColorType::RGBAF16,
+    }
+  }
+
+  pub fn data_type(&self) -> ColorType{
+    // js has no 16-bit float arrays, so half-float pages are read back as 32-bit floats
+    match self{
+      PixelFormat::RGBA8 => ColorType::RGBA8888,
+      PixelFormat::RGBA16 => ColorType::R16G16B16A16UNorm,
+      PixelFormat::RGBA16F => ColorType::RGBAF32,
+    }
+  }
+}
+
+use skia_safe::{SamplingOptions, FilterMode, MipmapMode, CubicResampler};
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FilterQuality{ None, Low, Medium, High }
+
 pub fn to_filter_quality(mode_name:&str) -> Option<FilterQuality>{
+  // in addition to the standard levels, the sampling method can be named directly (though each
+  // level only offers one choice of each, so there's no way to select e.g., Catmull-Rom)
+  let mode = match mode_name.to_lowercase().as_str(){
+    "low" | "linear" => FilterQuality::Low,
+    "medium" | "mipmap" => FilterQuality::Medium,
+    "high" | "cubic" => FilterQuality::High,
+    "nearest" => FilterQuality::None,
+    _ => return None
+  };
+  Some(mode)
+}
+
+pub fn to_filter_mode(mode_name:&str) -> Option<FilterMode>{
   let mode = match mode_name.to_lowercase().as_str(){
-    "low" => FilterQuality::Low,
-    "medium" => FilterQuality::Medium,
-    "high" => FilterQuality::High,
+    "nearest" => FilterMode::Nearest,
+    "linear" => FilterMode::Linear,
     _ => return None
   };
   Some(mode)
@@ -434,10 +665,23 @@ pub fn from_filter_quality(mode:FilterQuality) -> String{
     FilterQuality::Low => "low",
     FilterQuality::Medium => "medium",
     FilterQuality::High => "high",
-    _ => "low"
+    FilterQuality::None => "nearest",
   }.to_string()
 }
 
+impl FilterQuality{
+  pub fn sampling(&self) -> SamplingOptions{
+    // skia no longer stores a quality level in the paint, so each one is expanded into the
+    // sampling options passed along with the image when it's drawn
+    match self{
+      FilterQuality::None => SamplingOptions::new(FilterMode::Nearest, MipmapMode::None),
+      FilterQuality::Low => SamplingOptions::new(FilterMode::Linear, MipmapMode::None),
+      FilterQuality::Medium => SamplingOptions::new(FilterMode::Linear, MipmapMode::Nearest),
+      FilterQuality::High => CubicResampler::mitchell().into(),
+    }
+  }
+}
+
 use skia_safe::{PaintCap};
 pub fn to_stroke_cap(mode_name:&str) -> Option<PaintCap>{
   let mode = match mode_name.to_lowercase().as_str(){
@@ -476,6 +720,109 @@ pub fn from_stroke_join(mode:PaintJoin) -> String{
   }.to_string()
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Antialias{ None, Grayscale }
+
+pub fn to_antialias(mode_name:&str) -> Option<Antialias>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "none" => Antialias::None,
+    "grayscale" => Antialias::Grayscale,
+    _ => return None
+  };
+  Some(mode)
+}
+
+pub fn from_antialias(mode:Antialias) -> String{
+  match mode{
+    Antialias::None => "none",
+    Antialias::Grayscale => "grayscale",
+  }.to_string()
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Flatten{ Auto, Never, Always }
+
+pub fn to_flatten(mode_name:&str) -> Option<Flatten>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "auto" => Flatten::Auto,
+    "never" => Flatten::Never,
+    "always" => Flatten::Always,
+    _ => return None
+  };
+  Some(mode)
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PaintOrder{ FillFirst, StrokeFirst }
+
+pub fn to_paint_order(mode_name:&str) -> Option<PaintOrder>{
+  // follows the css paint-order syntax, where only the first of `fill` or `stroke` is significant
+  let mode_name = mode_name.to_lowercase();
+  let tokens:Vec<&str> = mode_name.split_whitespace().collect();
+  if tokens.is_empty(){ return None }
+  if tokens == ["normal"]{ return Some(PaintOrder::FillFirst) }
+
+  let mut order = None;
+  for token in tokens{
+    match token{
+      "fill" => order = order.or(Some(PaintOrder::FillFirst)),
+      "stroke" => order = order.or(Some(PaintOrder::StrokeFirst)),
+      "markers" => {},
+      _ => return None
+    }
+  }
+  order.or(Some(PaintOrder::FillFirst))
+}
+
+pub fn from_paint_order(mode:PaintOrder) -> String{
+  match mode{
+    PaintOrder::FillFirst => "normal",
+    PaintOrder::StrokeFirst => "stroke",
+  }.to_string()
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineAlignment{ Center, Inner, Outer }
+
+pub fn to_line_alignment(mode_name:&str) -> Option<LineAlignment>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "center" => LineAlignment::Center,
+    "inner" => LineAlignment::Inner,
+    "outer" => LineAlignment::Outer,
+    _ => return None
+  };
+  Some(mode)
+}
+
+pub fn from_line_alignment(mode:LineAlignment) -> String{
+  match mode{
+    LineAlignment::Center => "center",
+    LineAlignment::Inner => "inner",
+    LineAlignment::Outer => "outer",
+  }.to_string()
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RenderPass{ Normal, Alpha, Shadow }
+
+pub fn to_render_pass(pass_name:&str) -> Option<RenderPass>{
+  let pass = match pass_name.to_lowercase().as_str(){
+    "normal" => RenderPass::Normal,
+    "alpha" => RenderPass::Alpha,
+    "shadow" => RenderPass::Shadow,
+    _ => return None
+  };
+  Some(pass)
+}
+
+pub fn from_render_pass(pass:RenderPass) -> String{
+  match pass{
+    RenderPass::Normal => "normal",
+    RenderPass::Alpha => "alpha",
+    RenderPass::Shadow => "shadow",
+  }.to_string()
+}
+
 
 use skia_safe::{BlendMode};
 pub fn to_blend_mode(mode_name:&str) -> Option<BlendMode>{
@@ -492,7 +839,8 @@ pub fn to_blend_mode(mode_name:&str) -> Option<BlendMode>{
     "source-atop" => BlendMode::SrcATop,
     "destination-atop" => BlendMode::DstATop,
     "xor" => BlendMode::Xor,
-    "lighter" => BlendMode::Plus,
+    "lighter" | "plus" | "plus-lighter" => BlendMode::Plus,
+    "modulate" => BlendMode::Modulate,
     "multiply" => BlendMode::Multiply,
     "screen" => BlendMode::Screen,
     "overlay" => BlendMode::Overlay,
@@ -528,6 +876,7 @@ pub fn from_blend_mode(mode:BlendMode) -> String{
     BlendMode::DstATop => "destination-atop",
     BlendMode::Xor => "xor",
     BlendMode::Plus => "lighter",
+    BlendMode::Modulate => "modulate",
     BlendMode::Multiply => "multiply",
     BlendMode::Screen => "screen",
     BlendMode::Overlay => "overlay",
@@ -547,7 +896,26 @@ pub fn from_blend_mode(mode:BlendMode) -> String{
   }.to_string()
 }
 
-use skia_safe::path::FillType;
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ColorSelect{ Lighter, Darker }
+
+pub fn to_color_select(mode_name:&str) -> Option<ColorSelect>{
+  let mode = match mode_name.to_lowercase().as_str(){
+    "lighter-color" => ColorSelect::Lighter,
+    "darker-color" => ColorSelect::Darker,
+    _ => return None
+  };
+  Some(mode)
+}
+
+pub fn from_color_select(mode:ColorSelect) -> String{
+  match mode{
+    ColorSelect::Lighter => "lighter-color",
+    ColorSelect::Darker => "darker-color",
+  }.to_string()
+}
+
+use skia_safe::PathFillType as FillType;
 pub fn fill_rule_arg_or<T: This>(cx: &mut CallContext<'_, T>, idx: usize, default: &str) -> Result<FillType, Throw>{
   let rule = match string_arg_or(cx, idx, default).as_str(){
     "nonzero" => FillType::Winding,
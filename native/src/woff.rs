@@ -0,0 +1,14 @@
+//
+// Decompression of WOFF & WOFF2 webfonts into plain TrueType/OpenType (sfnt) data
+//
+const WOFF:&[u8] = b"wOFF";
+const WOFF2:&[u8] = b"wOF2";
+
+pub fn decode_font(bytes:Vec<u8>) -> Result<Vec<u8>, String>{
+  // pass sfnt data through untouched and unpack either flavor of webfont
+  match bytes.get(0..4){
+    Some(WOFF) => wuff::decompress_woff1(&bytes).map_err(|_| "Could not decompress WOFF font data".to_string()),
+    Some(WOFF2) => wuff::decompress_woff2(&bytes).map_err(|_| "Could not decompress WOFF2 font data".to_string()),
+    _ => Ok(bytes)
+  }
+}
@@ -1,6 +1,6 @@
 const _ = require('lodash'),
       fs = require('fs'),
-      {Canvas, DOMMatrix, FontLibrary, loadImage} = require('../lib'),
+      {Canvas, OffscreenCanvas, DOMMatrix, Path2D, Image, ImageData, TextureGenerator, FontLibrary, Window, loadImage} = require('../lib'),
       {parseFont} = require('../lib/parse');
 
 describe("Canvas", ()=>{
@@ -55,6 +55,16 @@ describe("Context2D", ()=>{
 
   describe("can get & set", ()=>{
 
+    test('backdropFilter', () => {
+      expect(ctx.backdropFilter).toBe('none')
+      ctx.backdropFilter = 'blur(5px) invert(100%)'
+      expect(ctx.backdropFilter).toBe('blur(5px) invert(100%)')
+      ctx.backdropFilter = 'invalid'
+      expect(ctx.backdropFilter).toBe('blur(5px) invert(100%)')
+      ctx.backdropFilter = 'none'
+      expect(ctx.backdropFilter).toBe('none')
+    })
+
     test('currentTransform', () => {
       ctx.scale(0.1, 0.3)
       let matrix = ctx.currentTransform
@@ -73,6 +83,98 @@ describe("Context2D", ()=>{
       )
     })
 
+    test('perspective transforms', () => {
+      ctx.setTransform3x3([1, 0, 0, 0, 1, 0, 0.002, 0, 1])
+      let matrix = ctx.getTransform()
+      expect(matrix.is2D).toBe(false)
+      expect(matrix.m14).toBeCloseTo(0.002)
+
+      // the far edge of the rect is foreshortened
+      ctx.fillStyle = 'black'
+      ctx.fillRect(0, 0, 200, 200)
+      expect(pixel(140, 5)[3]).toBe(255)
+      expect(pixel(150, 5)[3]).toBe(0)
+      let pt = ctx.localToDevice(200, 0)
+      expect(pt.x).toBeCloseTo(200 / 1.4)
+
+      // the perspective terms survive a round trip through a DOMMatrix
+      ctx.resetTransform()
+      ctx.setTransform(matrix)
+      expect(ctx.getTransform().m14).toBeCloseTo(0.002)
+
+      ctx.resetTransform()
+      ctx.transformPerspective(0, 0.001)
+      expect(ctx.getTransform().m24).toBeCloseTo(0.001)
+      expect(() => ctx.setTransform3x3([1, 2, 3])).toThrow()
+    })
+
+    test('3D rotations', () => {
+      ctx.translate(200, 200)
+      ctx.rotateY(Math.PI / 3)
+      expect(ctx.localToDevice(100, 0).x).toBeCloseTo(250)
+      expect(ctx.localToDevice(0, 100).y).toBeCloseTo(300)
+
+      // with a viewing distance, the edge swinging away from the viewer is foreshortened
+      ctx.resetTransform()
+      ctx.translate(200, 200)
+      ctx.rotateY(Math.PI / 4, 400)
+      let near = ctx.localToDevice(-100, 0).x,
+          far = ctx.localToDevice(100, 0).x;
+      expect(200 - near).toBeGreaterThan(far - 200)
+      expect(ctx.getTransform().is2D).toBe(false)
+
+      // concat44() accepts a DOMMatrix or its 16 column-major values
+      ctx.resetTransform()
+      ctx.concat44([1, 0, 0, 0,  0, 1, 0, 0,  0, 0, 1, 0,  30, 40, 0, 1])
+      expect(ctx.localToDevice(0, 0)).toMatchObject({x:30, y:40})
+      ctx.concat44(new DOMMatrix().rotateAxisAngle(1, 0, 0, 60))
+      expect(ctx.localToDevice(0, 100).y).toBeCloseTo(90)
+
+      // the z-axis terms are dropped when the result is flattened to 3x3
+      let {m13, m23, m31, m32, m33, m34, m43} = ctx.getTransform()
+      expect([m13, m23, m31, m32, m33, m34, m43]).toEqual([0, 0, 0, 0, 1, 0, 0])
+
+      // so separate rotations don't compose as they would in 3D, unlike a single combined matrix
+      let [rx, ry] = [new DOMMatrix().rotateAxisAngle(1, 0, 0, 45), new DOMMatrix().rotateAxisAngle(0, 1, 0, 45)]
+      ctx.resetTransform()
+      ctx.concat44(rx.multiply(ry))
+      let combined = ctx.localToDevice(100, 100)
+      ctx.resetTransform()
+      ctx.rotateX(Math.PI / 4)
+      ctx.rotateY(Math.PI / 4)
+      let chained = ctx.localToDevice(100, 100)
+      expect(chained.x).toBeCloseTo(combined.x)
+      expect(chained.y).not.toBeCloseTo(combined.y)
+    })
+
+    test('fillStyle with CSS Color 4 syntax', () => {
+      let near = (css, rgba) => {
+        ctx.fillStyle = css
+        ctx.fillRect(0, 0, 1, 1)
+        pixel(0, 0).forEach((c, i) => expect(Math.abs(c - rgba[i])).toBeLessThanOrEqual(2))
+      }
+
+      near('lab(54.29% 80.8 69.89)', [255, 0, 0, 255])
+      near('lch(54.29 106.84 40.85)', [255, 0, 0, 255])
+      near('oklab(0.628 0.2249 0.1258)', [255, 0, 0, 255])
+      near('oklch(62.8% 0.2577 29.23deg)', [255, 0, 0, 255])
+      near('hwb(120 0% 49.8%)', [0, 128, 0, 255])
+      near('color(srgb 0 0 1 / 50%)', [0, 0, 255, 128])
+      near('color(display-p3 0 1 0)', [0, 255, 0, 255])
+
+      // out-of-gamut colors keep their full range rather than being clipped
+      ctx.fillStyle = 'color(display-p3 1 0 0)'
+      expect(ctx.fillStyle).toMatch(/^color\(srgb 1\.09\d* -0\.2\d* -0\.1\d*\)$/)
+      ctx.fillStyle = 'hwb(240 0% 0%)'
+      expect(ctx.fillStyle).toBe('#0000ff')
+
+      // invalid colors are ignored
+      ctx.fillStyle = 'lab(50% 20)'
+      expect(ctx.fillStyle).toBe('#0000ff')
+      ctx.fillStyle = 'oklch(50% 0.1 20%)'
+      expect(ctx.fillStyle).toBe('#0000ff')
+    })
+
     test('font', () => {
       expect(ctx.font).toBe('10px sans-serif')
       let font = '16px Baskerville, serif',
@@ -83,6 +185,18 @@ describe("Context2D", ()=>{
       expect(ctx.font).toBe(canonical)
     })
 
+    test('fontFallbacks', () => {
+      expect(ctx.fontFallbacks).toEqual([])
+      ctx.fontFallbacks = ['Noto Sans', 'Noto Color Emoji']
+      expect(ctx.fontFallbacks).toEqual(['Noto Sans', 'Noto Color Emoji'])
+      ctx.fontFallbacks = null
+      expect(ctx.fontFallbacks).toEqual([])
+
+      let {fallbacks, missing} = ctx.measureText('plain ascii')
+      expect(fallbacks).toEqual([])
+      expect(missing).toEqual([])
+    })
+
     test('globalAlpha', () => {
       expect(ctx.globalAlpha).toBe(1)
       ctx.globalAlpha = 0.25
@@ -101,16 +215,33 @@ describe("Context2D", ()=>{
                  "source-atop", "destination-atop", "xor", "lighter", "multiply",
                  "screen", "overlay", "darken", "lighten", "color-dodge", "color-burn",
                  "hard-light", "soft-light", "difference", "exclusion", "hue",
-                 "saturation", "color", "luminosity"]
+                 "saturation", "color", "luminosity", "modulate", "lighter-color", "darker-color"]
 
       expect(ctx.globalCompositeOperation).toBe('source-over')
       ctx.globalCompositeOperation = 'invalid'
       expect(ctx.globalCompositeOperation).toBe('source-over')
+      ctx.globalCompositeOperation = 'plus-lighter'
+      expect(ctx.globalCompositeOperation).toBe('lighter')
 
       for (let op of ops){
         ctx.globalCompositeOperation = op
         expect(ctx.globalCompositeOperation).toBe(op)
       }
+
+      // whole-color comparisons keep one color or the other rather than mixing their channels
+      let swatch = op => {
+        ctx.clearRect(0, 0, WIDTH, HEIGHT)
+        ctx.globalCompositeOperation = 'source-over'
+        ctx.fillStyle = 'rgb(0, 0, 200)'
+        ctx.fillRect(0, 0, 20, 20)
+        ctx.globalCompositeOperation = op
+        ctx.fillStyle = 'rgb(150, 150, 0)'
+        ctx.fillRect(0, 0, 20, 20)
+        return [pixel(10, 10), ctx.globalCompositeOperation]
+      }
+      expect(swatch('lighten')).toEqual([[150, 150, 200, 255], 'lighten'])
+      expect(swatch('lighter-color')).toEqual([[150, 150, 0, 255], 'lighter-color'])
+      expect(swatch('darker-color')).toEqual([[0, 0, 200, 255], 'darker-color'])
     })
 
     test('imageSmoothingEnabled', () => {
@@ -119,6 +250,61 @@ describe("Context2D", ()=>{
       expect(ctx.imageSmoothingEnabled).toBe(false)
     })
 
+    test('antialias', () => {
+      expect(ctx.antialias).toBe('grayscale')
+      ctx.antialias = 'nonesuch'
+      expect(ctx.antialias).toBe('grayscale')
+      ctx.antialias = 'analytic'
+      expect(ctx.antialias).toBe('grayscale')
+
+      let alphas = () => {
+        ctx.clearRect(0, 0, 100, 100)
+        ctx.beginPath()
+        ctx.moveTo(0, 0)
+        ctx.lineTo(100, 37)
+        ctx.lineTo(20, 100)
+        ctx.fill()
+        return new Set(ctx.getImageData(0, 0, 100, 100).data.filter((_, i) => i % 4 == 3))
+      }
+      expect(alphas().size).toBeGreaterThan(2)
+
+      ctx.save()
+      ctx.antialias = 'none'
+      expect(ctx.antialias).toBe('none')
+      expect([...alphas()].sort()).toEqual([0, 255])
+      ctx.restore()
+      expect(ctx.antialias).toBe('grayscale')
+
+      let ctx2 = new Canvas(10, 10).getContext('2d', {antialias:false})
+      expect(ctx2.antialias).toBe('none')
+      expect(ctx2.getContextAttributes().antialias).toBe('none')
+    })
+
+    test('dither', () => {
+      expect(ctx.dither).toBe(false)
+      ctx.dither = true
+      expect(ctx.dither).toBe(true)
+      ctx.save()
+      ctx.dither = false
+      ctx.restore()
+      expect(ctx.dither).toBe(true)
+
+      // a shallow gradient spread over many pixels should break up into noise rather than bands
+      let transitions = () => {
+        let gradient = ctx.createLinearGradient(0, 0, WIDTH, 0)
+        gradient.addColorStop(0, '#646464')
+        gradient.addColorStop(1, '#666666')
+        ctx.fillStyle = gradient
+        ctx.fillRect(0, 0, WIDTH, 1)
+        let row = ctx.getImageData(0, 0, WIDTH, 1).data.filter((_, i) => i % 4 == 0)
+        return row.filter((v, i) => i > 0 && v != row[i - 1]).length
+      }
+      ctx.dither = false
+      expect(transitions()).toBeLessThanOrEqual(2)
+      ctx.dither = true
+      expect(transitions()).toBeGreaterThan(10)
+    })
+
 
     test('imageSmoothingQuality', () => {
       let vals = ["low", "medium", "high"]
@@ -131,6 +317,57 @@ describe("Context2D", ()=>{
         ctx.imageSmoothingQuality = val
         expect(ctx.imageSmoothingQuality).toBe(val)
       }
+
+      // sampling methods can also be specified by name
+      let aliases = {linear:'low', mipmap:'medium', cubic:'high', nearest:'nearest'}
+      for (let [alias, val] of Object.entries(aliases)){
+        ctx.imageSmoothingQuality = alias
+        expect(ctx.imageSmoothingQuality).toBe(val)
+      }
+    })
+
+    test('downscaling', async () => {
+      // a 1px checkerboard should average out to a flat gray when shrunk
+      let checks = new Canvas(400, 400),
+          checksCtx = checks.getContext('2d'),
+          bmp = checksCtx.createImageData(400, 400);
+      for (let i=0; i<bmp.data.length; i+=4){
+        let [x, y] = [(i / 4) % 400, Math.floor(i / 1600)],
+            val = (x + y) % 2 ? 255 : 0;
+        bmp.data.set([val, val, val, 255], i)
+      }
+      checksCtx.putImageData(bmp, 0, 0)
+      let img = await loadImage(checks.toBuffer('png'))
+
+      ctx.imageSmoothingQuality = 'high'
+      ctx.drawImage(img, 0, 0, 40, 40)
+      let shrunk = ctx.getImageData(0, 0, 40, 40).data.filter((_, i) => i % 4 == 0)
+      expect(Math.min(...shrunk)).toBeGreaterThan(100)
+      expect(Math.max(...shrunk)).toBeLessThan(156)
+
+      // degenerate destinations draw nothing (rather than reducing forever)
+      ctx.clearRect(0, 0, 40, 40)
+      ctx.drawImage(img, 0, 0, 0, 40)
+      ctx.drawImage(img, 0, 0, 40, 0)
+      ctx.drawImage(img, 0, 0, 0.001, 40)
+      expect(pixel(0, 20)).toEqual([0, 0, 0, 0])
+    })
+
+    test('snapToPixel', async () => {
+      expect(ctx.snapToPixel).toBe(false)
+      let sprite = new Canvas(1, 1),
+          spriteCtx = sprite.getContext('2d');
+      spriteCtx.fillStyle = 'red'
+      spriteCtx.fillRect(0, 0, 1, 1)
+      let img = await loadImage(sprite.toBuffer('png'))
+
+      ctx.snapToPixel = true
+      ctx.imageSmoothingQuality = 'nearest'
+      expect(ctx.snapToPixel).toBe(true)
+      ctx.drawImage(img, 0.4, 0.4, 10.2, 10.2)
+      expect(pixel(0, 0)).toEqual([255,0,0,255])
+      expect(pixel(10, 10)).toEqual([255,0,0,255])
+      expect(pixel(11, 11)).toEqual([0,0,0,0])
     })
 
     test('lineCap', () => {
@@ -154,6 +391,264 @@ describe("Context2D", ()=>{
       expect(ctx.getLineDash()).toEqual([1,2,3,4])
     })
 
+    test('strokePrecision', () => {
+      expect(ctx.strokePrecision).toBe('auto')
+      ctx.strokePrecision = 0.3
+      expect(ctx.strokePrecision).toBeCloseTo(0.3)
+      ctx.strokePrecision = -1
+      ctx.strokePrecision = 'nonesuch'
+      expect(ctx.strokePrecision).toBeCloseTo(0.3)
+
+      // a coarse tolerance becomes visible when the curve is magnified
+      let ring = precision => {
+        ctx.clearRect(0, 0, WIDTH, HEIGHT)
+        ctx.save()
+        ctx.strokePrecision = precision
+        ctx.scale(20, 20)
+        ctx.beginPath()
+        ctx.arc(10, 10, 8, 0, 2 * Math.PI)
+        ctx.stroke()
+        ctx.restore()
+        return ctx.getImageData(0, 0, 400, 400).data
+      }
+      expect(ring('auto')).not.toEqual(ring(0.3))
+    })
+
+    test('strokeWidthIsAbsolute', () => {
+      expect(ctx.strokeWidthIsAbsolute).toBe(false)
+
+      let thickness = () => {
+        ctx.clearRect(0, 0, WIDTH, HEIGHT)
+        ctx.save()
+        ctx.scale(4, 4)
+        ctx.lineWidth = 2
+        ctx.beginPath()
+        ctx.moveTo(10, 10.5)
+        ctx.lineTo(60, 10.5)
+        ctx.stroke()
+        ctx.restore()
+        return _.range(20, 60).filter(y => pixel(100, y)[3] > 128).length
+      }
+      expect(thickness()).toBe(8)
+      ctx.strokeWidthIsAbsolute = true
+      expect(ctx.strokeWidthIsAbsolute).toBe(true)
+      expect(thickness()).toBe(2)
+    })
+
+    test('dashesIgnoreTransform', () => {
+      expect(ctx.dashesIgnoreTransform).toBe(false)
+
+      let dashes = () => {
+        ctx.clearRect(0, 0, WIDTH, HEIGHT)
+        ctx.save()
+        ctx.scale(4, 4)
+        ctx.setLineDash([2, 2])
+        ctx.lineWidth = 1
+        ctx.beginPath()
+        ctx.moveTo(0, 10)
+        ctx.lineTo(100, 10)
+        ctx.stroke()
+        ctx.restore()
+        return _.range(0, 64).map(x => pixel(x, 40)[3] > 128 ? 1 : 0).join('')
+      }
+      expect(dashes()).toBe('11111111000000001111111100000000'.repeat(2))
+      ctx.dashesIgnoreTransform = true
+      expect(ctx.dashesIgnoreTransform).toBe(true)
+      expect(dashes()).toBe('1100'.repeat(16))
+    })
+
+    test('pathEffect', () => {
+      expect(ctx.pathEffect).toBe(null)
+      ctx.pathEffect = {type:'corner', radius:10}
+      expect(ctx.pathEffect).toEqual({type:'corner', radius:10})
+      ctx.pathEffect = {type:'discrete', segLength:5, deviation:2}
+      expect(ctx.pathEffect).toEqual({type:'discrete', segLength:5, deviation:2, seed:0})
+      expect(() => ctx.pathEffect = {type:'wobbly'}).toThrow(TypeError)
+      expect(() => ctx.pathEffect = {type:'corner', radius:0}).toThrow(RangeError)
+
+      ctx.pathEffect = {type:'corner', radius:20}
+      ctx.fillStyle = 'black'
+      ctx.fillRect(0, 0, 50, 50)
+      expect(pixel(1, 1)).toEqual([0,0,0,0])
+      expect(pixel(25, 1)).toEqual([0,0,0,255])
+      expect(pixel(25, 25)).toEqual([0,0,0,255])
+
+      ctx.pathEffect = null
+      expect(ctx.pathEffect).toBe(null)
+      ctx.fillRect(0, 0, 50, 50)
+      expect(pixel(1, 1)).toEqual([0,0,0,255])
+    })
+
+    test('lineDashMarker', () => {
+      expect(ctx.getLineDashMarker()).toBe(null)
+      let dot = new Path2D()
+      dot.rect(-2, -2, 4, 4)
+      ctx.setLineDashMarker(dot, {spacing:20})
+      let {path, spacing, offset, rotate, morph} = ctx.getLineDashMarker()
+      expect(path).toBeInstanceOf(Path2D)
+      expect(ctx.isPointInPath(path, 1, -1)).toBe(true)
+      expect(ctx.isPointInPath(path, 3, 0)).toBe(false)
+      expect([spacing, offset, rotate, morph]).toEqual([20, 0, true, false])
+      expect(() => ctx.setLineDashMarker(dot, {spacing:0})).toThrow(RangeError)
+
+      ctx.strokeStyle = 'red'
+      ctx.beginPath()
+      ctx.moveTo(0, 10)
+      ctx.lineTo(100, 10)
+      ctx.stroke()
+      expect(pixel(0, 10)).toEqual([255,0,0,255])
+      expect(pixel(20, 10)).toEqual([255,0,0,255])
+      expect(pixel(40, 11)).toEqual([255,0,0,255])
+      expect(pixel(10, 10)).toEqual([0,0,0,0])
+      expect(pixel(30, 10)).toEqual([0,0,0,0])
+
+      ctx.save()
+      ctx.setLineDashMarker(null)
+      expect(ctx.getLineDashMarker()).toBe(null)
+      ctx.restore()
+      expect(ctx.getLineDashMarker().spacing).toBe(20)
+
+      // morphed markers bend to follow curves (so a chain of bars forms a continuous ring)
+      let bar = new Path2D()
+      bar.rect(0, -3, 10, 6)
+      ctx.clearRect(0, 0, 200, 200)
+      ctx.setLineDashMarker(bar, {spacing:10, morph:true})
+      expect(ctx.getLineDashMarker().morph).toBe(true)
+      ctx.beginPath()
+      ctx.arc(100, 100, 50, 0, 2 * Math.PI)
+      ctx.stroke()
+      for (let angle=0; angle < 2 * Math.PI; angle += Math.PI / 7){
+        let [x, y] = [100 + 50 * Math.cos(angle), 100 + 50 * Math.sin(angle)]
+        expect(pixel(Math.round(x), Math.round(y))[3]).toBeGreaterThan(200)
+      }
+    })
+
+    test('direction', () => {
+      let vals = ["ltr", "rtl", "inherit"]
+
+      expect(ctx.direction).toBe('inherit')
+      ctx.direction = 'invalid'
+      expect(ctx.direction).toBe('inherit')
+
+      for (let val of vals){
+        ctx.direction = val
+        expect(ctx.direction).toBe(val)
+      }
+    })
+
+    test('fontHinting', () => {
+      expect(ctx.fontHinting).toBe(true)
+      ctx.fontHinting = false
+      expect(ctx.fontHinting).toBe(false)
+    })
+
+    test('fontFeatures', () => {
+      expect(ctx.fontFeatures).toEqual({})
+      ctx.fontFeatures = {tnum: 1, liga: false, ss01: '1'}
+      expect(ctx.fontFeatures).toEqual({tnum: 1, liga: 0, ss01: 1})
+      ctx.fontFeatures = {toolong: 1, cv01: 3}
+      expect(ctx.fontFeatures).toEqual({cv01: 3})
+      ctx.fontFeatures = 'invalid'
+      expect(ctx.fontFeatures).toEqual({cv01: 3})
+      ctx.fontFeatures = null
+      expect(ctx.fontFeatures).toEqual({})
+    })
+
+    test('lineAlignment', () => {
+      let vals = ["center", "inner", "outer"]
+
+      expect(ctx.lineAlignment).toBe('center')
+      ctx.lineAlignment = 'invalid'
+      expect(ctx.lineAlignment).toBe('center')
+
+      for (let val of vals){
+        ctx.lineAlignment = val
+        expect(ctx.lineAlignment).toBe(val)
+      }
+    })
+
+    test('lineBreak & hyphenator', () => {
+      let vals = ["normal", "strict", "hyphenate"]
+
+      expect(ctx.lineBreak).toBe('normal')
+      ctx.lineBreak = 'invalid'
+      expect(ctx.lineBreak).toBe('normal')
+
+      for (let val of vals){
+        ctx.lineBreak = val
+        expect(ctx.lineBreak).toBe(val)
+      }
+
+      let hyphenate = word => word.match(/.{1,3}/g)
+      expect(ctx.hyphenator).toBe(null)
+      ctx.hyphenator = hyphenate
+      expect(ctx.hyphenator).toBe(hyphenate)
+      ctx.hyphenator = 'invalid'
+      expect(ctx.hyphenator).toBe(hyphenate)
+      ctx.hyphenator = null
+      expect(ctx.hyphenator).toBe(null)
+    })
+
+    test('lineHeight', () => {
+      expect(ctx.lineHeight).toBe('normal')
+      ctx.lineHeight = 1.5
+      expect(ctx.lineHeight).toBe(1.5)
+      ctx.lineHeight = '24px'
+      expect(ctx.lineHeight).toBe('24px')
+      ctx.lineHeight = '2em'
+      expect(ctx.lineHeight).toBe(2)
+      ctx.lineHeight = 'invalid'
+      expect(ctx.lineHeight).toBe(2)
+      ctx.lineHeight = 'normal'
+      expect(ctx.lineHeight).toBe('normal')
+    })
+
+    test('tabSize & tabStops', () => {
+      expect(ctx.tabSize).toBe(8)
+      ctx.tabSize = 4
+      expect(ctx.tabSize).toBe(4)
+      ctx.tabSize = '40px'
+      expect(ctx.tabSize).toBe('40px')
+      ctx.tabSize = -2
+      expect(ctx.tabSize).toBe('40px')
+
+      expect(ctx.tabStops).toEqual([])
+      ctx.tabStops = [50, '100px', 180]
+      expect(ctx.tabStops).toEqual([50, 100, 180])
+      ctx.tabStops = [100, 50]
+      expect(ctx.tabStops).toEqual([50, 100, 180])
+      ctx.tabStops = null
+      expect(ctx.tabStops).toEqual([])
+    })
+
+    test('textBackground', () => {
+      expect(ctx.textBackground).toBe(null)
+      ctx.textBackground = 'yellow'
+      expect(ctx.textBackground).toEqual({color:'#ffff00', padding:0, radius:0})
+      ctx.textBackground = {color:'rgba(0,0,255,0.5)', padding:[2, 6], radius:4}
+      expect(ctx.textBackground).toEqual({color:'rgba(0, 0, 255, 0.5)', padding:[2, 6], radius:4})
+      ctx.textBackground = {color:'red', padding:-1}
+      expect(ctx.textBackground.color).toBe('rgba(0, 0, 255, 0.5)')
+      ctx.textBackground = null
+      expect(ctx.textBackground).toBe(null)
+    })
+
+    test('textOverflow & maxLines', () => {
+      expect(ctx.textOverflow).toBe('clip')
+      ctx.textOverflow = 'invalid'
+      expect(ctx.textOverflow).toBe('clip')
+      ctx.textOverflow = 'ellipsis'
+      expect(ctx.textOverflow).toBe('ellipsis')
+
+      expect(ctx.maxLines).toBe('none')
+      ctx.maxLines = 3
+      expect(ctx.maxLines).toBe(3)
+      ctx.maxLines = 0
+      expect(ctx.maxLines).toBe(3)
+      ctx.maxLines = 'none'
+      expect(ctx.maxLines).toBe('none')
+    })
+
     test('lineJoin', () => {
       let vals = ["miter", "round", "bevel"]
 
@@ -180,8 +675,167 @@ describe("Context2D", ()=>{
       expect(ctx.lineWidth).toBe(10)
     })
 
+    test('renderPass', () => {
+      expect(ctx.renderPass).toBe('normal')
+      ctx.renderPass = 'invalid'
+      expect(ctx.renderPass).toBe('normal')
+
+      let draw = pass => {
+        ctx.clearRect(0, 0, WIDTH, HEIGHT)
+        ctx.save()
+        ctx.renderPass = pass
+        ctx.fillStyle = 'rgba(255,0,0,0.5)'
+        ctx.shadowColor = 'black'
+        ctx.shadowOffsetX = 50
+        ctx.fillRect(10, 10, 20, 20)
+        ctx.restore()
+        return [pixel(20, 20), pixel(70, 20)]
+      }
+
+      let [shape, shadow] = draw('normal')
+      expect(shape[0]).toBeGreaterThan(200)
+      expect(shape[3]).toBeGreaterThan(100)
+      expect(shadow[3]).toBeGreaterThan(0)
+
+      ;[shape, shadow] = draw('alpha')
+      expect(shape).toEqual([255, 255, 255, expect.any(Number)])
+      expect(shape[3]).toBeLessThan(255)
+      expect(shadow).toEqual([0, 0, 0, 0])
+
+      ;[shape, shadow] = draw('shadow')
+      expect(shape).toEqual([0, 0, 0, 0])
+      expect(shadow[3]).toBeGreaterThan(0)
+      expect(ctx.renderPass).toBe('normal')
+    })
+
+    test('shadowInset & shadowSpread', () => {
+      expect(ctx.shadowInset).toBe(false)
+      expect(ctx.shadowSpread).toBe(0)
+      ctx.shadowInset = true
+      ctx.shadowSpread = 4
+      expect(ctx.shadowInset).toBe(true)
+      expect(ctx.shadowSpread).toBe(4)
+
+      // an inner shadow is cast onto the shape's interior from the edge opposite its offset
+      ctx.shadowSpread = 0
+      ctx.shadowColor = 'black'
+      ctx.shadowOffsetX = 10
+      ctx.fillStyle = 'red'
+      ctx.fillRect(10, 10, 50, 50)
+      expect(pixel(15, 35)).toEqual([0, 0, 0, 255])
+      expect(pixel(40, 35)).toEqual([255, 0, 0, 255])
+      expect(pixel(65, 35)).toEqual([0, 0, 0, 0])
+
+      // spread grows the silhouette of a drop shadow beyond the shape's edges
+      ctx.shadowInset = false
+      ctx.shadowOffsetX = 0
+      ctx.shadowSpread = 5
+      ctx.fillRect(100, 100, 20, 20)
+      expect(pixel(110, 110)).toEqual([255, 0, 0, 255])
+      expect(pixel(97, 110)).toEqual([0, 0, 0, 255])
+      expect(pixel(93, 110)).toEqual([0, 0, 0, 0])
+    })
+
+    test('shadowsIgnoreTransform', () => {
+      expect(ctx.shadowsIgnoreTransform).toBe(true)
+
+      // by default the offset is in device pixels regardless of the context's scale...
+      ctx.scale(2, 2)
+      ctx.shadowColor = 'black'
+      ctx.shadowOffsetX = 20
+      ctx.fillStyle = 'red'
+      ctx.fillRect(10, 10, 10, 10)
+      expect(pixel(30, 30)).toEqual([255, 0, 0, 255])
+      expect(pixel(50, 30)).toEqual([0, 0, 0, 255])
+      expect(pixel(70, 30)).toEqual([0, 0, 0, 0])
+
+      // ...but can be scaled along with the shape instead
+      ctx.shadowsIgnoreTransform = false
+      expect(ctx.shadowsIgnoreTransform).toBe(false)
+      ctx.fillRect(10, 50, 10, 10)
+      expect(pixel(30, 110)).toEqual([255, 0, 0, 255])
+      expect(pixel(50, 110)).toEqual([0, 0, 0, 0])
+      expect(pixel(70, 110)).toEqual([0, 0, 0, 255])
+    })
+
+    test('textDecoration', () => {
+      expect(ctx.textDecoration).toBe('none')
+      ctx.textDecoration = 'underline'
+      expect(ctx.textDecoration).toBe('underline')
+      ctx.textDecoration = 'wavy overline line-through red 2px'
+      expect(ctx.textDecoration).toBe('overline line-through wavy red 2px')
+      ctx.textDecoration = 'invalid'
+      expect(ctx.textDecoration).toBe('overline line-through wavy red 2px')
+      ctx.textDecoration = 'none'
+      expect(ctx.textDecoration).toBe('none')
+    })
+
+    test('textIndent', () => {
+      expect(ctx.textIndent).toBe(0)
+      ctx.textIndent = 20
+      expect(ctx.textIndent).toBe(20)
+      ctx.textIndent = -5
+      expect(ctx.textIndent).toBe(20)
+      ctx.textIndent = '1in'
+      expect(ctx.textIndent).toBe(96)
+    })
+
+    test('textRendering', () => {
+      let vals = ["auto", "optimizeSpeed", "optimizeLegibility", "geometricPrecision"]
+
+      expect(ctx.textRendering).toBe('auto')
+      ctx.textRendering = 'invalid'
+      expect(ctx.textRendering).toBe('auto')
+
+      for (let val of vals){
+        ctx.textRendering = val
+        expect(ctx.textRendering).toBe(val)
+      }
+    })
+
+    test('paintOrder', () => {
+      expect(ctx.paintOrder).toBe('normal')
+      ctx.paintOrder = 'invalid'
+      expect(ctx.paintOrder).toBe('normal')
+
+      ctx.paintOrder = 'stroke fill'
+      expect(ctx.paintOrder).toBe('stroke')
+      ctx.paintOrder = 'fill'
+      expect(ctx.paintOrder).toBe('normal')
+      ctx.paintOrder = 'markers stroke'
+      expect(ctx.paintOrder).toBe('stroke')
+      ctx.paintOrder = 'normal'
+      expect(ctx.paintOrder).toBe('normal')
+    })
+
+    test('textOrientation', () => {
+      let vals = ["mixed", "upright", "sideways"]
+
+      expect(ctx.textOrientation).toBe('mixed')
+      ctx.textOrientation = 'invalid'
+      expect(ctx.textOrientation).toBe('mixed')
+
+      for (let val of vals){
+        ctx.textOrientation = val
+        expect(ctx.textOrientation).toBe(val)
+      }
+    })
+
+    test('writingMode', () => {
+      let vals = ["horizontal-tb", "vertical-rl", "vertical-lr"]
+
+      expect(ctx.writingMode).toBe('horizontal-tb')
+      ctx.writingMode = 'invalid'
+      expect(ctx.writingMode).toBe('horizontal-tb')
+
+      for (let val of vals){
+        ctx.writingMode = val
+        expect(ctx.writingMode).toBe(val)
+      }
+    })
+
     test('textAlign', () => {
-      let vals = ["start", "end", "left", "center", "right"]
+      let vals = ["start", "end", "left", "center", "right", "justify"]
 
       expect(ctx.textAlign).toBe('start')
       ctx.textAlign = 'invalid'
@@ -193,6 +847,134 @@ describe("Context2D", ()=>{
       }
     })
 
+    test('save & restore', () => {
+      let gradA = ctx.createLinearGradient(0, 0, 10, 10),
+          gradB = ctx.createLinearGradient(0, 0, 10, 10);
+
+      ctx.fillStyle = gradA
+      ctx.save()
+      ctx.fillStyle = gradB
+      ctx.imageSmoothingQuality = 'high'
+      ctx.filter = 'blur(2px)'
+      ctx.setLineDash([4, 2])
+      ctx.fontVariant = 'small-caps'
+      ctx.restore()
+
+      expect(ctx.fillStyle).toBe(gradA)
+      expect(ctx.imageSmoothingQuality).toBe('low')
+      expect(ctx.filter).toBe('none')
+      expect(ctx.getLineDash()).toEqual([])
+      expect(ctx.fontVariant).toBe('normal')
+
+      // a fresh save shouldn't resurrect styles from a previously restored frame
+      ctx.save()
+      expect(ctx.fillStyle).toBe(gradA)
+      ctx.restore()
+    })
+
+    test('state snapshots', () => {
+      ctx.font = 'bold 24px serif'
+      ctx.fillStyle = 'red'
+      ctx.lineWidth = 7
+      ctx.setLineDash([3, 1])
+      ctx.translate(10, 20)
+      let state = ctx.getState()
+      expect(state.fillStyle).toBe('#ff0000')
+      expect(state.lineDash).toEqual([3, 1])
+      expect(state.transform.e).toBe(10)
+
+      let other = new Canvas(WIDTH, HEIGHT).getContext('2d')
+      other.setState(state)
+      expect(other.font).toBe(ctx.font)
+      expect(other.fillStyle).toBe('#ff0000')
+      expect(other.lineWidth).toBe(7)
+      expect(other.getLineDash()).toEqual([3, 1])
+      expect(other.getTransform().f).toBe(20)
+      expect(() => other.setState(null)).toThrow(TypeError)
+    })
+
+    test('clip bounds', () => {
+      let full = ctx.clipBounds()
+      expect([full.x, full.y, full.width, full.height]).toEqual([0, 0, WIDTH, HEIGHT])
+
+      ctx.translate(100, 100)
+      ctx.beginPath()
+      ctx.rect(0, 0, 50, 20)
+      ctx.clip()
+      let local = ctx.clipBounds(),
+          device = ctx.clipBounds('device');
+      expect([local.x, local.y, local.width, local.height]).toEqual([0, 0, 50, 20])
+      expect([device.x, device.y, device.width, device.height]).toEqual([100, 100, 50, 20])
+      expect(() => ctx.clipBounds('world')).toThrow(TypeError)
+
+      expect(ctx.isRectVisible(10, 10, 5, 5)).toBe(true)
+      expect(ctx.isRectVisible({x:-20, y:-20, width:10, height:10})).toBe(false)
+      expect(ctx.isRectVisible(60, 0, 10, 10)).toBe(false)
+    })
+
+    test('clip bounds after transforming', () => {
+      ctx.beginPath()
+      ctx.rect(10, 10, 50, 20)
+      ctx.clip()
+      ctx.translate(50, 0)
+
+      let device = ctx.clipBounds('device'),
+          local = ctx.clipBounds();
+      expect([device.x, device.y, device.width, device.height]).toEqual([10, 10, 50, 20])
+      expect([local.x, local.y, local.width, local.height]).toEqual([-40, 10, 50, 20])
+      expect(ctx.isRectVisible(-30, 15, 5, 5)).toBe(true)
+      expect(ctx.isRectVisible(20, 15, 5, 5)).toBe(false)
+
+      // restoring a state with a later transform shouldn't shift the clip either
+      ctx.save()
+      ctx.restore()
+      device = ctx.clipBounds('device')
+      expect([device.x, device.y, device.width, device.height]).toEqual([10, 10, 50, 20])
+
+      ctx.fillStyle = 'red'
+      ctx.fillRect(-50, 0, WIDTH, HEIGHT)
+      expect(pixel(15, 15)).toEqual([255, 0, 0, 255])
+      expect(pixel(65, 15)).toEqual([0, 0, 0, 0])
+    })
+
+    test('content bounds', () => {
+      let empty = ctx.contentBounds()
+      expect([empty.x, empty.y, empty.width, empty.height]).toEqual([0, 0, 0, 0])
+
+      ctx.fillRect(10, 20, 30, 40)
+      ctx.translate(100, 100)
+      ctx.scale(2, 2)
+      ctx.fillRect(0, 0, 10, 5)
+      let device = ctx.contentBounds(),
+          local = ctx.contentBounds('local');
+      expect([device.x, device.y, device.width, device.height]).toEqual([10, 20, 110, 90])
+      expect([local.x, local.y, local.width, local.height]).toEqual([-45, -40, 55, 45])
+
+      // strokes are measured geometrically, including their line width
+      ctx.resetTransform()
+      ctx.lineWidth = 10
+      ctx.lineJoin = 'round'
+      ctx.strokeRect(100, 100, 50, 50)
+      let stroked = ctx.contentBounds()
+      expect([stroked.x, stroked.y, stroked.width, stroked.height]).toEqual([10, 20, 145, 135])
+      expect(() => ctx.contentBounds('world')).toThrow(TypeError)
+    })
+
+    test('coordinate conversion', () => {
+      ctx.translate(100, 50)
+      ctx.scale(2, 4)
+      let pt = ctx.localToDevice(10, 10)
+      expect([pt.x, pt.y]).toEqual([120, 90])
+      pt = ctx.deviceToLocal({x:120, y:90})
+      expect([pt.x, pt.y]).toEqual([10, 10])
+
+      ctx.resetTransform()
+      ctx.rotate(Math.PI / 2)
+      pt = ctx.localToDevice(10, 0)
+      expect(pt.x).toBeCloseTo(0)
+      expect(pt.y).toBeCloseTo(10)
+    })
+
   })
 
   describe("can create", ()=>{
@@ -211,6 +993,48 @@ describe("Context2D", ()=>{
       expect(canvas.pages[1]).toBe(ctx2)
       expect(ctx.canvas).toBe(canvas)
       expect(ctx2.canvas).toBe(canvas)
+
+      // omitted dimensions are taken from the current page
+      let ctx3 = canvas.newPage()
+      expect(canvas.pages).toEqual([ctx, ctx2, ctx3])
+      expect([canvas.width, canvas.height]).toEqual([WIDTH*2, HEIGHT*2])
+
+      // each page keeps its own size when exported
+      let size = png => [png.readUInt32BE(16), png.readUInt32BE(20)]
+      expect(size(canvas.toBuffer('png', {page:1}))).toEqual([WIDTH, HEIGHT])
+      expect(size(canvas.toBuffer('png', {page:3}))).toEqual([WIDTH*2, HEIGHT*2])
+    })
+
+    test('layers', async () => {
+      let below = canvas.createLayer('below', {zIndex:-1}),
+          above = canvas.createLayer('above');
+      expect(below.canvas).toBe(canvas)
+      expect(canvas.layers.map(l => l.name)).toEqual(['below', 'above'])
+      expect(canvas.layers[1].context).toBe(above)
+      expect(() => canvas.createLayer('above')).toThrow()
+
+      below.fillStyle = 'red'
+      below.fillRect(0, 0, 20, 20)
+      ctx.fillStyle = 'lime'
+      ctx.fillRect(10, 0, 20, 20)
+      above.fillStyle = 'blue'
+      above.fillRect(20, 0, 20, 20)
+
+      let check = new Canvas(WIDTH, HEIGHT),
+          checkCtx = check.getContext('2d');
+      checkCtx.drawImage(await loadImage(canvas.toBuffer('png')), 0, 0)
+      expect(Array.from(checkCtx.getImageData(5, 5, 1, 1).data)).toEqual([255, 0, 0, 255])
+      expect(Array.from(checkCtx.getImageData(15, 5, 1, 1).data)).toEqual([0, 255, 0, 255])
+      expect(Array.from(checkCtx.getImageData(25, 5, 1, 1).data)).toEqual([0, 0, 255, 255])
+
+      // the page can be cleared without affecting its layers
+      ctx.clearRect(0, 0, WIDTH, HEIGHT)
+      expect(canvas.removeLayer('above')).toBe(true)
+      expect(canvas.removeLayer('above')).toBe(false)
+      checkCtx.clearRect(0, 0, WIDTH, HEIGHT)
+      checkCtx.drawImage(canvas, 0, 0)
+      expect(Array.from(checkCtx.getImageData(15, 5, 1, 1).data)).toEqual([255, 0, 0, 255])
+      expect(Array.from(checkCtx.getImageData(25, 5, 1, 1).data)).toEqual([0, 0, 0, 0])
     })
 
     test("ImageData", () => {
@@ -220,6 +1044,937 @@ describe("Context2D", ()=>{
       expect(bmp.height).toBe(height)
       expect(bmp.data.length).toBe(width * height * 4)
       expect(Array.from(bmp.data.slice(0,4))).toEqual([0,0,0,0])
+      expect(bmp.colorType).toBe('rgba')
+      expect(bmp.premultiplied).toBe(false)
+    })
+
+    test("ImageData.resize() & convert()", () => {
+      ctx.fillStyle = 'rgba(255, 0, 0, 0.5)'
+      ctx.fillRect(0, 0, 10, 20)
+      ctx.fillStyle = 'blue'
+      ctx.fillRect(10, 0, 10, 20)
+      let bmp = ctx.getImageData(0, 0, 20, 20)
+
+      let small = bmp.resize(2, 2)
+      expect([small.width, small.height]).toEqual([2, 2])
+      expect(small.data.length).toBe(16)
+      expect(Array.from(small.data.slice(0, 4))).toEqual([255, 0, 0, 128])
+      expect(Array.from(small.data.slice(4, 8))).toEqual([0, 0, 255, 255])
+      expect(() => bmp.resize(0, 10)).toThrow(RangeError)
+      expect(() => bmp.resize(10, 10, {quality:'ultra'})).toThrow(TypeError)
+
+      // float pixels are resampled without being quantized to 8 bits
+      let hdr = new ImageData(2, 2, {colorType:'rgba32f'})
+      hdr.data.set(Array(4).fill([0.3, 0.6, 0.9, 1]).flat())
+      let big = hdr.resize(4, 4, {quality:'nearest'})
+      expect(big.colorType).toBe('rgba32f')
+      Array.from(big.data.slice(0, 4)).forEach((val, i) => expect(val).toBeCloseTo([0.3, 0.6, 0.9, 1][i], 5))
+
+      let bgra = bmp.convert({colorType:'bgra', premultiply:true})
+      expect(bgra.colorType).toBe('bgra')
+      expect(bgra.premultiplied).toBe(true)
+      expect(Array.from(bgra.data.slice(0, 4))).toEqual([0, 0, 128, 128])
+
+      let gray = bmp.convert({colorType:'gray'})
+      expect(gray.data.length).toBe(20 * 20)
+      expect(() => bmp.convert({colorType:'cmyk'})).toThrow(TypeError)
+
+      // converted data can be drawn back to the canvas
+      ctx.clearRect(0, 0, 20, 20)
+      ctx.putImageData(bgra, 0, 0)
+      expect(pixel(15, 5)).toEqual([0, 0, 255, 255])
+    })
+
+    test("renderInto()", () => {
+      let small = new Canvas(4, 2),
+          smallCtx = small.getContext("2d");
+      smallCtx.fillStyle = 'rgba(0, 0, 255, 0.5)'
+      smallCtx.fillRect(0, 0, 2, 2)
+
+      let shared = new SharedArrayBuffer(4 * 4 * 2),
+          pixels = new Uint8Array(shared);
+      expect(small.renderInto(shared)).toBe(shared)
+      expect(Array.from(pixels.slice(0, 4))).toEqual([0, 0, 255, 128])
+      expect(Array.from(pixels.slice(12, 16))).toEqual([0, 0, 0, 0])
+
+      // alternate formats & padded rows
+      let padded = Buffer.alloc(24 * 2, 0xff)
+      small.renderInto(padded, {format:'bgra', premultiplied:true, rowBytes:24})
+      expect(Array.from(padded.slice(0, 4))).toEqual([128, 0, 0, 128])
+      expect(Array.from(padded.slice(24, 28))).toEqual([128, 0, 0, 128])
+      expect(Array.from(padded.slice(16, 24))).toEqual([0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])
+
+      expect(() => small.renderInto(new ArrayBuffer(8))).toThrow(RangeError)
+      expect(() => small.renderInto(shared, {rowBytes:8})).toThrow(RangeError)
+      expect(() => small.renderInto(shared, {format:'cmyk'})).toThrow(TypeError)
+      expect(() => small.renderInto("pixels")).toThrow(TypeError)
+    })
+
+    test("an animation loop", async () => {
+      let frames = []
+      let anim = canvas.animate(100, (ctx, {frame, time, delta, dropped}) => {
+        frames.push(frame)
+        expect(time).toBeGreaterThanOrEqual(0)
+        expect(delta).toBeGreaterThanOrEqual(0)
+        expect(dropped).toBeGreaterThanOrEqual(0)
+        ctx.fillStyle = 'red'
+        ctx.fillRect(0, 0, 10, 10)
+        return frames.length < 3
+      })
+      await anim.finished
+      expect(frames.length).toBe(3)
+      expect(frames).toEqual([...frames].sort((a, b) => a - b))
+      expect(pixel(5, 5)).toEqual([255,0,0,255])
+
+      let failure = canvas.animate(() => { throw new Error('oops') })
+      await expect(failure.finished).rejects.toThrow('oops')
+      expect(() => canvas.animate(0, () => {})).toThrow(RangeError)
+
+      let endless = canvas.animate(async () => {})
+      endless.stop()
+      await expect(endless.finished).resolves.toBeGreaterThanOrEqual(0)
+    })
+
+    test("a video Recorder", async () => {
+      let {PassThrough} = require('stream'),
+          output = new PassThrough(),
+          chunks = [];
+      output.on('data', chunk => chunks.push(chunk))
+
+      canvas.width = 4
+      canvas.height = 2
+      let recorder = canvas.record({fps:24, output})
+      ctx.fillStyle = 'red'
+      ctx.fillRect(0, 0, 4, 2)
+      expect(await recorder.addFrame()).toBe(1)
+      ctx.fillStyle = 'blue'
+      ctx.fillRect(0, 0, 4, 2)
+      expect(await recorder.addFrame()).toBe(2)
+      expect(await recorder.finish()).toBe(2)
+      expect(() => recorder.addFrame()).toThrow()
+
+      let data = Buffer.concat(chunks)
+      expect(data.length).toBe(2 * 4 * 2 * 4)
+      expect(Array.from(data.slice(0, 4))).toEqual([255,0,0,255])
+      expect(Array.from(data.slice(32, 36))).toEqual([0,0,255,255])
+
+      expect(() => canvas.record({output:'movie.xyz'})).toThrow()
+      expect(() => canvas.record({})).toThrow(TypeError)
+    })
+
+    test("a Canvas from a serialized picture", () => {
+      canvas.width = 40
+      canvas.height = 30
+      ctx.fillStyle = 'red'
+      ctx.fillRect(10, 10, 10, 10)
+
+      let skp = canvas.toBuffer('skp'),
+          scene = Canvas.fromPicture(skp),
+          sceneCtx = scene.getContext('2d');
+      expect(scene.width).toBe(40)
+      expect(scene.height).toBe(30)
+      expect(Array.from(sceneCtx.getImageData(15, 15, 1, 1).data)).toEqual([255, 0, 0, 255])
+      expect(Array.from(sceneCtx.getImageData(5, 5, 1, 1).data)).toEqual([0, 0, 0, 0])
+
+      expect(() => Canvas.fromPicture(Buffer.from('not a picture'))).toThrow()
+    })
+
+    test("a Canvas with a wide-gamut or linear color space", async () => {
+      expect(canvas.colorSpace).toBe('srgb')
+      expect(ctx.getContextAttributes().colorSpace).toBe('srgb')
+      expect(() => new Canvas(10, 10, {colorSpace:'cmyk'})).toThrow(TypeError)
+
+      // sRGB colors are converted into the P3 gamut and back again when exported
+      let p3 = new Canvas(10, 10, {colorSpace:'display-p3'}),
+          p3ctx = p3.getContext('2d');
+      expect(p3.colorSpace).toBe('display-p3')
+      p3ctx.fillStyle = 'red'
+      p3ctx.fillRect(0, 0, 10, 10)
+      let [r, g, b] = p3ctx.getImageData(5, 5, 1, 1).data
+      expect(r).toBeLessThan(245)
+      expect(g).toBeGreaterThan(40)
+      expect(b).toBeGreaterThan(25)
+
+      let img = await loadImage(p3.toBuffer('png'))
+      ctx.drawImage(img, 0, 0)
+      let [r2, g2, b2] = pixel(5, 5)
+      expect(r2).toBeGreaterThan(250)
+      expect(g2).toBeLessThan(5)
+      expect(b2).toBeLessThan(5)
+
+      // blending happens in linear light (but ImageData is still sRGB-encoded)
+      let linear = new Canvas(2, 1),
+          linearCtx = linear.getContext('2d', {colorSpace:'srgb-linear'});
+      expect(linear.colorSpace).toBe('srgb-linear')
+      linearCtx.fillStyle = 'white'
+      linearCtx.globalAlpha = 0.5
+      linearCtx.fillRect(0, 0, 2, 1)
+      linearCtx.globalAlpha = 1
+      linearCtx.globalCompositeOperation = 'destination-over'
+      linearCtx.fillStyle = 'black'
+      linearCtx.fillRect(0, 0, 2, 1)
+      let [gray] = linearCtx.getImageData(0, 0, 1, 1).data
+      expect(gray).toBeGreaterThan(180)
+      expect(linear.newPage(2, 1).getContextAttributes().colorSpace).toBe('srgb-linear')
+    })
+
+    test("a Canvas with a high bit-depth pixel format", () => {
+      expect(canvas.pixelFormat).toBe('rgba8')
+      expect(() => new Canvas(10, 10, {pixelFormat:'rgba64'})).toThrow(TypeError)
+
+      // 16-bit pages return one Uint16Array element per channel & export as 16-bit pngs
+      let deep = new Canvas(4, 4, {pixelFormat:'rgba16'}),
+          deepCtx = deep.getContext('2d');
+      expect(deep.pixelFormat).toBe('rgba16')
+      deepCtx.fillStyle = 'rgb(50%, 25%, 0%)'
+      deepCtx.fillRect(0, 0, 4, 4)
+      let imgData = deepCtx.getImageData(0, 0, 4, 4)
+      expect(imgData.colorType).toBe('rgba16')
+      expect(imgData.data).toBeInstanceOf(Uint16Array)
+      expect(imgData.data.length).toBe(4 * 4 * 4)
+      let [r, g, b, a] = imgData.data
+      expect(Math.abs(r - 32768)).toBeLessThan(256)
+      expect(Math.abs(g - 16384)).toBeLessThan(256)
+      expect([b, a]).toEqual([0, 65535])
+
+      let png = deep.toBuffer('png')
+      expect(png.readUInt8(24)).toBe(16) // the IHDR chunk's bit depth
+      expect(deep.newPage(2, 2).getContextAttributes().pixelFormat).toBe('rgba16')
+
+      // half-float pages are read back as 32-bit floats and exported as floating point tiffs
+      let hdr = new Canvas(4, 4, {pixelFormat:'rgba16f'}),
+          hdrCtx = hdr.getContext('2d');
+      hdrCtx.fillStyle = 'white'
+      hdrCtx.fillRect(0, 0, 2, 4)
+      let floats = hdrCtx.getImageData(0, 0, 4, 1)
+      expect(floats.colorType).toBe('rgba32f')
+      expect(floats.data).toBeInstanceOf(Float32Array)
+      expect(Array.from(floats.data.slice(0, 4))).toEqual([1, 1, 1, 1])
+      expect(Array.from(floats.data.slice(8, 12))).toEqual([0, 0, 0, 0])
+
+      let tiff = hdr.toBuffer('tiff')
+      expect(tiff.slice(0, 4)).toEqual(Buffer.from([0x49, 0x49, 42, 0]))
+      let ifd = tiff.readUInt32LE(4),
+          tags = Array.from({length:tiff.readUInt16LE(ifd)}, (_, i) => tiff.readUInt16LE(ifd + 2 + i * 12));
+      expect(tags).toContain(339) // SampleFormat
+      expect(() => deep.toBuffer('tiff', {profile:42})).toThrow(TypeError)
+    })
+
+    test("an image comparison", () => {
+      canvas.width = 20
+      canvas.height = 20
+      ctx.fillStyle = 'red'
+      ctx.fillRect(5, 5, 10, 10)
+      let golden = canvas.toBuffer('png')
+
+      let same = Canvas.compare(golden, canvas)
+      expect(same).toMatchObject({mismatched:0, antialiased:0, total:400, ratio:0, maxDifference:0})
+      expect([same.diff.width, same.diff.height]).toEqual([20, 20])
+
+      // a one-pixel shift is excused as antialiasing unless the tolerance is disabled
+      ctx.clearRect(0, 0, 20, 20)
+      ctx.fillRect(6, 5, 10, 10)
+      let shifted = Canvas.compare(golden, canvas)
+      expect(shifted.mismatched).toBe(0)
+      expect(shifted.antialiased).toBe(20)
+      expect(Canvas.compare(golden, canvas, {antialiasTolerance:0}).mismatched).toBe(20)
+
+      ctx.fillStyle = 'blue'
+      ctx.fillRect(0, 0, 4, 1)
+      let changed = Canvas.compare(golden, canvas, {threshold:0.2})
+      expect(changed.mismatched).toBe(4)
+      expect(changed.ratio).toBeCloseTo(4 / 400)
+
+      expect(() => Canvas.compare(golden, new Canvas(10, 10))).toThrow(RangeError)
+      expect(() => Canvas.compare(golden, canvas, {threshold:2})).toThrow(RangeError)
+    })
+
+    test("a cropped export", async () => {
+      ctx.fillStyle = 'red'
+      ctx.fillRect(0, 0, 256, 256)
+      ctx.fillStyle = 'blue'
+      ctx.fillRect(256, 256, 256, 256)
+
+      let tile = await loadImage(canvas.toBuffer('png', {left:192, top:192, width:128, height:128}))
+      expect([tile.width, tile.height]).toEqual([128, 128])
+
+      let check = new Canvas(128, 128),
+          checkCtx = check.getContext('2d'),
+          at = (x, y) => Array.from(checkCtx.getImageData(x, y, 1, 1).data);
+      checkCtx.drawImage(tile, 0, 0)
+      expect(at(0, 0)).toEqual([255, 0, 0, 255])
+      expect(at(127, 127)).toEqual([0, 0, 255, 255])
+      expect(at(127, 0)).toEqual([0, 0, 0, 0])
+
+      // omitted dimensions extend to the canvas's far edge
+      let corner = await loadImage(canvas.toBuffer({format:'png', left:500, top:400}))
+      expect([corner.width, corner.height]).toEqual([12, 112])
+      expect(canvas.toDataURL('png', {width:10, height:10})).toMatch(/^data:image\/png;base64,/)
+
+      expect(() => canvas.toBuffer('png', {width:0, height:10})).toThrow(RangeError)
+      expect(() => canvas.toBuffer('png', {left:NaN})).toThrow(TypeError)
+    })
+
+    test("a tile pyramid", async () => {
+      canvas.width = 512
+      canvas.height = 256
+      ctx.fillStyle = 'red'
+      ctx.fillRect(0, 0, 256, 256)
+      ctx.fillStyle = 'blue'
+      ctx.fillRect(256, 0, 256, 256)
+
+      let tiles = {},
+          count = await canvas.exportTiles({tileSize:128, zoomLevels:1, callback:(buf, {z, x, y}) => {
+            tiles[`${z}/${x}/${y}`] = buf
+          }})
+      expect(count).toBe(3)
+      expect(Object.keys(tiles).sort()).toEqual(['0/0/0', '1/0/0', '1/1/0'])
+
+      let check = new Canvas(128, 128),
+          checkCtx = check.getContext('2d'),
+          at = (x, y) => Array.from(checkCtx.getImageData(x, y, 1, 1).data);
+      checkCtx.drawImage(await loadImage(tiles['0/0/0']), 0, 0)
+      expect(at(32, 32)).toEqual([255, 0, 0, 255])
+      expect(at(96, 32)).toEqual([0, 0, 255, 255])
+      expect(at(64, 96)).toEqual([0, 0, 0, 0])
+
+      checkCtx.drawImage(await loadImage(tiles['1/1/0']), 0, 0)
+      expect(at(64, 64)).toEqual([0, 0, 255, 255])
+
+      let flipped = []
+      await canvas.exportTiles({tileSize:128, zoomLevels:[1], scheme:'tms', callback:(buf, {y}) => flipped.push(y)})
+      expect(flipped).toEqual([1, 1])
+
+      await expect(canvas.exportTiles({zoomLevels:1})).rejects.toThrow(TypeError)
+      await expect(canvas.exportTiles({format:'pdf', callback:() => {}})).rejects.toThrow()
+    })
+
+    test("consistent export options", async () => {
+      canvas.width = 40
+      canvas.height = 20
+      ctx.fillStyle = 'red'
+      ctx.fillRect(0, 0, 20, 20)
+
+      let size = png => [png.readUInt32BE(16), png.readUInt32BE(20)],
+          opts = {left:10, width:20, density:2};
+      expect(size(canvas.toBuffer('png', opts))).toEqual([40, 40])
+      expect(size(await canvas.toBufferAsync('png', opts))).toEqual([40, 40])
+      expect(await canvas.toDataURLAsync('png', opts)).toEqual(canvas.toDataURL('png', opts))
+
+      // transparent regions are filled by the matte color
+      let check = new Canvas(40, 20),
+          checkCtx = check.getContext('2d'),
+          at = (x, y) => Array.from(checkCtx.getImageData(x, y, 1, 1).data);
+      checkCtx.drawImage(await loadImage(await canvas.toBufferAsync('png', {matte:'blue'})), 0, 0)
+      expect(at(5, 5)).toEqual([255, 0, 0, 255])
+      expect(at(35, 5)).toEqual([0, 0, 255, 255])
+
+      // files receive the same treatment, whether saved singly or as a sequence
+      let dir = fs.mkdtempSync(require('path').join(require('os').tmpdir(), 'skia-canvas-'))
+      canvas.saveAs(`${dir}/cropped.png`, opts)
+      expect(size(fs.readFileSync(`${dir}/cropped.png`))).toEqual([40, 40])
+      canvas.newPage(10, 10)
+      await canvas.saveAsAsync(`${dir}/page-{2}.png`, {density:3})
+      expect(size(fs.readFileSync(`${dir}/page-01.png`))).toEqual([120, 60])
+      expect(size(fs.readFileSync(`${dir}/page-02.png`))).toEqual([30, 30])
+      fs.rmSync(dir, {recursive:true})
+
+      expect(() => canvas.toBuffer('png', {density:0})).toThrow(RangeError)
+      expect(() => canvas.toBuffer('png', {matte:'not-a-color'})).toThrow(TypeError)
+      await expect(canvas.toBufferAsync('bmp')).rejects.toThrow()
+    })
+
+    test("a matte applied at export time", async () => {
+      canvas.width = 32
+      canvas.height = 16
+      ctx.fillStyle = 'red'
+      ctx.fillRect(0, 0, 16, 16)
+
+      let check = new Canvas(32, 16),
+          checkCtx = check.getContext('2d'),
+          at = (x, y) => Array.from(checkCtx.getImageData(x, y, 1, 1).data),
+          load = async buf => { checkCtx.clearRect(0, 0, 32, 16); checkCtx.drawImage(await loadImage(buf), 0, 0) };
+
+      // the same recording can produce both matted and transparent images
+      await load(canvas.toBuffer('jpg', {matte:'white'}))
+      expect(at(24, 8).every(c => c > 250)).toBe(true)
+      await load(canvas.toBuffer('png'))
+      expect(at(24, 8)).toEqual([0, 0, 0, 0])
+
+      await load(canvas.toBuffer('png', {matte:'checkerboard'}))
+      expect(at(20, 4)).toEqual([255, 255, 255, 255])
+      expect(at(28, 4)).toEqual([192, 192, 192, 255])
+      expect(at(8, 8)).toEqual([255, 0, 0, 255])
+
+      await load(canvas.toBuffer('png', {matte:{size:4, colors:['black', 'lime']}}))
+      expect(at(17, 1)).toEqual([0, 0, 0, 255])
+      expect(at(21, 1)).toEqual([0, 255, 0, 255])
+
+      expect(() => canvas.toBuffer('png', {matte:{colors:['black']}})).toThrow(TypeError)
+    })
+
+    test("a trimmed export", async () => {
+      canvas.width = 100
+      canvas.height = 100
+      let size = png => [png.readUInt32BE(16), png.readUInt32BE(20)]
+      expect(() => canvas.toBuffer('png', {trim:true})).toThrow(RangeError)
+
+      ctx.fillStyle = 'red'
+      ctx.fillRect(20, 30, 40, 10)
+      ctx.fillRect(50, 50, 10, 20)
+      expect(size(canvas.toBuffer('png', {trim:true}))).toEqual([40, 40])
+      expect(size(canvas.toBuffer('png', {trim:true, padding:5}))).toEqual([50, 50])
+      expect(size(canvas.toBuffer('png', {trim:true, density:2}))).toEqual([80, 80])
+
+      let check = new Canvas(50, 50),
+          checkCtx = check.getContext('2d'),
+          at = (x, y) => Array.from(checkCtx.getImageData(x, y, 1, 1).data);
+      checkCtx.drawImage(await loadImage(canvas.toBuffer('png', {trim:true, padding:5})), 0, 0)
+      expect(at(5, 5)).toEqual([255, 0, 0, 255])
+      expect(at(4, 4)).toEqual([0, 0, 0, 0])
+      expect(at(10, 30)).toEqual([0, 0, 0, 0])
+      expect(at(40, 44)).toEqual([255, 0, 0, 255])
+
+      expect(() => canvas.toBuffer('png', {trim:true, left:10})).toThrow(TypeError)
+    })
+
+    test("vector text or outlines", () => {
+      ctx.font = '32px serif'
+      ctx.fillText('Invoice #1234', 20, 50)
+
+      let svg = canvas.toBuffer('svg').toString()
+      expect(svg).toMatch(/<text/)
+      expect(svg).toMatch(/Invoice/)
+
+      let outlined = canvas.toBuffer('svg', {outlineText:true}).toString()
+      expect(outlined).not.toMatch(/<text/)
+      expect(outlined).toMatch(/<path/)
+      expect(canvas.toDataURL('svg', {outlineText:true})).toMatch(/^data:image\/svg\+xml;base64,/)
+
+      // pdfs always keep their text selectable
+      expect(canvas.toBuffer('pdf').toString('latin1')).toMatch(/\/Type \/Font\b/)
+      expect(() => canvas.toBuffer('pdf', {outlineText:true})).toThrow(TypeError)
+    })
+
+    test("PDF metadata", () => {
+      ctx.fillRect(0, 0, 10, 10)
+      let metadata = {title:'Quarterly Report', author:'Accounts', keywords:['finance', 'q3']},
+          pdf = canvas.toBuffer('pdf', {metadata}).toString('latin1')
+      expect(pdf).toMatch(/\/Title \(Quarterly Report\)/)
+      expect(pdf).toMatch(/\/Author \(Accounts\)/)
+      expect(pdf).toMatch(/\/Keywords \(finance, q3\)/)
+      expect(pdf).not.toMatch(/pdfaid/)
+
+      let archival = canvas.toBuffer('pdf', {metadata, pdfa:true}).toString('latin1')
+      expect(archival).toMatch(/pdfaid:part/)
+    })
+
+    test("PDF bookmarks", () => {
+      let title = str => `<${Buffer.from('\uFEFF' + str, 'utf16le').swap16().toString('hex').toUpperCase()}>`
+
+      ctx.fillRect(0, 0, 10, 10)
+      let intro = canvas.addBookmark('Introduction')
+      canvas.newPage(WIDTH, HEIGHT)
+      canvas.addBookmark('Résumé', {y:100, parent:intro})
+      canvas.addBookmark('Appendix', {page:1, y:HEIGHT / 2})
+
+      let pdf = canvas.toBuffer('pdf').toString('latin1')
+      expect(pdf).toMatch(/\/Outlines \d+ 0 R/)
+      expect(pdf).toMatch(/\/PageMode \/UseOutlines/)
+      expect(pdf).toMatch(/\/Type \/Outlines [^>]*\/Count 3/)
+      for (const name of ['Introduction', 'Résumé', 'Appendix']) expect(pdf).toContain(title(name))
+      // each entry points to a named destination that skia recorded in the page
+      let dests = [...pdf.matchAll(/\/Dest \/(bookmark-\d+)/g)].map(m => m[1])
+      expect(dests).toHaveLength(3)
+      for (const dest of dests) expect(pdf).toMatch(new RegExp(`/${dest}\\s*\\[\\s*\\d+ 0 R\\s*/XYZ`))
+
+      // exporting a single page only includes its own bookmarks
+      let page = canvas.toBuffer('pdf', {page:2}).toString('latin1')
+      expect(page).toContain(title('Résumé'))
+      expect(page).not.toContain(title('Introduction'))
+
+      expect(() => canvas.addBookmark('Nowhere', {page:3})).toThrow(RangeError)
+      expect(() => canvas.addBookmark('Orphan', {parent:{title:'Introduction'}})).toThrow(TypeError)
+
+      // saving a page sequence keeps each file’s bookmarks
+      let dir = fs.mkdtempSync(require('path').join(require('os').tmpdir(), 'skia-canvas-'))
+      canvas.saveAs(`${dir}/page-{}.pdf`)
+      expect(fs.readFileSync(`${dir}/page-1.pdf`, 'latin1')).toContain(title('Appendix'))
+      expect(fs.readFileSync(`${dir}/page-2.pdf`, 'latin1')).toContain(title('Résumé'))
+      fs.rmSync(dir, {recursive:true})
+
+      // resizing clears the current page & discards the bookmarks that pointed to it
+      canvas.width = WIDTH / 2
+      let resized = canvas.toBuffer('pdf').toString('latin1')
+      expect(resized).toContain(title('Introduction'))
+      expect(resized).not.toContain(title('Résumé'))
+    })
+
+    test("PDF/A output", () => {
+      ctx.fillRect(0, 0, 10, 10)
+      let creation = new Date(Date.UTC(2024, 2, 15, 9, 30, 0)),
+          modified = new Date(Date.UTC(2024, 3, 1, 12, 0, 0)),
+          pdf = canvas.toBuffer('pdf', {pdfa:true, metadata:{title:'Proof', creation, modified}}).toString('latin1')
+
+      // xmp metadata, dates, and an srgb output intent with its icc profile are all embedded
+      expect(pdf).toMatch(/pdfaid:part>2</)
+      expect(pdf).toMatch(/pdfaid:conformance>B</)
+      expect(pdf).toMatch(/\/CreationDate \(D:20240315093000/)
+      expect(pdf).toMatch(/\/ModDate \(D:20240401120000/)
+      expect(pdf).toMatch(/xmp:CreateDate>2024-03-15T09:30:00/)
+      expect(pdf).toMatch(/\/OutputIntents/)
+      expect(pdf).toMatch(/GTS_PDFA1/)
+
+      // dates default to the time of export
+      let year = new Date().getUTCFullYear()
+      expect(canvas.toBuffer('pdf', {pdfa:true}).toString('latin1')).toMatch(new RegExp(`/CreationDate \\(D:${year}`))
+      expect(() => canvas.toBuffer('pdf', {metadata:{creation:'yesterday'}})).toThrow(TypeError)
+    })
+
+    test("CMYK TIFF output", () => {
+      ctx.fillRect(0, 0, 10, 10)
+
+      // conversion requires a cmyk output profile & a known rendering intent
+      expect(() => canvas.toBuffer('tif', {profile:42})).toThrow(TypeError)
+      expect(() => canvas.toBuffer('tiff', {profile:Buffer.alloc(16), intent:'vivid'})).toThrow(TypeError)
+      expect(() => canvas.toBuffer('tiff', {profile:Buffer.alloc(16)})).toThrow(/ICC profile/)
+      expect(() => canvas.toBuffer('tiff', {profile:'/nonexistent/press.icc'})).toThrow()
+
+      // the fixture profile prints everything using black ink in proportion to its darkness
+      let icc = `${__dirname}/assets/cmyk.icc`,
+          tiff = canvas.toBuffer('tiff', {profile:icc}),
+          count = tiff.readUInt16LE(8),
+          tags = {};
+      expect(tiff.toString('latin1', 0, 2)).toBe('II')
+      for (let i=0, pos=10; i<count; i++, pos+=12){
+        let [tag, kind, len] = [tiff.readUInt16LE(pos), tiff.readUInt16LE(pos + 2), tiff.readUInt32LE(pos + 4)]
+        tags[tag] = kind == 3 && len == 1 ? tiff.readUInt16LE(pos + 8) : tiff.readUInt32LE(pos + 8)
+      }
+      expect([tags[256], tags[257]]).toEqual([WIDTH, HEIGHT]) // dimensions
+      expect(tags[262]).toBe(5) // PhotometricInterpretation: separated
+      expect(tags[332]).toBe(1) // InkSet: cmyk
+      expect(tags[277]).toBe(4) // SamplesPerPixel
+      expect(tiff.subarray(tags[34675], tags[34675] + fs.statSync(icc).size)).toEqual(fs.readFileSync(icc))
+
+      let pixels = require('zlib').inflateSync(tiff.subarray(tags[273], tags[273] + tags[279])),
+          at = (x, y) => Array.from(pixels.subarray((y * WIDTH + x) * 4, (y * WIDTH + x + 1) * 4));
+      expect(pixels.length).toBe(WIDTH * HEIGHT * 4)
+      expect(at(5, 5)).toEqual([0, 0, 0, 255])
+      expect(at(50, 50)).toEqual([0, 0, 0, 0])
+    })
+
+    test("a pooled canvas", async () => {
+      let first = Canvas.withPooled(20, 10, (canvas, ctx) => {
+        expect(canvas.width).toBe(20)
+        expect(canvas.height).toBe(10)
+        ctx.fillStyle = 'red'
+        ctx.fillRect(0, 0, 20, 10)
+        canvas.newPage(30, 30)
+        return canvas
+      })
+
+      // the same canvas is reused, with its content, pages, and state reset
+      let second = await Canvas.withPooled(20, 10, async (canvas, ctx) => {
+        expect(canvas.pages.length).toBe(1)
+        expect(canvas.height).toBe(10)
+        expect(ctx.fillStyle).toBe('#000000')
+        expect(Array.from(ctx.getImageData(5, 5, 1, 1).data)).toEqual([0,0,0,0])
+        return canvas
+      })
+      expect(second).toBe(first)
+
+      expect(() => Canvas.withPooled(20, 10, () => { throw new Error('oops') })).toThrow('oops')
+      expect(Canvas.withPooled(20, 10, canvas => canvas)).toBe(first)
+    })
+
+    test("a rendering engine", () => {
+      expect(canvas.engine).toMatchObject({api:'cpu', device:0})
+      expect(canvas.engine.error).toBeUndefined()
+
+      // no gpu backends are compiled in, so they all fall back to the cpu
+      for (const api of ['vulkan', 'metal', 'gl']){
+        let fallback = new Canvas(10, 10, {gpu:api})
+        expect(fallback.engine.api).toBe('cpu')
+        expect(fallback.engine.error).toMatch(`without ${api} support`)
+        expect(() => new Canvas(10, 10, {gpu:api, fallback:false})).toThrow()
+      }
+
+      expect(() => new Canvas(10, 10, {gpu:'nonesuch', fallback:false})).toThrow()
+      expect(() => new Canvas(10, 10, {gpu:'cpu', device:3, fallback:false})).toThrow()
+      expect(new Canvas(10, 10, {density:2}).density).toBe(2)
+    })
+
+    test("a picture budget", () => {
+      expect(canvas.engine).toMatchObject({flatten:'auto', pictureBudgetMB:null})
+      ctx.fillStyle = 'red'
+      ctx.fillRect(0, 0, 10, 10)
+      expect(canvas.toBuffer('svg').toString()).not.toMatch(/<image/)
+
+      // flattening trades the vector content for a bitmap
+      canvas.engine = {flatten:'always'}
+      expect(canvas.engine).toMatchObject({api:'cpu', flatten:'always'})
+      ctx.fillRect(20, 0, 10, 10)
+      expect(canvas.toBuffer('svg').toString()).toMatch(/<image/)
+      expect(pixel(5, 5)).toEqual([255, 0, 0, 255])
+      expect(pixel(25, 5)).toEqual([255, 0, 0, 255])
+
+      // in auto mode the recording is only flattened once it exceeds the budget
+      let other = new Canvas(WIDTH, HEIGHT),
+          otherCtx = other.getContext('2d');
+      other.engine = {pictureBudgetMB:0.01}
+      for (let i=0; i<500; i++) otherCtx.fillRect(i % WIDTH, i % 50, 1, 1)
+      expect(other.toBuffer('svg').toString()).toMatch(/<image/)
+
+      expect(() => canvas.engine = {flatten:'sometimes'}).toThrow(TypeError)
+      expect(() => canvas.engine = {pictureBudgetMB:-1}).toThrow(RangeError)
+      expect(canvas.engine.flatten).toBe('always')
+    })
+
+    test("OffscreenCanvas.transferToImageBitmap()", () => {
+      let offscreen = new OffscreenCanvas(40, 20),
+          offCtx = offscreen.getContext("2d");
+      expect(offscreen).toBeInstanceOf(Canvas)
+      offCtx.fillStyle = 'red'
+      offCtx.translate(10, 0)
+      offCtx.fillRect(0, 0, 10, 10)
+
+      let bitmap = offscreen.transferToImageBitmap()
+      expect(bitmap).toBeInstanceOf(Image)
+      expect(bitmap.complete).toBe(true)
+      expect([bitmap.width, bitmap.height]).toEqual([40, 20])
+
+      // the canvas is left blank but keeps its drawing state
+      expect(Array.from(offCtx.getImageData(15, 5, 1, 1).data)).toEqual([0, 0, 0, 0])
+      expect(offCtx.fillStyle).toBe('#ff0000')
+      expect(offCtx.getTransform().e).toBe(10)
+
+      ctx.drawImage(bitmap, 0, 0)
+      expect(pixel(15, 5)).toEqual([255, 0, 0, 255])
+      expect(pixel(5, 5)).toEqual([0, 0, 0, 0])
+    })
+
+    test("drawImage() from a Canvas or context", () => {
+      let src = new Canvas(20, 20),
+          srcCtx = src.getContext("2d");
+      srcCtx.fillStyle = 'blue'
+      srcCtx.fillRect(0, 0, 20, 20)
+
+      ctx.drawImage(src, 0, 0)
+      ctx.drawImage(srcCtx, 20, 0, 40, 40)
+      expect(pixel(10, 10)).toEqual([0, 0, 255, 255])
+      expect(pixel(50, 30)).toEqual([0, 0, 255, 255])
+      expect(pixel(70, 30)).toEqual([0, 0, 0, 0])
+
+      // the usual compositing settings apply
+      ctx.globalAlpha = 0.5
+      ctx.drawImage(src, 100, 0)
+      expect(pixel(110, 10)).toEqual([0, 0, 255, 128])
+
+      // the source's vector content is preserved in exports
+      let svg = canvas.toBuffer('svg').toString()
+      expect(svg).not.toMatch(/<image/)
+    })
+
+    test("drawImageNine()", () => {
+      let src = new Canvas(30, 30),
+          srcCtx = src.getContext("2d");
+      srcCtx.fillStyle = 'red'
+      srcCtx.fillRect(0, 0, 30, 30)
+      srcCtx.fillStyle = 'lime'
+      srcCtx.fillRect(10, 10, 10, 10)
+
+      // corners stay fixed while the edges & center stretch
+      ctx.drawImageNine(src, [10, 10, 10, 10], {x:0, y:0, width:300, height:300}, 'nearest')
+      expect(pixel(5, 5)).toEqual([255, 0, 0, 255])
+      expect(pixel(295, 295)).toEqual([255, 0, 0, 255])
+      expect(pixel(5, 150)).toEqual([255, 0, 0, 255])
+      expect(pixel(150, 5)).toEqual([255, 0, 0, 255])
+      expect(pixel(15, 15)).toEqual([0, 255, 0, 255])
+      expect(pixel(285, 285)).toEqual([0, 255, 0, 255])
+      expect(pixel(305, 150)).toEqual([0, 0, 0, 0])
+
+      expect(() => ctx.drawImageNine(src, [20, 20, 20, 20], [0, 0, 100, 100])).toThrow(RangeError)
+      expect(() => ctx.drawImageNine(src, [10, 10, 10, 10], [0, 0, 100, 100], 'cubic')).toThrow(TypeError)
+    })
+
+    test("drawImageMesh()", () => {
+      let src = new Canvas(20, 20),
+          srcCtx = src.getContext("2d");
+      srcCtx.fillStyle = 'red'
+      srcCtx.fillRect(0, 0, 10, 20)
+      srcCtx.fillStyle = 'blue'
+      srcCtx.fillRect(10, 0, 10, 20)
+
+      // map the image onto a quad built from two triangles sharing a diagonal
+      let quad = [[0, 0], [200, 0], [200, 100], [0, 100]],
+          uvs = [{x:0, y:0}, {x:20, y:0}, {x:20, y:20}, {x:0, y:20}];
+      ctx.drawImageMesh(src, quad, uvs, [0, 1, 2, 0, 2, 3])
+      expect(pixel(50, 50)).toEqual([255, 0, 0, 255])
+      expect(pixel(150, 50)).toEqual([0, 0, 255, 255])
+      expect(pixel(250, 50)).toEqual([0, 0, 0, 0])
+
+      // un-indexed meshes use each trio of points as a triangle
+      ctx.drawImageMesh(src, [0,200, 200,200, 0,300], [0,0, 20,0, 0,20])
+      expect(pixel(20, 220)).toEqual([255, 0, 0, 255])
+      expect(pixel(150, 290)).toEqual([0, 0, 0, 0])
+
+      expect(() => ctx.drawImageMesh(src, quad, uvs)).toThrow(RangeError)
+      expect(() => ctx.drawImageMesh(src, quad, uvs.slice(1), [0, 1, 2])).toThrow(RangeError)
+      expect(() => ctx.drawImageMesh(src, quad, uvs, [0, 1, 4])).toThrow(RangeError)
+    })
+
+    test("drawMesh()", () => {
+      // vertex colors are interpolated across each triangle
+      ctx.drawMesh({positions:[[0, 0], [100, 0], [100, 100], [0, 100]], indices:[0, 1, 2, 0, 2, 3],
+                    colors:['red', 'red', 'blue', 'blue']})
+      let [r, g, b, a] = pixel(50, 50)
+      expect(r).toBeGreaterThan(50)
+      expect(b).toBeGreaterThan(50)
+      expect(a).toBe(255)
+      let [top, bottom] = [pixel(50, 2), pixel(50, 98)]
+      expect(top[0]).toBeGreaterThan(top[2])
+      expect(bottom[2]).toBeGreaterThan(bottom[0])
+
+      // without colors the mesh is filled with the fillStyle (or an explicit shader)
+      ctx.fillStyle = 'lime'
+      ctx.drawMesh({positions:new Float32Array([200, 0, 300, 0, 200, 100]), mode:'fan'})
+      expect(pixel(210, 10)).toEqual([0, 255, 0, 255])
+
+      let gradient = ctx.createLinearGradient(0, 0, 0, 100)
+      gradient.addColorStop(0, 'blue')
+      gradient.addColorStop(1, 'blue')
+      ctx.drawMesh({positions:[{x:300, y:0}, {x:400, y:0}, {x:300, y:100}], shader:gradient})
+      expect(pixel(310, 10)).toEqual([0, 0, 255, 255])
+
+      expect(() => ctx.drawMesh({})).toThrow(TypeError)
+      expect(() => ctx.drawMesh({positions:[0,0, 1,0, 0,1], mode:'quads'})).toThrow(TypeError)
+      expect(() => ctx.drawMesh({positions:[0,0, 1,0, 0,1], colors:['red']})).toThrow(RangeError)
+      expect(() => ctx.drawMesh({positions:[0,0, 1,0, 0]})).toThrow(RangeError)
+      expect(() => ctx.drawMesh({positions:[0,0, 1,0, 0,1], texCoords:[0,0, 1,0, 0]})).toThrow(RangeError)
+      expect(() => ctx.drawMesh({positions:[0,0, 1,0, 0,1], texCoords:[0, 0]})).toThrow(RangeError)
+    })
+
+    test("fillRects() & drawPoints()", () => {
+      ctx.fillStyle = 'red'
+      ctx.fillRects(new Float32Array([0, 0, 10, 10, 20, 0, 10, 10]))
+      expect(pixel(5, 5)).toEqual([255, 0, 0, 255])
+      expect(pixel(15, 5)).toEqual([0, 0, 0, 0])
+      expect(pixel(25, 5)).toEqual([255, 0, 0, 255])
+
+      ctx.fillStyle = 'blue'
+      ctx.fillRects([[0, 50, 10, 10]])
+      expect(pixel(5, 55)).toEqual([0, 0, 255, 255])
+      expect(() => ctx.fillRects([1, 2, 3])).toThrow(RangeError)
+
+      ctx.strokeStyle = 'lime'
+      ctx.lineWidth = 4
+      ctx.lineCap = 'square'
+      ctx.drawPoints('points', [100, 100, 120, 100])
+      expect(pixel(100, 100)).toEqual([0, 255, 0, 255])
+      expect(pixel(120, 100)).toEqual([0, 255, 0, 255])
+      expect(pixel(110, 100)).toEqual([0, 0, 0, 0])
+
+      ctx.drawPoints('lines', new Float32Array([100, 150, 200, 150]))
+      expect(pixel(150, 150)).toEqual([0, 255, 0, 255])
+      expect(() => ctx.drawPoints('nonesuch', [0, 0])).toThrow(TypeError)
+      expect(() => ctx.drawPoints('points', [0])).toThrow(RangeError)
+    })
+
+    test("drawAtlas()", () => {
+      let src = new Canvas(20, 10),
+          srcCtx = src.getContext("2d");
+      srcCtx.fillStyle = 'red'
+      srcCtx.fillRect(0, 0, 10, 10)
+      srcCtx.fillStyle = 'white'
+      srcCtx.fillRect(10, 0, 10, 10)
+
+      // sprites are positioned with [scale·cos, scale·sin, x, y] transforms
+      ctx.drawAtlas(src, [1, 0, 0, 0,  2, 0, 50, 0], [0, 0, 10, 10,  10, 0, 10, 10], ['white', 'blue'])
+      expect(pixel(5, 5)).toEqual([255, 0, 0, 255])
+      expect(pixel(60, 10)).toEqual([0, 0, 255, 255])
+      expect(pixel(30, 5)).toEqual([0, 0, 0, 0])
+
+      expect(() => ctx.drawAtlas(src, [1, 0, 0, 0], [])).toThrow(RangeError)
+      expect(() => ctx.drawAtlas(src, [1, 0, 0, 0], [0, 0, 10, 10], ['red', 'blue'])).toThrow(RangeError)
+    })
+
+    test("command history", () => {
+      expect(ctx.recordHistory).toBe(false)
+      ctx.fillRect(0, 0, 10, 10)
+      expect(ctx.history()).toEqual([])
+
+      ctx.recordHistory = true
+      ctx.fillStyle = 'red'
+      ctx.fillRect(10, 10, 20, 20)
+      ctx.strokeStyle = 'rgba(0, 0, 255, .5)'
+      ctx.lineWidth = 4
+      ctx.strokeRect(50, 50, 10, 10)
+      ctx.clearRect(0, 0, 5, 5)
+
+      let [fill, stroke, clear] = ctx.history()
+      expect(ctx.history().length).toBe(3)
+      expect(fill.op).toBe('fillRect')
+      expect(fill.paint).toMatchObject({style:'fill', color:'#ff0000', alpha:1, blendMode:'source-over', shadow:false})
+      expect([fill.bounds.x, fill.bounds.y, fill.bounds.width, fill.bounds.height]).toEqual([10, 10, 20, 20])
+      expect(stroke.op).toBe('strokeRect')
+      expect(stroke.paint).toMatchObject({style:'stroke', color:'#0000ff', lineWidth:4})
+      expect(stroke.paint.alpha).toBeCloseTo(0.5, 1)
+      expect([stroke.bounds.x, stroke.bounds.width]).toEqual([48, 14])
+      expect(clear.op).toBe('clearRect')
+
+      // only the commands that pass the filter are redrawn
+      let replayed = ctx.replay(({op}) => op == 'fillRect'),
+          replayedCtx = replayed.getContext("2d"),
+          at = (x, y) => [...replayedCtx.getImageData(x, y, 1, 1).data];
+      expect([replayed.width, replayed.height]).toEqual([canvas.width, canvas.height])
+      expect(at(15, 15)).toEqual([255, 0, 0, 255])
+      expect(at(50, 55)).toEqual([0, 0, 0, 0])
+
+      // high-density canvases are replayed at the same resolution
+      let hires = new Canvas(100, 100, {density:2}),
+          hiresCtx = hires.getContext("2d");
+      hiresCtx.recordHistory = true
+      hiresCtx.fillRect(80, 80, 10, 10)
+      let copy = hiresCtx.replay()
+      expect(copy.density).toBe(2)
+      expect(copy.toBuffer('png')).toEqual(hires.toBuffer('png'))
+
+      ctx.recordHistory = false
+      expect(ctx.history()).toEqual([])
+    })
+
+    test("recording stats", () => {
+      let empty = ctx.stats()
+      expect(empty).toMatchObject({opCount:0, pathVerbs:0, pathPoints:0, stackDepth:0})
+
+      ctx.fillRect(0, 0, 10, 10)
+      ctx.fillRect(-20, -20, 10, 10) // culled
+      ctx.save()
+      ctx.beginPath()
+      ctx.moveTo(0, 0)
+      ctx.lineTo(10, 10)
+      let stats = ctx.stats()
+      expect(stats.opCount).toBe(1)
+      expect(stats.bytesUsed).toBeGreaterThan(empty.bytesUsed)
+      expect(stats.pathVerbs).toBe(2)
+      expect(stats.pathPoints).toBe(2)
+      expect(stats.stackDepth).toBe(1)
+      ctx.restore()
+
+      canvas.width = WIDTH
+      expect(ctx.stats().opCount).toBe(0)
+    })
+
+    test("offscreen culling", () => {
+      ctx.recordHistory = true
+      ctx.fillRect(-50, -50, 20, 20)
+      ctx.fillRect(WIDTH + 10, 0, 20, 20)
+      expect(ctx.history().length).toBe(0)
+
+      // strokes are padded by their width when deciding whether they're visible
+      ctx.lineWidth = 20
+      ctx.strokeRect(-18, 10, 10, 10)
+      expect(ctx.history().length).toBe(1)
+
+      ctx.save()
+      ctx.beginPath()
+      ctx.rect(0, 0, 100, 100)
+      ctx.clip()
+      ctx.fillRect(200, 200, 20, 20)
+      expect(ctx.history().length).toBe(1)
+
+      // shadows may land within the clip even when the shape itself doesn't
+      ctx.shadowColor = 'black'
+      ctx.shadowOffsetX = -150
+      ctx.shadowOffsetY = -150
+      ctx.fillRect(200, 200, 20, 20)
+      expect(ctx.history().length).toBe(2)
+      ctx.restore()
+
+      ctx.fillStyle = 'red'
+      ctx.fillRect(200, 200, 20, 20)
+      expect(ctx.history().length).toBe(3)
+      expect(pixel(210, 210)).toEqual([255, 0, 0, 255])
+    })
+
+    test("Image from SVG", async () => {
+      let svg = Buffer.from(`<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10" width="10">
+        <rect x="0" y="0" width="5" height="10" fill="#f00"/>
+        <rect x="5" y="0" width="5" height="10" fill="#00f"/>
+      </svg>`),
+          image = await loadImage(svg);
+      expect(image.complete).toBe(true)
+      expect(image.width).toBe(10)
+      expect(image.height).toBe(10)
+
+      // the vector content is rendered at the destination size
+      ctx.drawImage(image, 0, 0, 100, 100)
+      expect(pixel(25, 50)).toEqual([255, 0, 0, 255])
+      expect(pixel(75, 50)).toEqual([0, 0, 255, 255])
+      expect(pixel(150, 50)).toEqual([0, 0, 0, 0])
+    })
+
+    test("Image decoding", async () => {
+      let img = new Image()
+      await expect(img.decode()).rejects.toThrow()
+
+      // local images are decoded synchronously by default...
+      expect(img.decoding).toBe('auto')
+      img.src = `${__dirname}/assets/globe.jpg`
+      expect(img.complete).toBe(true)
+      expect([img.width, img.height]).toEqual([400, 266])
+      await img.decode()
+
+      // ...or in the background if requested
+      let deferred = Object.assign(new Image(), {decoding:'async'})
+      deferred.decoding = 'invalid'
+      expect(deferred.decoding).toBe('async')
+      deferred.src = img.src
+      expect(deferred.complete).toBe(false)
+      await deferred.decode()
+      expect(deferred.complete).toBe(true)
+      expect([deferred.width, deferred.height]).toEqual([400, 266])
+
+      // onload & onerror are called when decoding finishes
+      let loaded = await new Promise(onload => Object.assign(new Image(), {onload, src:img.src}))
+      expect(loaded.complete).toBe(true)
+
+      let broken = new Image(),
+          err = await new Promise(onerror => Object.assign(broken, {onerror, src:Buffer.from("not an image")}))
+      expect(err).toBeInstanceOf(Error)
+      expect(broken.complete).toBe(false)
+      await expect(broken.decode()).rejects.toThrow("Could not decode image data")
+    })
+
+    test("Image with EXIF orientation", async () => {
+      let upright = await loadAsset('globe.jpg'),
+          rotated = await loadAsset('globe-rotated.jpg');
+      expect(upright.orientation).toBe(1)
+      expect([upright.width, upright.height]).toEqual([400, 266])
+
+      // the 90° rotation tag swaps the intrinsic dimensions
+      expect(rotated.orientation).toBe(6)
+      expect([rotated.width, rotated.height]).toEqual([266, 400])
+    })
+
+    test("animated Image frames", async () => {
+      let image = await loadAsset('frames.gif')
+      expect(image.frameCount).toBe(2)
+      expect(image.frameDuration(0)).toBe(100)
+      expect(image.frameDuration(1)).toBe(200)
+
+      // the first frame is shown by default
+      ctx.drawImage(image, 0, 0)
+      expect(pixel(3, 3)).toEqual([255, 0, 0, 255])
+
+      // later frames are composited atop the frames they depend on
+      expect(image.decodeFrame(1)).toBe(image)
+      ctx.drawImage(image, 0, 0)
+      expect(pixel(0, 0)).toEqual([255, 0, 0, 255])
+      expect(pixel(3, 3)).toEqual([0, 0, 255, 255])
+
+      expect(() => image.decodeFrame(2)).toThrow(RangeError)
+      expect(() => image.decodeFrame(-1)).toThrow(RangeError)
     })
 
     describe("CanvasPattern", () => {
@@ -266,6 +2021,32 @@ describe("Context2D", ()=>{
         }
       })
 
+      test("from Path2D", () => {
+        let dot = new Path2D()
+        dot.rect(0, 0, 5, 5)
+        let pattern = ctx.createPattern(dot, {fillStyle:'red', width:10, height:10})
+        ctx.fillStyle = pattern
+        ctx.fillRect(0, 0, 40, 40)
+        expect(pixel(2, 2)).toEqual([255,0,0,255])
+        expect(pixel(12, 32)).toEqual([255,0,0,255])
+        expect(pixel(7, 2)).toEqual([0,0,0,0])
+        expect(pixel(32, 37)).toEqual([0,0,0,0])
+
+        // vector tiles stay sharp when the pattern is scaled up
+        pattern = ctx.createPattern(dot, 'repeat-x', {strokeStyle:'blue', lineWidth:2, width:10, height:10})
+        pattern.setTransform(new DOMMatrix().scale(4))
+        ctx.clearRect(0, 0, 100, 100)
+        ctx.fillStyle = pattern
+        ctx.fillRect(0, 0, 100, 100)
+        expect(pixel(0, 10)).toEqual([0,0,255,255])
+        expect(pixel(10, 10)).toEqual([0,0,0,0])
+        expect(pixel(41, 0)).toEqual([0,0,255,255])
+        expect(pixel(41, 50)).toEqual([0,0,0,0])
+
+        expect(() => ctx.createPattern(dot, {fillStyle:'nonesuch'})).toThrow(TypeError)
+        expect(() => ctx.createPattern(dot, {width:0})).toThrow(RangeError)
+      })
+
       test("with local transform", () => {
 
         // call func with an ImageData-offset and pixel color value appropriate for a 4-quadrant pattern within
@@ -317,6 +2098,54 @@ describe("Context2D", ()=>{
       })
     })
 
+    test("CanvasShader", () => {
+      let shader = ctx.createShader(`
+        uniform half4 tint;
+        half4 main(float2 xy){ return tint; }
+      `, {tint:[1, 0, 0, 1]})
+      expect(shader.uniforms).toEqual(['tint'])
+      expect(() => shader.setUniform('nonesuch', 1)).toThrow()
+
+      ctx.fillStyle = shader
+      expect(ctx.fillStyle).toBe(shader)
+      ctx.fillRect(0,0,10,10)
+      expect(pixel(5, 5)).toEqual([255,0,0,255])
+
+      shader.setUniform('tint', [0, 0, 1, 1])
+      ctx.fillRect(0,0,10,10)
+      expect(pixel(5, 5)).toEqual([0,0,255,255])
+
+      expect(() => ctx.createShader('not sksl')).toThrow()
+    })
+
+    test("TextureGenerator", () => {
+      let tex = new TextureGenerator(20, 10)
+      expect(tex.width).toBe(20)
+      expect(tex.height).toBe(10)
+      expect(() => new TextureGenerator(0, 10)).toThrow()
+
+      let checks = tex.checkerboard({size:5, colors:['white', 'black']})
+      expect(checks.complete).toBe(true)
+      expect(checks.width).toBe(20)
+      expect(checks.height).toBe(10)
+      ctx.drawImage(checks, 0, 0)
+      expect(pixel(2, 2)).toEqual([255,255,255,255])
+      expect(pixel(7, 2)).toEqual([0,0,0,255])
+      expect(pixel(7, 7)).toEqual([255,255,255,255])
+
+      let gradient = ctx.createLinearGradient(0,0,20,0)
+      gradient.addColorStop(0, 'red')
+      gradient.addColorStop(1, 'red')
+      ctx.drawImage(tex.fill(gradient), 0, 0)
+      expect(pixel(10, 5)).toEqual([255,0,0,255])
+
+      let noise = tex.noise({type:'turbulence', frequency:0.1, octaves:2})
+      ctx.fillStyle = ctx.createPattern(noise, 'repeat')
+      ctx.fillRect(0, 0, 40, 40)
+      expect(pixel(25, 15)).toEqual(pixel(5, 5))
+      expect(() => tex.noise({type:'nonesuch'})).toThrow()
+    })
+
     describe("CanvasGradient", () => {
       test("linear", () => {
         let gradient = ctx.createLinearGradient(1,1,19,1);
@@ -347,10 +2176,107 @@ describe("Context2D", ()=>{
         expect(pixel(x+outside, y)).toEqual([255,0,0,255])
         expect(pixel(x, y+outside)).toEqual([255,0,0,255])
       })
+
+      test("focal radial", () => {
+        // an off-center starting circle (like SVG's fx/fy) compresses the gradient on the near side
+        let gradient = ctx.createRadialGradient(30, 50, 0, 50, 50, 40)
+        gradient.addColorStop(0, 'red')
+        gradient.addColorStop(1, 'blue')
+        ctx.fillStyle = gradient
+        ctx.fillRect(0, 0, 100, 100)
+
+        expect(pixel(30, 50)).toEqual([255,0,0,255])
+        let [near, far] = [pixel(20, 50), pixel(40, 50)]
+        expect(near[2]).toBeGreaterThan(far[2] + 50)
+        expect(near[0]).toBeLessThan(far[0] - 50)
+        expect(pixel(50, 12)[2]).toBeGreaterThan(pixel(50, 12)[0])
+      })
+
+      test("interpolation", () => {
+        let midpoint = (options) => {
+          let gradient = ctx.createLinearGradient(0, 0, 100, 0, options)
+          gradient.addColorStop(0, 'red')
+          gradient.addColorStop(1, 'blue')
+          ctx.fillStyle = gradient
+          ctx.fillRect(0, 0, 100, 1)
+          return pixel(50, 0)
+        }
+        let near = (actual, expected) => actual.forEach((c, i) => expect(Math.abs(c - expected[i])).toBeLessThanOrEqual(6))
+
+        let gradient = ctx.createLinearGradient(0, 0, 100, 0)
+        expect(gradient.interpolation).toBe('srgb')
+        expect(gradient.hue).toBe('shorter')
+        expect(() => gradient.interpolation = 'cmyk').toThrow(TypeError)
+        expect(() => gradient.hue = 'sideways').toThrow(TypeError)
+
+        near(midpoint(), [128, 0, 128, 255])
+        near(midpoint({interpolation:'srgb-linear'}), [188, 0, 188, 255])
+        near(midpoint({interpolation:'oklab'}), [140, 83, 162, 255])
+        near(midpoint({interpolation:'oklch'}), [186, 0, 194, 255])
+        near(midpoint({interpolation:'oklch', hue:'longer'}), [0, 147, 0, 255])
+
+        // changes to a gradient's settings apply to styles that are already using it
+        gradient = ctx.createLinearGradient(0, 0, 100, 0)
+        gradient.addColorStop(0, 'red')
+        gradient.addColorStop(1, 'blue')
+        ctx.fillStyle = gradient
+        gradient.interpolation = 'srgb-linear'
+        ctx.fillRect(0, 0, 100, 1)
+        near(pixel(50, 0), [188, 0, 188, 255])
+      })
+
+      test("hints & premultiplication", () => {
+        let near = (actual, expected) => actual.forEach((c, i) => expect(Math.abs(c - expected[i])).toBeLessThanOrEqual(6))
+        let gradient = ctx.createLinearGradient(0, 0, 100, 0)
+        gradient.addColorStop(0, 'red')
+        gradient.addColorStop(1, 'blue')
+        gradient.addColorHint(0.25)
+        ctx.fillStyle = gradient
+        ctx.fillRect(0, 0, 100, 1)
+        near(pixel(25, 0), [128, 0, 128, 255])
+        expect(pixel(50, 0)[2]).toBeGreaterThan(160)
+        expect(() => gradient.addColorHint(1.5)).toThrow(RangeError)
+
+        gradient = ctx.createLinearGradient(0, 0, 100, 0)
+        gradient.addColorStop(0, 'rgba(0,0,0,0)')
+        gradient.addColorStop(1, 'red')
+        expect(gradient.premultiplied).toBe(false)
+        ctx.fillStyle = gradient
+        ctx.clearRect(0, 0, 100, 1)
+        ctx.fillRect(0, 0, 100, 1)
+        near(pixel(50, 0), [128, 0, 0, 128])
+
+        gradient.premultiplied = true
+        ctx.clearRect(0, 0, 100, 1)
+        ctx.fillRect(0, 0, 100, 1)
+        near(pixel(50, 0), [255, 0, 0, 128])
+      })
     })
   })
 
   describe("supports", () => {
+    test("gradient shadows", () => {
+      let gradient = ctx.createLinearGradient(0, 0, 50, 0)
+      gradient.addColorStop(0, 'red')
+      gradient.addColorStop(1, 'blue')
+      ctx.shadowColor = gradient
+      expect(ctx.shadowColor).toBe(gradient)
+
+      // the dye is positioned relative to the shape and travels along with the shadow's offset
+      ctx.shadowOffsetX = 100
+      ctx.fillStyle = 'rgba(0,0,0,0.5)'
+      ctx.fillRect(0, 0, 50, 50)
+      let [left, right] = [pixel(102, 25), pixel(148, 25)]
+      expect(left[0]).toBeGreaterThan(left[2])
+      expect(right[2]).toBeGreaterThan(right[0])
+      expect(left[3]).toBeGreaterThan(100)
+      expect(left[3]).toBeLessThan(160)
+      expect(pixel(175, 25)).toEqual([0,0,0,0])
+
+      ctx.shadowColor = 'green'
+      expect(ctx.shadowColor).toBe('#008000')
+    })
+
     test("clip()", () => {
       ctx.fillStyle = 'white'
       ctx.fillRect(0, 0, 2, 2)
@@ -414,6 +2340,38 @@ describe("Context2D", ()=>{
       expect(pixel(1, 1)).toEqual([0,0,0,255])
     })
 
+    test("colorMatrix()", () => {
+      // halve the red channel, then swap red & blue
+      ctx.colorMatrix([
+        [0.5, 0, 0, 0, 0],
+        [0, 1, 0, 0, 0],
+        [0, 0, 1, 0, 0],
+        [0, 0, 0, 1, 0],
+      ])
+      ctx.colorMatrix([
+        [0, 0, 1, 0, 0],
+        [0, 1, 0, 0, 0],
+        [1, 0, 0, 0, 0],
+        [0, 0, 0, 1, 0],
+      ])
+
+      // the second call is composed with the first rather than replacing it
+      expect(ctx.filter.match(/color-matrix/g)).toHaveLength(1)
+      ctx.fillStyle = 'red'
+      ctx.fillRect(0, 0, 2, 2)
+      let [r, g, b, a] = pixel(1, 1)
+      expect([r, g, a]).toEqual([0, 0, 255])
+      expect(Math.abs(b - 128)).toBeLessThanOrEqual(1)
+
+      // offsets in the later matrix are added after the earlier one has been applied
+      ctx.filter = 'none'
+      ctx.colorMatrix([0, 0, 0, 0, 0,  0, 1, 0, 0, 0,  0, 0, 1, 0, 0,  0, 0, 0, 1, 0])
+      ctx.colorMatrix([1, 0, 0, 0, 1,  0, 1, 0, 0, 0,  0, 0, 1, 0, 0,  0, 0, 0, 1, 0])
+      ctx.fillStyle = 'black'
+      ctx.fillRect(0, 0, 2, 2)
+      expect(pixel(1, 1)).toEqual([255, 0, 0, 255])
+    })
+
     test("fillText()", () => {
       let argsets = [
         [['A', 10, 10], true],
@@ -434,6 +2392,233 @@ describe("Context2D", ()=>{
       })
     })
 
+    test("fillAndStrokeText()", () => {
+      let tally = () => {
+        let data = ctx.getImageData(0, 0, 300, 150).data,
+            blue = 0, red = 0;
+        for (let i=0; i<data.length; i+=4){
+          if (data[i]==0 && data[i+2]==255) blue++
+          if (data[i]==255 && data[i+2]==0) red++
+        }
+        return {blue, red}
+      }
+
+      ctx.font = 'bold 96px sans-serif'
+      ctx.fillStyle = '#00f'
+      ctx.strokeStyle = '#f00'
+      ctx.lineWidth = 8
+      ctx.fillAndStrokeText('Hi', 20, 120)
+      let strokeOnTop = tally()
+      expect(strokeOnTop.blue).toBeGreaterThan(0)
+      expect(strokeOnTop.red).toBeGreaterThan(0)
+
+      // painting the stroke first lets the fill cover its inner half
+      ctx.clearRect(0, 0, WIDTH, HEIGHT)
+      ctx.paintOrder = 'stroke fill'
+      ctx.fillAndStrokeText('Hi', 20, 120)
+      let fillOnTop = tally()
+      expect(fillOnTop.blue).toBeGreaterThan(strokeOnTop.blue)
+      expect(fillOnTop.red).toBeLessThan(strokeOnTop.red)
+
+      // the outline is the same as the one strokeText() would draw
+      let other = new Canvas(WIDTH, HEIGHT).getContext('2d')
+      for (let c of [ctx, other]){
+        c.clearRect(0, 0, WIDTH, HEIGHT)
+        Object.assign(c, {font:'bold 96px sans-serif', fillStyle:'#00f', strokeStyle:'#f00', lineWidth:8, paintOrder:'normal'})
+      }
+      ctx.fillAndStrokeText('Hi', 20, 120)
+      other.fillText('Hi', 20, 120)
+      other.strokeText('Hi', 20, 120)
+      expect(ctx.getImageData(0, 0, 300, 150).data).toEqual(other.getImageData(0, 0, 300, 150).data)
+    })
+
+    test("tab stops", () => {
+      let xPositions = text => ctx.measureText(text).lines[0].graphemes
+        .filter(g => g.text != '\t').map(g => Math.round(g.x));
+
+      ctx.font = '16px sans-serif'
+      ctx.tabSize = '50px'
+      expect(xPositions("a\tb\tc")).toEqual([0, 50, 100])
+      expect(xPositions("abcdefghij\tb")[10]).toBe(100)
+
+      // explicit stops come first, then the text continues at multiples of the tabSize
+      ctx.tabStops = [30, 120]
+      expect(xPositions("a\tb\tc\td")).toEqual([0, 30, 120, 150])
+
+      // a tab's width is measured in spaces by default
+      ctx.tabStops = []
+      ctx.tabSize = 4
+      let narrow = xPositions("\tx")[0]
+      ctx.tabSize = 8
+      expect(narrow).toBeGreaterThan(0)
+      expect(Math.abs(xPositions("\tx")[0] - narrow * 2)).toBeLessThan(2)
+
+      // wrapped lines start counting from zero again
+      ctx.textWrap = true
+      ctx.tabSize = '50px'
+      let [first, second] = ctx.measureText("ab\tc\nd\te").lines
+      expect(Math.round(second.graphemes.find(g => g.text == 'e').x)).toBe(50)
+    })
+
+    test("text backgrounds", () => {
+      ctx.font = '20px sans-serif'
+      ctx.textWrap = true
+      ctx.fillStyle = 'black'
+      ctx.textBaseline = 'top'
+      ctx.textBackground = {color:'#00f', padding:4, radius:6}
+      ctx.fillText("Caption\nText", 40, 40)
+
+      // the highlight is drawn behind each line and extends into the padding
+      let [first, second] = ctx.measureText("Caption\nText").lines,
+          middle = line => Math.round(40 + line.y + line.height / 2);
+      expect(pixel(40 - 2, middle(first))).toEqual([0, 0, 255, 255])
+      expect(pixel(Math.round(40 + second.width + 2), middle(second))).toEqual([0, 0, 255, 255])
+      expect(pixel(Math.round(40 + first.width + 2), middle(second))).toEqual([0, 0, 0, 0])
+
+      // rounded corners leave the outermost pixels blank
+      expect(pixel(40 - 4, 40 + first.y - 4)[3]).toBeLessThan(64)
+    })
+
+    test("text overflow", () => {
+      let text = "The quick brown fox jumps over the lazy dog"
+      ctx.font = '16px sans-serif'
+
+      // a single line is truncated mid-word with an ellipsis rather than at the last word that fits
+      let clipped = ctx.measureText(text, 100)
+      ctx.textOverflow = 'ellipsis'
+      let truncated = ctx.measureText(text, 100)
+      expect(truncated.width).toBeLessThanOrEqual(100)
+      expect(truncated.width).toBeGreaterThan(clipped.width)
+
+      // wrapped text stops after maxLines, with the last one ellipsized
+      ctx.textWrap = true
+      expect(ctx.measureText(text, 100).lines.length).toBeGreaterThan(2)
+      ctx.maxLines = 2
+      let {lines} = ctx.measureText(text, 100)
+      expect(lines.length).toBe(2)
+      expect(lines[1].width).toBeLessThanOrEqual(100)
+    })
+
+    test("drawCanvas()", () => {
+      let src = new Canvas(100, 100),
+          srcCtx = src.getContext("2d");
+      srcCtx.fillStyle = 'red'
+      srcCtx.fillRect(0, 0, 100, 100)
+
+      ctx.drawCanvas(src, 0, 0)
+      ctx.drawCanvas(src, 200, 0, {live:true})
+      ctx.fillStyle = 'blue'
+      ctx.fillRect(250, 50, 10, 10)
+
+      // later changes to the source show up in the live copy (but not the static one)
+      srcCtx.fillStyle = '#0f0'
+      srcCtx.fillRect(0, 0, 100, 100)
+      expect(pixel(50, 50)).toEqual([255, 0, 0, 255])
+      expect(pixel(210, 10)).toEqual([0, 255, 0, 255])
+      expect(pixel(255, 55)).toEqual([0, 0, 255, 255])
+
+      // the link is also resolved when the canvas is used as an image source
+      srcCtx.fillStyle = '#fff'
+      srcCtx.fillRect(0, 0, 100, 100)
+      let out = new Canvas(WIDTH, HEIGHT)
+      out.getContext("2d").drawImage(canvas, 0, 0)
+      expect(Array.from(out.getContext("2d").getImageData(210, 10, 1, 1).data)).toEqual([255, 255, 255, 255])
+
+      // circular links don't recurse forever
+      srcCtx.drawCanvas(canvas, 0, 0, {live:true})
+      expect(pixel(210, 10)[3]).toBe(255)
+      expect(() => ctx.drawCanvas({}, 0, 0)).toThrow(TypeError)
+    })
+
+    test("linkTo()", () => {
+      ctx.fillRect(10, 10, 100, 20)
+      ctx.linkTo('https://example.com/invoice', {x:10, y:10, width:100, height:20})
+      ctx.beginPath()
+      ctx.rect(10, 50, 40, 40)
+      ctx.linkTo('https://example.com/path')
+
+      let svg = canvas.toBuffer('svg').toString()
+      expect(svg).toMatch(/<a [^>]*href="https:\/\/example.com\/invoice"/)
+      expect(svg).toMatch(/href="https:\/\/example.com\/path"/)
+
+      let pdf = canvas.toBuffer('pdf').toString('latin1')
+      expect(pdf).toMatch(/\/URI \(https:\/\/example.com\/invoice\)/)
+      expect(pdf).toMatch(/\/Subtype \/Link/)
+
+      // links don't alter the bitmap
+      expect(pixel(30, 70)).toEqual([0, 0, 0, 0])
+      expect(() => ctx.linkTo('https://example.com', {x:0, y:0})).toThrow(TypeError)
+    })
+
+    test("layoutText() & drawGlyphs()", () => {
+      let ink = () => {
+        let data = ctx.getImageData(0, 0, 300, 100).data,
+            count = 0, left = Infinity, top = Infinity;
+        for (let i=3; i<data.length; i+=4){
+          if (data[i] < 128) continue
+          let px = (i - 3) / 4
+          count++
+          left = Math.min(left, px % 300)
+          top = Math.min(top, Math.floor(px / 300))
+        }
+        return {count, left, top}
+      }
+
+      ctx.font = '32px sans-serif'
+      ctx.textBaseline = 'middle'
+      let {glyphs, positions} = ctx.layoutText("Wave")
+      expect(glyphs.map(g => g.text)).toEqual(["W", "a", "v", "e"])
+      expect(glyphs.map(g => g.index)).toEqual([0, 1, 2, 3])
+      expect(positions[1].x).toBeGreaterThan(positions[0].x)
+      expect(new Set(positions.map(pos => pos.y)).size).toBe(1)
+      expect(positions.every(pos => pos.rotation === 0)).toBe(true)
+
+      // unmodified positions reproduce the fillText() output
+      ctx.fillText("Wave", 50, 50)
+      let expected = ink()
+      ctx.clearRect(0, 0, WIDTH, HEIGHT)
+      ctx.translate(50, 50)
+      ctx.drawGlyphs(glyphs, positions)
+      let drawn = ink()
+      expect(Math.abs(drawn.count - expected.count)).toBeLessThan(expected.count * 0.05)
+      expect(Math.abs(drawn.left - expected.left)).toBeLessThan(2)
+      expect(Math.abs(drawn.top - expected.top)).toBeLessThan(2)
+
+      // individual glyphs can be moved & rotated
+      ctx.clearRect(-50, -50, WIDTH, HEIGHT)
+      ctx.drawGlyphs(glyphs, positions.map(({x, y}, i) => [x, y + i * 5, Math.PI / 8]))
+      expect(ink().count).toBeGreaterThan(0)
+      expect(ink().top).not.toEqual(expected.top)
+
+      expect(() => ctx.drawGlyphs(glyphs, positions.slice(1))).toThrow(TypeError)
+
+      // combining marks are shaped along with (and move with) their base character
+      ctx.clearRect(-50, -50, WIDTH, HEIGHT)
+      let plain = ctx.layoutText("e"),
+          accented = ctx.layoutText("e\u0301");
+      expect(accented.glyphs.map(g => g.text)).toEqual(["e\u0301"])
+      ctx.drawGlyphs(plain.glyphs, plain.positions)
+      let bare = ink()
+      ctx.clearRect(-50, -50, WIDTH, HEIGHT)
+      ctx.drawGlyphs(accented.glyphs, accented.positions.map(({x, y}) => ({x:x + 20, y})))
+      let marked = ink()
+      expect(marked.top).toBeLessThan(bare.top)
+      expect(marked.left).toBeGreaterThan(bare.left + 10)
+    })
+
+    test("fillTextSpans()", () => {
+      ctx.textBaseline = 'middle'
+      ctx.fillTextSpans([
+        {text:'A', font:'32px sans-serif', fillStyle:'#f00'},
+        {text:'B', font:'32px sans-serif', fillStyle:'#00f', underline:true},
+      ], 10, 20)
+
+      let rgb = ([r, g, b, a]) => [r>0, g>0, b>0]
+      let pixels = _.range(0, 100).map(x => pixel(x, 20))
+      expect(pixels.some(px => _.isEqual(rgb(px), [true, false, false]))).toBe(true)
+      expect(pixels.some(px => _.isEqual(rgb(px), [false, false, true]))).toBe(true)
+    })
+
     test('getImageData()', () => {
       ctx.fillStyle = 'rgba(255,0,0, 0.25)'
       ctx.fillRect(0,0,1,6)
@@ -481,6 +2666,49 @@ describe("Context2D", ()=>{
       ])
     })
 
+    test('getImageData() after further drawing', () => {
+      ctx.fillStyle = 'red'
+      ctx.fillRect(0, 0, 10, 10)
+      expect(pixel(5, 5)).toEqual([255, 0, 0, 255])
+      expect(pixel(15, 5)).toEqual([0, 0, 0, 0])
+
+      // reads reflect every kind of change made since the previous read
+      ctx.fillStyle = 'blue'
+      ctx.fillRect(10, 0, 10, 10)
+      expect(pixel(15, 5)).toEqual([0, 0, 255, 255])
+      ctx.clearRect(0, 0, 10, 10)
+      expect(pixel(5, 5)).toEqual([0, 0, 0, 0])
+      ctx.putImageData(ctx.getImageData(10, 0, 10, 10), 0, 0)
+      expect(pixel(5, 5)).toEqual([0, 0, 255, 255])
+
+      // regions beyond the canvas edges are transparent
+      let edge = ctx.getImageData(-5, -5, 10, 10)
+      expect(Array.from(edge.data.slice(0, 4))).toEqual([0, 0, 0, 0])
+      expect(Array.from(edge.data.slice(-4))).toEqual([0, 0, 255, 255])
+    })
+
+    test('putImageData() with dirty rects', () => {
+      let red = ctx.createImageData(10, 10),
+          clear = ctx.createImageData(10, 10);
+      for (let i=0; i<red.data.length; i+=4) red.data.set([255, 0, 0, 255], i)
+
+      // consecutive calls are batched, with later regions replacing earlier ones
+      ctx.putImageData(red, 0, 0)
+      ctx.putImageData(red, 20, 0, 5, 0, 5, 10)
+      ctx.putImageData(clear, 5, 5, 0, 0, 2, 2)
+      expect(pixel(2, 2)).toEqual([255, 0, 0, 255])
+      expect(pixel(6, 6)).toEqual([0, 0, 0, 0])
+      expect(pixel(22, 5)).toEqual([0, 0, 0, 0])
+      expect(pixel(27, 5)).toEqual([255, 0, 0, 255])
+
+      // drawing operations are ordered correctly relative to the batched pixels
+      ctx.fillStyle = 'blue'
+      ctx.fillRect(0, 0, 5, 5)
+      ctx.putImageData(red, 0, 0, 0, 0, 2, 2)
+      expect(pixel(1, 1)).toEqual([255, 0, 0, 255])
+      expect(pixel(3, 3)).toEqual([0, 0, 255, 255])
+    })
+
     test("isPointInPath()", () => {
       let inStroke = [100, 94],
           inFill = [150, 150],
@@ -523,6 +2751,111 @@ describe("Context2D", ()=>{
       expect(metrics.alphabeticBaseline).toBeGreaterThan(0)
       expect(metrics.actualBoundingBoxAscent).toBeGreaterThan(0)
       expect(metrics.actualBoundingBoxDescent).toBeLessThan(0)
+
+      // each character's position, advance & UTF-16 index is reported per line
+      ctx.textBaseline = "alphabetic"
+      let [line] = ctx.measureText("a🙂b").lines
+      expect(line.glyphs.map(g => g.index)).toEqual([0, 1, 3])
+      expect(line.glyphs[0].x).toBeLessThan(line.glyphs[1].x)
+      expect(line.glyphs[1].x).toBeLessThan(line.glyphs[2].x)
+      line.glyphs.forEach(g => expect(g.advance).toBeGreaterThan(0))
+
+      // multi-codepoint sequences are merged into a single grapheme
+      let str = "e\u0301x\u{1F1FA}\u{1F1F8}";
+      [line] = ctx.measureText(str).lines
+      expect(line.graphemes.map(g => g.text)).toEqual(["e\u0301", "x", "\u{1F1FA}\u{1F1F8}"])
+      expect(line.graphemes.map(g => g.index)).toEqual([0, 2, 3])
+    })
+
+    test("cached measurements", () => {
+      ctx.font = "20px Arial, DejaVu Sans"
+      let small = ctx.measureText("cache me"),
+          again = ctx.measureText("cache me");
+      expect(again).toEqual(small)
+
+      // changing any of the settings that affect layout bypasses the earlier result
+      ctx.font = "40px Arial, DejaVu Sans"
+      expect(ctx.measureText("cache me").width).toBeGreaterThan(small.width)
+      ctx.font = "20px Arial, DejaVu Sans"
+      ctx.textTracking = 100
+      expect(ctx.measureText("cache me").width).toBeGreaterThan(small.width)
+      ctx.textTracking = 0
+      ctx.textAlign = "right"
+      expect(ctx.measureText("cache me").actualBoundingBoxLeft).not.toEqual(small.actualBoundingBoxLeft)
+      ctx.textAlign = "start"
+      expect(ctx.measureText("cache me").width).toEqual(small.width)
+
+      // drawing reuses the measured layout but still picks up the current fill and baseline
+      ctx.textBaseline = "top"
+      ctx.fillStyle = "red"
+      ctx.fillText("cache me", 10, 10)
+      ctx.fillStyle = "blue"
+      ctx.fillText("cache me", 10, 10)
+      let colors = _.range(10, 10 + small.width).flatMap(x => _.range(10, 30).map(y => pixel(x, y)))
+      expect(colors.some(([r, g, b, a]) => b > 200 && a == 255)).toBe(true)
+      expect(colors.some(([r, g, b, a]) => r > 200 && a == 255)).toBe(false)
+    })
+
+    test("bidi-aware textAlign", () => {
+      ctx.font = "20px Arial, DejaVu Sans"
+      ctx.textAlign = "start"
+      let hebrew = "\u05E9\u05DC\u05D5\u05DD world",
+          [ltr] = ctx.measureText("hello world").lines,
+          [rtl] = ctx.measureText(hebrew).lines;
+
+      // with an inherited direction, start/end resolve against the text's own base direction
+      expect(ltr.x).toBeGreaterThanOrEqual(0)
+      expect(rtl.x).toBeLessThan(0)
+
+      // but an explicit direction takes precedence
+      ctx.direction = "ltr"
+      expect(ctx.measureText(hebrew).lines[0].x).toBeGreaterThanOrEqual(0)
+      ctx.textAlign = "end"
+      expect(ctx.measureText(hebrew).lines[0].x).toBeLessThan(0)
+    })
+
+    test("vertical text", () => {
+      ctx.font = "32px Arial, DejaVu Sans"
+      ctx.writingMode = 'vertical-rl'
+      ctx.fillText("AB", 50, 10)
+
+      // the letters should be stacked in a column rather than running across the canvas
+      let column = _.range(10, 80).map(y => pixel(50, y)),
+          row = _.range(80, 150).map(x => pixel(x, 20));
+      expect(column.some(([r, g, b, a]) => a > 0)).toBe(true)
+      expect(row.some(([r, g, b, a]) => a > 0)).toBe(false)
+    })
+
+    test("outlineText()", () => {
+      ctx.font = "48px Arial, DejaVu Sans"
+      let path = ctx.outlineText("Hi", 10, 50)
+      expect(path).toBeInstanceOf(Path2D)
+      expect(ctx.isPointInPath(path, 5, 5)).toBe(false)
+
+      ctx.fill(path)
+      let pixels = _.range(10, 60).map(x => pixel(x, 35))
+      expect(pixels.some(([r, g, b, a]) => a > 0)).toBe(true)
+
+      // decorations are included along with the glyphs
+      let xs = _.range(12, 50)
+      expect(xs.some(x => ctx.isPointInPath(path, x, 57))).toBe(false)
+      ctx.textDecoration = 'underline 4px'
+      let underlined = ctx.outlineText("Hi", 10, 50)
+      expect(xs.every(x => ctx.isPointInPath(underlined, x, 57))).toBe(true)
+    })
+
+    test("textBounds()", () => {
+      ctx.font = "20px Arial, DejaVu Sans"
+      let [all] = ctx.textBounds("foo bar"),
+          [foo] = ctx.textBounds("foo bar", 0, 3),
+          [bar] = ctx.textBounds("foo bar", 4, 7);
+      expect(foo.width).toBeLessThan(all.width)
+      expect(bar.x).toBeGreaterThan(foo.x + foo.width)
+      expect(ctx.textBounds("foo bar", 3, 3)).toEqual([])
+
+      // wrapped text produces a rect for each line of the selection
+      ctx.textWrap = true
+      expect(ctx.textBounds("foo bar", 0, 7, 30).length).toBe(2)
     })
 
   })
@@ -699,6 +3032,15 @@ describe("FontLibrary", ()=>{
     expect(FontLibrary.has("_n_o_n_e_s_u_c_h_")).toBe(false)
   })
 
+  test("can select an emoji font", ()=>{
+    let systemEmoji = FontLibrary.emoji
+    expect(systemEmoji === null || typeof systemEmoji == 'string').toBe(true)
+    FontLibrary.emoji = "DejaVu Sans"
+    expect(FontLibrary.emoji).toBe("DejaVu Sans")
+    FontLibrary.emoji = systemEmoji
+    expect(FontLibrary.emoji).toBe(systemEmoji)
+  })
+
   test("can describe a family", ()=>{
     let fam = FontLibrary.has("Arial") ? "Arial"
             : FontLibrary.has("DejaVu Sans") ? "DejaVu Sans"
@@ -726,5 +3068,123 @@ describe("FontLibrary", ()=>{
     expect(FontLibrary.has(alias)).toBe(true)
     expect(FontLibrary.family(alias).weights).toContain(400)
   })
+
+  test("can register fonts from buffers", ()=>{
+    let ttf = fs.readFileSync(findFont("AmstelvarAlpha-VF.ttf")),
+        dataURL = `data:font/ttf;base64,${ttf.toString('base64')}`;
+
+    expect(FontLibrary.use("BufferedBosch", ttf)[0]).toMatchObject({family:"BufferedBosch"})
+    expect(FontLibrary.has("BufferedBosch")).toBe(true)
+
+    expect(() => FontLibrary.use("EncodedBosch", [dataURL])).not.toThrow()
+    expect(FontLibrary.has("EncodedBosch")).toBe(true)
+
+    let [font] = FontLibrary.use({src:ttf, family:"DescribedBosch", weight:700})
+    expect(font).toMatchObject({family:"DescribedBosch"})
+    expect(FontLibrary.has("DescribedBosch")).toBe(true)
+  })
+
+  test("can register webfonts", ()=>{
+    let ctx = new Canvas(200, 50).getContext('2d'),
+        widths = {};
+
+    for (let ext of ['ttf', 'woff', 'woff2']){
+      let family = `Webfont${ext.toUpperCase()}Bosch`,
+          [font] = FontLibrary.use(family, findFont(`AmstelvarAlpha-VF.${ext}`));
+      expect(font).toMatchObject({family})
+      ctx.font = `24px ${family}`
+      widths[ext] = ctx.measureText('Quartz glyph jocks').width
+    }
+    expect(widths.woff).toEqual(widths.ttf)
+    expect(widths.woff2).toEqual(widths.ttf)
+
+    let corrupt = fs.readFileSync(findFont("AmstelvarAlpha-VF.woff2")).slice(0, 200)
+    expect(() => FontLibrary.use("CorruptBosch", corrupt)).toThrow(/WOFF2/)
+  })
+
+  test("can match fonts & list styles", ()=>{
+    let ttf = findFont("AmstelvarAlpha-VF.ttf")
+    FontLibrary.use("MatchedBosch", ttf)
+
+    let face = FontLibrary.match("bold 12px MatchedBosch, sans-serif")
+    expect(face).toMatchObject({family:"MatchedBosch", file:ttf})
+    expect(face.weight).toBeGreaterThan(0)
+
+    // system fonts are matched too, but their locations aren't reported
+    let sys = FontLibrary.match("12px _n_o_n_e_s_u_c_h_, Arial, DejaVu Sans")
+    expect(sys && ["Arial", "DejaVu Sans"].includes(sys.family)).toBe(true)
+    expect(sys.file).toBe(null)
+
+    let listed = FontLibrary.listFamilies({withStyles:true}),
+        entry = listed.find(fam => fam.family == "MatchedBosch");
+    expect(FontLibrary.listFamilies()).toEqual(FontLibrary.families)
+    expect(entry.weights).toContain(400)
+    expect(entry.files).toEqual([ttf])
+  })
+
+  test("can create isolated libraries", ()=>{
+    let ttf = findFont("AmstelvarAlpha-VF.ttf"),
+        isolated = FontLibrary.create();
+
+    // fonts added to an isolated library aren't visible globally (and vice versa)
+    isolated.use("IsolatedBosch", ttf)
+    expect(isolated.has("IsolatedBosch")).toBe(true)
+    expect(FontLibrary.has("IsolatedBosch")).toBe(false)
+    FontLibrary.use("GlobalBosch", ttf)
+    expect(isolated.has("GlobalBosch")).toBe(false)
+
+    // but system fonts are available to both
+    expect(isolated.has("Arial") || isolated.has("DejaVu Sans")).toBe(true)
+
+    let canvas = new Canvas(200, 50, {fontLibrary:isolated}),
+        ctx = canvas.getContext('2d');
+    expect(canvas.fontLibrary).toBe(isolated)
+    expect(new Canvas(10, 10).fontLibrary).toBe(FontLibrary)
+    expect(canvas.newPage(200, 50).canvas.fontLibrary).toBe(isolated)
+    expect(() => new Canvas(10, 10, {fontLibrary:{}})).toThrow(TypeError)
+
+    // the global library falls back to a default font for the unknown family
+    let globalCtx = new Canvas(200, 50).getContext('2d')
+    ctx.font = globalCtx.font = '24px IsolatedBosch'
+    expect(ctx.measureText('hello').width).not.toEqual(globalCtx.measureText('hello').width)
+  })
+
+  test("can ignore system fonts", ()=>{
+    let ttf = findFont("AmstelvarAlpha-VF.ttf"),
+        portable = FontLibrary.create();
+    expect(portable.systemFonts).toBe(true)
+
+    portable.use("PortableBosch", ttf)
+    portable.reset({systemFonts:false})
+    expect(portable.systemFonts).toBe(false)
+    expect(portable.families).toEqual([])
+    expect(portable.emoji).toBeNull()
+    expect(portable.match("12px sans-serif")).toBeNull()
+    expect(FontLibrary.systemFonts).toBe(true)
+
+    portable.use("PortableBosch", ttf)
+    expect(portable.families).toEqual(["PortableBosch"])
+    expect(portable.match("12px PortableBosch, sans-serif").family).toEqual("PortableBosch")
+
+    expect(FontLibrary.create({systemFonts:false}).families).toEqual([])
+    portable.reset()
+    expect(portable.has("PortableBosch")).toBe(false)
+    expect(portable.has("Arial") || portable.has("DejaVu Sans")).toBe(true)
+  })
 })
 
+describe("Window", ()=>{
+  // invalid options are rejected before a native window is opened, so these don't require a display
+  test("validates its options", ()=>{
+    expect(() => new Window({canvas:{width:100, height:100}})).toThrow(TypeError)
+    expect(() => new Window({width:0})).toThrow(RangeError)
+    expect(() => new Window({height:-50})).toThrow(RangeError)
+    expect(() => new Window({width:'wide'})).toThrow(RangeError)
+    expect(() => new Window({width:NaN})).toThrow(RangeError)
+    expect(() => new Window({background:0xffffff})).toThrow(TypeError)
+    expect(() => new Window({fps:0})).toThrow(RangeError)
+    expect(() => new Window({fps:Infinity})).toThrow(RangeError)
+    expect(Window.open.size).toBe(0)
+    expect(Window.timer).toBeNull()
+  })
+})